@@ -0,0 +1,69 @@
+//! Quick-switcher search over a 20k-note vault.
+
+use criterion::{Criterion, criterion_group, criterion_main};
+use synapse_core::Note;
+use synapse_core::fuzzy::FuzzyIndex;
+
+const NOTES: usize = 20_000;
+const NOW: i64 = 1_700_000_000;
+
+/// Titles mixing English and Chinese words, with an alias on every tenth note and folders
+fn vault() -> FuzzyIndex {
+    let words = [
+        "meeting",
+        "notes",
+        "project",
+        "rust",
+        "design",
+        "review",
+        "weekly",
+        "plan",
+        "知识",
+        "管理",
+        "读书",
+        "笔记",
+        "kubernetes",
+        "deploy",
+        "journal",
+        "ideas",
+    ];
+    let folders = [
+        "/Work",
+        "/Work/Infra",
+        "/Personal/Journal",
+        "/Archive/2023",
+        "/Reading",
+    ];
+
+    let mut index = FuzzyIndex::new();
+    for i in 0..NOTES {
+        let title = format!(
+            "{} {} {} {}",
+            words[i % words.len()],
+            words[(i / 7) % words.len()],
+            words[(i / 53) % words.len()],
+            i
+        );
+        let mut note = Note::new(format!("note-{}", i), title, format!("notes/{}.md", i));
+        note.updated_at = NOW - (i as i64) * 600;
+        let aliases = if i % 10 == 0 {
+            vec![format!("alias {}", i)]
+        } else {
+            Vec::new()
+        };
+        index.insert(&note, &aliases, &[folders[i % folders.len()].to_string()]);
+    }
+    index
+}
+
+fn bench_fuzzy_titles(c: &mut Criterion) {
+    let index = vault();
+    for query in ["mtn", "rust review", "知管", "infkub", "zzzz"] {
+        c.bench_function(&format!("fuzzy_20k_{}", query), |b| {
+            b.iter(|| index.search(query, 20, NOW))
+        });
+    }
+}
+
+criterion_group!(benches, bench_fuzzy_titles);
+criterion_main!(benches);
@@ -0,0 +1,102 @@
+//! Loading a note's relations: a note with 200 tags and 200 backlinks.
+//!
+//! Statements are counted with SQLite's trace hook. The set-based service methods run one
+//! statement per call; the per-ID baseline runs one per related row plus the ID lookup.
+//! The hook is set on a pooled reader, which the next read on this thread reuses.
+
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use criterion::{Criterion, criterion_group, criterion_main};
+use rusqlite::trace::{TraceEvent, TraceEventCodes};
+use synapse_core::storage::{NoteTagDao, TagDao};
+use synapse_core::{LinkService, NoteService, ServiceContext, TagService};
+
+const RELATED: usize = 200;
+
+static STATEMENTS: AtomicUsize = AtomicUsize::new(0);
+
+fn count_statement(event: TraceEvent<'_>) {
+    if let TraceEvent::Stmt(..) = event {
+        STATEMENTS.fetch_add(1, Ordering::Relaxed);
+    }
+}
+
+/// Statements run by `f`
+fn statements<T>(ctx: &ServiceContext, f: impl FnOnce() -> T) -> usize {
+    ctx.store()
+        .unwrap()
+        .trace_v2(TraceEventCodes::SQLITE_TRACE_STMT, Some(count_statement));
+    STATEMENTS.store(0, Ordering::Relaxed);
+    f();
+    ctx.store()
+        .unwrap()
+        .trace_v2(TraceEventCodes::empty(), None);
+    STATEMENTS.load(Ordering::Relaxed)
+}
+
+fn vault(ctx: &ServiceContext) -> String {
+    let hub = NoteService::create(ctx, "Hub".to_string(), String::new()).unwrap();
+    for i in 0..RELATED {
+        let tag = TagService::create(ctx, format!("tag-{}", i)).unwrap();
+        NoteService::add_tag(ctx, &hub.id, &tag.id).unwrap();
+        let source = NoteService::create(ctx, format!("Source {}", i), String::new()).unwrap();
+        LinkService::create_note_link(ctx, source.id, hub.id.clone(), None).unwrap();
+    }
+    hub.id
+}
+
+/// The lookup `NoteService::get_tags` used to do: IDs first, then one query per tag
+fn tags_per_id(ctx: &ServiceContext, note_id: &str) -> usize {
+    let conn = ctx.store().unwrap();
+    let mut tags = 0;
+    for tag_id in NoteTagDao::get_tags_for_note(&conn, note_id).unwrap() {
+        tags += TagDao::get_by_id(&conn, &tag_id).unwrap().is_some() as usize;
+    }
+    tags
+}
+
+fn bench_relations(c: &mut Criterion) {
+    let dir = std::env::temp_dir().join(format!("synapse-bench-relations-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let ctx = ServiceContext::new(dir.join("synapse.db"), dir.clone()).unwrap();
+    let hub = vault(&ctx);
+
+    assert_eq!(NoteService::get_tags(&ctx, &hub).unwrap().len(), RELATED);
+    assert_eq!(
+        LinkService::get_backlinks(&ctx, &hub).unwrap().len(),
+        RELATED
+    );
+    let counts = [
+        (
+            "get_tags",
+            statements(&ctx, || NoteService::get_tags(&ctx, &hub)),
+        ),
+        (
+            "get_backlinks",
+            statements(&ctx, || LinkService::get_backlinks(&ctx, &hub)),
+        ),
+        ("tags_per_id", statements(&ctx, || tags_per_id(&ctx, &hub))),
+    ];
+    for (name, count) in counts {
+        println!(
+            "{} with {} related rows: {} statement(s)",
+            name, RELATED, count
+        );
+    }
+    assert_eq!(counts[0].1, 1);
+    assert_eq!(counts[1].1, 1);
+
+    c.bench_function("get_tags_200", |b| {
+        b.iter(|| NoteService::get_tags(&ctx, &hub))
+    });
+    c.bench_function("get_backlinks_200", |b| {
+        b.iter(|| LinkService::get_backlinks(&ctx, &hub))
+    });
+    c.bench_function("tags_per_id_200", |b| b.iter(|| tags_per_id(&ctx, &hub)));
+
+    drop(ctx);
+    std::fs::remove_dir_all(&dir).ok();
+}
+
+criterion_group!(benches, bench_relations);
+criterion_main!(benches);
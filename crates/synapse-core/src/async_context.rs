@@ -0,0 +1,104 @@
+//! Async facade over [`ServiceContext`] (feature `async`).
+//!
+//! Services block on SQLite and the file system, so async callers such as Tauri commands run
+//! them on tokio's blocking thread pool instead of an executor thread:
+//!
+//! ```ignore
+//! let note = ctx.run(move |ctx| NoteService::create(ctx, title, content)).await?;
+//! ```
+
+use std::sync::Arc;
+
+use crate::services::ServiceContext;
+use crate::storage::{SqliteBackend, StorageBackend};
+use crate::{Error, Result};
+
+/// A shared [`ServiceContext`] whose calls run on the blocking thread pool; clones share it
+pub struct AsyncServiceContext<B: StorageBackend = SqliteBackend> {
+    ctx: Arc<ServiceContext<B>>,
+}
+
+impl<B: StorageBackend + 'static> AsyncServiceContext<B> {
+    pub fn new(ctx: ServiceContext<B>) -> Self {
+        Self { ctx: Arc::new(ctx) }
+    }
+
+    /// The synchronous context, for calls that are cheap enough to make in place
+    pub fn context(&self) -> &ServiceContext<B> {
+        &self.ctx
+    }
+
+    /// Run `f` with the context on the blocking thread pool. A panic in `f` is resumed in
+    /// the caller.
+    pub async fn run<T, F>(&self, f: F) -> Result<T>
+    where
+        F: FnOnce(&ServiceContext<B>) -> Result<T> + Send + 'static,
+        T: Send + 'static,
+    {
+        let ctx = Arc::clone(&self.ctx);
+        match tokio::task::spawn_blocking(move || f(&ctx)).await {
+            Ok(result) => result,
+            Err(e) if e.is_panic() => std::panic::resume_unwind(e.into_panic()),
+            Err(e) => Err(Error::Storage(format!("Background task failed: {}", e))),
+        }
+    }
+}
+
+impl<B: StorageBackend> Clone for AsyncServiceContext<B> {
+    fn clone(&self) -> Self {
+        Self {
+            ctx: Arc::clone(&self.ctx),
+        }
+    }
+}
+
+impl<B: StorageBackend + 'static> From<ServiceContext<B>> for AsyncServiceContext<B> {
+    fn from(ctx: ServiceContext<B>) -> Self {
+        Self::new(ctx)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::services::{FolderService, NoteService, SearchService};
+
+    #[test]
+    fn test_run_on_blocking_pool() {
+        let dir = std::env::temp_dir().join(format!("synapse-async-{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let ctx = AsyncServiceContext::new(
+            ServiceContext::new(dir.join("synapse.db"), dir.clone()).unwrap(),
+        );
+
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .build()
+            .unwrap();
+        runtime.block_on(async {
+            let mut tasks = Vec::new();
+            for i in 0..8 {
+                let ctx = ctx.clone();
+                tasks.push(tokio::spawn(async move {
+                    ctx.run(move |ctx| {
+                        NoteService::create(ctx, format!("Note {}", i), String::new())
+                    })
+                    .await
+                }));
+            }
+            for task in tasks {
+                task.await.unwrap().unwrap();
+            }
+            let notes = ctx.run(|ctx| NoteService::list(ctx, false)).await.unwrap();
+            assert_eq!(notes.len(), 8);
+            let matches = ctx
+                .run(|ctx| SearchService::fuzzy_titles(ctx, "note 7", 5))
+                .await
+                .unwrap();
+            assert_eq!(matches[0].title, "Note 7");
+            assert!(ctx.run(FolderService::tree).await.unwrap().is_empty());
+        });
+
+        drop(ctx);
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,396 @@
+//! Where note content lives.
+//!
+//! A note's row keeps only its `content_path`; the markdown itself is in a [`ContentStore`].
+//! [`FileContent`] keeps it in files under the data directory, [`DatabaseContent`] as blobs in
+//! the vault database for single-file vaults, and [`MemoryContent`] in memory for tests and
+//! [`crate::ServiceContext::in_memory`]. [`ContentConfig`] picks between the first two when a
+//! vault is created, and the vault records its choice.
+//!
+//! Writes are staged by a [`crate::UnitOfWork`] and land when it commits: files are renamed
+//! into place and memory is updated just before the transaction commits and put back if it
+//! fails, while blobs are written in the transaction itself.
+
+use std::collections::HashMap;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::RwLock;
+
+use serde::{Deserialize, Serialize};
+
+use crate::storage::BlobStorage;
+use crate::unit_of_work::StagedFiles;
+use crate::{Error, Result};
+
+/// Reads note content and stages writes to it
+pub trait ContentStore: Send + Sync {
+    /// Content at `path`, or `None` if nothing is stored there. `blobs` is the store the
+    /// caller reads rows from, for content kept in the database.
+    fn read(&self, blobs: &dyn BlobStorage, path: &str) -> Result<Option<String>>;
+
+    /// Start collecting the content writes of one unit of work
+    fn changes(&self) -> Box<dyn ContentChanges + '_>;
+}
+
+/// Content writes of a unit of work; dropped before [`ContentChanges::apply`], they are
+/// discarded
+pub trait ContentChanges {
+    /// Stage `content` to replace what is at `path`. `blobs` is the unit's transaction.
+    fn write(&mut self, blobs: &dyn BlobStorage, path: &str, content: &str) -> Result<()>;
+
+    /// Make the staged writes visible, just before the transaction commits
+    fn apply(&mut self) -> Result<()>;
+
+    /// Undo [`ContentChanges::apply`] after the transaction failed to commit
+    fn revert(&mut self);
+
+    /// Clean up after the transaction committed
+    fn finish(&mut self);
+}
+
+/// Which [`ContentStore`] a vault keeps note content in
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ContentConfig {
+    /// Files under the data directory
+    #[default]
+    Files,
+    /// Blobs in the vault database, so the vault is a single file
+    Database,
+}
+
+impl ContentConfig {
+    /// Name used in configuration
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ContentConfig::Files => "files",
+            ContentConfig::Database => "database",
+        }
+    }
+
+    /// Parse a configured name
+    pub fn parse(name: &str) -> Option<Self> {
+        match name {
+            "files" => Some(ContentConfig::Files),
+            "database" => Some(ContentConfig::Database),
+            _ => None,
+        }
+    }
+
+    /// Open the configured store for a vault whose data directory is `data_dir`
+    pub fn open(self, data_dir: &Path) -> Result<Box<dyn ContentStore>> {
+        Ok(match self {
+            ContentConfig::Files => Box::new(FileContent::new(data_dir)),
+            ContentConfig::Database => Box::new(DatabaseContent),
+        })
+    }
+}
+
+/// Note content in files, at content paths relative to a root directory
+pub struct FileContent {
+    root: PathBuf,
+}
+
+impl FileContent {
+    /// Keep content under `root`; directories are created as content is written
+    pub fn new<P: AsRef<Path>>(root: P) -> Self {
+        Self {
+            root: root.as_ref().to_path_buf(),
+        }
+    }
+
+    /// Directory content paths are relative to
+    pub fn root(&self) -> &Path {
+        &self.root
+    }
+}
+
+impl ContentStore for FileContent {
+    fn read(&self, _blobs: &dyn BlobStorage, path: &str) -> Result<Option<String>> {
+        match fs::read_to_string(self.root.join(path)) {
+            Ok(content) => Ok(Some(content)),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    fn changes(&self) -> Box<dyn ContentChanges + '_> {
+        Box::new(FileChanges {
+            root: &self.root,
+            files: StagedFiles::default(),
+        })
+    }
+}
+
+struct FileChanges<'a> {
+    root: &'a Path,
+    files: StagedFiles,
+}
+
+impl ContentChanges for FileChanges<'_> {
+    fn write(&mut self, _blobs: &dyn BlobStorage, path: &str, content: &str) -> Result<()> {
+        self.files.write(self.root.join(path), content.as_bytes())
+    }
+
+    fn apply(&mut self) -> Result<()> {
+        self.files.apply()
+    }
+
+    fn revert(&mut self) {
+        self.files.revert();
+    }
+
+    fn finish(&mut self) {
+        self.files.finish();
+    }
+}
+
+/// Note content as blobs in the storage backend, written in the unit's transaction
+pub struct DatabaseContent;
+
+impl ContentStore for DatabaseContent {
+    fn read(&self, blobs: &dyn BlobStorage, path: &str) -> Result<Option<String>> {
+        blobs
+            .get_blob(path)?
+            .map(|data| {
+                String::from_utf8(data)
+                    .map_err(|e| Error::Storage(format!("Content at {} is not UTF-8: {}", path, e)))
+            })
+            .transpose()
+    }
+
+    fn changes(&self) -> Box<dyn ContentChanges + '_> {
+        Box::new(DatabaseContent)
+    }
+}
+
+impl ContentChanges for DatabaseContent {
+    fn write(&mut self, blobs: &dyn BlobStorage, path: &str, content: &str) -> Result<()> {
+        blobs.put_blob(path, content.as_bytes())
+    }
+
+    // The transaction commits or rolls back the blobs with everything else
+    fn apply(&mut self) -> Result<()> {
+        Ok(())
+    }
+
+    fn revert(&mut self) {}
+
+    fn finish(&mut self) {}
+}
+
+/// Note content in memory, for tests that should not touch the file system
+#[derive(Default)]
+pub struct MemoryContent {
+    contents: RwLock<HashMap<String, String>>,
+}
+
+impl ContentStore for MemoryContent {
+    fn read(&self, _blobs: &dyn BlobStorage, path: &str) -> Result<Option<String>> {
+        let contents = self.contents.read().unwrap_or_else(|e| e.into_inner());
+        Ok(contents.get(path).cloned())
+    }
+
+    fn changes(&self) -> Box<dyn ContentChanges + '_> {
+        Box::new(MemoryChanges {
+            contents: &self.contents,
+            staged: Vec::new(),
+            replaced: Vec::new(),
+        })
+    }
+}
+
+struct MemoryChanges<'a> {
+    contents: &'a RwLock<HashMap<String, String>>,
+    staged: Vec<(String, String)>,
+    /// What applied writes replaced, in the order they were applied
+    replaced: Vec<(String, Option<String>)>,
+}
+
+impl ContentChanges for MemoryChanges<'_> {
+    fn write(&mut self, _blobs: &dyn BlobStorage, path: &str, content: &str) -> Result<()> {
+        self.staged.push((path.to_string(), content.to_string()));
+        Ok(())
+    }
+
+    fn apply(&mut self) -> Result<()> {
+        let mut contents = self.contents.write().unwrap_or_else(|e| e.into_inner());
+        for (path, content) in self.staged.drain(..) {
+            let previous = contents.insert(path.clone(), content);
+            self.replaced.push((path, previous));
+        }
+        Ok(())
+    }
+
+    fn revert(&mut self) {
+        let mut contents = self.contents.write().unwrap_or_else(|e| e.into_inner());
+        for (path, previous) in self.replaced.drain(..).rev() {
+            match previous {
+                Some(content) => contents.insert(path, content),
+                None => contents.remove(&path),
+            };
+        }
+    }
+
+    fn finish(&mut self) {
+        self.replaced.clear();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::UnitOfWork;
+    use crate::storage::{SqliteBackend, StorageBackend};
+
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir =
+            std::env::temp_dir().join(format!("synapse-content-{}-{}", name, uuid::Uuid::new_v4()));
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    fn read(storage: &SqliteBackend, content: &dyn ContentStore) -> Option<String> {
+        content
+            .read(&*storage.reader().unwrap(), "notes/a.md")
+            .unwrap()
+    }
+
+    #[test]
+    fn test_writes_follow_the_transaction() {
+        for config in ["files", "database", "memory"] {
+            let dir = temp_dir(config);
+            let storage = SqliteBackend::open(dir.join("synapse.db")).unwrap();
+            let content: Box<dyn ContentStore> = match ContentConfig::parse(config) {
+                Some(known) => known.open(&dir).unwrap(),
+                None => Box::new(MemoryContent::default()),
+            };
+
+            let mut unit = UnitOfWork::begin(&storage, &*content).unwrap();
+            unit.write_content("notes/a.md", "first").unwrap();
+            drop(unit);
+            assert_eq!(read(&storage, &*content), None, "{:?}", config);
+
+            let mut unit = UnitOfWork::begin(&storage, &*content).unwrap();
+            unit.write_content("notes/a.md", "first").unwrap();
+            unit.write_content("notes/a.md", "second").unwrap();
+            unit.commit().unwrap();
+            assert_eq!(
+                read(&storage, &*content).as_deref(),
+                Some("second"),
+                "{:?}",
+                config
+            );
+
+            let mut unit = UnitOfWork::begin(&storage, &*content).unwrap();
+            // A deferred foreign key violation only fails at COMMIT, after content moved
+            unit.store()
+                .execute_batch(
+                    r#"
+                    PRAGMA defer_foreign_keys = ON;
+                    INSERT INTO note_tags (note_id, tag_id, created_at) VALUES ('missing', 'missing', 0);
+                    "#,
+                )
+                .unwrap();
+            unit.write_content("notes/a.md", "third").unwrap();
+            unit.write_content("notes/b.md", "new").unwrap();
+            assert!(unit.commit().is_err());
+            assert_eq!(
+                read(&storage, &*content).as_deref(),
+                Some("second"),
+                "{:?}",
+                config
+            );
+            let reader = storage.reader().unwrap();
+            assert_eq!(
+                content.read(&*reader, "notes/b.md").unwrap(),
+                None,
+                "{:?}",
+                config
+            );
+            drop(reader);
+
+            assert_eq!(dir.join("notes/a.md").exists(), config == "files");
+            drop(storage);
+            fs::remove_dir_all(dir).unwrap();
+        }
+    }
+
+    #[test]
+    fn test_vault_records_where_content_is_kept() {
+        use crate::storage::BlobStorage;
+        use crate::{NoteService, ServiceContext};
+
+        let dir = temp_dir("vault");
+        let db_path = dir.join("synapse.db");
+        let ctx = ServiceContext::open(&db_path, &dir, ContentConfig::Database).unwrap();
+        let note =
+            NoteService::create(&ctx, "Single".to_string(), "In the vault".to_string()).unwrap();
+        drop(ctx);
+
+        // Opened without a choice, the vault keeps the one it was created with
+        let ctx = ServiceContext::new(&db_path, &dir).unwrap();
+        let read = NoteService::get_by_id(&ctx, &note.id, false)
+            .unwrap()
+            .unwrap();
+        assert_eq!(read.content, "In the vault");
+        drop(ctx);
+        assert!(matches!(
+            ServiceContext::open(&db_path, &dir, ContentConfig::Files),
+            Err(Error::InvalidInput(_))
+        ));
+
+        // A vault from before the choice was recorded is recognised by its notes
+        let conn = rusqlite::Connection::open(&db_path).unwrap();
+        conn.execute("DELETE FROM settings", []).unwrap();
+        drop(conn);
+        assert!(matches!(
+            ServiceContext::open(&db_path, &dir, ContentConfig::Files),
+            Err(Error::InvalidInput(_))
+        ));
+
+        // Opening reads the recorded choice without waiting for the writer
+        let writer = ServiceContext::new(&db_path, &dir).unwrap();
+        let unit = writer.begin().unwrap();
+        let started = std::time::Instant::now();
+        drop(ServiceContext::new(&db_path, &dir).unwrap());
+        assert!(started.elapsed() < std::time::Duration::from_secs(1));
+        drop(unit);
+        drop(writer);
+
+        // A note whose content is gone reads as empty and can still be edited
+        let ctx = ServiceContext::new(&db_path, &dir).unwrap();
+        ctx.transaction(|unit| unit.store().delete_blob(&note.content_path))
+            .unwrap();
+        let read = NoteService::get_by_id(&ctx, &note.id, false)
+            .unwrap()
+            .unwrap();
+        assert_eq!(read.content, "");
+        NoteService::set_property(
+            &ctx,
+            &note.id,
+            "status",
+            crate::PropertyValue::Text("draft".to_string()),
+        )
+        .unwrap();
+        assert_eq!(
+            NoteService::get_by_id(&ctx, &note.id, false)
+                .unwrap()
+                .unwrap()
+                .content,
+            "---\nstatus: draft\n---\n"
+        );
+        drop(ctx);
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_config_names() {
+        for config in [ContentConfig::Files, ContentConfig::Database] {
+            assert_eq!(ContentConfig::parse(config.as_str()), Some(config));
+        }
+        // Memory content is for tests and embedding, not a vault setting
+        assert_eq!(ContentConfig::parse("memory"), None);
+        assert_eq!(ContentConfig::parse("cloud"), None);
+    }
+}
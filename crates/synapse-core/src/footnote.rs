@@ -0,0 +1,127 @@
+//! Footnotes: `[^label]` references and `[^label]: text` definitions.
+//!
+//! Definitions are parsed into `footnote` blocks holding their Markdown source, and references
+//! stay in the content of the block that uses them. [`footnote_links`] pairs the two;
+//! [`crate::BlockService::apply_diff`] stores each pair as a block reference.
+
+use std::collections::HashMap;
+
+use crate::models::{Block, BlockId};
+
+/// Block type of a footnote definition
+pub const FOOTNOTE_BLOCK_TYPE: &str = "footnote";
+
+/// Label of a footnote definition (`[^note]: text` -> `note`)
+pub fn footnote_label(content: &str) -> Option<&str> {
+    let rest = content.trim_start().strip_prefix("[^")?;
+    let end = rest.find("]:")?;
+    let label = &rest[..end];
+    (!label.is_empty() && !label.contains(char::is_whitespace)).then_some(label)
+}
+
+/// Labels referenced as `[^label]` in a block's content, in order of first use
+pub fn footnote_references(content: &str) -> Vec<&str> {
+    let mut labels = Vec::new();
+    let mut rest = content;
+    while let Some(start) = rest.find("[^") {
+        rest = &rest[start + 2..];
+        let Some(end) = rest.find(']') else { break };
+        let label = &rest[..end];
+        // `[^label]:` at the start of a line is a definition, not a reference
+        let is_definition = rest[end + 1..].starts_with(':');
+        if !label.is_empty()
+            && !label.contains(char::is_whitespace)
+            && !is_definition
+            && !labels.contains(&label)
+        {
+            labels.push(label);
+        }
+        rest = &rest[end + 1..];
+    }
+    labels
+}
+
+/// `(referencing block ID, footnote block ID)` pairs for every footnote reference
+pub fn footnote_links(blocks: &[Block]) -> Vec<(BlockId, BlockId)> {
+    let definitions: HashMap<&str, &BlockId> = blocks
+        .iter()
+        .filter(|block| block.block_type == FOOTNOTE_BLOCK_TYPE)
+        .filter_map(|block| footnote_label(&block.content).map(|label| (label, &block.id)))
+        .collect();
+
+    blocks
+        .iter()
+        .filter(|block| block.block_type != FOOTNOTE_BLOCK_TYPE)
+        .flat_map(|block| {
+            footnote_references(&block.content)
+                .into_iter()
+                .filter_map(|label| definitions.get(label))
+                .map(|target| (block.id.clone(), (*target).clone()))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::services::{BlockService, ServiceContext};
+    use crate::storage::MemoryBackend;
+    use crate::{BlockDiff, NoteService};
+
+    #[test]
+    fn test_footnote_references_and_labels() {
+        assert_eq!(footnote_label("[^note]: Some text"), Some("note"));
+        assert_eq!(footnote_label("Not a [^note] definition"), None);
+        assert_eq!(
+            footnote_references("See [^a] and [^b], again [^a]. [^c]: def"),
+            vec!["a", "b"]
+        );
+    }
+
+    #[test]
+    fn test_apply_diff_stores_footnote_references() {
+        let ctx = ServiceContext::in_memory();
+        let note = NoteService::create(&ctx, "Claims".to_string(), String::new()).unwrap();
+        let block = |id: &str, block_type: &str, content: &str, position| {
+            Block::new(
+                id.to_string(),
+                note.id.clone(),
+                block_type.to_string(),
+                content.to_string(),
+                position,
+            )
+        };
+        let claim = block("b1", "paragraph", "Claim[^a] and[^b].", 0);
+        let source_a = block("f1", FOOTNOTE_BLOCK_TYPE, "[^a]: First source", 1);
+        let source_b = block("f2", FOOTNOTE_BLOCK_TYPE, "[^b]: Second source", 2);
+        let referenced = |ctx: &ServiceContext<MemoryBackend>| -> Vec<String> {
+            let blocks = BlockService::get_referenced_blocks(ctx, "b1").unwrap();
+            blocks.into_iter().map(|block| block.id).collect()
+        };
+
+        let diff = BlockDiff {
+            inserted: vec![claim.clone(), source_a, source_b],
+            ..Default::default()
+        };
+        BlockService::apply_diff(&ctx, &diff).unwrap();
+        assert_eq!(referenced(&ctx), vec!["f1", "f2"]);
+        let referencing = BlockService::get_referencing_blocks(&ctx, "f1").unwrap();
+        assert_eq!(referencing[0].id, "b1");
+
+        // Dropping a reference or its definition drops the stored link
+        let mut edited = claim;
+        edited.update_content("Claim[^a].".to_string());
+        let diff = BlockDiff {
+            updated: vec![edited],
+            ..Default::default()
+        };
+        BlockService::apply_diff(&ctx, &diff).unwrap();
+        assert_eq!(referenced(&ctx), vec!["f1"]);
+        let diff = BlockDiff {
+            removed: vec!["f1".to_string()],
+            ..Default::default()
+        };
+        BlockService::apply_diff(&ctx, &diff).unwrap();
+        assert!(referenced(&ctx).is_empty());
+    }
+}
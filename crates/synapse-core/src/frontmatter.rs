@@ -0,0 +1,541 @@
+//! YAML front matter: split, parse into typed properties, and write back.
+//!
+//! Front matter is a YAML mapping between `---` lines at the very start of a note.
+//! Writing properties back only rewrites the entries that changed; the rest of the header,
+//! comments included, and the body are kept byte for byte.
+
+use std::ops::Range;
+
+use yaml_rust2::{Yaml, YamlLoader};
+
+use crate::models::PropertyValue;
+use crate::{Error, Result};
+
+/// Parsed front matter: properties in the order they appear
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct FrontMatter {
+    pub properties: Vec<(String, PropertyValue)>,
+}
+
+/// Split content into (front matter YAML, body). Returns `None` if there is no front matter.
+///
+/// Front matter opens with a `---` line at the very start and closes at the next `---` or
+/// `...` line; trailing whitespace on either is ignored. This is the only detector: the
+/// editor's parser and renderer use it too, so every layer agrees on where the body starts.
+pub fn split_front_matter(content: &str) -> (Option<&str>, &str) {
+    let mut lines = content.split_inclusive('\n');
+    let Some(opening) = lines.next() else {
+        return (None, content);
+    };
+    if !opening.ends_with('\n') || opening.trim_end() != "---" {
+        return (None, content);
+    }
+
+    let mut offset = opening.len();
+    for line in lines {
+        if matches!(line.trim_end(), "---" | "...") {
+            let yaml = &content[opening.len()..offset];
+            let body = &content[offset + line.len()..];
+            return (Some(yaml), body);
+        }
+        offset += line.len();
+    }
+    (None, content)
+}
+
+impl FrontMatter {
+    /// Parse the front matter of a note. Returns `Ok(None)` if the note has none.
+    pub fn parse(content: &str) -> Result<Option<Self>> {
+        let (Some(yaml), _) = split_front_matter(content) else {
+            return Ok(None);
+        };
+        let docs = YamlLoader::load_from_str(yaml)
+            .map_err(|e| Error::InvalidInput(format!("Invalid front matter: {}", e)))?;
+
+        let properties = match docs.into_iter().next() {
+            None | Some(Yaml::Null) => Vec::new(),
+            Some(Yaml::Hash(hash)) => hash
+                .into_iter()
+                .filter_map(|(k, v)| yaml_key(&k).map(|k| (k, yaml_to_value(v))))
+                .collect(),
+            Some(_) => {
+                return Err(Error::InvalidInput(
+                    "Invalid front matter: expected a mapping".to_string(),
+                ));
+            }
+        };
+        Ok(Some(Self { properties }))
+    }
+
+    /// Get a property by key
+    pub fn get(&self, key: &str) -> Option<&PropertyValue> {
+        self.properties
+            .iter()
+            .find(|(k, _)| k == key)
+            .map(|(_, v)| v)
+    }
+
+    /// Set a property, keeping its position if it already exists
+    pub fn set(&mut self, key: &str, value: PropertyValue) {
+        match self.properties.iter_mut().find(|(k, _)| k == key) {
+            Some((_, v)) => *v = value,
+            None => self.properties.push((key.to_string(), value)),
+        }
+    }
+
+    /// Remove a property
+    pub fn remove(&mut self, key: &str) -> Option<PropertyValue> {
+        let index = self.properties.iter().position(|(k, _)| k == key)?;
+        Some(self.properties.remove(index).1)
+    }
+
+    /// Tag names from `tags:` (or `tag:`), without a leading `#`. `None` if the key is absent.
+    pub fn tags(&self) -> Option<Vec<String>> {
+        self.string_list(&["tags", "tag"]).map(|tags| {
+            tags.into_iter()
+                .map(|t| t.trim_start_matches('#').to_string())
+                .filter(|t| !t.is_empty())
+                .collect()
+        })
+    }
+
+    /// Add a tag to `tags:` (or `tag:`). Returns false if the key is absent or already lists it.
+    pub fn add_tag(&mut self, name: &str) -> bool {
+        self.edit_tags(|items| {
+            if items.iter().any(|item| tag_name(item) == name) {
+                return false;
+            }
+            items.push(PropertyValue::Text(name.to_string()));
+            true
+        })
+    }
+
+    /// Remove a tag from `tags:` (or `tag:`). Returns false if the key is absent or does not
+    /// list it.
+    pub fn remove_tag(&mut self, name: &str) -> bool {
+        self.edit_tags(|items| {
+            let len = items.len();
+            items.retain(|item| tag_name(item) != name);
+            items.len() != len
+        })
+    }
+
+    /// Edit the items of `tags:` (or `tag:`) as a list; it is only rewritten if `edit` says
+    /// it changed something
+    fn edit_tags(&mut self, edit: impl FnOnce(&mut Vec<PropertyValue>) -> bool) -> bool {
+        let Some((_, value)) = self.properties.iter_mut().find(|(k, _)| {
+            ["tags", "tag"]
+                .iter()
+                .any(|key| k.eq_ignore_ascii_case(key))
+        }) else {
+            return false;
+        };
+        let mut items = match &*value {
+            PropertyValue::List(items) => items.clone(),
+            PropertyValue::Text(s) => s
+                .split(',')
+                .map(str::trim)
+                .filter(|item| !item.is_empty())
+                .map(|item| PropertyValue::Text(item.to_string()))
+                .collect(),
+            PropertyValue::Null => Vec::new(),
+            other => vec![other.clone()],
+        };
+        if !edit(&mut items) {
+            return false;
+        }
+        *value = PropertyValue::List(items);
+        true
+    }
+
+    /// Aliases from `aliases:` (or `alias:`). `None` if the key is absent.
+    pub fn aliases(&self) -> Option<Vec<String>> {
+        self.string_list(&["aliases", "alias"])
+    }
+
+    /// Values of the first matching key as strings; a single string may be comma separated
+    fn string_list(&self, keys: &[&str]) -> Option<Vec<String>> {
+        let value = self
+            .properties
+            .iter()
+            .find(|(k, _)| keys.iter().any(|key| k.eq_ignore_ascii_case(key)))
+            .map(|(_, v)| v)?;
+        let items = match value {
+            PropertyValue::Text(s) => s.split(',').map(str::to_string).collect(),
+            other => other.as_string_list(),
+        };
+        let mut seen = Vec::new();
+        for item in items {
+            let item = item.trim().to_string();
+            if !item.is_empty() && !seen.contains(&item) {
+                seen.push(item);
+            }
+        }
+        Some(seen)
+    }
+
+    /// Serialize the properties as YAML (without the `---` delimiters)
+    pub fn to_yaml(&self) -> String {
+        let mut out = String::new();
+        emit_mapping(&mut out, &self.properties, 0);
+        out
+    }
+
+    /// Replace the front matter of `content` with these properties, keeping the body intact.
+    /// Entries whose value did not change keep their text, comments and order; changed ones
+    /// are rewritten in place and new ones added at the end. Empty properties remove the
+    /// front matter.
+    pub fn apply_to(&self, content: &str) -> String {
+        let (yaml, body) = split_front_matter(content);
+        if self.properties.is_empty() {
+            return body.to_string();
+        }
+        let yaml = yaml
+            .and_then(|yaml| self.update_yaml(yaml))
+            .unwrap_or_else(|| self.to_yaml());
+        format!("---\n{}---\n{}", yaml, body)
+    }
+
+    /// `yaml` with only the entries that differ from these properties rewritten. `None` if
+    /// its entries cannot be told apart, e.g. a mapping in flow style.
+    fn update_yaml(&self, yaml: &str) -> Option<String> {
+        let entries = yaml_entries(yaml)?;
+        let mut out = String::new();
+        let mut end = 0;
+        for (key, value, range) in &entries {
+            out.push_str(&yaml[end..range.start]);
+            end = range.end;
+            match self.get(key) {
+                Some(new) if new == value => out.push_str(&yaml[range.clone()]),
+                Some(new) => emit_mapping(&mut out, &[(key.clone(), new.clone())], 0),
+                None => {}
+            }
+        }
+        out.push_str(&yaml[end..]);
+        if !out.is_empty() && !out.ends_with('\n') {
+            out.push('\n');
+        }
+        let added: Vec<_> = self
+            .properties
+            .iter()
+            .filter(|(k, _)| !entries.iter().any(|(key, ..)| key == k))
+            .cloned()
+            .collect();
+        emit_mapping(&mut out, &added, 0);
+        Some(out)
+    }
+}
+
+/// Top-level entries of a block mapping: key, value and the lines holding it (a key line and
+/// the indented or `-` lines after it). `None` unless each entry parses to exactly one key
+/// and no key repeats.
+fn yaml_entries(yaml: &str) -> Option<Vec<(String, PropertyValue, Range<usize>)>> {
+    let mut ranges: Vec<Range<usize>> = Vec::new();
+    // Whether lines still continue the last entry
+    let mut open = false;
+    let mut offset = 0;
+    for line in yaml.split_inclusive('\n') {
+        let start = offset;
+        offset += line.len();
+        let text = line.trim_end_matches(['\n', '\r']);
+        if text.trim().is_empty() {
+            continue;
+        }
+        if text.starts_with([' ', '\t', '-']) {
+            match ranges.last_mut() {
+                Some(range) if open => range.end = offset,
+                _ => return None,
+            }
+        } else if text.starts_with('#') {
+            open = false;
+        } else {
+            ranges.push(start..offset);
+            open = true;
+        }
+    }
+
+    let mut entries: Vec<(String, PropertyValue, Range<usize>)> = Vec::new();
+    for range in ranges {
+        let docs = YamlLoader::load_from_str(&yaml[range.clone()]).ok()?;
+        let Some(Yaml::Hash(hash)) = docs.into_iter().next() else {
+            return None;
+        };
+        if hash.len() != 1 {
+            return None;
+        }
+        let (key, value) = hash.into_iter().next()?;
+        let key = yaml_key(&key)?;
+        if entries.iter().any(|(k, ..)| *k == key) {
+            return None;
+        }
+        entries.push((key, yaml_to_value(value), range));
+    }
+    Some(entries)
+}
+
+fn yaml_key(key: &Yaml) -> Option<String> {
+    match key {
+        Yaml::String(s) | Yaml::Real(s) => Some(s.clone()),
+        Yaml::Integer(i) => Some(i.to_string()),
+        Yaml::Boolean(b) => Some(b.to_string()),
+        _ => None,
+    }
+}
+
+/// Largest integer magnitude an `f64` holds exactly
+const MAX_EXACT_INTEGER: u64 = 1 << 53;
+
+fn yaml_to_value(yaml: Yaml) -> PropertyValue {
+    match yaml {
+        Yaml::String(s) if is_date(&s) => PropertyValue::Date(s),
+        Yaml::String(s) => PropertyValue::Text(s),
+        // Beyond 2^53 an f64 would silently round the value, so keep its digits as text
+        Yaml::Integer(i) if i.unsigned_abs() <= MAX_EXACT_INTEGER => {
+            PropertyValue::Number(i as f64)
+        }
+        Yaml::Integer(i) => PropertyValue::Text(i.to_string()),
+        Yaml::Real(s) => s
+            .parse()
+            .map(PropertyValue::Number)
+            .unwrap_or(PropertyValue::Text(s)),
+        Yaml::Boolean(b) => PropertyValue::Boolean(b),
+        Yaml::Array(items) => PropertyValue::List(items.into_iter().map(yaml_to_value).collect()),
+        Yaml::Hash(hash) => PropertyValue::Map(
+            hash.into_iter()
+                .filter_map(|(k, v)| yaml_key(&k).map(|k| (k, yaml_to_value(v))))
+                .collect(),
+        ),
+        Yaml::Null | Yaml::Alias(_) | Yaml::BadValue => PropertyValue::Null,
+    }
+}
+
+/// Name of a `tags:` item, as [`FrontMatter::tags`] reads it
+fn tag_name(item: &PropertyValue) -> String {
+    item.as_string_list()
+        .concat()
+        .trim()
+        .trim_start_matches('#')
+        .to_string()
+}
+
+/// An integer literal that [`yaml_to_value`] reads as text because an `f64` would round it
+fn is_large_integer(s: &str) -> bool {
+    s.parse::<i64>()
+        .is_ok_and(|i| i.unsigned_abs() > MAX_EXACT_INTEGER && i.to_string() == s)
+}
+
+/// `YYYY-MM-DD`, optionally followed by a time part
+fn is_date(s: &str) -> bool {
+    let b = s.as_bytes();
+    b.len() >= 10
+        && b[..4].iter().all(u8::is_ascii_digit)
+        && b[4] == b'-'
+        && b[5..7].iter().all(u8::is_ascii_digit)
+        && b[7] == b'-'
+        && b[8..10].iter().all(u8::is_ascii_digit)
+        && (b.len() == 10 || b[10] == b'T' || b[10] == b' ')
+}
+
+fn emit_mapping(out: &mut String, entries: &[(String, PropertyValue)], indent: usize) {
+    let pad = " ".repeat(indent);
+    for (key, value) in entries {
+        out.push_str(&pad);
+        out.push_str(&scalar_text(key));
+        out.push(':');
+        match value {
+            PropertyValue::Null => out.push('\n'),
+            PropertyValue::List(items) if items.is_empty() => out.push_str(" []\n"),
+            PropertyValue::Map(map) if map.is_empty() => out.push_str(" {}\n"),
+            PropertyValue::List(items) => {
+                out.push('\n');
+                for item in items {
+                    out.push_str(&pad);
+                    out.push_str("  - ");
+                    out.push_str(&flow_value(item));
+                    out.push('\n');
+                }
+            }
+            PropertyValue::Map(map) => {
+                out.push('\n');
+                emit_mapping(out, map, indent + 2);
+            }
+            scalar => {
+                out.push(' ');
+                out.push_str(&flow_value(scalar));
+                out.push('\n');
+            }
+        }
+    }
+}
+
+/// Single-line YAML for a value (flow style for nested collections)
+fn flow_value(value: &PropertyValue) -> String {
+    match value {
+        PropertyValue::Null => "null".to_string(),
+        // Integers too large for a number are read as text; write them back bare
+        PropertyValue::Text(s) if is_large_integer(s) => s.clone(),
+        PropertyValue::Text(s) => scalar_text(s),
+        PropertyValue::Number(n) => n.to_string(),
+        PropertyValue::Boolean(b) => b.to_string(),
+        PropertyValue::Date(d) => d.clone(),
+        PropertyValue::List(items) => format!(
+            "[{}]",
+            items.iter().map(flow_value).collect::<Vec<_>>().join(", ")
+        ),
+        PropertyValue::Map(map) => format!(
+            "{{{}}}",
+            map.iter()
+                .map(|(k, v)| format!("{}: {}", scalar_text(k), flow_value(v)))
+                .collect::<Vec<_>>()
+                .join(", ")
+        ),
+    }
+}
+
+/// A string as a YAML scalar, double-quoted when it would otherwise change meaning
+fn scalar_text(s: &str) -> String {
+    let needs_quotes = s.is_empty()
+        || s.trim() != s
+        || s.starts_with(|c: char| "-?:,[]{}#&*!|>'\"%@`".contains(c))
+        || s.contains(": ")
+        || s.contains(" #")
+        || s.contains(['\n', '\r', '\t'])
+        || s.ends_with(':')
+        || matches!(
+            s.to_ascii_lowercase().as_str(),
+            "true" | "false" | "yes" | "no" | "on" | "off" | "null" | "~"
+        )
+        || s.parse::<f64>().is_ok()
+        || is_date(s);
+    if !needs_quotes {
+        return s.to_string();
+    }
+    let escaped = s
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+        .replace('\r', "\\r")
+        .replace('\t', "\\t");
+    format!("\"{}\"", escaped)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const NOTE: &str = "---\ntitle: Meeting\ntags: [work, \"#inbox\"]\naliases:\n  - Standup\nrating: 4.5\ndone: false\ndue: 2024-03-01\n---\n# Body\n\nText: here\n";
+
+    #[test]
+    fn test_parse_typed_properties() {
+        let fm = FrontMatter::parse(NOTE).unwrap().unwrap();
+        assert_eq!(
+            fm.get("title"),
+            Some(&PropertyValue::Text("Meeting".to_string()))
+        );
+        assert_eq!(fm.get("rating"), Some(&PropertyValue::Number(4.5)));
+        assert_eq!(fm.get("done"), Some(&PropertyValue::Boolean(false)));
+        assert_eq!(
+            fm.get("due"),
+            Some(&PropertyValue::Date("2024-03-01".to_string()))
+        );
+        assert_eq!(
+            fm.tags(),
+            Some(vec!["work".to_string(), "inbox".to_string()])
+        );
+        assert_eq!(fm.aliases(), Some(vec!["Standup".to_string()]));
+
+        assert!(FrontMatter::parse("# No front matter").unwrap().is_none());
+        assert!(FrontMatter::parse("---\n- a\n---\n").is_err());
+    }
+
+    #[test]
+    fn test_write_back_keeps_body() {
+        let mut fm = FrontMatter::parse(NOTE).unwrap().unwrap();
+        fm.set("done", PropertyValue::Boolean(true));
+        fm.set("status", PropertyValue::Text("yes".to_string()));
+        let updated = fm.apply_to(NOTE);
+
+        assert!(updated.ends_with("---\n# Body\n\nText: here\n"));
+        let reparsed = FrontMatter::parse(&updated).unwrap().unwrap();
+        assert_eq!(reparsed, fm);
+        assert_eq!(reparsed.properties[0].0, "title");
+
+        let (_, body) = split_front_matter(NOTE);
+        assert_eq!(FrontMatter::default().apply_to(NOTE), body);
+        assert_eq!(
+            fm.apply_to("plain body"),
+            format!("---\n{}---\nplain body", fm.to_yaml())
+        );
+    }
+
+    #[test]
+    fn test_large_integers_keep_their_digits() {
+        let fm = FrontMatter::parse(
+            "---\nexact: 9007199254740992\nlow: -9007199254740992\nid: 9007199254740993\n---\n",
+        )
+        .unwrap()
+        .unwrap();
+        assert_eq!(
+            fm.get("exact"),
+            Some(&PropertyValue::Number(9007199254740992.0))
+        );
+        assert_eq!(
+            fm.get("low"),
+            Some(&PropertyValue::Number(-9007199254740992.0))
+        );
+        assert_eq!(
+            fm.get("id"),
+            Some(&PropertyValue::Text("9007199254740993".to_string()))
+        );
+
+        let reparsed = FrontMatter::parse(&fm.apply_to("")).unwrap().unwrap();
+        assert_eq!(reparsed, fm);
+    }
+
+    #[test]
+    fn test_edit_tags() {
+        let mut fm = FrontMatter::parse(NOTE).unwrap().unwrap();
+        assert!(fm.add_tag("idea"));
+        assert!(!fm.add_tag("inbox"));
+        assert!(fm.remove_tag("inbox"));
+        assert_eq!(
+            fm.tags(),
+            Some(vec!["work".to_string(), "idea".to_string()])
+        );
+
+        let mut fm = FrontMatter::parse("---\ntag: a, b\n---\n")
+            .unwrap()
+            .unwrap();
+        assert!(fm.remove_tag("a"));
+        assert_eq!(fm.tags(), Some(vec!["b".to_string()]));
+        assert!(!FrontMatter::default().add_tag("a"));
+    }
+
+    #[test]
+    fn test_write_back_touches_only_changed_entries() {
+        let note = "---\n# Meeting notes\ntitle:   Meeting\ntags: [work, inbox]  # triage\nid: 9007199254740993\nrating: 4.5\nowners:\n- ana\n- bo\n---\nBody\n";
+        let mut fm = FrontMatter::parse(note).unwrap().unwrap();
+        fm.set("rating", PropertyValue::Number(5.0));
+        fm.remove("title");
+        fm.set("status", PropertyValue::Text("draft".to_string()));
+        assert_eq!(
+            fm.apply_to(note),
+            "---\n# Meeting notes\ntags: [work, inbox]  # triage\nid: 9007199254740993\nrating: 5\nowners:\n- ana\n- bo\nstatus: draft\n---\nBody\n"
+        );
+        assert_eq!(fm.apply_to(&fm.apply_to(note)), fm.apply_to(note));
+
+        // Large integers stay integers when the whole header is written, and digits set as
+        // text stay quoted
+        let fm = FrontMatter {
+            properties: vec![
+                (
+                    "id".to_string(),
+                    PropertyValue::Text("9007199254740993".to_string()),
+                ),
+                ("code".to_string(), PropertyValue::Text("42".to_string())),
+            ],
+        };
+        assert_eq!(fm.to_yaml(), "id: 9007199254740993\ncode: \"42\"\n");
+        assert_eq!(FrontMatter::parse(&fm.apply_to("")).unwrap().unwrap(), fm);
+    }
+}
@@ -0,0 +1,512 @@
+//! Fuzzy matching for the quick switcher.
+//!
+//! A query matches when its characters appear in order in a note's title, one of its aliases
+//! or its folder path, ignoring case and whitespace in the query. Among all alignments the
+//! highest-scoring one is kept: every matched character scores, matches at word starts and
+//! runs of consecutive matches earn bonuses, and skipped characters between matches cost a
+//! little. Recently updated notes get a bonus that fades over a few weeks.
+//!
+//! Titles and aliases in Chinese also match by pinyin, either in full or by initials
+//! ("zsgl" finds "知识管理"), with the matched Chinese characters highlighted.
+
+use serde::{Deserialize, Serialize};
+
+use crate::models::{Note, NoteId};
+use crate::pinyin::pinyin_of;
+use crate::stats::is_cjk;
+
+/// Score of every matched character
+const SCORE_MATCH: i32 = 16;
+/// Bonus for a match at the start of a word
+const BONUS_WORD_START: i32 = 10;
+/// Bonus for a match right after the previous one
+const BONUS_CONSECUTIVE: i32 = 8;
+/// Cost of each character skipped between two matches
+const PENALTY_GAP: i32 = 1;
+/// Bonus for a note updated just now; halves after a week
+const BONUS_RECENT: i64 = 24;
+
+const SECONDS_PER_DAY: i64 = 86_400;
+const NONE: i32 = i32::MIN / 2;
+
+/// Which text of a note a query matched
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum MatchField {
+    Title,
+    Alias,
+    Path,
+}
+
+/// A note matched by a fuzzy query
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FuzzyMatch {
+    pub note_id: NoteId,
+    pub title: String,
+    pub field: MatchField,
+    /// The matched text: the title, an alias, or the folder path followed by the title
+    pub text: String,
+    /// Indices of the matched characters (not bytes) in `text`, for highlighting
+    pub positions: Vec<usize>,
+    pub score: i64,
+}
+
+/// In-memory index of note titles, aliases and folder paths
+#[derive(Debug, Default)]
+pub struct FuzzyIndex {
+    notes: Vec<IndexedNote>,
+}
+
+#[derive(Debug)]
+struct IndexedNote {
+    id: NoteId,
+    title: String,
+    updated_at: i64,
+    candidates: Vec<Candidate>,
+}
+
+/// A text a note can be found by, prepared for matching
+#[derive(Debug)]
+struct Candidate {
+    field: MatchField,
+    text: String,
+    /// The text itself, then its pinyin spelling if it has Chinese characters
+    keys: Vec<Key>,
+}
+
+/// A spelling of a candidate's text that queries are matched against
+#[derive(Debug)]
+struct Key {
+    /// Lowercased characters
+    chars: Vec<char>,
+    /// Word-start bonus of each character
+    bonus: Vec<i32>,
+    /// Index in the original text of each character; `None` when it is the text itself
+    origin: Option<Vec<usize>>,
+}
+
+impl FuzzyIndex {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add a note with its aliases and the paths of the folders it is in
+    pub fn insert(&mut self, note: &Note, aliases: &[String], folder_paths: &[String]) {
+        let mut candidates = vec![Candidate::new(MatchField::Title, note.title.clone(), true)];
+        candidates.extend(
+            aliases
+                .iter()
+                .map(|alias| Candidate::new(MatchField::Alias, alias.clone(), true)),
+        );
+        candidates.extend(folder_paths.iter().map(|path| {
+            let path = path.trim_matches('/');
+            Candidate::new(MatchField::Path, format!("{}/{}", path, note.title), false)
+        }));
+        self.notes.push(IndexedNote {
+            id: note.id.clone(),
+            title: note.title.clone(),
+            updated_at: note.updated_at,
+            candidates,
+        });
+    }
+
+    pub fn len(&self) -> usize {
+        self.notes.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.notes.is_empty()
+    }
+
+    /// Best matches for `query`, highest score first. `now` is a Unix timestamp used for the
+    /// recency bonus. An empty query lists the most recently updated notes.
+    pub fn search(&self, query: &str, limit: usize, now: i64) -> Vec<FuzzyMatch> {
+        let query: Vec<char> = query
+            .chars()
+            .filter(|c| !c.is_whitespace())
+            .map(fold_case)
+            .collect();
+
+        // Score everything first; positions and strings are only built for the results
+        let mut scratch = Scratch::default();
+        let mut hits = Vec::new();
+        for (note_index, note) in self.notes.iter().enumerate() {
+            let best = note
+                .candidates
+                .iter()
+                .enumerate()
+                .flat_map(|(i, candidate)| candidate.keys.iter().enumerate().map(move |k| (i, k)))
+                .filter_map(|(i, (k, key))| Some((scratch.align(&query, key)?, i, k)))
+                // Earlier candidates win ties, so titles come before aliases and paths
+                .reduce(|best, next| if next.0 > best.0 { next } else { best });
+            if let Some((score, candidate, key)) = best {
+                hits.push(Hit {
+                    score: score as i64 + recency_bonus(note.updated_at, now),
+                    len: note.candidates[candidate].keys[0].chars.len(),
+                    note: note_index,
+                    candidate,
+                    key,
+                });
+            }
+        }
+
+        let order = |a: &Hit, b: &Hit| b.score.cmp(&a.score).then(a.len.cmp(&b.len));
+        if hits.len() > limit && limit > 0 {
+            hits.select_nth_unstable_by(limit - 1, order);
+        }
+        hits.truncate(limit);
+        hits.sort_by(order);
+
+        hits.into_iter()
+            .map(|hit| {
+                let note = &self.notes[hit.note];
+                let candidate = &note.candidates[hit.candidate];
+                let key = &candidate.keys[hit.key];
+                scratch.align(&query, key);
+                let mut positions = scratch.positions(query.len(), key.chars.len());
+                if let Some(origin) = &key.origin {
+                    // Several letters of one syllable highlight the same character
+                    positions = positions.into_iter().map(|j| origin[j]).collect();
+                    positions.dedup();
+                }
+                FuzzyMatch {
+                    note_id: note.id.clone(),
+                    title: note.title.clone(),
+                    field: candidate.field,
+                    text: candidate.text.clone(),
+                    positions,
+                    score: hit.score,
+                }
+            })
+            .collect()
+    }
+}
+
+impl Candidate {
+    /// With `pinyin`, a text containing Chinese characters also gets a pinyin key
+    fn new(field: MatchField, text: String, pinyin: bool) -> Self {
+        let original: Vec<char> = text.chars().collect();
+        let bonus: Vec<i32> = (0..original.len())
+            .map(|i| {
+                if is_word_start(&original, i) {
+                    BONUS_WORD_START
+                } else {
+                    0
+                }
+            })
+            .collect();
+        let spelled = (pinyin
+            && original
+                .iter()
+                .any(|&c| is_cjk(c) && pinyin_of(c).is_some()))
+        .then(|| Key::pinyin(&original, &bonus));
+        let mut keys = vec![Key {
+            chars: original.into_iter().map(fold_case).collect(),
+            bonus,
+            origin: None,
+        }];
+        keys.extend(spelled);
+        Self { field, text, keys }
+    }
+}
+
+impl Key {
+    /// Spell every Chinese character of `original` as its pinyin syllable, each syllable
+    /// starting a word so that initials score like an acronym
+    fn pinyin(original: &[char], bonus: &[i32]) -> Self {
+        let mut chars = Vec::with_capacity(original.len() * 3);
+        let mut key_bonus = Vec::with_capacity(original.len() * 3);
+        let mut origin = Vec::with_capacity(original.len() * 3);
+        for (i, &c) in original.iter().enumerate() {
+            match pinyin_of(c).filter(|_| is_cjk(c)) {
+                Some(syllable) => {
+                    for (k, letter) in syllable.chars().enumerate() {
+                        chars.push(letter);
+                        key_bonus.push(if k == 0 { BONUS_WORD_START } else { 0 });
+                        origin.push(i);
+                    }
+                }
+                None => {
+                    chars.push(fold_case(c));
+                    key_bonus.push(bonus[i]);
+                    origin.push(i);
+                }
+            }
+        }
+        Key {
+            chars,
+            bonus: key_bonus,
+            origin: Some(origin),
+        }
+    }
+}
+
+/// A matched note before its result is built
+struct Hit {
+    score: i64,
+    len: usize,
+    note: usize,
+    candidate: usize,
+    key: usize,
+}
+
+/// Reusable score and back-pointer tables
+#[derive(Default)]
+struct Scratch {
+    score: Vec<i32>,
+    from: Vec<usize>,
+    /// Where the best alignment of the last `align` call ends
+    end: usize,
+}
+
+impl Scratch {
+    /// Score of the best alignment of `query` in the key; see [`Scratch::positions`]
+    fn align(&mut self, query: &[char], key: &Key) -> Option<i32> {
+        let text = &key.chars;
+        if query.is_empty() {
+            return Some(0);
+        }
+        if !is_subsequence(query, text) {
+            return None;
+        }
+
+        // score[i * n + j]: best score of query[..=i] with query[i] matched at text[j]
+        let n = text.len();
+        self.score.clear();
+        self.score.resize(query.len() * n, NONE);
+        self.from.clear();
+        self.from.resize(query.len() * n, usize::MAX);
+
+        for (i, &q) in query.iter().enumerate() {
+            let row = i * n;
+            // Best predecessor in the previous row so far, net of the gap up to `j`
+            let mut run = NONE;
+            let mut run_from = usize::MAX;
+            for (j, &c) in text.iter().enumerate() {
+                if i > 0 && j > 0 {
+                    run -= PENALTY_GAP;
+                    let previous = self.score[row - n + j - 1];
+                    if previous > run {
+                        run = previous;
+                        run_from = j - 1;
+                    }
+                }
+                if c != q {
+                    continue;
+                }
+                let gain = SCORE_MATCH + key.bonus[j];
+                if i == 0 {
+                    self.score[row + j] = gain;
+                    continue;
+                }
+                let consecutive = if j > 0 {
+                    self.score[row - n + j - 1] + BONUS_CONSECUTIVE
+                } else {
+                    NONE
+                };
+                // A gap-free match without the consecutive bonus is never better
+                let (best, from) = if consecutive >= run {
+                    (consecutive, j.wrapping_sub(1))
+                } else {
+                    (run, run_from)
+                };
+                if best > NONE / 2 {
+                    self.score[row + j] = best + gain;
+                    self.from[row + j] = from;
+                }
+            }
+        }
+
+        let last = (query.len() - 1) * n;
+        let (end, &score) = self.score[last..last + n]
+            .iter()
+            .enumerate()
+            .max_by_key(|&(j, &score)| (score, std::cmp::Reverse(j)))?;
+        if score <= NONE / 2 {
+            return None;
+        }
+        self.end = end;
+        Some(score)
+    }
+
+    /// Matched positions of the last successful `align` of a query of `len` characters in
+    /// a text of `n` characters
+    fn positions(&self, len: usize, n: usize) -> Vec<usize> {
+        let mut positions = vec![0; len];
+        let mut j = self.end;
+        for i in (0..len).rev() {
+            positions[i] = j;
+            j = self.from[i * n + j];
+        }
+        positions
+    }
+}
+
+fn is_subsequence(query: &[char], text: &[char]) -> bool {
+    let mut text = text.iter();
+    query.iter().all(|q| text.any(|c| c == q))
+}
+
+/// Whether a word starts at `chars[i]`: after a separator, at a lower-to-upper case change,
+/// at a letter-digit change, or at any Chinese or Japanese character
+fn is_word_start(chars: &[char], i: usize) -> bool {
+    let c = chars[i];
+    let Some(&previous) = i.checked_sub(1).and_then(|p| chars.get(p)) else {
+        return true;
+    };
+    if is_cjk(c) {
+        return true;
+    }
+    if !c.is_alphanumeric() {
+        return false;
+    }
+    !previous.is_alphanumeric()
+        || is_cjk(previous)
+        || (previous.is_lowercase() && c.is_uppercase())
+        || (previous.is_alphabetic() && c.is_numeric())
+        || (previous.is_numeric() && c.is_alphabetic())
+}
+
+/// Single-character lowercase so positions stay aligned with the original text
+fn fold_case(c: char) -> char {
+    c.to_lowercase().next().unwrap_or(c)
+}
+
+fn recency_bonus(updated_at: i64, now: i64) -> i64 {
+    let age_days = (now - updated_at).max(0) / SECONDS_PER_DAY;
+    BONUS_RECENT * 7 / (7 + age_days)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const NOW: i64 = 1_700_000_000;
+
+    fn note(id: &str, title: &str, age_days: i64) -> Note {
+        let mut note = Note::new(
+            id.to_string(),
+            title.to_string(),
+            format!("notes/{}.md", id),
+        );
+        note.updated_at = NOW - age_days * SECONDS_PER_DAY;
+        note
+    }
+
+    fn index(notes: &[(Note, &[&str], &[&str])]) -> FuzzyIndex {
+        let mut index = FuzzyIndex::new();
+        for (note, aliases, paths) in notes {
+            let aliases: Vec<String> = aliases.iter().map(|s| s.to_string()).collect();
+            let paths: Vec<String> = paths.iter().map(|s| s.to_string()).collect();
+            index.insert(note, &aliases, &paths);
+        }
+        index
+    }
+
+    #[test]
+    fn test_subsequence_and_positions() {
+        let index = index(&[
+            (note("a", "Meeting Notes", 30), &[], &[]),
+            (note("b", "Rust ownership", 30), &[], &[]),
+        ]);
+        let matches = index.search("mtn", 10, NOW);
+        assert_eq!(matches.len(), 1);
+        assert_eq!(matches[0].note_id, "a");
+        assert_eq!(matches[0].field, MatchField::Title);
+        // "n" is taken from the start of "Notes", not from "Meeting"
+        assert_eq!(matches[0].positions, vec![0, 3, 8]);
+
+        assert!(index.search("xyz", 10, NOW).is_empty());
+        assert_eq!(
+            index.search("RUST own", 10, NOW)[0].positions,
+            vec![0, 1, 2, 3, 5, 6, 7]
+        );
+    }
+
+    #[test]
+    fn test_word_starts_and_runs_rank_first() {
+        let index = index(&[
+            (note("scattered", "Design review", 30), &[], &[]),
+            (note("starts", "Daily Review", 30), &[], &[]),
+            (note("camel", "setupDailyRoutine", 30), &[], &[]),
+        ]);
+        let ids: Vec<String> = index
+            .search("dr", 10, NOW)
+            .into_iter()
+            .map(|m| m.note_id)
+            .collect();
+        assert_eq!(ids[2], "scattered");
+
+        let index = self::index(&[
+            (note("gap", "cargo build", 30), &[], &[]),
+            (note("run", "carbon", 30), &[], &[]),
+        ]);
+        assert_eq!(index.search("carb", 10, NOW)[0].note_id, "run");
+    }
+
+    #[test]
+    fn test_recency_breaks_close_scores() {
+        let index = index(&[
+            (note("old", "Weekly plan", 365), &[], &[]),
+            (note("new", "Weekly plan", 0), &[], &[]),
+        ]);
+        let matches = index.search("plan", 10, NOW);
+        assert_eq!(matches[0].note_id, "new");
+        assert!(matches[0].score > matches[1].score);
+
+        // An empty query lists recent notes first
+        let matches = index.search("", 1, NOW);
+        assert_eq!(matches.len(), 1);
+        assert_eq!(matches[0].note_id, "new");
+    }
+
+    #[test]
+    fn test_aliases_paths_and_cjk() {
+        let index = index(&[
+            (note("a", "Kubernetes", 30), &["k8s"], &["/Work/Infra"]),
+            (note("b", "个人知识管理", 30), &[], &[]),
+        ]);
+        let matches = index.search("k8s", 10, NOW);
+        assert_eq!(matches[0].field, MatchField::Alias);
+        assert_eq!(matches[0].text, "k8s");
+
+        let matches = index.search("infkub", 10, NOW);
+        assert_eq!(matches[0].field, MatchField::Path);
+        assert_eq!(matches[0].text, "Work/Infra/Kubernetes");
+        assert_eq!(matches[0].positions, vec![5, 6, 7, 11, 12, 13]);
+
+        let matches = index.search("知管", 10, NOW);
+        assert_eq!(matches[0].note_id, "b");
+        assert_eq!(matches[0].positions, vec![2, 4]);
+    }
+
+    #[test]
+    fn test_pinyin_full_and_initials() {
+        let index = index(&[
+            (note("a", "知识管理", 30), &[], &["/学习"]),
+            (note("b", "Rust 笔记", 30), &["北京 trip"], &[]),
+            (note("c", "zsgl draft", 30), &[], &[]),
+        ]);
+        let matches = index.search("zsgl", 10, NOW);
+        assert_eq!(matches.len(), 2);
+        let pinyin = matches.iter().find(|m| m.note_id == "a").unwrap();
+        assert_eq!(pinyin.field, MatchField::Title);
+        assert_eq!(pinyin.positions, vec![0, 1, 2, 3]);
+
+        let matches = index.search("zhishiguanli", 10, NOW);
+        assert_eq!(matches[0].note_id, "a");
+        assert_eq!(matches[0].positions, vec![0, 1, 2, 3]);
+
+        // Pinyin mixes with Latin text; aliases are spelled too
+        assert_eq!(
+            index.search("rustbiji", 10, NOW)[0].positions,
+            vec![0, 1, 2, 3, 5, 6]
+        );
+        let matches = index.search("bjtrip", 10, NOW);
+        assert_eq!(matches[0].field, MatchField::Alias);
+        assert_eq!(matches[0].positions, vec![0, 1, 3, 4, 5, 6]);
+
+        // Folder paths are matched as written
+        assert!(index.search("xuexi", 10, NOW).is_empty());
+    }
+}
@@ -1,14 +1,30 @@
 //! Synapse Core: models, storage abstraction, and services.
 
+#[cfg(feature = "async")]
+pub mod async_context;
+pub mod content;
 pub mod error;
+pub mod footnote;
+pub mod frontmatter;
+pub mod fuzzy;
 pub mod models;
-pub mod storage;
+pub mod pinyin;
+pub mod query;
 pub mod services;
+pub mod stats;
+pub mod storage;
+pub mod unit_of_work;
 
+#[cfg(feature = "async")]
+pub use async_context::AsyncServiceContext;
+pub use content::{ContentConfig, ContentStore};
 pub use error::{Error, Result};
+pub use frontmatter::FrontMatter;
+pub use fuzzy::{FuzzyMatch, MatchField};
 pub use models::*;
 pub use services::{
-    ServiceContext,
-    NoteService, TagService, FolderService, LinkService,
-    SearchService, BlockService, AttachmentService,
+    AttachmentService, BlockService, FolderService, LinkService, NoteService, SavedSearchService,
+    SearchService, ServiceContext, TagService,
 };
+pub use stats::TextStats;
+pub use unit_of_work::UnitOfWork;
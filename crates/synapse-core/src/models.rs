@@ -24,6 +24,9 @@ pub type DatabaseId = String;
 /// Unique identifier for an attachment
 pub type AttachmentId = String;
 
+/// Unique identifier for a saved search
+pub type SavedSearchId = String;
+
 /// A note in the knowledge base
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Note {
@@ -80,6 +83,56 @@ pub struct NoteWithContent {
     pub content: String,
 }
 
+/// Typed value of a note property (a key of the note's YAML front matter)
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", content = "value", rename_all = "snake_case")]
+pub enum PropertyValue {
+    Null,
+    Text(String),
+    Number(f64),
+    Boolean(bool),
+    /// ISO 8601 date or date-time, kept as written
+    Date(String),
+    List(Vec<PropertyValue>),
+    Map(Vec<(String, PropertyValue)>),
+}
+
+impl PropertyValue {
+    /// Type name stored alongside the value
+    pub fn type_name(&self) -> &'static str {
+        match self {
+            PropertyValue::Null => "null",
+            PropertyValue::Text(_) => "text",
+            PropertyValue::Number(_) => "number",
+            PropertyValue::Boolean(_) => "boolean",
+            PropertyValue::Date(_) => "date",
+            PropertyValue::List(_) => "list",
+            PropertyValue::Map(_) => "map",
+        }
+    }
+
+    /// Flatten the value to a list of strings (for tags and aliases)
+    pub fn as_string_list(&self) -> Vec<String> {
+        match self {
+            PropertyValue::Null => Vec::new(),
+            PropertyValue::Text(s) | PropertyValue::Date(s) => vec![s.clone()],
+            PropertyValue::Number(n) => vec![n.to_string()],
+            PropertyValue::Boolean(b) => vec![b.to_string()],
+            PropertyValue::List(items) => items.iter().flat_map(|i| i.as_string_list()).collect(),
+            PropertyValue::Map(_) => Vec::new(),
+        }
+    }
+}
+
+/// A note property (front matter key), stored in `note_properties`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct NoteProperty {
+    pub note_id: NoteId,
+    pub key: String,
+    pub value: PropertyValue,
+    pub position: i64,
+}
+
 /// A block in a note
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Block {
@@ -126,6 +179,21 @@ impl Block {
     }
 }
 
+/// Changes to a note's blocks, e.g. from reparsing the part of a note that was edited.
+/// Updated blocks keep their IDs; `updated` also lists blocks that only moved position.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct BlockDiff {
+    pub inserted: Vec<Block>,
+    pub updated: Vec<Block>,
+    pub removed: Vec<BlockId>,
+}
+
+impl BlockDiff {
+    pub fn is_empty(&self) -> bool {
+        self.inserted.is_empty() && self.updated.is_empty() && self.removed.is_empty()
+    }
+}
+
 /// A folder for organizing notes
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Folder {
@@ -153,6 +221,156 @@ impl Folder {
     }
 }
 
+/// Field a note listing is ordered by
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SortKey {
+    Title,
+    Created,
+    #[default]
+    Updated,
+    WordCount,
+    /// Manual order within a folder
+    Position,
+}
+
+impl SortKey {
+    /// Name stored in the database
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            SortKey::Title => "title",
+            SortKey::Created => "created",
+            SortKey::Updated => "updated",
+            SortKey::WordCount => "word_count",
+            SortKey::Position => "position",
+        }
+    }
+
+    /// Parse a stored name
+    pub fn parse(name: &str) -> Option<Self> {
+        match name {
+            "title" => Some(SortKey::Title),
+            "created" => Some(SortKey::Created),
+            "updated" => Some(SortKey::Updated),
+            "word_count" => Some(SortKey::WordCount),
+            "position" => Some(SortKey::Position),
+            _ => None,
+        }
+    }
+}
+
+/// Order of a note listing
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct NoteSort {
+    pub key: SortKey,
+    pub descending: bool,
+}
+
+impl Default for NoteSort {
+    /// Most recently updated first
+    fn default() -> Self {
+        Self {
+            key: SortKey::Updated,
+            descending: true,
+        }
+    }
+}
+
+/// Default number of items in a page
+pub const DEFAULT_PAGE_SIZE: usize = 50;
+
+/// Largest number of items in a page; larger limits are clamped to it
+pub const MAX_PAGE_SIZE: usize = 1000;
+
+/// Request for one page of a note listing
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PageRequest {
+    pub sort: NoteSort,
+    /// Maximum number of items in the page, clamped to [`MAX_PAGE_SIZE`]
+    pub limit: usize,
+    /// `next_cursor` of the previous page, or `None` for the first page
+    pub cursor: Option<String>,
+}
+
+impl PageRequest {
+    /// First page in the given order
+    pub fn new(sort: NoteSort, limit: usize) -> Self {
+        Self {
+            sort,
+            limit: limit.min(MAX_PAGE_SIZE),
+            cursor: None,
+        }
+    }
+
+    /// `limit` clamped to [`MAX_PAGE_SIZE`], for requests whose field was set directly
+    pub fn page_size(&self) -> usize {
+        self.limit.min(MAX_PAGE_SIZE)
+    }
+
+    /// The same request continuing after `cursor`
+    pub fn with_cursor(mut self, cursor: Option<String>) -> Self {
+        self.cursor = cursor;
+        self
+    }
+}
+
+impl Default for PageRequest {
+    fn default() -> Self {
+        Self::new(NoteSort::default(), DEFAULT_PAGE_SIZE)
+    }
+}
+
+/// One page of a listing
+#[derive(Debug, Clone, Serialize)]
+pub struct Page<T> {
+    pub items: Vec<T>,
+    /// Cursor of the following page, or `None` on the last page
+    pub next_cursor: Option<String>,
+    /// Size of the whole listing when this page was read; it can drift as notes change
+    pub total_estimate: u64,
+}
+
+/// A named note query, shown as a smart folder next to real folders
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SavedSearch {
+    pub id: SavedSearchId,
+    pub name: String,
+    /// Query in the syntax of [`crate::query::Query`], e.g. `tag:inbox -tag:done`
+    pub query: String,
+    pub sort: NoteSort,
+    pub position: i64,
+    pub created_at: i64,
+    pub updated_at: i64,
+}
+
+impl SavedSearch {
+    pub fn new(id: SavedSearchId, name: String, query: String, sort: NoteSort) -> Self {
+        let now = Utc::now().timestamp();
+        Self {
+            id,
+            name,
+            query,
+            sort,
+            position: 0,
+            created_at: now,
+            updated_at: now,
+        }
+    }
+}
+
+/// An entry of the folder tree: a folder with its subfolders, or a saved search
+#[derive(Debug, Clone, Serialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum FolderTreeNode {
+    Folder {
+        folder: Folder,
+        children: Vec<FolderTreeNode>,
+    },
+    SmartFolder {
+        search: SavedSearch,
+    },
+}
+
 /// A tag for organizing notes
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Tag {
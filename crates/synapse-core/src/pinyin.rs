@@ -0,0 +1,42 @@
+//! Pinyin readings of Chinese characters, for matching titles typed on a Latin keyboard.
+//!
+//! Readings come from an embedded table (`pinyin.txt`) with one toneless syllable per
+//! character. Polyphonic characters keep only their most common reading.
+
+use std::collections::HashMap;
+use std::sync::OnceLock;
+
+const TABLE: &str = include_str!("pinyin.txt");
+
+fn readings() -> &'static HashMap<char, &'static str> {
+    static READINGS: OnceLock<HashMap<char, &'static str>> = OnceLock::new();
+    READINGS.get_or_init(|| {
+        let mut map = HashMap::new();
+        for line in TABLE.lines().filter(|line| !line.starts_with('#')) {
+            let Some((syllable, chars)) = line.split_once(' ') else {
+                continue;
+            };
+            for c in chars.chars() {
+                map.entry(c).or_insert(syllable);
+            }
+        }
+        map
+    })
+}
+
+/// Toneless pinyin of a Chinese character, with "v" for ü
+pub fn pinyin_of(c: char) -> Option<&'static str> {
+    readings().get(&c).copied()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_readings() {
+        assert_eq!(pinyin_of('知'), Some("zhi"));
+        assert_eq!(pinyin_of('女'), Some("nv"));
+        assert_eq!(pinyin_of('a'), None);
+    }
+}
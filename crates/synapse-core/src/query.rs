@@ -0,0 +1,147 @@
+//! Note queries, as stored in saved searches.
+//!
+//! A query is a list of terms separated by whitespace, all of which must hold:
+//!
+//! - `tag:name` matches notes with the tag (case-insensitive, a leading `#` is optional)
+//! - `folder:/path` matches notes in the folder or any folder below it
+//! - any other word, or a `"quoted phrase"`, matches the title or content by full text
+//!
+//! A leading `-` negates a term, so `tag:inbox -tag:done` is the open inbox. Values may be
+//! quoted to include spaces: `folder:"/Work/Big project"`. The empty query matches every note.
+
+use crate::{Error, Result};
+
+/// A parsed query
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct Query {
+    pub terms: Vec<Term>,
+}
+
+/// One condition of a query
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Term {
+    pub negated: bool,
+    pub filter: Filter,
+}
+
+/// What a term tests
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Filter {
+    /// Tag name, without `#`
+    Tag(String),
+    /// Folder path with a leading `/` and no trailing `/`
+    Folder(String),
+    /// Word or phrase searched in titles and content
+    Text(String),
+}
+
+impl Query {
+    /// Parse a query, rejecting unbalanced quotes and filters without a value
+    pub fn parse(input: &str) -> Result<Self> {
+        let mut terms = Vec::new();
+        for token in split_terms(input)? {
+            let (negated, token) = match token.strip_prefix('-') {
+                Some(rest) if !rest.is_empty() => (true, rest),
+                _ => (false, token),
+            };
+            let filter = if let Some(value) = token.strip_prefix("tag:") {
+                let name = filter_value("tag", value)?;
+                Filter::Tag(name.strip_prefix('#').unwrap_or(&name).to_string())
+            } else if let Some(value) = token.strip_prefix("folder:") {
+                let path = filter_value("folder", value)?;
+                Filter::Folder(format!("/{}", path.trim_matches('/')))
+            } else {
+                let text = unquote(token);
+                // Punctuation alone has nothing to search for
+                if !text.chars().any(char::is_alphanumeric) {
+                    continue;
+                }
+                Filter::Text(text)
+            };
+            terms.push(Term { negated, filter });
+        }
+        Ok(Self { terms })
+    }
+}
+
+/// Split on whitespace outside double quotes, keeping the quotes in each term
+fn split_terms(input: &str) -> Result<Vec<&str>> {
+    let mut terms = Vec::new();
+    let mut start = None;
+    let mut quoted = false;
+    for (i, c) in input.char_indices() {
+        if c == '"' {
+            quoted = !quoted;
+        }
+        if c.is_whitespace() && !quoted {
+            if let Some(s) = start.take() {
+                terms.push(&input[s..i]);
+            }
+        } else if start.is_none() {
+            start = Some(i);
+        }
+    }
+    if quoted {
+        return Err(Error::InvalidInput(format!(
+            "Unterminated quote in query: {}",
+            input
+        )));
+    }
+    if let Some(s) = start {
+        terms.push(&input[s..]);
+    }
+    Ok(terms)
+}
+
+fn unquote(value: &str) -> String {
+    value.replace('"', "")
+}
+
+fn filter_value(name: &str, value: &str) -> Result<String> {
+    let value = unquote(value);
+    if value.trim().is_empty() {
+        return Err(Error::InvalidInput(format!("Missing value for {}:", name)));
+    }
+    Ok(value)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn term(negated: bool, filter: Filter) -> Term {
+        Term { negated, filter }
+    }
+
+    #[test]
+    fn test_parse_filters_and_negation() {
+        let query = Query::parse("tag:inbox -tag:#done  folder:Work/ rust").unwrap();
+        assert_eq!(
+            query.terms,
+            vec![
+                term(false, Filter::Tag("inbox".to_string())),
+                term(true, Filter::Tag("done".to_string())),
+                term(false, Filter::Folder("/Work".to_string())),
+                term(false, Filter::Text("rust".to_string())),
+            ]
+        );
+        assert!(Query::parse("  ").unwrap().terms.is_empty());
+    }
+
+    #[test]
+    fn test_parse_quotes() {
+        let query = Query::parse(r#"folder:"/Work/Big project" -"meeting notes" - "#).unwrap();
+        assert_eq!(
+            query.terms,
+            vec![
+                term(false, Filter::Folder("/Work/Big project".to_string())),
+                term(true, Filter::Text("meeting notes".to_string())),
+            ]
+        );
+        assert!(matches!(
+            Query::parse(r#"tag:"open"#),
+            Err(Error::InvalidInput(_))
+        ));
+        assert!(matches!(Query::parse("-tag:"), Err(Error::InvalidInput(_))));
+    }
+}
@@ -1,50 +1,244 @@
 //! Service layer for core business logic.
 //!
-//! Services take `ctx: &ServiceContext` (ctx passed in, not held).
-//! Storage is abstracted behind [`crate::storage::StorageBackend`].
+//! Services take `ctx: &ServiceContext` (ctx passed in, not held) and are generic over its
+//! [`StorageBackend`], so they run the same on SQLite and on [`crate::storage::MemoryBackend`].
 
+use std::collections::HashMap;
 use std::fs;
 use std::io::Read;
 use std::path::{Path, PathBuf};
+use std::sync::RwLock;
 
 use mime_guess::from_path;
 use sha2::{Digest, Sha256};
 
+use crate::content::{ContentConfig, ContentStore, MemoryContent};
+use crate::footnote::{FOOTNOTE_BLOCK_TYPE, footnote_links};
+use crate::frontmatter::FrontMatter;
+use crate::fuzzy::{FuzzyIndex, FuzzyMatch};
 use crate::models::*;
+use crate::query::Query;
+use crate::stats::TextStats;
 use crate::storage::{
-    AttachmentDao, BlockDao, DatabaseManager, FolderDao, LinkDao, NoteDao, TagDao,
+    AttachmentStorage, BlobStorage, BlockStorage, FolderStorage, LinkStorage, NoteStorage,
+    SavedSearchStorage, SettingStorage, StorageTransaction, TagStorage,
 };
-use crate::storage::{BlockAttachmentDao, NoteAttachmentDao, NoteFolderDao, NoteTagDao};
+use crate::storage::{FRONT_MATTER_PENDING, REINDEX_PENDING};
+use crate::storage::{MemoryBackend, PooledConnection, SqliteBackend, Storage, StorageBackend};
+use crate::unit_of_work::UnitOfWork;
 use crate::{Error, Result};
 
-/// Service context: holds storage backend and data directory. Passed into each service call.
-/// Currently holds SQLite (DatabaseManager); storage trait is defined for future swap.
-pub struct ServiceContext {
-    db: DatabaseManager,
-    data_dir: PathBuf,
+/// Setting that records where a vault keeps note content
+const CONTENT_SETTING: &str = "content_store";
+
+/// Service context: holds storage backend, content store and data directory. Passed into each
+/// service call. SQLite by default; [`ServiceContext::with_storage`] takes any [`StorageBackend`]
+/// and [`ServiceContext::in_memory`] touches no files at all. Note content is in files unless a
+/// [`ContentConfig`] says otherwise when the vault is created; the vault records the choice.
+/// Directories are created when a file is first written.
+///
+/// The context is `Send + Sync`: open it once and share it between threads. Reads borrow a
+/// store from the backend; writes go through units of work on its single transaction.
+pub struct ServiceContext<B: StorageBackend = SqliteBackend> {
+    storage: B,
+    content: Box<dyn ContentStore>,
+    data_dir: Option<PathBuf>,
+    /// Quick-switcher index, built on first use and dropped when titles, aliases or folders change
+    title_index: RwLock<Option<FuzzyIndex>>,
 }
 
 impl ServiceContext {
-    /// Create a new service context with SQLite at the given paths.
+    /// Create a new service context with SQLite at the given paths. Note content stays where
+    /// the vault keeps it; a new vault keeps it in files.
     pub fn new<P: AsRef<Path>>(db_path: P, data_dir: P) -> Result<Self> {
-        let db = DatabaseManager::new(db_path)?;
+        Self::open_vault(db_path, data_dir, None)
+    }
+
+    /// Create a service context with SQLite, keeping note content where `content` says. An
+    /// existing vault that keeps its content elsewhere is refused.
+    pub fn open<P: AsRef<Path>>(db_path: P, data_dir: P, content: ContentConfig) -> Result<Self> {
+        Self::open_vault(db_path, data_dir, Some(content))
+    }
+
+    /// Database connection for reading; write through [`ServiceContext::transaction`].
+    pub fn conn(&self) -> Result<PooledConnection<'_>> {
+        self.storage.reader()
+    }
+
+    fn open_vault<P: AsRef<Path>>(
+        db_path: P,
+        data_dir: P,
+        content: Option<ContentConfig>,
+    ) -> Result<Self> {
+        let storage = SqliteBackend::open(db_path)?;
+        let ctx = Self::with_vault_content(storage, data_dir, content)?;
+        // Finish upgrade work that needs note content; each clears its marker when it commits
+        let (reindex, front_matter) = {
+            let store = ctx.store()?;
+            (
+                store.get_setting(REINDEX_PENDING)?.is_some(),
+                store.get_setting(FRONT_MATTER_PENDING)?.is_some(),
+            )
+        };
+        if reindex {
+            SearchService::reindex_notes(&ctx)?;
+        }
+        if front_matter {
+            NoteService::sync_all_front_matter(&ctx)?;
+        }
+        Ok(ctx)
+    }
+}
+
+impl ServiceContext<MemoryBackend> {
+    /// Create a service context with storage and note content in memory and no data
+    /// directory, for tests and embedding. It has nowhere to keep attachments.
+    pub fn in_memory() -> Self {
+        Self::build(
+            MemoryBackend::new(),
+            Box::new(MemoryContent::default()),
+            None,
+        )
+    }
+}
+
+impl<B: StorageBackend> ServiceContext<B> {
+    /// Create a service context over `storage`, e.g. a [`crate::storage::MemoryBackend`],
+    /// with note content where the vault keeps it
+    pub fn with_storage<P: AsRef<Path>>(storage: B, data_dir: P) -> Result<Self> {
+        Self::with_vault_content(storage, data_dir, None)
+    }
+
+    /// Create a service context over `storage`, keeping note content where `content` says
+    pub fn with_content<P: AsRef<Path>>(
+        storage: B,
+        data_dir: P,
+        content: ContentConfig,
+    ) -> Result<Self> {
+        Self::with_vault_content(storage, data_dir, Some(content))
+    }
+
+    fn with_vault_content<P: AsRef<Path>>(
+        storage: B,
+        data_dir: P,
+        requested: Option<ContentConfig>,
+    ) -> Result<Self> {
         let data_dir = data_dir.as_ref().to_path_buf();
+        let content = Self::vault_content(&storage, requested)?.open(&data_dir)?;
+        Ok(Self::build(storage, content, Some(data_dir)))
+    }
+
+    /// Where the vault in `storage` keeps note content; a new vault records `requested`, or
+    /// files. A request for anything else is refused: the vault's notes would read as missing
+    /// and new ones would be kept apart from them.
+    fn vault_content(storage: &B, requested: Option<ContentConfig>) -> Result<ContentConfig> {
+        // Most opens find the choice recorded and need no write lock
+        if let Some(recorded) = Self::recorded_content(&*storage.reader()?)? {
+            return Self::check_content(recorded, requested);
+        }
+
+        let transaction = storage.begin()?;
+        // Another context may have recorded it since
+        if let Some(recorded) = Self::recorded_content(&transaction)? {
+            return Self::check_content(recorded, requested);
+        }
+        let config = match Self::infer_content(&transaction)? {
+            Some(existing) => Self::check_content(existing, requested)?,
+            None => requested.unwrap_or_default(),
+        };
+        transaction.set_setting(CONTENT_SETTING, config.as_str())?;
+        transaction.commit()?;
+        Ok(config)
+    }
+
+    /// Helper: Where the vault records that it keeps note content, if it does
+    fn recorded_content(store: &B::Store) -> Result<Option<ContentConfig>> {
+        store
+            .get_setting(CONTENT_SETTING)?
+            .map(|name| {
+                ContentConfig::parse(&name).ok_or_else(|| {
+                    Error::Storage(format!(
+                        "Unknown content store recorded in the vault: {}",
+                        name
+                    ))
+                })
+            })
+            .transpose()
+    }
+
+    /// Helper: The vault's content store, unless the caller asked for another one
+    fn check_content(
+        existing: ContentConfig,
+        requested: Option<ContentConfig>,
+    ) -> Result<ContentConfig> {
+        match requested {
+            Some(requested) if requested != existing => Err(Error::InvalidInput(format!(
+                "The vault keeps note content in {}, not {}",
+                existing.as_str(),
+                requested.as_str()
+            ))),
+            _ => Ok(existing),
+        }
+    }
+
+    /// Helper: Where a vault created before the choice was recorded keeps note content: in
+    /// files, unless its notes are blobs. `None` for a vault without notes.
+    fn infer_content(store: &B::Store) -> Result<Option<ContentConfig>> {
+        let Some(note) = store.list_notes(true)?.into_iter().next() else {
+            return Ok(None);
+        };
+        Ok(Some(match store.get_blob(&note.content_path)? {
+            Some(_) => ContentConfig::Database,
+            None => ContentConfig::Files,
+        }))
+    }
+
+    fn build(storage: B, content: Box<dyn ContentStore>, data_dir: Option<PathBuf>) -> Self {
+        Self {
+            storage,
+            content,
+            data_dir,
+            title_index: RwLock::new(None),
+        }
+    }
+
+    /// Storage backend.
+    pub fn storage(&self) -> &B {
+        &self.storage
+    }
+
+    /// Store for reading; write through [`ServiceContext::transaction`].
+    pub fn store(&self) -> Result<B::Reader<'_>> {
+        self.storage.reader()
+    }
 
-        fs::create_dir_all(&data_dir)?;
-        fs::create_dir_all(data_dir.join("notes"))?;
-        fs::create_dir_all(data_dir.join("attachments"))?;
+    /// Store for note content.
+    pub fn content(&self) -> &dyn ContentStore {
+        &*self.content
+    }
+
+    /// Data directory path; `None` for a context made by [`ServiceContext::in_memory`].
+    pub fn data_dir(&self) -> Option<&Path> {
+        self.data_dir.as_deref()
+    }
 
-        Ok(Self { db, data_dir })
+    /// Start a unit of work: a transaction plus staged file and content writes. See
+    /// [`UnitOfWork`].
+    pub fn begin(&self) -> Result<UnitOfWork<'_, B>> {
+        UnitOfWork::begin(&self.storage, &*self.content)
     }
 
-    /// Database connection.
-    pub fn conn(&self) -> &rusqlite::Connection {
-        self.db.conn()
+    /// Run `f` in a unit of work, committing if it succeeds and rolling back otherwise.
+    pub fn transaction<T>(&self, f: impl FnOnce(&mut UnitOfWork<'_, B>) -> Result<T>) -> Result<T> {
+        let mut unit = self.begin()?;
+        let value = f(&mut unit)?;
+        unit.commit()?;
+        Ok(value)
     }
 
-    /// Data directory path.
-    pub fn data_dir(&self) -> &Path {
-        &self.data_dir
+    /// Drop the quick-switcher index so the next fuzzy search rebuilds it.
+    pub(crate) fn invalidate_title_index(&self) {
+        *self.title_index.write().unwrap_or_else(|e| e.into_inner()) = None;
     }
 }
 
@@ -53,7 +247,11 @@ pub struct NoteService;
 
 impl NoteService {
     /// Create a new note with content
-    pub fn create(ctx: &ServiceContext, title: String, content: String) -> Result<Note> {
+    pub fn create<B: StorageBackend>(
+        ctx: &ServiceContext<B>,
+        title: String,
+        content: String,
+    ) -> Result<Note> {
         // Generate note ID
         let uuid = uuid::Uuid::new_v4();
         let note_id = format!("note-{}", uuid);
@@ -61,40 +259,37 @@ impl NoteService {
         // Generate file path (simplified: just use UUID for now, slug can be added later)
         let file_name = format!("{}-{}.md", uuid, Self::slugify(&title));
         let content_path = format!("notes/{}", file_name);
-        let full_path = ctx.data_dir().join(&content_path);
 
         // Create note model
-        let mut note = Note::new(note_id.clone(), title, content_path.clone());
-
-        // Write content to file
-        fs::write(&full_path, content.as_bytes())?;
+        let mut note = Note::new(note_id.clone(), title, content_path);
 
         // Calculate word count
         note.update_word_count(Self::count_words(&content));
 
-        // Save to database
-        NoteDao::create(ctx.conn(), &note)?;
+        // Write the content and the rows together
+        ctx.transaction(|unit| {
+            unit.write_content(&note.content_path, &content)?;
+            unit.store().create_note(&note)?;
+            unit.store().index_note_content(&note.id, &content)?;
+            Self::sync_front_matter(unit.store(), &note.id, &content)
+        })?;
+        ctx.invalidate_title_index();
 
         Ok(note)
     }
 
-    /// Get a note by ID (including content from file)
-    pub fn get_by_id(
-        ctx: &ServiceContext,
+    /// Get a note by ID (including its content)
+    pub fn get_by_id<B: StorageBackend>(
+        ctx: &ServiceContext<B>,
         id: &str,
         include_deleted: bool,
     ) -> Result<Option<NoteWithContent>> {
-        let note = NoteDao::get_by_id(ctx.conn(), id, include_deleted)?;
+        let store = ctx.store()?;
+        let note = store.get_note(id, include_deleted)?;
 
         match note {
             Some(note) => {
-                // Read content from file
-                let content_path = ctx.data_dir().join(&note.content_path);
-                let content = if content_path.exists() {
-                    fs::read_to_string(&content_path)?
-                } else {
-                    String::new()
-                };
+                let content = Self::read_content(ctx, &*store, &note)?;
 
                 Ok(Some(NoteWithContent { note, content }))
             }
@@ -102,126 +297,358 @@ impl NoteService {
         }
     }
 
+    /// Word and character counts and reading time of a note's body
+    pub fn get_statistics<B: StorageBackend>(
+        ctx: &ServiceContext<B>,
+        id: &str,
+    ) -> Result<TextStats> {
+        let note = Self::get_by_id(ctx, id, false)?
+            .ok_or_else(|| Error::NotFound(format!("Note not found: {}", id)))?;
+        Ok(TextStats::of_note(&note.content))
+    }
+
     /// Update note title and/or content
-    pub fn update(
-        ctx: &ServiceContext,
+    pub fn update<B: StorageBackend>(
+        ctx: &ServiceContext<B>,
         id: &str,
         title: Option<String>,
         content: Option<String>,
     ) -> Result<()> {
-        let mut note = NoteDao::get_by_id(ctx.conn(), id, false)?
-            .ok_or_else(|| Error::NotFound(format!("Note not found: {}", id)))?;
-
-        // Update title if provided
-        if let Some(new_title) = title {
-            note.update_title(new_title);
-        }
-
-        // Update content if provided
-        if let Some(new_content) = content {
-            let content_path = ctx.data_dir().join(&note.content_path);
-            fs::write(&content_path, new_content.as_bytes())?;
-            note.update_word_count(Self::count_words(&new_content));
-        }
+        ctx.transaction(|unit| {
+            let mut note = unit
+                .store()
+                .get_note(id, false)?
+                .ok_or_else(|| Error::NotFound(format!("Note not found: {}", id)))?;
+
+            // Update title if provided
+            if let Some(new_title) = title {
+                note.update_title(new_title);
+            }
 
-        // Update in database
-        NoteDao::update(ctx.conn(), &note)?;
+            // Update content if provided
+            match content {
+                Some(ref new_content) => Self::save_content(unit, &mut note, new_content),
+                None => unit.store().update_note(&note),
+            }
+        })?;
+        ctx.invalidate_title_index();
 
         Ok(())
     }
 
     /// Update note content only
-    pub fn update_content(ctx: &ServiceContext, id: &str, content: String) -> Result<()> {
+    pub fn update_content<B: StorageBackend>(
+        ctx: &ServiceContext<B>,
+        id: &str,
+        content: String,
+    ) -> Result<()> {
         Self::update(ctx, id, None, Some(content))
     }
 
     /// Update note title only
-    pub fn update_title(ctx: &ServiceContext, id: &str, title: String) -> Result<()> {
+    pub fn update_title<B: StorageBackend>(
+        ctx: &ServiceContext<B>,
+        id: &str,
+        title: String,
+    ) -> Result<()> {
         Self::update(ctx, id, Some(title), None)
     }
 
     /// Soft delete a note
-    pub fn delete(ctx: &ServiceContext, id: &str) -> Result<()> {
-        NoteDao::soft_delete(ctx.conn(), id)?;
+    pub fn delete<B: StorageBackend>(ctx: &ServiceContext<B>, id: &str) -> Result<()> {
+        ctx.transaction(|unit| unit.store().soft_delete_note(id))?;
+        ctx.invalidate_title_index();
         Ok(())
     }
 
     /// Restore a soft-deleted note
-    pub fn restore(ctx: &ServiceContext, id: &str) -> Result<()> {
-        NoteDao::restore(ctx.conn(), id)?;
+    pub fn restore<B: StorageBackend>(ctx: &ServiceContext<B>, id: &str) -> Result<()> {
+        ctx.transaction(|unit| unit.store().restore_note(id))?;
+        ctx.invalidate_title_index();
         Ok(())
     }
 
     /// List all notes
-    pub fn list(ctx: &ServiceContext, include_deleted: bool) -> Result<Vec<Note>> {
-        NoteDao::list(ctx.conn(), include_deleted)
+    pub fn list<B: StorageBackend>(
+        ctx: &ServiceContext<B>,
+        include_deleted: bool,
+    ) -> Result<Vec<Note>> {
+        let store = ctx.store()?;
+        store.list_notes(include_deleted)
+    }
+
+    /// List one page of notes; pass the returned `next_cursor` to get the following page
+    pub fn list_page<B: StorageBackend>(
+        ctx: &ServiceContext<B>,
+        include_deleted: bool,
+        request: &PageRequest,
+    ) -> Result<Page<Note>> {
+        let store = ctx.store()?;
+        store.list_notes_page(include_deleted, request)
     }
 
     /// Search notes by title
-    pub fn search_by_title(
-        ctx: &ServiceContext,
+    pub fn search_by_title<B: StorageBackend>(
+        ctx: &ServiceContext<B>,
         query: &str,
         include_deleted: bool,
     ) -> Result<Vec<Note>> {
-        NoteDao::search_by_title(ctx.conn(), query, include_deleted)
+        let store = ctx.store()?;
+        store.search_notes_by_title(query, include_deleted)
     }
 
     /// Get notes in a folder
-    pub fn get_by_folder(
-        ctx: &ServiceContext,
+    pub fn get_by_folder<B: StorageBackend>(
+        ctx: &ServiceContext<B>,
         folder_id: &str,
         include_deleted: bool,
     ) -> Result<Vec<Note>> {
-        NoteDao::get_by_folder(ctx.conn(), folder_id, include_deleted)
+        let store = ctx.store()?;
+        store.notes_in_folder(folder_id, include_deleted)
+    }
+
+    /// Get one page of the notes in a folder, which may be in the folder's manual order
+    pub fn get_by_folder_page<B: StorageBackend>(
+        ctx: &ServiceContext<B>,
+        folder_id: &str,
+        include_deleted: bool,
+        request: &PageRequest,
+    ) -> Result<Page<Note>> {
+        let store = ctx.store()?;
+        store.notes_in_folder_page(folder_id, include_deleted, request)
     }
 
     /// Add note to folder
-    pub fn add_to_folder(
-        ctx: &ServiceContext,
+    pub fn add_to_folder<B: StorageBackend>(
+        ctx: &ServiceContext<B>,
         note_id: &str,
         folder_id: &str,
         is_primary: bool,
         position: i64,
     ) -> Result<()> {
-        NoteFolderDao::add(ctx.conn(), note_id, folder_id, is_primary, position)?;
+        ctx.transaction(|unit| {
+            unit.store()
+                .add_note_to_folder(note_id, folder_id, is_primary, position)
+        })?;
+        ctx.invalidate_title_index();
         Ok(())
     }
 
     /// Remove note from folder
-    pub fn remove_from_folder(ctx: &ServiceContext, note_id: &str, folder_id: &str) -> Result<()> {
-        NoteFolderDao::remove(ctx.conn(), note_id, folder_id)?;
+    pub fn remove_from_folder<B: StorageBackend>(
+        ctx: &ServiceContext<B>,
+        note_id: &str,
+        folder_id: &str,
+    ) -> Result<()> {
+        ctx.transaction(|unit| unit.store().remove_note_from_folder(note_id, folder_id))?;
+        ctx.invalidate_title_index();
         Ok(())
     }
 
     /// Add tag to note
-    pub fn add_tag(ctx: &ServiceContext, note_id: &str, tag_id: &str) -> Result<()> {
-        NoteTagDao::add(ctx.conn(), note_id, tag_id)?;
+    pub fn add_tag<B: StorageBackend>(
+        ctx: &ServiceContext<B>,
+        note_id: &str,
+        tag_id: &str,
+    ) -> Result<()> {
+        ctx.transaction(|unit| {
+            unit.store().add_note_tag(note_id, tag_id)?;
+            Self::edit_front_matter_tags(ctx, unit, note_id, tag_id, FrontMatter::add_tag)
+        })?;
         Ok(())
     }
 
     /// Remove tag from note
-    pub fn remove_tag(ctx: &ServiceContext, note_id: &str, tag_id: &str) -> Result<()> {
-        NoteTagDao::remove(ctx.conn(), note_id, tag_id)?;
+    pub fn remove_tag<B: StorageBackend>(
+        ctx: &ServiceContext<B>,
+        note_id: &str,
+        tag_id: &str,
+    ) -> Result<()> {
+        ctx.transaction(|unit| {
+            unit.store().remove_note_tag(note_id, tag_id)?;
+            Self::edit_front_matter_tags(ctx, unit, note_id, tag_id, FrontMatter::remove_tag)
+        })?;
         Ok(())
     }
 
     /// Get all tags for a note
-    pub fn get_tags(ctx: &ServiceContext, note_id: &str) -> Result<Vec<Tag>> {
-        let tag_ids = NoteTagDao::get_tags_for_note(ctx.conn(), note_id)?;
-        let mut tags = Vec::new();
+    pub fn get_tags<B: StorageBackend>(ctx: &ServiceContext<B>, note_id: &str) -> Result<Vec<Tag>> {
+        let store = ctx.store()?;
+        store.tags_for_note(note_id)
+    }
 
-        for tag_id in tag_ids {
-            if let Some(tag) = TagDao::get_by_id(ctx.conn(), &tag_id)? {
-                tags.push(tag);
+    /// Get the front matter properties of a note
+    pub fn get_properties<B: StorageBackend>(
+        ctx: &ServiceContext<B>,
+        note_id: &str,
+    ) -> Result<Vec<NoteProperty>> {
+        let store = ctx.store()?;
+        store.note_properties(note_id)
+    }
+
+    /// Get the aliases of a note
+    pub fn get_aliases<B: StorageBackend>(
+        ctx: &ServiceContext<B>,
+        note_id: &str,
+    ) -> Result<Vec<String>> {
+        let store = ctx.store()?;
+        store.note_aliases(note_id)
+    }
+
+    /// Set a property; the note's front matter is rewritten and the body left untouched
+    pub fn set_property<B: StorageBackend>(
+        ctx: &ServiceContext<B>,
+        note_id: &str,
+        key: &str,
+        value: PropertyValue,
+    ) -> Result<()> {
+        Self::edit_front_matter(ctx, note_id, |front_matter| front_matter.set(key, value))
+    }
+
+    /// Remove a property from the note's front matter
+    pub fn remove_property<B: StorageBackend>(
+        ctx: &ServiceContext<B>,
+        note_id: &str,
+        key: &str,
+    ) -> Result<()> {
+        Self::edit_front_matter(ctx, note_id, |front_matter| {
+            front_matter.remove(key);
+        })
+    }
+
+    /// Helper: Apply an edit to the front matter and save the note
+    fn edit_front_matter<B: StorageBackend>(
+        ctx: &ServiceContext<B>,
+        note_id: &str,
+        edit: impl FnOnce(&mut FrontMatter),
+    ) -> Result<()> {
+        // Read and write in one unit so a concurrent save is not overwritten with stale content
+        ctx.transaction(|unit| {
+            let mut note = unit
+                .store()
+                .get_note(note_id, false)?
+                .ok_or_else(|| Error::NotFound(format!("Note not found: {}", note_id)))?;
+            let content = Self::read_content(ctx, unit.store(), &note)?;
+
+            let mut front_matter = FrontMatter::parse(&content)?.unwrap_or_default();
+            edit(&mut front_matter);
+            let content = front_matter.apply_to(&content);
+
+            Self::save_content(unit, &mut note, &content)
+        })?;
+        ctx.invalidate_title_index();
+        Ok(())
+    }
+
+    /// Helper: Mirror a tag change in the `tags:` front matter of a note that has one, so the
+    /// next save, which takes tags from the front matter, keeps it
+    fn edit_front_matter_tags<B: StorageBackend>(
+        ctx: &ServiceContext<B>,
+        unit: &mut UnitOfWork<'_, B>,
+        note_id: &str,
+        tag_id: &str,
+        edit: impl FnOnce(&mut FrontMatter, &str) -> bool,
+    ) -> Result<()> {
+        let (Some(mut note), Some(tag)) = (
+            unit.store().get_note(note_id, false)?,
+            unit.store().get_tag(tag_id)?,
+        ) else {
+            return Ok(());
+        };
+        let content = Self::read_content(ctx, unit.store(), &note)?;
+        let Ok(Some(mut front_matter)) = FrontMatter::parse(&content) else {
+            return Ok(());
+        };
+        if !edit(&mut front_matter, &tag.name) {
+            return Ok(());
+        }
+        let content = front_matter.apply_to(&content);
+        Self::save_content(unit, &mut note, &content)
+    }
+
+    /// Helper: Read the content of a note; content that is gone (e.g. a file deleted outside
+    /// the app) reads as empty so the note can still be opened and edited
+    fn read_content<B: StorageBackend>(
+        ctx: &ServiceContext<B>,
+        store: &dyn BlobStorage,
+        note: &Note,
+    ) -> Result<String> {
+        Ok(ctx
+            .content()
+            .read(store, &note.content_path)?
+            .unwrap_or_default())
+    }
+
+    /// Helper: Write new content of a note and update its row, index and front matter
+    fn save_content<B: StorageBackend>(
+        unit: &mut UnitOfWork<'_, B>,
+        note: &mut Note,
+        content: &str,
+    ) -> Result<()> {
+        unit.write_content(&note.content_path, content)?;
+        note.update_word_count(Self::count_words(content));
+        unit.store().update_note(note)?;
+        unit.store().index_note_content(&note.id, content)?;
+        Self::sync_front_matter(unit.store(), &note.id, content)
+    }
+
+    /// Refresh the properties, tags and aliases of every note from its front matter, which
+    /// completes a pending upgrade backfill
+    pub fn sync_all_front_matter<B: StorageBackend>(ctx: &ServiceContext<B>) -> Result<()> {
+        ctx.transaction(|unit| {
+            for note in unit.store().list_notes(true)? {
+                if let Some(content) = ctx.content().read(unit.store(), &note.content_path)? {
+                    Self::sync_front_matter(unit.store(), &note.id, &content)?;
+                }
+            }
+            unit.store().delete_setting(FRONT_MATTER_PENDING)
+        })?;
+        ctx.invalidate_title_index();
+        Ok(())
+    }
+
+    /// Helper: Store front matter as note properties and sync `tags`/`aliases`.
+    ///
+    /// Invalid YAML leaves the stored properties untouched so that saving a note never fails
+    /// because of a typo in its header. Tags and aliases are only synced when their key is
+    /// present, so tags added through the API survive on notes without a `tags:` key.
+    fn sync_front_matter<S: Storage>(store: &S, note_id: &str, content: &str) -> Result<()> {
+        let Ok(front_matter) = FrontMatter::parse(content) else {
+            return Ok(());
+        };
+        let front_matter = front_matter.unwrap_or_default();
+
+        store.replace_note_properties(note_id, &front_matter.properties)?;
+
+        if let Some(tag_names) = front_matter.tags() {
+            let mut tag_ids = Vec::new();
+            for name in tag_names {
+                let tag = match store.get_tag_by_name(&name)? {
+                    Some(tag) => tag,
+                    None => TagService::create_in(store, name)?,
+                };
+                tag_ids.push(tag.id);
+            }
+
+            let current = store.note_tag_ids(note_id)?;
+            for tag_id in current.iter().filter(|id| !tag_ids.contains(id)) {
+                store.remove_note_tag(note_id, tag_id)?;
+            }
+            for tag_id in tag_ids.iter().filter(|id| !current.contains(id)) {
+                store.add_note_tag(note_id, tag_id)?;
             }
         }
 
-        Ok(tags)
+        if let Some(aliases) = front_matter.aliases() {
+            store.replace_note_aliases(note_id, &aliases)?;
+        }
+
+        Ok(())
     }
 
-    /// Helper: Count words in content
+    /// Helper: Count words in content (front matter excluded, CJK characters count as words)
     fn count_words(content: &str) -> i64 {
-        content.split_whitespace().count() as i64
+        TextStats::of_note(content).words as i64
     }
 
     /// Helper: Slugify title (simplified version)
@@ -259,9 +686,14 @@ pub struct TagService;
 
 impl TagService {
     /// Create a new tag
-    pub fn create(ctx: &ServiceContext, name: String) -> Result<Tag> {
+    pub fn create<B: StorageBackend>(ctx: &ServiceContext<B>, name: String) -> Result<Tag> {
+        ctx.transaction(|unit| Self::create_in(unit.store(), name))
+    }
+
+    /// Helper: Create a tag on a store that may be inside a unit of work
+    fn create_in<S: Storage>(store: &S, name: String) -> Result<Tag> {
         // Check if tag with same name already exists
-        if let Some(_) = TagDao::get_by_name(ctx.conn(), &name)? {
+        if store.get_tag_by_name(&name)?.is_some() {
             return Err(Error::InvalidInput(format!(
                 "Tag '{}' already exists",
                 name
@@ -272,50 +704,61 @@ impl TagService {
         let tag_id = format!("tag-{}", uuid);
         let tag = Tag::new(tag_id.clone(), name);
 
-        TagDao::create(ctx.conn(), &tag)?;
+        store.create_tag(&tag)?;
 
         Ok(tag)
     }
 
     /// Get a tag by ID
-    pub fn get_by_id(ctx: &ServiceContext, id: &str) -> Result<Option<Tag>> {
-        TagDao::get_by_id(ctx.conn(), id)
+    pub fn get_by_id<B: StorageBackend>(ctx: &ServiceContext<B>, id: &str) -> Result<Option<Tag>> {
+        let store = ctx.store()?;
+        store.get_tag(id)
     }
 
     /// Get a tag by name
-    pub fn get_by_name(ctx: &ServiceContext, name: &str) -> Result<Option<Tag>> {
-        TagDao::get_by_name(ctx.conn(), name)
+    pub fn get_by_name<B: StorageBackend>(
+        ctx: &ServiceContext<B>,
+        name: &str,
+    ) -> Result<Option<Tag>> {
+        let store = ctx.store()?;
+        store.get_tag_by_name(name)
     }
 
     /// List all tags
-    pub fn list(ctx: &ServiceContext) -> Result<Vec<Tag>> {
-        TagDao::list(ctx.conn())
+    pub fn list<B: StorageBackend>(ctx: &ServiceContext<B>) -> Result<Vec<Tag>> {
+        let store = ctx.store()?;
+        store.list_tags()
     }
 
     /// Update a tag
-    pub fn update(ctx: &ServiceContext, tag: &Tag) -> Result<()> {
-        TagDao::update(ctx.conn(), tag)?;
+    pub fn update<B: StorageBackend>(ctx: &ServiceContext<B>, tag: &Tag) -> Result<()> {
+        ctx.transaction(|unit| unit.store().update_tag(tag))?;
         Ok(())
     }
 
     /// Delete a tag
-    pub fn delete(ctx: &ServiceContext, id: &str) -> Result<()> {
-        TagDao::delete(ctx.conn(), id)?;
+    pub fn delete<B: StorageBackend>(ctx: &ServiceContext<B>, id: &str) -> Result<()> {
+        ctx.transaction(|unit| unit.store().delete_tag(id))?;
         Ok(())
     }
 
-    /// Get all notes with a tag
-    pub fn get_notes(ctx: &ServiceContext, tag_id: &str) -> Result<Vec<Note>> {
-        let note_ids = NoteTagDao::get_notes_with_tag(ctx.conn(), tag_id)?;
-        let mut notes = Vec::new();
-
-        for note_id in note_ids {
-            if let Some(note) = NoteDao::get_by_id(ctx.conn(), &note_id, false)? {
-                notes.push(note);
-            }
-        }
+    /// Get one page of the notes with a tag
+    pub fn get_notes_page<B: StorageBackend>(
+        ctx: &ServiceContext<B>,
+        tag_id: &str,
+        request: &PageRequest,
+    ) -> Result<Page<Note>> {
+        let store = ctx.store()?;
+        store.notes_with_tag_page(tag_id, false, request)
+    }
 
-        Ok(notes)
+    /// Get all notes with a tag
+    pub fn get_notes<B: StorageBackend>(
+        ctx: &ServiceContext<B>,
+        tag_id: &str,
+    ) -> Result<Vec<Note>> {
+        let store = ctx.store()?;
+        store.notes_with_tag(tag_id, false)
     }
 }
 
@@ -324,78 +767,262 @@ pub struct FolderService;
 
 impl FolderService {
     /// Create a new folder
-    pub fn create(ctx: &ServiceContext, name: String, parent_id: Option<String>) -> Result<Folder> {
-        // Validate parent exists if provided
-        if let Some(ref pid) = parent_id {
-            if FolderDao::get_by_id(ctx.conn(), pid)?.is_none() {
+    pub fn create<B: StorageBackend>(
+        ctx: &ServiceContext<B>,
+        name: String,
+        parent_id: Option<String>,
+    ) -> Result<Folder> {
+        ctx.transaction(|unit| {
+            // Validate parent exists if provided
+            if let Some(ref pid) = parent_id
+                && unit.store().get_folder(pid)?.is_none()
+            {
                 return Err(Error::NotFound(format!("Parent folder not found: {}", pid)));
             }
-        }
 
-        let uuid = uuid::Uuid::new_v4();
-        let folder_id = format!("folder-{}", uuid);
+            let uuid = uuid::Uuid::new_v4();
+            let folder_id = format!("folder-{}", uuid);
 
-        // Calculate path
-        let path = if let Some(ref pid) = parent_id {
-            if let Some(parent) = FolderDao::get_by_id(ctx.conn(), pid)? {
-                format!("{}/{}", parent.path, name)
+            // Calculate path
+            let path = if let Some(ref pid) = parent_id {
+                if let Some(parent) = unit.store().get_folder(pid)? {
+                    format!("{}/{}", parent.path, name)
+                } else {
+                    format!("/{}", name)
+                }
             } else {
                 format!("/{}", name)
-            }
-        } else {
-            format!("/{}", name)
-        };
+            };
 
-        let folder = Folder::new(folder_id.clone(), name, parent_id, path);
+            let folder = Folder::new(folder_id.clone(), name, parent_id, path);
 
-        FolderDao::create(ctx.conn(), &folder)?;
+            unit.store().create_folder(&folder)?;
 
-        Ok(folder)
+            Ok(folder)
+        })
     }
 
     /// Get a folder by ID
-    pub fn get_by_id(ctx: &ServiceContext, id: &str) -> Result<Option<Folder>> {
-        FolderDao::get_by_id(ctx.conn(), id)
+    pub fn get_by_id<B: StorageBackend>(
+        ctx: &ServiceContext<B>,
+        id: &str,
+    ) -> Result<Option<Folder>> {
+        let store = ctx.store()?;
+        store.get_folder(id)
     }
 
     /// Get root folders
-    pub fn get_roots(ctx: &ServiceContext) -> Result<Vec<Folder>> {
-        FolderDao::get_roots(ctx.conn())
+    pub fn get_roots<B: StorageBackend>(ctx: &ServiceContext<B>) -> Result<Vec<Folder>> {
+        let store = ctx.store()?;
+        store.root_folders()
     }
 
     /// Get child folders
-    pub fn get_children(ctx: &ServiceContext, parent_id: &str) -> Result<Vec<Folder>> {
-        FolderDao::get_children(ctx.conn(), parent_id)
+    pub fn get_children<B: StorageBackend>(
+        ctx: &ServiceContext<B>,
+        parent_id: &str,
+    ) -> Result<Vec<Folder>> {
+        let store = ctx.store()?;
+        store.child_folders(parent_id)
     }
 
     /// Update a folder
-    pub fn update(ctx: &ServiceContext, folder: &Folder) -> Result<()> {
-        FolderDao::update(ctx.conn(), folder)?;
+    pub fn update<B: StorageBackend>(ctx: &ServiceContext<B>, folder: &Folder) -> Result<()> {
+        ctx.transaction(|unit| unit.store().update_folder(folder))?;
+        ctx.invalidate_title_index();
         Ok(())
     }
 
     /// Delete a folder
-    pub fn delete(ctx: &ServiceContext, id: &str) -> Result<()> {
-        // Check if folder has children
-        let children = FolderDao::get_children(ctx.conn(), id)?;
-        if !children.is_empty() {
-            return Err(Error::InvalidInput(format!(
-                "Cannot delete folder with children: {}",
-                id
-            )));
-        }
+    pub fn delete<B: StorageBackend>(ctx: &ServiceContext<B>, id: &str) -> Result<()> {
+        ctx.transaction(|unit| {
+            // Check if folder has children
+            let children = unit.store().child_folders(id)?;
+            if !children.is_empty() {
+                return Err(Error::InvalidInput(format!(
+                    "Cannot delete folder with children: {}",
+                    id
+                )));
+            }
 
-        FolderDao::delete(ctx.conn(), id)?;
+            unit.store().delete_folder(id)
+        })?;
+        ctx.invalidate_title_index();
         Ok(())
     }
 
     /// Get all notes in a folder
-    pub fn get_notes(
-        ctx: &ServiceContext,
+    pub fn get_notes<B: StorageBackend>(
+        ctx: &ServiceContext<B>,
         folder_id: &str,
         include_deleted: bool,
     ) -> Result<Vec<Note>> {
-        NoteDao::get_by_folder(ctx.conn(), folder_id, include_deleted)
+        let store = ctx.store()?;
+        store.notes_in_folder(folder_id, include_deleted)
+    }
+
+    /// Get one page of the notes in a folder
+    pub fn get_notes_page<B: StorageBackend>(
+        ctx: &ServiceContext<B>,
+        folder_id: &str,
+        include_deleted: bool,
+        request: &PageRequest,
+    ) -> Result<Page<Note>> {
+        let store = ctx.store()?;
+        store.notes_in_folder_page(folder_id, include_deleted, request)
+    }
+
+    /// The whole folder tree, with saved searches listed as smart folders after the root folders
+    pub fn tree<B: StorageBackend>(ctx: &ServiceContext<B>) -> Result<Vec<FolderTreeNode>> {
+        let store = ctx.store()?;
+        let mut children: HashMap<Option<FolderId>, Vec<Folder>> = HashMap::new();
+        for folder in store.list_folders()? {
+            children
+                .entry(folder.parent_id.clone())
+                .or_default()
+                .push(folder);
+        }
+
+        let mut tree = Self::subtree(&mut children, None);
+        tree.extend(
+            store
+                .list_saved_searches()?
+                .into_iter()
+                .map(|search| FolderTreeNode::SmartFolder { search }),
+        );
+        Ok(tree)
+    }
+
+    fn subtree(
+        children: &mut HashMap<Option<FolderId>, Vec<Folder>>,
+        parent_id: Option<FolderId>,
+    ) -> Vec<FolderTreeNode> {
+        children
+            .remove(&parent_id)
+            .unwrap_or_default()
+            .into_iter()
+            .map(|folder| {
+                let nested = Self::subtree(children, Some(folder.id.clone()));
+                FolderTreeNode::Folder {
+                    folder,
+                    children: nested,
+                }
+            })
+            .collect()
+    }
+}
+
+/// Saved search service: named queries shown as smart folders
+pub struct SavedSearchService;
+
+impl SavedSearchService {
+    /// Save a query under a name; see [`crate::query`] for the syntax
+    pub fn create<B: StorageBackend>(
+        ctx: &ServiceContext<B>,
+        name: String,
+        query: String,
+        sort: NoteSort,
+    ) -> Result<SavedSearch> {
+        ctx.transaction(|unit| {
+            Self::validate(&name, &query, sort)?;
+
+            let uuid = uuid::Uuid::new_v4();
+            let mut search = SavedSearch::new(format!("search-{}", uuid), name, query, sort);
+            search.position = unit.store().list_saved_searches()?.len() as i64;
+
+            unit.store().create_saved_search(&search)?;
+
+            Ok(search)
+        })
+    }
+
+    /// Get a saved search by ID
+    pub fn get_by_id<B: StorageBackend>(
+        ctx: &ServiceContext<B>,
+        id: &str,
+    ) -> Result<Option<SavedSearch>> {
+        let store = ctx.store()?;
+        store.get_saved_search(id)
+    }
+
+    /// List saved searches in display order
+    pub fn list<B: StorageBackend>(ctx: &ServiceContext<B>) -> Result<Vec<SavedSearch>> {
+        let store = ctx.store()?;
+        store.list_saved_searches()
+    }
+
+    /// Update a saved search
+    pub fn update<B: StorageBackend>(ctx: &ServiceContext<B>, search: &SavedSearch) -> Result<()> {
+        Self::validate(&search.name, &search.query, search.sort)?;
+        let mut search = search.clone();
+        search.updated_at = chrono::Utc::now().timestamp();
+        ctx.transaction(|unit| unit.store().update_saved_search(&search))
+    }
+
+    /// Delete a saved search
+    pub fn delete<B: StorageBackend>(ctx: &ServiceContext<B>, id: &str) -> Result<()> {
+        ctx.transaction(|unit| unit.store().delete_saved_search(id))
+    }
+
+    /// Current notes of a saved search
+    pub fn evaluate<B: StorageBackend>(ctx: &ServiceContext<B>, id: &str) -> Result<Vec<Note>> {
+        let store = ctx.store()?;
+        let search = store
+            .get_saved_search(id)?
+            .ok_or_else(|| Error::NotFound(format!("Saved search not found: {}", id)))?;
+        Self::run(ctx, &search.query, search.sort)
+    }
+
+    /// One page of the current notes of a saved search, in the saved search's order (the
+    /// sort of `request` is ignored)
+    pub fn evaluate_page<B: StorageBackend>(
+        ctx: &ServiceContext<B>,
+        id: &str,
+        request: &PageRequest,
+    ) -> Result<Page<Note>> {
+        let store = ctx.store()?;
+        let search = store
+            .get_saved_search(id)?
+            .ok_or_else(|| Error::NotFound(format!("Saved search not found: {}", id)))?;
+        let request = PageRequest {
+            sort: search.sort,
+            ..request.clone()
+        };
+        Self::run_page(ctx, &search.query, &request)
+    }
+
+    /// Evaluate a query without saving it, e.g. to preview a smart folder
+    pub fn run<B: StorageBackend>(
+        ctx: &ServiceContext<B>,
+        query: &str,
+        sort: NoteSort,
+    ) -> Result<Vec<Note>> {
+        let store = ctx.store()?;
+        store.query_notes(&Query::parse(query)?, sort, false)
+    }
+
+    /// One page of [`SavedSearchService::run`], in the order of `request`
+    pub fn run_page<B: StorageBackend>(
+        ctx: &ServiceContext<B>,
+        query: &str,
+        request: &PageRequest,
+    ) -> Result<Page<Note>> {
+        let store = ctx.store()?;
+        store.query_notes_page(&Query::parse(query)?, false, request)
+    }
+
+    fn validate(name: &str, query: &str, sort: NoteSort) -> Result<()> {
+        if name.trim().is_empty() {
+            return Err(Error::InvalidInput(
+                "Saved search name is empty".to_string(),
+            ));
+        }
+        if sort.key == SortKey::Position {
+            return Err(Error::InvalidInput(
+                "Saved searches have no manual order".to_string(),
+            ));
+        }
+        Query::parse(query).map(|_| ())
     }
 }
 
@@ -404,98 +1031,129 @@ pub struct LinkService;
 
 impl LinkService {
     /// Create a note link
-    pub fn create_note_link(
-        ctx: &ServiceContext,
+    pub fn create_note_link<B: StorageBackend>(
+        ctx: &ServiceContext<B>,
         source_note_id: String,
         target_note_id: String,
         link_text: Option<String>,
     ) -> Result<Link> {
-        // Validate notes exist
-        if NoteDao::get_by_id(ctx.conn(), &source_note_id, false)?.is_none() {
-            return Err(Error::NotFound(format!(
-                "Source note not found: {}",
-                source_note_id
-            )));
-        }
-        if NoteDao::get_by_id(ctx.conn(), &target_note_id, false)?.is_none() {
-            return Err(Error::NotFound(format!(
-                "Target note not found: {}",
-                target_note_id
-            )));
-        }
+        ctx.transaction(|unit| {
+            // Validate notes exist
+            if unit.store().get_note(&source_note_id, false)?.is_none() {
+                return Err(Error::NotFound(format!(
+                    "Source note not found: {}",
+                    source_note_id
+                )));
+            }
+            if unit.store().get_note(&target_note_id, false)?.is_none() {
+                return Err(Error::NotFound(format!(
+                    "Target note not found: {}",
+                    target_note_id
+                )));
+            }
 
-        let uuid = uuid::Uuid::new_v4();
-        let link_id = format!("link-{}", uuid);
-        let link = Link::new_note_link(link_id.clone(), source_note_id, target_note_id, link_text);
+            let uuid = uuid::Uuid::new_v4();
+            let link_id = format!("link-{}", uuid);
+            let link =
+                Link::new_note_link(link_id.clone(), source_note_id, target_note_id, link_text);
 
-        LinkDao::create(ctx.conn(), &link)?;
+            unit.store().create_link(&link)?;
 
-        Ok(link)
+            Ok(link)
+        })
     }
 
     /// Create a block reference
-    pub fn create_block_reference(
-        ctx: &ServiceContext,
+    pub fn create_block_reference<B: StorageBackend>(
+        ctx: &ServiceContext<B>,
         source_block_id: String,
         target_block_id: String,
         source_note_id: String,
     ) -> Result<Link> {
-        // Validate blocks exist
-        if BlockDao::get_by_id(ctx.conn(), &source_block_id, false)?.is_none() {
-            return Err(Error::NotFound(format!(
-                "Source block not found: {}",
-                source_block_id
-            )));
-        }
-        if BlockDao::get_by_id(ctx.conn(), &target_block_id, false)?.is_none() {
-            return Err(Error::NotFound(format!(
-                "Target block not found: {}",
-                target_block_id
-            )));
-        }
+        ctx.transaction(|unit| {
+            // Validate blocks exist
+            if unit.store().get_block(&source_block_id, false)?.is_none() {
+                return Err(Error::NotFound(format!(
+                    "Source block not found: {}",
+                    source_block_id
+                )));
+            }
+            if unit.store().get_block(&target_block_id, false)?.is_none() {
+                return Err(Error::NotFound(format!(
+                    "Target block not found: {}",
+                    target_block_id
+                )));
+            }
 
-        let uuid = uuid::Uuid::new_v4();
-        let link_id = format!("link-{}", uuid);
-        let link = Link::new_block_reference(
-            link_id.clone(),
-            source_block_id,
-            target_block_id,
-            source_note_id,
-        );
+            let uuid = uuid::Uuid::new_v4();
+            let link_id = format!("link-{}", uuid);
+            let link = Link::new_block_reference(
+                link_id.clone(),
+                source_block_id,
+                target_block_id,
+                source_note_id,
+            );
 
-        LinkDao::create(ctx.conn(), &link)?;
+            unit.store().create_link(&link)?;
 
-        Ok(link)
+            Ok(link)
+        })
     }
 
     /// Get a link by ID
-    pub fn get_by_id(ctx: &ServiceContext, id: &str) -> Result<Option<Link>> {
-        LinkDao::get_by_id(ctx.conn(), id)
+    pub fn get_by_id<B: StorageBackend>(ctx: &ServiceContext<B>, id: &str) -> Result<Option<Link>> {
+        let store = ctx.store()?;
+        store.get_link(id)
     }
 
     /// Get outgoing links from a note
-    pub fn get_outgoing_links(ctx: &ServiceContext, note_id: &str) -> Result<Vec<Link>> {
-        LinkDao::get_outgoing_links(ctx.conn(), note_id)
+    pub fn get_outgoing_links<B: StorageBackend>(
+        ctx: &ServiceContext<B>,
+        note_id: &str,
+    ) -> Result<Vec<Link>> {
+        let store = ctx.store()?;
+        store.outgoing_links(note_id)
     }
 
     /// Get incoming links to a note
-    pub fn get_incoming_links(ctx: &ServiceContext, note_id: &str) -> Result<Vec<Link>> {
-        LinkDao::get_incoming_links(ctx.conn(), note_id)
+    pub fn get_incoming_links<B: StorageBackend>(
+        ctx: &ServiceContext<B>,
+        note_id: &str,
+    ) -> Result<Vec<Link>> {
+        let store = ctx.store()?;
+        store.incoming_links(note_id)
+    }
+
+    /// Get the notes linking to a note (its backlinks)
+    pub fn get_backlinks<B: StorageBackend>(
+        ctx: &ServiceContext<B>,
+        note_id: &str,
+    ) -> Result<Vec<Note>> {
+        let store = ctx.store()?;
+        store.notes_linking_to(note_id, false)
     }
 
     /// Get links from a block
-    pub fn get_links_from_block(ctx: &ServiceContext, block_id: &str) -> Result<Vec<Link>> {
-        LinkDao::get_links_from_block(ctx.conn(), block_id)
+    pub fn get_links_from_block<B: StorageBackend>(
+        ctx: &ServiceContext<B>,
+        block_id: &str,
+    ) -> Result<Vec<Link>> {
+        let store = ctx.store()?;
+        store.links_from_block(block_id)
     }
 
     /// Get links to a block
-    pub fn get_links_to_block(ctx: &ServiceContext, block_id: &str) -> Result<Vec<Link>> {
-        LinkDao::get_links_to_block(ctx.conn(), block_id)
+    pub fn get_links_to_block<B: StorageBackend>(
+        ctx: &ServiceContext<B>,
+        block_id: &str,
+    ) -> Result<Vec<Link>> {
+        let store = ctx.store()?;
+        store.links_to_block(block_id)
     }
 
     /// Delete a link
-    pub fn delete(ctx: &ServiceContext, id: &str) -> Result<()> {
-        LinkDao::delete(ctx.conn(), id)?;
+    pub fn delete<B: StorageBackend>(ctx: &ServiceContext<B>, id: &str) -> Result<()> {
+        ctx.transaction(|unit| unit.store().delete_link(id))?;
         Ok(())
     }
 }
@@ -504,95 +1162,84 @@ impl LinkService {
 pub struct SearchService;
 
 impl SearchService {
-    /// Search notes by full-text (using FTS5)
-    pub fn search_notes(
-        ctx: &ServiceContext,
+    /// Quick-switcher search over note titles, aliases and folder paths.
+    ///
+    /// Matches are fuzzy subsequences ranked by word starts, consecutive runs and recency;
+    /// see [`crate::fuzzy`]. The index is kept in memory and rebuilt after notes change.
+    pub fn fuzzy_titles<B: StorageBackend>(
+        ctx: &ServiceContext<B>,
         query: &str,
-        include_deleted: bool,
-    ) -> Result<Vec<Note>> {
-        let conn = ctx.conn();
-
-        // Build FTS query - FTS5 uses rowid to join with source table
-        let mut sql = r#"
-            SELECT DISTINCT n.id, n.title, n.content_path, n.created_at, n.updated_at, n.word_count, n.is_deleted, n.deleted_at
-            FROM notes_fts fts
-            INNER JOIN notes n ON n.rowid = fts.rowid
-            WHERE notes_fts MATCH ?1
-        "#
-        .to_string();
-
-        if !include_deleted {
-            sql.push_str(" AND n.is_deleted = 0");
+        limit: usize,
+    ) -> Result<Vec<FuzzyMatch>> {
+        let now = chrono::Utc::now().timestamp();
+        let index = ctx.title_index.read().unwrap_or_else(|e| e.into_inner());
+        if let Some(index) = index.as_ref() {
+            return Ok(index.search(query, limit, now));
         }
+        drop(index);
 
-        sql.push_str(" ORDER BY n.updated_at DESC");
-
-        let mut stmt = conn.prepare(&sql)?;
-        let rows = stmt.query_map(params![query], |row| {
-            Ok(Note {
-                id: row.get(0)?,
-                title: row.get(1)?,
-                content_path: row.get(2)?,
-                created_at: row.get(3)?,
-                updated_at: row.get(4)?,
-                word_count: row.get(5)?,
-                is_deleted: row.get::<_, i32>(6)? != 0,
-                deleted_at: row.get(7)?,
-            })
-        })?;
+        // Build under the write lock, so an invalidation waits for the build to be stored
+        let mut index = ctx.title_index.write().unwrap_or_else(|e| e.into_inner());
+        if index.is_none() {
+            *index = Some(Self::build_title_index(ctx)?);
+        }
+        Ok(index
+            .as_ref()
+            .map(|index| index.search(query, limit, now))
+            .unwrap_or_default())
+    }
+
+    fn build_title_index<B: StorageBackend>(ctx: &ServiceContext<B>) -> Result<FuzzyIndex> {
+        let store = ctx.store()?;
+        let mut aliases: HashMap<String, Vec<String>> = HashMap::new();
+        for (note_id, alias) in store.all_note_aliases()? {
+            aliases.entry(note_id).or_default().push(alias);
+        }
+        let mut paths: HashMap<String, Vec<String>> = HashMap::new();
+        for (note_id, path) in store.all_note_folder_paths()? {
+            paths.entry(note_id).or_default().push(path);
+        }
 
-        let mut notes = Vec::new();
-        for row in rows {
-            notes.push(row?);
+        let mut index = FuzzyIndex::new();
+        for note in store.list_notes(false)? {
+            let note_aliases = aliases.get(&note.id).map(Vec::as_slice).unwrap_or_default();
+            let note_paths = paths.get(&note.id).map(Vec::as_slice).unwrap_or_default();
+            index.insert(&note, note_aliases, note_paths);
         }
+        Ok(index)
+    }
+
+    /// Reindex the content of every note from the content store, which completes a pending
+    /// reindex after an upgrade
+    pub fn reindex_notes<B: StorageBackend>(ctx: &ServiceContext<B>) -> Result<()> {
+        ctx.transaction(|unit| {
+            for note in unit.store().list_notes(true)? {
+                if let Some(content) = ctx.content().read(unit.store(), &note.content_path)? {
+                    unit.store().index_note_content(&note.id, &content)?;
+                }
+            }
+            unit.store().delete_setting(REINDEX_PENDING)
+        })
+    }
 
-        Ok(notes)
+    /// Search notes by full-text (using FTS5)
+    pub fn search_notes<B: StorageBackend>(
+        ctx: &ServiceContext<B>,
+        query: &str,
+        include_deleted: bool,
+    ) -> Result<Vec<Note>> {
+        let store = ctx.store()?;
+        store.search_note_content(query, include_deleted)
     }
 
     /// Search blocks by full-text (using FTS5)
-    pub fn search_blocks(
-        ctx: &ServiceContext,
+    pub fn search_blocks<B: StorageBackend>(
+        ctx: &ServiceContext<B>,
         query: &str,
         include_deleted: bool,
     ) -> Result<Vec<Block>> {
-        let conn = ctx.conn();
-
-        // Build FTS query - FTS5 uses rowid to join with source table
-        let mut sql = r#"
-            SELECT DISTINCT b.id, b.note_id, b.block_type, b.content, b.position, b.created_at, b.updated_at, b.is_deleted, b.deleted_at
-            FROM blocks_fts fts
-            INNER JOIN blocks b ON b.rowid = fts.rowid
-            WHERE blocks_fts MATCH ?1
-        "#
-        .to_string();
-
-        if !include_deleted {
-            sql.push_str(" AND b.is_deleted = 0");
-        }
-
-        sql.push_str(" ORDER BY b.position");
-
-        let mut stmt = conn.prepare(&sql)?;
-        let rows = stmt.query_map(params![query], |row| {
-            Ok(Block {
-                id: row.get(0)?,
-                note_id: row.get(1)?,
-                block_type: row.get(2)?,
-                content: row.get(3)?,
-                position: row.get(4)?,
-                created_at: row.get(5)?,
-                updated_at: row.get(6)?,
-                is_deleted: row.get::<_, i32>(7)? != 0,
-                deleted_at: row.get(8)?,
-            })
-        })?;
-
-        let mut blocks = Vec::new();
-        for row in rows {
-            blocks.push(row?);
-        }
-
-        Ok(blocks)
+        let store = ctx.store()?;
+        store.search_block_content(query, include_deleted)
     }
 }
 
@@ -601,157 +1248,217 @@ pub struct BlockService;
 
 impl BlockService {
     /// Create a new block
-    pub fn create(
-        ctx: &ServiceContext,
+    pub fn create<B: StorageBackend>(
+        ctx: &ServiceContext<B>,
         note_id: String,
         block_type: String,
         content: String,
         position: i64,
     ) -> Result<Block> {
-        // Validate note exists
-        if NoteDao::get_by_id(ctx.conn(), &note_id, false)?.is_none() {
-            return Err(Error::NotFound(format!("Note not found: {}", note_id)));
-        }
+        ctx.transaction(|unit| {
+            // Validate note exists
+            if unit.store().get_note(&note_id, false)?.is_none() {
+                return Err(Error::NotFound(format!("Note not found: {}", note_id)));
+            }
 
-        let uuid = uuid::Uuid::new_v4();
-        let block_id = format!("block-{}", uuid);
-        let block = Block::new(block_id.clone(), note_id, block_type, content, position);
+            let uuid = uuid::Uuid::new_v4();
+            let block_id = format!("block-{}", uuid);
+            let block = Block::new(block_id.clone(), note_id, block_type, content, position);
 
-        BlockDao::create(ctx.conn(), &block)?;
+            unit.store().create_block(&block)?;
 
-        Ok(block)
+            Ok(block)
+        })
     }
 
     /// Get a block by ID
-    pub fn get_by_id(
-        ctx: &ServiceContext,
+    pub fn get_by_id<B: StorageBackend>(
+        ctx: &ServiceContext<B>,
         id: &str,
         include_deleted: bool,
     ) -> Result<Option<Block>> {
-        BlockDao::get_by_id(ctx.conn(), id, include_deleted)
+        let store = ctx.store()?;
+        store.get_block(id, include_deleted)
     }
 
     /// Get all blocks for a note
-    pub fn get_by_note(
-        ctx: &ServiceContext,
+    pub fn get_by_note<B: StorageBackend>(
+        ctx: &ServiceContext<B>,
         note_id: &str,
         include_deleted: bool,
     ) -> Result<Vec<Block>> {
-        BlockDao::get_by_note(ctx.conn(), note_id, include_deleted)
+        let store = ctx.store()?;
+        store.blocks_for_note(note_id, include_deleted)
     }
 
     /// Update a block
-    pub fn update(ctx: &ServiceContext, block: &Block) -> Result<()> {
-        BlockDao::update(ctx.conn(), block)?;
+    pub fn update<B: StorageBackend>(ctx: &ServiceContext<B>, block: &Block) -> Result<()> {
+        ctx.transaction(|unit| unit.store().update_block(block))?;
         Ok(())
     }
 
-    /// Update block content
-    pub fn update_content(ctx: &ServiceContext, id: &str, content: String) -> Result<()> {
-        let mut block = BlockDao::get_by_id(ctx.conn(), id, false)?
-            .ok_or_else(|| Error::NotFound(format!("Block not found: {}", id)))?;
+    /// Persist a block diff in one transaction: insert new blocks, update changed or moved
+    /// ones and soft delete removed ones. Footnote references of the notes it touches are
+    /// stored as block references.
+    pub fn apply_diff<B: StorageBackend>(ctx: &ServiceContext<B>, diff: &BlockDiff) -> Result<()> {
+        ctx.transaction(|unit| {
+            let mut note_ids: Vec<NoteId> = Vec::new();
+            for block in diff.inserted.iter().chain(&diff.updated) {
+                if !note_ids.contains(&block.note_id) {
+                    note_ids.push(block.note_id.clone());
+                }
+            }
+            for id in &diff.removed {
+                if let Some(block) = unit.store().get_block(id, true)?
+                    && !note_ids.contains(&block.note_id)
+                {
+                    note_ids.push(block.note_id);
+                }
+            }
+
+            for block in &diff.inserted {
+                unit.store().create_block(block)?;
+            }
+            for block in &diff.updated {
+                unit.store().update_block(block)?;
+            }
+            for id in &diff.removed {
+                unit.store().soft_delete_block(id)?;
+            }
+            for note_id in &note_ids {
+                Self::sync_footnote_references(unit.store(), note_id)?;
+            }
+            Ok(())
+        })
+    }
 
-        block.update_content(content);
-        BlockDao::update(ctx.conn(), &block)?;
+    /// Helper: Reference each footnote a block cites, and drop references to footnotes it no
+    /// longer cites or that were deleted
+    fn sync_footnote_references<S: Storage>(store: &S, note_id: &str) -> Result<()> {
+        let blocks = store.blocks_for_note(note_id, false)?;
+        let wanted = footnote_links(&blocks);
+        let current = store.block_references_in_note(note_id, FOOTNOTE_BLOCK_TYPE)?;
 
+        for (source, target) in current.iter().filter(|link| !wanted.contains(link)) {
+            store.delete_block_reference(source, target)?;
+        }
+        for (source, target) in wanted.iter().filter(|link| !current.contains(link)) {
+            let ref_id = format!("ref-{}", uuid::Uuid::new_v4());
+            store.create_block_reference(&ref_id, source, target)?;
+        }
         Ok(())
     }
 
+    /// Update block content
+    pub fn update_content<B: StorageBackend>(
+        ctx: &ServiceContext<B>,
+        id: &str,
+        content: String,
+    ) -> Result<()> {
+        ctx.transaction(|unit| {
+            let mut block = unit
+                .store()
+                .get_block(id, false)?
+                .ok_or_else(|| Error::NotFound(format!("Block not found: {}", id)))?;
+
+            block.update_content(content);
+            unit.store().update_block(&block)?;
+
+            Ok(())
+        })
+    }
+
     /// Update block position
-    pub fn update_position(ctx: &ServiceContext, id: &str, position: i64) -> Result<()> {
-        let mut block = BlockDao::get_by_id(ctx.conn(), id, false)?
-            .ok_or_else(|| Error::NotFound(format!("Block not found: {}", id)))?;
+    pub fn update_position<B: StorageBackend>(
+        ctx: &ServiceContext<B>,
+        id: &str,
+        position: i64,
+    ) -> Result<()> {
+        ctx.transaction(|unit| {
+            let mut block = unit
+                .store()
+                .get_block(id, false)?
+                .ok_or_else(|| Error::NotFound(format!("Block not found: {}", id)))?;
 
-        block.position = position;
-        block.updated_at = chrono::Utc::now().timestamp();
-        BlockDao::update(ctx.conn(), &block)?;
+            block.position = position;
+            block.updated_at = chrono::Utc::now().timestamp();
+            unit.store().update_block(&block)?;
 
-        Ok(())
+            Ok(())
+        })
     }
 
     /// Soft delete a block
-    pub fn delete(ctx: &ServiceContext, id: &str) -> Result<()> {
-        BlockDao::soft_delete(ctx.conn(), id)?;
+    pub fn delete<B: StorageBackend>(ctx: &ServiceContext<B>, id: &str) -> Result<()> {
+        ctx.transaction(|unit| unit.store().soft_delete_block(id))?;
         Ok(())
     }
 
     /// Restore a soft-deleted block
-    pub fn restore(ctx: &ServiceContext, id: &str) -> Result<()> {
-        BlockDao::restore(ctx.conn(), id)?;
+    pub fn restore<B: StorageBackend>(ctx: &ServiceContext<B>, id: &str) -> Result<()> {
+        ctx.transaction(|unit| unit.store().restore_block(id))?;
         Ok(())
     }
 
     /// Get blocks that reference a block
-    pub fn get_referencing_blocks(ctx: &ServiceContext, block_id: &str) -> Result<Vec<Block>> {
-        use crate::storage::BlockReferenceDao;
-
-        let referencing_ids = BlockReferenceDao::get_referencing_blocks(ctx.conn(), block_id)?;
-        let mut blocks = Vec::new();
-
-        for id in referencing_ids {
-            if let Some(block) = BlockDao::get_by_id(ctx.conn(), &id, false)? {
-                blocks.push(block);
-            }
-        }
-
-        Ok(blocks)
+    pub fn get_referencing_blocks<B: StorageBackend>(
+        ctx: &ServiceContext<B>,
+        block_id: &str,
+    ) -> Result<Vec<Block>> {
+        let store = ctx.store()?;
+        store.blocks_referencing(block_id, false)
     }
 
     /// Get blocks referenced by a block
-    pub fn get_referenced_blocks(ctx: &ServiceContext, block_id: &str) -> Result<Vec<Block>> {
-        use crate::storage::BlockReferenceDao;
-
-        let referenced_ids = BlockReferenceDao::get_referenced_blocks(ctx.conn(), block_id)?;
-        let mut blocks = Vec::new();
-
-        for id in referenced_ids {
-            if let Some(block) = BlockDao::get_by_id(ctx.conn(), &id, false)? {
-                blocks.push(block);
-            }
-        }
-
-        Ok(blocks)
+    pub fn get_referenced_blocks<B: StorageBackend>(
+        ctx: &ServiceContext<B>,
+        block_id: &str,
+    ) -> Result<Vec<Block>> {
+        let store = ctx.store()?;
+        store.blocks_referenced_by(block_id, false)
     }
 
     /// Create a block reference
-    pub fn create_reference(
-        ctx: &ServiceContext,
+    pub fn create_reference<B: StorageBackend>(
+        ctx: &ServiceContext<B>,
         source_block_id: String,
         target_block_id: String,
     ) -> Result<()> {
-        use crate::storage::BlockReferenceDao;
-
-        // Validate blocks exist
-        if BlockDao::get_by_id(ctx.conn(), &source_block_id, false)?.is_none() {
-            return Err(Error::NotFound(format!(
-                "Source block not found: {}",
-                source_block_id
-            )));
-        }
-        if BlockDao::get_by_id(ctx.conn(), &target_block_id, false)?.is_none() {
-            return Err(Error::NotFound(format!(
-                "Target block not found: {}",
-                target_block_id
-            )));
-        }
+        ctx.transaction(|unit| {
+            // Validate blocks exist
+            if unit.store().get_block(&source_block_id, false)?.is_none() {
+                return Err(Error::NotFound(format!(
+                    "Source block not found: {}",
+                    source_block_id
+                )));
+            }
+            if unit.store().get_block(&target_block_id, false)?.is_none() {
+                return Err(Error::NotFound(format!(
+                    "Target block not found: {}",
+                    target_block_id
+                )));
+            }
 
-        let uuid = uuid::Uuid::new_v4();
-        let ref_id = format!("ref-{}", uuid);
+            let uuid = uuid::Uuid::new_v4();
+            let ref_id = format!("ref-{}", uuid);
 
-        BlockReferenceDao::create(ctx.conn(), &ref_id, &source_block_id, &target_block_id)?;
+            unit.store()
+                .create_block_reference(&ref_id, &source_block_id, &target_block_id)?;
 
-        Ok(())
+            Ok(())
+        })
     }
 
     /// Delete a block reference
-    pub fn delete_reference(
-        ctx: &ServiceContext,
+    pub fn delete_reference<B: StorageBackend>(
+        ctx: &ServiceContext<B>,
         source_block_id: String,
         target_block_id: String,
     ) -> Result<()> {
-        use crate::storage::BlockReferenceDao;
-
-        BlockReferenceDao::delete(ctx.conn(), &source_block_id, &target_block_id)?;
+        ctx.transaction(|unit| {
+            unit.store()
+                .delete_block_reference(&source_block_id, &target_block_id)
+        })?;
         Ok(())
     }
 }
@@ -761,8 +1468,8 @@ pub struct AttachmentService;
 
 impl AttachmentService {
     /// Upload an attachment from a file path
-    pub fn upload_from_path(
-        ctx: &ServiceContext,
+    pub fn upload_from_path<B: StorageBackend>(
+        ctx: &ServiceContext<B>,
         file_path: &Path,
         original_name: Option<String>,
     ) -> Result<Attachment> {
@@ -784,8 +1491,8 @@ impl AttachmentService {
     }
 
     /// Upload an attachment from bytes
-    pub fn upload_from_bytes(
-        ctx: &ServiceContext,
+    pub fn upload_from_bytes<B: StorageBackend>(
+        ctx: &ServiceContext<B>,
         content: &[u8],
         file_name: &str,
     ) -> Result<Attachment> {
@@ -794,8 +1501,10 @@ impl AttachmentService {
         hasher.update(content);
         let hash = format!("{:x}", hasher.finalize());
 
+        let mut unit = ctx.begin()?;
+
         // Check if attachment with same hash already exists (deduplication)
-        if let Some(existing) = AttachmentDao::get_by_hash(ctx.conn(), &hash)? {
+        if let Some(existing) = unit.store().get_attachment_by_hash(&hash)? {
             // Return existing attachment (deduplication)
             return Ok(existing);
         }
@@ -816,17 +1525,17 @@ impl AttachmentService {
         let uuid = uuid::Uuid::new_v4();
         let attachment_id = format!("attachment-{}", uuid);
         let file_path = format!("attachments/{}.{}", uuid, ext);
-        let full_path = ctx.data_dir().join(&file_path);
+        let full_path = Self::data_dir(ctx)?.join(&file_path);
 
-        // Write file to disk
-        fs::write(&full_path, content)?;
+        // Stage the file; it is moved into place when the row commits
+        unit.write_file(&full_path, content)?;
 
         // Get file size
         let file_size = content.len() as i64;
 
         // For images, try to detect dimensions (optional, can be enhanced later)
         let (width, height) = if file_type == "image" {
-            Self::detect_image_dimensions(&content).unwrap_or((None, None))
+            Self::detect_image_dimensions(content).unwrap_or((None, None))
         } else {
             (None, None)
         };
@@ -850,151 +1559,171 @@ impl AttachmentService {
         }
 
         // Save to database
-        AttachmentDao::create(ctx.conn(), &attachment)?;
+        unit.store().create_attachment(&attachment)?;
+        unit.commit()?;
 
         Ok(attachment)
     }
 
     /// Get an attachment by ID
-    pub fn get_by_id(ctx: &ServiceContext, id: &str) -> Result<Option<Attachment>> {
-        AttachmentDao::get_by_id(ctx.conn(), id)
+    pub fn get_by_id<B: StorageBackend>(
+        ctx: &ServiceContext<B>,
+        id: &str,
+    ) -> Result<Option<Attachment>> {
+        let store = ctx.store()?;
+        store.get_attachment(id)
     }
 
     /// Get an attachment by hash (for deduplication check)
-    pub fn get_by_hash(ctx: &ServiceContext, hash: &str) -> Result<Option<Attachment>> {
-        AttachmentDao::get_by_hash(ctx.conn(), hash)
+    pub fn get_by_hash<B: StorageBackend>(
+        ctx: &ServiceContext<B>,
+        hash: &str,
+    ) -> Result<Option<Attachment>> {
+        let store = ctx.store()?;
+        store.get_attachment_by_hash(hash)
     }
 
     /// Get attachment file path
-    pub fn get_file_path(ctx: &ServiceContext, attachment: &Attachment) -> PathBuf {
-        ctx.data_dir().join(&attachment.file_path)
+    pub fn get_file_path<B: StorageBackend>(
+        ctx: &ServiceContext<B>,
+        attachment: &Attachment,
+    ) -> Result<PathBuf> {
+        Ok(Self::data_dir(ctx)?.join(&attachment.file_path))
     }
 
     /// Read attachment file content
-    pub fn read_file(ctx: &ServiceContext, attachment: &Attachment) -> Result<Vec<u8>> {
-        let file_path = Self::get_file_path(ctx, attachment);
+    pub fn read_file<B: StorageBackend>(
+        ctx: &ServiceContext<B>,
+        attachment: &Attachment,
+    ) -> Result<Vec<u8>> {
+        let file_path = Self::get_file_path(ctx, attachment)?;
         Ok(fs::read(file_path)?)
     }
 
-    /// Delete an attachment (and its file)
-    pub fn delete(ctx: &ServiceContext, id: &str) -> Result<()> {
-        // Get attachment to find file path
-        if let Some(attachment) = AttachmentDao::get_by_id(ctx.conn(), id)? {
-            let file_path = Self::get_file_path(ctx, &attachment);
+    /// Delete an attachment (and its file, once the row is gone)
+    pub fn delete<B: StorageBackend>(ctx: &ServiceContext<B>, id: &str) -> Result<()> {
+        ctx.transaction(|unit| {
+            // Get attachment to find file path
+            if let Some(attachment) = unit.store().get_attachment(id)? {
+                let file_path = Self::get_file_path(ctx, &attachment)?;
 
-            // Check if other attachments have the same hash (deduplication)
-            // Count how many attachments share this hash
-            let mut stmt = ctx
-                .conn()
-                .prepare("SELECT COUNT(*) FROM attachments WHERE hash = ?1")?;
-            let count: i64 = stmt.query_row(params![attachment.hash], |row| row.get(0))?;
+                // Check if other attachments have the same hash (deduplication)
+                // Count how many attachments share this hash
+                let count = unit.store().count_attachments_with_hash(&attachment.hash)?;
 
-            // Only delete file if this is the only attachment with this hash
-            // (meaning no other attachments reference the same file)
-            if count <= 1 && file_path.exists() {
-                let _ = fs::remove_file(file_path); // Ignore errors if file already deleted
+                // Only delete file if this is the only attachment with this hash
+                // (meaning no other attachments reference the same file)
+                if count <= 1 {
+                    unit.remove_file(file_path);
+                }
             }
-        }
 
-        // Delete from database
-        AttachmentDao::delete(ctx.conn(), id)?;
-        Ok(())
+            // Delete from database
+            unit.store().delete_attachment(id)
+        })
     }
 
     /// Add attachment to a note
-    pub fn add_to_note(
-        ctx: &ServiceContext,
+    pub fn add_to_note<B: StorageBackend>(
+        ctx: &ServiceContext<B>,
         note_id: &str,
         attachment_id: &str,
         position: i64,
     ) -> Result<()> {
-        // Validate note and attachment exist
-        if NoteDao::get_by_id(ctx.conn(), note_id, false)?.is_none() {
-            return Err(Error::NotFound(format!("Note not found: {}", note_id)));
-        }
-        if AttachmentDao::get_by_id(ctx.conn(), attachment_id)?.is_none() {
-            return Err(Error::NotFound(format!(
-                "Attachment not found: {}",
-                attachment_id
-            )));
-        }
+        ctx.transaction(|unit| {
+            // Validate note and attachment exist
+            if unit.store().get_note(note_id, false)?.is_none() {
+                return Err(Error::NotFound(format!("Note not found: {}", note_id)));
+            }
+            if unit.store().get_attachment(attachment_id)?.is_none() {
+                return Err(Error::NotFound(format!(
+                    "Attachment not found: {}",
+                    attachment_id
+                )));
+            }
 
-        NoteAttachmentDao::add(ctx.conn(), note_id, attachment_id, position)?;
-        Ok(())
+            unit.store()
+                .add_note_attachment(note_id, attachment_id, position)?;
+            Ok(())
+        })
     }
 
     /// Remove attachment from a note
-    pub fn remove_from_note(
-        ctx: &ServiceContext,
+    pub fn remove_from_note<B: StorageBackend>(
+        ctx: &ServiceContext<B>,
         note_id: &str,
         attachment_id: &str,
     ) -> Result<()> {
-        NoteAttachmentDao::remove(ctx.conn(), note_id, attachment_id)?;
+        ctx.transaction(|unit| unit.store().remove_note_attachment(note_id, attachment_id))?;
         Ok(())
     }
 
     /// Get all attachments for a note
-    pub fn get_for_note(ctx: &ServiceContext, note_id: &str) -> Result<Vec<Attachment>> {
-        let attachment_ids = NoteAttachmentDao::get_attachments_for_note(ctx.conn(), note_id)?;
-        let mut attachments = Vec::new();
-
-        for id in attachment_ids {
-            if let Some(attachment) = AttachmentDao::get_by_id(ctx.conn(), &id)? {
-                attachments.push(attachment);
-            }
-        }
-
-        Ok(attachments)
+    pub fn get_for_note<B: StorageBackend>(
+        ctx: &ServiceContext<B>,
+        note_id: &str,
+    ) -> Result<Vec<Attachment>> {
+        let store = ctx.store()?;
+        store.attachments_for_note(note_id)
     }
 
     /// Add attachment to a block
-    pub fn add_to_block(ctx: &ServiceContext, block_id: &str, attachment_id: &str) -> Result<()> {
-        // Validate block and attachment exist
-        if BlockDao::get_by_id(ctx.conn(), block_id, false)?.is_none() {
-            return Err(Error::NotFound(format!("Block not found: {}", block_id)));
-        }
-        if AttachmentDao::get_by_id(ctx.conn(), attachment_id)?.is_none() {
-            return Err(Error::NotFound(format!(
-                "Attachment not found: {}",
-                attachment_id
-            )));
-        }
+    pub fn add_to_block<B: StorageBackend>(
+        ctx: &ServiceContext<B>,
+        block_id: &str,
+        attachment_id: &str,
+    ) -> Result<()> {
+        ctx.transaction(|unit| {
+            // Validate block and attachment exist
+            if unit.store().get_block(block_id, false)?.is_none() {
+                return Err(Error::NotFound(format!("Block not found: {}", block_id)));
+            }
+            if unit.store().get_attachment(attachment_id)?.is_none() {
+                return Err(Error::NotFound(format!(
+                    "Attachment not found: {}",
+                    attachment_id
+                )));
+            }
 
-        BlockAttachmentDao::add(ctx.conn(), block_id, attachment_id)?;
-        Ok(())
+            unit.store().add_block_attachment(block_id, attachment_id)?;
+            Ok(())
+        })
     }
 
     /// Remove attachment from a block
-    pub fn remove_from_block(
-        ctx: &ServiceContext,
+    pub fn remove_from_block<B: StorageBackend>(
+        ctx: &ServiceContext<B>,
         block_id: &str,
         attachment_id: &str,
     ) -> Result<()> {
-        BlockAttachmentDao::remove(ctx.conn(), block_id, attachment_id)?;
+        ctx.transaction(|unit| {
+            unit.store()
+                .remove_block_attachment(block_id, attachment_id)
+        })?;
         Ok(())
     }
 
     /// Get all attachments for a block
-    pub fn get_for_block(ctx: &ServiceContext, block_id: &str) -> Result<Vec<Attachment>> {
-        let attachment_ids = BlockAttachmentDao::get_attachments_for_block(ctx.conn(), block_id)?;
-        let mut attachments = Vec::new();
-
-        for id in attachment_ids {
-            if let Some(attachment) = AttachmentDao::get_by_id(ctx.conn(), &id)? {
-                attachments.push(attachment);
-            }
-        }
+    pub fn get_for_block<B: StorageBackend>(
+        ctx: &ServiceContext<B>,
+        block_id: &str,
+    ) -> Result<Vec<Attachment>> {
+        let store = ctx.store()?;
+        store.attachments_for_block(block_id)
+    }
 
-        Ok(attachments)
+    /// Helper: Data directory attachment files live in
+    fn data_dir<B: StorageBackend>(ctx: &ServiceContext<B>) -> Result<&Path> {
+        ctx.data_dir().ok_or_else(|| {
+            Error::InvalidInput("Attachments need a context with a data directory".to_string())
+        })
     }
 
     /// Determine file type from MIME type
     fn determine_file_type(mime_type: &str) -> String {
         if mime_type.starts_with("image/") {
             "image".to_string()
-        } else if mime_type.starts_with("video/") {
-            "media".to_string()
-        } else if mime_type.starts_with("audio/") {
+        } else if mime_type.starts_with("video/") || mime_type.starts_with("audio/") {
             "media".to_string()
         } else if mime_type == "application/pdf"
             || mime_type.starts_with("application/msword")
@@ -1017,5 +1746,3 @@ impl AttachmentService {
         None
     }
 }
-
-use rusqlite::params;
@@ -0,0 +1,147 @@
+//! Text statistics: word and character counts and reading time.
+//!
+//! Scripts written without spaces between words (Chinese and Japanese) count one word per
+//! ideograph or kana; everything else counts whitespace-separated words that contain at least
+//! one letter or digit, so Markdown markers such as `#` or `-` are not words. Hangul is
+//! written with spaces and is counted by words too.
+
+use std::ops::{Add, AddAssign};
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize};
+use unicode_segmentation::UnicodeSegmentation;
+
+use crate::frontmatter::split_front_matter;
+
+/// Reading speed for space-separated words
+pub const WORDS_PER_MINUTE: u64 = 200;
+
+/// Reading speed for Chinese and Japanese characters
+pub const CJK_CHARS_PER_MINUTE: u64 = 400;
+
+/// Counts for a piece of text
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TextStats {
+    /// Words, counting each Chinese or Japanese character as one word
+    pub words: usize,
+    /// Chinese and Japanese characters (included in `words`)
+    pub cjk_chars: usize,
+    /// Characters (grapheme clusters), whitespace excluded
+    pub chars: usize,
+    /// Characters (grapheme clusters), whitespace included
+    pub chars_with_spaces: usize,
+}
+
+impl TextStats {
+    pub fn of(text: &str) -> Self {
+        let mut stats = Self::default();
+        let mut in_word = false;
+        for c in text.chars() {
+            if is_cjk(c) {
+                stats.cjk_chars += 1;
+                stats.words += in_word as usize;
+                in_word = false;
+            } else if c.is_whitespace() {
+                stats.words += in_word as usize;
+                in_word = false;
+            } else if c.is_alphanumeric() {
+                in_word = true;
+            }
+        }
+        stats.words += in_word as usize + stats.cjk_chars;
+
+        for grapheme in text.graphemes(true) {
+            stats.chars_with_spaces += 1;
+            if !grapheme.chars().all(char::is_whitespace) {
+                stats.chars += 1;
+            }
+        }
+        stats
+    }
+
+    /// Statistics of a note's body, front matter excluded
+    pub fn of_note(content: &str) -> Self {
+        Self::of(split_front_matter(content).1)
+    }
+
+    /// Estimated reading time, rounded up to whole seconds
+    pub fn reading_time(&self) -> Duration {
+        let words = (self.words - self.cjk_chars) as u64;
+        let cjk = self.cjk_chars as u64;
+        // Milliseconds per word and per character, summed before rounding
+        let millis = words * 60_000 / WORDS_PER_MINUTE + cjk * 60_000 / CJK_CHARS_PER_MINUTE;
+        Duration::from_secs(millis.div_ceil(1000))
+    }
+}
+
+impl Add for TextStats {
+    type Output = Self;
+
+    fn add(self, other: Self) -> Self {
+        Self {
+            words: self.words + other.words,
+            cjk_chars: self.cjk_chars + other.cjk_chars,
+            chars: self.chars + other.chars,
+            chars_with_spaces: self.chars_with_spaces + other.chars_with_spaces,
+        }
+    }
+}
+
+impl AddAssign for TextStats {
+    fn add_assign(&mut self, other: Self) {
+        *self = *self + other;
+    }
+}
+
+/// Han ideographs, hiragana and katakana
+pub(crate) fn is_cjk(c: char) -> bool {
+    matches!(c,
+        '\u{3040}'..='\u{30FF}'      // Hiragana, Katakana
+        | '\u{31F0}'..='\u{31FF}'    // Katakana phonetic extensions
+        | '\u{3400}'..='\u{4DBF}'    // CJK Extension A
+        | '\u{4E00}'..='\u{9FFF}'    // CJK Unified Ideographs
+        | '\u{F900}'..='\u{FAFF}'    // CJK Compatibility Ideographs
+        | '\u{FF66}'..='\u{FF9D}'    // Halfwidth katakana
+        | '\u{20000}'..='\u{3134F}'  // Extensions B to G
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_counts_words_and_cjk_characters() {
+        let stats = TextStats::of("# Title\n\n- Hello, world! 42");
+        assert_eq!(stats.words, 4);
+        assert_eq!(stats.cjk_chars, 0);
+
+        // Chinese punctuation is not a word
+        let stats = TextStats::of("我们今天学习Rust，很有趣。");
+        assert_eq!(stats.words, 10);
+        assert_eq!(stats.cjk_chars, 9);
+        let stats = TextStats::of("ひらがなとカタカナ and 한국어 단어");
+        assert_eq!(stats.words, 9 + 1 + 2);
+
+        // Characters are grapheme clusters
+        let stats = TextStats::of("a 👨\u{200d}👩\u{200d}👧\ne\u{301}");
+        assert_eq!(stats.chars, 3);
+        assert_eq!(stats.chars_with_spaces, 5);
+    }
+
+    #[test]
+    fn test_note_stats_skip_front_matter() {
+        let stats = TextStats::of_note("---\ntitle: Long title here\n---\nTwo words");
+        assert_eq!(stats.words, 2);
+    }
+
+    #[test]
+    fn test_reading_time_and_sum() {
+        let latin = TextStats::of(&"word ".repeat(300));
+        assert_eq!(latin.reading_time(), Duration::from_secs(90));
+        let chinese = TextStats::of(&"字".repeat(200));
+        assert_eq!(chinese.reading_time(), Duration::from_secs(30));
+        assert_eq!((latin + chinese).reading_time(), Duration::from_secs(120));
+        assert_eq!(TextStats::default().reading_time(), Duration::ZERO);
+    }
+}
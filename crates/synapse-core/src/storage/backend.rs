@@ -1,30 +1,258 @@
-//! Storage backend trait for swappable persistence.
+//! Storage traits for swappable persistence.
 //!
-//! Defined for future use (e.g. mock in tests, alternative backends).
-//! Current code path uses DatabaseManager directly because rusqlite::Connection
-//! is !Send/!Sync; use from a single thread (e.g. Tauri main thread).
+//! Each entity has its own trait ([`NoteStorage`], [`BlockStorage`], ...) and [`Storage`]
+//! combines them. A [`StorageBackend`] hands out stores for reading and transactions for
+//! writing; services are generic over it. [`super::SqliteBackend`] implements the traits on
+//! a SQLite connection and [`super::MemoryBackend`] keeps everything in memory for tests.
 
-use rusqlite::Connection;
+use std::ops::Deref;
 
-use super::DatabaseManager;
+use crate::Result;
+use crate::models::*;
+use crate::query::Query;
 
-/// Storage backend abstraction. Implementations can be swapped (e.g. SQLite, mock).
-/// No Send/Sync bound so that SQLite backend can be used from a single thread.
-pub trait StorageBackend {
-    fn conn(&self) -> &Connection;
+/// Persistence for services: read access to a [`Storage`] and transactions to write to it
+pub trait StorageBackend: Send + Sync {
+    /// The store the entity traits are implemented on
+    type Store: Storage;
+    /// A store for reading committed data
+    type Reader<'a>: Deref<Target = Self::Store>
+    where
+        Self: 'a;
+    /// A store whose writes become visible when it commits
+    type Transaction<'a>: StorageTransaction + Deref<Target = Self::Store>
+    where
+        Self: 'a;
+
+    /// Borrow a store for reading; write through [`StorageBackend::begin`]
+    fn reader(&self) -> Result<Self::Reader<'_>>;
+
+    /// Begin a transaction. Only one is open at a time; others wait for it to finish.
+    fn begin(&self) -> Result<Self::Transaction<'_>>;
+}
+
+/// An open transaction; dropped without [`StorageTransaction::commit`], it rolls back
+pub trait StorageTransaction: Sized {
+    fn commit(self) -> Result<()>;
+}
+
+/// Every entity storage trait
+pub trait Storage:
+    NoteStorage
+    + BlockStorage
+    + TagStorage
+    + FolderStorage
+    + LinkStorage
+    + AttachmentStorage
+    + SavedSearchStorage
+    + BlobStorage
+    + SettingStorage
+{
 }
 
-/// SQLite-backed storage (default implementation).
-pub struct SqliteBackend(DatabaseManager);
+impl<T> Storage for T where
+    T: NoteStorage
+        + BlockStorage
+        + TagStorage
+        + FolderStorage
+        + LinkStorage
+        + AttachmentStorage
+        + SavedSearchStorage
+        + BlobStorage
+        + SettingStorage
+{
+}
+
+/// Notes, their indexed content, properties and aliases
+pub trait NoteStorage {
+    fn create_note(&self, note: &Note) -> Result<()>;
+    fn get_note(&self, id: &str, include_deleted: bool) -> Result<Option<Note>>;
+    fn update_note(&self, note: &Note) -> Result<()>;
+    fn soft_delete_note(&self, id: &str) -> Result<()>;
+    fn restore_note(&self, id: &str) -> Result<()>;
+    /// Most recently updated first
+    fn list_notes(&self, include_deleted: bool) -> Result<Vec<Note>>;
+    fn list_notes_page(&self, include_deleted: bool, request: &PageRequest) -> Result<Page<Note>>;
+    /// Notes whose title contains `query`, most recently updated first
+    fn search_notes_by_title(&self, query: &str, include_deleted: bool) -> Result<Vec<Note>>;
+    /// Notes matching a parsed query, in the given order
+    fn query_notes(
+        &self,
+        query: &Query,
+        sort: NoteSort,
+        include_deleted: bool,
+    ) -> Result<Vec<Note>>;
+    fn query_notes_page(
+        &self,
+        query: &Query,
+        include_deleted: bool,
+        request: &PageRequest,
+    ) -> Result<Page<Note>>;
+    /// Index the content of a note for [`NoteStorage::search_note_content`]
+    fn index_note_content(&self, note_id: &str, content: &str) -> Result<()>;
+    /// Full-text search over titles and indexed content, most recently updated first
+    fn search_note_content(&self, query: &str, include_deleted: bool) -> Result<Vec<Note>>;
+    fn replace_note_properties(
+        &self,
+        note_id: &str,
+        properties: &[(String, PropertyValue)],
+    ) -> Result<()>;
+    /// Properties in front matter order
+    fn note_properties(&self, note_id: &str) -> Result<Vec<NoteProperty>>;
+    /// Replace the aliases of a note; repeated aliases are stored once
+    fn replace_note_aliases(&self, note_id: &str, aliases: &[String]) -> Result<()>;
+    fn note_aliases(&self, note_id: &str) -> Result<Vec<String>>;
+    /// Every alias of every note, as `(note_id, alias)`
+    fn all_note_aliases(&self) -> Result<Vec<(NoteId, String)>>;
+}
+
+/// Blocks and references between them
+pub trait BlockStorage {
+    fn create_block(&self, block: &Block) -> Result<()>;
+    fn get_block(&self, id: &str, include_deleted: bool) -> Result<Option<Block>>;
+    /// Blocks of a note, by position
+    fn blocks_for_note(&self, note_id: &str, include_deleted: bool) -> Result<Vec<Block>>;
+    fn update_block(&self, block: &Block) -> Result<()>;
+    fn soft_delete_block(&self, id: &str) -> Result<()>;
+    fn restore_block(&self, id: &str) -> Result<()>;
+    /// Blocks that reference `block_id`, oldest reference first
+    fn blocks_referencing(&self, block_id: &str, include_deleted: bool) -> Result<Vec<Block>>;
+    /// Blocks `block_id` references, oldest reference first
+    fn blocks_referenced_by(&self, block_id: &str, include_deleted: bool) -> Result<Vec<Block>>;
+    fn create_block_reference(
+        &self,
+        id: &str,
+        source_block_id: &str,
+        target_block_id: &str,
+    ) -> Result<()>;
+    fn delete_block_reference(&self, source_block_id: &str, target_block_id: &str) -> Result<()>;
+    /// `(source, target)` block IDs of the references from live blocks of a note to blocks of
+    /// `target_type`, deleted or not
+    fn block_references_in_note(
+        &self,
+        note_id: &str,
+        target_type: &str,
+    ) -> Result<Vec<(BlockId, BlockId)>>;
+    /// Full-text search over block content, by position
+    fn search_block_content(&self, query: &str, include_deleted: bool) -> Result<Vec<Block>>;
+}
+
+/// Tags and the tags of notes
+pub trait TagStorage {
+    /// Fails if a tag with the same name exists
+    fn create_tag(&self, tag: &Tag) -> Result<()>;
+    fn get_tag(&self, id: &str) -> Result<Option<Tag>>;
+    fn get_tag_by_name(&self, name: &str) -> Result<Option<Tag>>;
+    /// All tags, by name
+    fn list_tags(&self) -> Result<Vec<Tag>>;
+    fn update_tag(&self, tag: &Tag) -> Result<()>;
+    /// Delete a tag and remove it from its notes
+    fn delete_tag(&self, id: &str) -> Result<()>;
+    /// Tags of a note, by name
+    fn tags_for_note(&self, note_id: &str) -> Result<Vec<Tag>>;
+    fn note_tag_ids(&self, note_id: &str) -> Result<Vec<TagId>>;
+    /// Fails if the note already has the tag
+    fn add_note_tag(&self, note_id: &str, tag_id: &str) -> Result<()>;
+    fn remove_note_tag(&self, note_id: &str, tag_id: &str) -> Result<()>;
+    /// Notes with a tag, most recently updated first
+    fn notes_with_tag(&self, tag_id: &str, include_deleted: bool) -> Result<Vec<Note>>;
+    fn notes_with_tag_page(
+        &self,
+        tag_id: &str,
+        include_deleted: bool,
+        request: &PageRequest,
+    ) -> Result<Page<Note>>;
+}
+
+/// Folders and the notes in them
+pub trait FolderStorage {
+    fn create_folder(&self, folder: &Folder) -> Result<()>;
+    fn get_folder(&self, id: &str) -> Result<Option<Folder>>;
+    /// Folders without a parent, by position
+    fn root_folders(&self) -> Result<Vec<Folder>>;
+    /// Children of a folder, by position
+    fn child_folders(&self, parent_id: &str) -> Result<Vec<Folder>>;
+    /// All folders, by position and then path
+    fn list_folders(&self) -> Result<Vec<Folder>>;
+    fn update_folder(&self, folder: &Folder) -> Result<()>;
+    /// Delete a folder with its subfolders and take their notes out of them
+    fn delete_folder(&self, id: &str) -> Result<()>;
+    /// Fails if the note is already in the folder
+    fn add_note_to_folder(
+        &self,
+        note_id: &str,
+        folder_id: &str,
+        is_primary: bool,
+        position: i64,
+    ) -> Result<()>;
+    fn remove_note_from_folder(&self, note_id: &str, folder_id: &str) -> Result<()>;
+    /// Notes in a folder, by position in the folder and then most recently updated
+    fn notes_in_folder(&self, folder_id: &str, include_deleted: bool) -> Result<Vec<Note>>;
+    /// One page of the notes in a folder; [`SortKey::Position`] is the folder's manual order
+    fn notes_in_folder_page(
+        &self,
+        folder_id: &str,
+        include_deleted: bool,
+        request: &PageRequest,
+    ) -> Result<Page<Note>>;
+    /// Folder path of every note-folder pair, as `(note_id, path)`, primary folder first
+    fn all_note_folder_paths(&self) -> Result<Vec<(NoteId, String)>>;
+}
+
+/// Links between notes and blocks
+pub trait LinkStorage {
+    fn create_link(&self, link: &Link) -> Result<()>;
+    fn get_link(&self, id: &str) -> Result<Option<Link>>;
+    fn outgoing_links(&self, note_id: &str) -> Result<Vec<Link>>;
+    fn incoming_links(&self, note_id: &str) -> Result<Vec<Link>>;
+    fn links_from_block(&self, block_id: &str) -> Result<Vec<Link>>;
+    fn links_to_block(&self, block_id: &str) -> Result<Vec<Link>>;
+    fn delete_link(&self, id: &str) -> Result<()>;
+    /// Notes linking to a note (its backlinks), most recently updated first
+    fn notes_linking_to(&self, note_id: &str, include_deleted: bool) -> Result<Vec<Note>>;
+}
+
+/// Attachments and where they are attached
+pub trait AttachmentStorage {
+    /// Fails if an attachment with the same hash exists
+    fn create_attachment(&self, attachment: &Attachment) -> Result<()>;
+    fn get_attachment(&self, id: &str) -> Result<Option<Attachment>>;
+    fn get_attachment_by_hash(&self, hash: &str) -> Result<Option<Attachment>>;
+    fn count_attachments_with_hash(&self, hash: &str) -> Result<i64>;
+    /// Delete an attachment and detach it from notes and blocks
+    fn delete_attachment(&self, id: &str) -> Result<()>;
+    /// Attachments of a note, by position
+    fn attachments_for_note(&self, note_id: &str) -> Result<Vec<Attachment>>;
+    /// Attachments of a block, in the order they were added
+    fn attachments_for_block(&self, block_id: &str) -> Result<Vec<Attachment>>;
+    fn add_note_attachment(&self, note_id: &str, attachment_id: &str, position: i64) -> Result<()>;
+    fn remove_note_attachment(&self, note_id: &str, attachment_id: &str) -> Result<()>;
+    fn add_block_attachment(&self, block_id: &str, attachment_id: &str) -> Result<()>;
+    fn remove_block_attachment(&self, block_id: &str, attachment_id: &str) -> Result<()>;
+}
+
+/// Saved searches
+pub trait SavedSearchStorage {
+    fn create_saved_search(&self, search: &SavedSearch) -> Result<()>;
+    fn get_saved_search(&self, id: &str) -> Result<Option<SavedSearch>>;
+    /// All saved searches, by position and then name
+    fn list_saved_searches(&self) -> Result<Vec<SavedSearch>>;
+    fn update_saved_search(&self, search: &SavedSearch) -> Result<()>;
+    fn delete_saved_search(&self, id: &str) -> Result<()>;
+}
 
-impl SqliteBackend {
-    pub fn new(db: DatabaseManager) -> Self {
-        Self(db)
-    }
+/// Content kept in the database by path; see [`crate::content::DatabaseContent`]
+pub trait BlobStorage {
+    fn get_blob(&self, path: &str) -> Result<Option<Vec<u8>>>;
+    /// Store `data` at `path`, replacing what is there
+    fn put_blob(&self, path: &str, data: &[u8]) -> Result<()>;
+    fn delete_blob(&self, path: &str) -> Result<()>;
 }
 
-impl StorageBackend for SqliteBackend {
-    fn conn(&self) -> &Connection {
-        self.0.conn()
-    }
+/// Vault-wide settings by key
+pub trait SettingStorage {
+    fn get_setting(&self, key: &str) -> Result<Option<String>>;
+    /// Store `value` at `key`, replacing what is there
+    fn set_setting(&self, key: &str, value: &str) -> Result<()>;
+    fn delete_setting(&self, key: &str) -> Result<()>;
 }
@@ -1,9 +1,12 @@
 //! Data Access Object (DAO) layer for database operations
 
-use rusqlite::{params, Connection, Row};
+use rusqlite::types::Value;
+use rusqlite::{Connection, Row, params, params_from_iter};
+use serde::{Deserialize, Serialize};
 
-use crate::models::*;
 use crate::Error;
+use crate::models::*;
+use crate::query::{Filter, Query};
 
 /// Note DAO
 pub struct NoteDao;
@@ -38,7 +41,7 @@ impl NoteDao {
         }
 
         let mut stmt = conn.prepare(&query)?;
-        let mut rows = stmt.query_map(params![id], |row| Self::row_to_note(row))?;
+        let mut rows = stmt.query_map(params![id], Self::row_to_note)?;
 
         match rows.next() {
             Some(Ok(note)) => Ok(Some(note)),
@@ -96,7 +99,7 @@ impl NoteDao {
         query.push_str(" ORDER BY updated_at DESC");
 
         let mut stmt = conn.prepare(&query)?;
-        let rows = stmt.query_map([], |row| Self::row_to_note(row))?;
+        let rows = stmt.query_map([], Self::row_to_note)?;
 
         let mut notes = Vec::new();
         for row in rows {
@@ -115,7 +118,7 @@ impl NoteDao {
 
         let search_pattern = format!("%{}%", query);
         let mut stmt = conn.prepare(&sql)?;
-        let rows = stmt.query_map(params![search_pattern], |row| Self::row_to_note(row))?;
+        let rows = stmt.query_map(params![search_pattern], Self::row_to_note)?;
 
         let mut notes = Vec::new();
         for row in rows {
@@ -139,7 +142,138 @@ impl NoteDao {
         query.push_str(" ORDER BY nf.position, n.updated_at DESC");
 
         let mut stmt = conn.prepare(&query)?;
-        let rows = stmt.query_map(params![folder_id], |row| Self::row_to_note(row))?;
+        let rows = stmt.query_map(params![folder_id], Self::row_to_note)?;
+
+        let mut notes = Vec::new();
+        for row in rows {
+            notes.push(row?);
+        }
+        Ok(notes)
+    }
+
+    /// Get the notes matching a query, in the given order
+    pub fn query(
+        conn: &Connection,
+        query: &Query,
+        sort: NoteSort,
+        include_deleted: bool,
+    ) -> Result<Vec<Note>, Error> {
+        let (from, values) = Self::query_from(query, include_deleted);
+        let sql = format!(
+            "SELECT n.id, n.title, n.content_path, n.created_at, n.updated_at, n.word_count, n.is_deleted, n.deleted_at {}{}",
+            from,
+            order_by(sort, None)?
+        );
+
+        let mut stmt = conn.prepare(&sql)?;
+        let rows = stmt.query_map(params_from_iter(values), Self::row_to_note)?;
+
+        let mut notes = Vec::new();
+        for row in rows {
+            notes.push(row?);
+        }
+        Ok(notes)
+    }
+
+    /// Get one page of the notes matching a query
+    pub fn query_page(
+        conn: &Connection,
+        query: &Query,
+        include_deleted: bool,
+        request: &PageRequest,
+    ) -> Result<Page<Note>, Error> {
+        let (from, values) = Self::query_from(query, include_deleted);
+        Self::page(conn, &from, values, None, request)
+    }
+
+    /// FROM ... WHERE clause (notes aliased as `n`) and parameters selecting the notes that
+    /// match a query
+    fn query_from(query: &Query, include_deleted: bool) -> (String, Vec<Value>) {
+        let mut from = "FROM notes n WHERE 1 = 1".to_string();
+        if !include_deleted {
+            from.push_str(" AND n.is_deleted = 0");
+        }
+
+        let mut values = Vec::with_capacity(query.terms.len());
+        for term in &query.terms {
+            let p = values.len() + 1;
+            let condition = match &term.filter {
+                Filter::Tag(name) => {
+                    values.push(Value::Text(name.clone()));
+                    format!(
+                        "EXISTS (SELECT 1 FROM note_tags nt INNER JOIN tags t ON t.id = nt.tag_id \
+                         WHERE nt.note_id = n.id AND t.name = ?{p} COLLATE NOCASE)"
+                    )
+                }
+                Filter::Folder(path) => {
+                    values.push(Value::Text(path.clone()));
+                    format!(
+                        "EXISTS (SELECT 1 FROM note_folders nf INNER JOIN folders f ON f.id = nf.folder_id \
+                         WHERE nf.note_id = n.id AND (f.path = ?{p} OR substr(f.path, 1, length(?{p}) + 1) = ?{p} || '/'))"
+                    )
+                }
+                Filter::Text(text) => {
+                    // Searched as one FTS5 phrase so operators in the text are not interpreted
+                    values.push(Value::Text(format!("\"{}\"", text.replace('"', "\"\""))));
+                    format!("n.rowid IN (SELECT rowid FROM notes_fts WHERE notes_fts MATCH ?{p})")
+                }
+            };
+            if term.negated {
+                from.push_str(&format!(" AND NOT {}", condition));
+            } else {
+                from.push_str(&format!(" AND {}", condition));
+            }
+        }
+        (from, values)
+    }
+
+    /// Get several notes in one query, in the order of `ids`; unknown IDs are skipped
+    pub fn get_many(
+        conn: &Connection,
+        ids: &[String],
+        include_deleted: bool,
+    ) -> Result<Vec<Note>, Error> {
+        let mut query = r#"
+            SELECT n.id, n.title, n.content_path, n.created_at, n.updated_at, n.word_count, n.is_deleted, n.deleted_at
+            FROM json_each(?1) ids
+            INNER JOIN notes n ON n.id = ids.value
+        "#
+        .to_string();
+        if !include_deleted {
+            query.push_str(" WHERE n.is_deleted = 0");
+        }
+        query.push_str(" ORDER BY ids.key");
+
+        let mut stmt = conn.prepare(&query)?;
+        let rows = stmt.query_map(params![serde_json::to_string(ids)?], Self::row_to_note)?;
+
+        let mut notes = Vec::new();
+        for row in rows {
+            notes.push(row?);
+        }
+        Ok(notes)
+    }
+
+    /// Get notes by tag ID
+    pub fn get_by_tag(
+        conn: &Connection,
+        tag_id: &str,
+        include_deleted: bool,
+    ) -> Result<Vec<Note>, Error> {
+        let mut query = r#"
+            SELECT n.id, n.title, n.content_path, n.created_at, n.updated_at, n.word_count, n.is_deleted, n.deleted_at
+            FROM notes n
+            INNER JOIN note_tags nt ON n.id = nt.note_id
+            WHERE nt.tag_id = ?1
+        "#
+        .to_string();
+        if !include_deleted {
+            query.push_str(" AND n.is_deleted = 0");
+        }
+        query.push_str(" ORDER BY n.updated_at DESC");
+
+        let mut stmt = conn.prepare(&query)?;
+        let rows = stmt.query_map(params![tag_id], Self::row_to_note)?;
 
         let mut notes = Vec::new();
         for row in rows {
@@ -148,6 +282,198 @@ impl NoteDao {
         Ok(notes)
     }
 
+    /// Get the notes that link to a note (its backlinks)
+    pub fn get_linking_to(
+        conn: &Connection,
+        note_id: &str,
+        include_deleted: bool,
+    ) -> Result<Vec<Note>, Error> {
+        let mut query = r#"
+            SELECT n.id, n.title, n.content_path, n.created_at, n.updated_at, n.word_count, n.is_deleted, n.deleted_at
+            FROM notes n
+            WHERE n.id IN (SELECT source_note_id FROM links WHERE target_note_id = ?1)
+        "#
+        .to_string();
+        if !include_deleted {
+            query.push_str(" AND n.is_deleted = 0");
+        }
+        query.push_str(" ORDER BY n.updated_at DESC");
+
+        let mut stmt = conn.prepare(&query)?;
+        let rows = stmt.query_map(params![note_id], Self::row_to_note)?;
+
+        let mut notes = Vec::new();
+        for row in rows {
+            notes.push(row?);
+        }
+        Ok(notes)
+    }
+
+    /// Search titles and indexed content by full text (FTS5 query syntax)
+    pub fn search_content(
+        conn: &Connection,
+        query: &str,
+        include_deleted: bool,
+    ) -> Result<Vec<Note>, Error> {
+        // FTS5 uses rowid to join with the source table
+        let mut sql = r#"
+            SELECT DISTINCT n.id, n.title, n.content_path, n.created_at, n.updated_at, n.word_count, n.is_deleted, n.deleted_at
+            FROM notes_fts fts
+            INNER JOIN notes n ON n.rowid = fts.rowid
+            WHERE notes_fts MATCH ?1
+        "#
+        .to_string();
+        if !include_deleted {
+            sql.push_str(" AND n.is_deleted = 0");
+        }
+        sql.push_str(" ORDER BY n.updated_at DESC");
+
+        let mut stmt = conn.prepare(&sql)?;
+        let rows = stmt.query_map(params![query], Self::row_to_note)?;
+
+        let mut notes = Vec::new();
+        for row in rows {
+            notes.push(row?);
+        }
+        Ok(notes)
+    }
+
+    /// List one page of notes
+    pub fn list_page(
+        conn: &Connection,
+        include_deleted: bool,
+        request: &PageRequest,
+    ) -> Result<Page<Note>, Error> {
+        let mut from = "FROM notes n WHERE 1 = 1".to_string();
+        if !include_deleted {
+            from.push_str(" AND n.is_deleted = 0");
+        }
+        Self::page(conn, &from, Vec::new(), None, request)
+    }
+
+    /// Get one page of the notes in a folder; [`SortKey::Position`] is the folder's manual order
+    pub fn get_by_folder_page(
+        conn: &Connection,
+        folder_id: &str,
+        include_deleted: bool,
+        request: &PageRequest,
+    ) -> Result<Page<Note>, Error> {
+        let mut from =
+            "FROM notes n INNER JOIN note_folders nf ON n.id = nf.note_id WHERE nf.folder_id = ?1"
+                .to_string();
+        if !include_deleted {
+            from.push_str(" AND n.is_deleted = 0");
+        }
+        let values = vec![Value::Text(folder_id.to_string())];
+        Self::page(
+            conn,
+            &from,
+            values,
+            Some("COALESCE(nf.position, 0)"),
+            request,
+        )
+    }
+
+    /// Get one page of the notes with a tag
+    pub fn get_by_tag_page(
+        conn: &Connection,
+        tag_id: &str,
+        include_deleted: bool,
+        request: &PageRequest,
+    ) -> Result<Page<Note>, Error> {
+        let mut from =
+            "FROM notes n INNER JOIN note_tags nt ON n.id = nt.note_id WHERE nt.tag_id = ?1"
+                .to_string();
+        if !include_deleted {
+            from.push_str(" AND n.is_deleted = 0");
+        }
+        let values = vec![Value::Text(tag_id.to_string())];
+        Self::page(conn, &from, values, None, request)
+    }
+
+    /// Read the page after `request.cursor` of the notes selected by `from`, a FROM ... WHERE
+    /// clause with notes aliased as `n` and parameters `values`. Pages are keyset-based: the
+    /// cursor holds the sort value and ID of the last note, so later pages cost the same as
+    /// the first and notes added meanwhile do not shift the rest.
+    fn page(
+        conn: &Connection,
+        from: &str,
+        mut values: Vec<Value>,
+        position: Option<&'static str>,
+        request: &PageRequest,
+    ) -> Result<Page<Note>, Error> {
+        let limit = request.page_size();
+        if limit == 0 {
+            return Err(Error::InvalidInput(
+                "Page limit must be positive".to_string(),
+            ));
+        }
+        let cursor = request
+            .cursor
+            .as_deref()
+            .map(PageCursor::decode)
+            .transpose()?;
+        if cursor.as_ref().is_some_and(|c| c.sort != request.sort) {
+            return Err(Error::InvalidInput(
+                "Cursor belongs to a different sort order".to_string(),
+            ));
+        }
+        // Counting is a full scan of the listing: do it for the first page only and carry
+        // the result along in the cursor
+        let total_estimate = match &cursor {
+            Some(cursor) => cursor.total,
+            None => conn.query_row(
+                &format!("SELECT COUNT(*) {}", from),
+                params_from_iter(&values),
+                |row| row.get::<_, i64>(0),
+            )? as u64,
+        };
+
+        let column = sort_column(request.sort.key, position)?;
+        let mut sql = format!(
+            "SELECT n.id, n.title, n.content_path, n.created_at, n.updated_at, n.word_count, n.is_deleted, n.deleted_at, {} {}",
+            column, from
+        );
+        if let Some(cursor) = cursor {
+            let operator = if request.sort.descending { "<" } else { ">" };
+            sql.push_str(&format!(
+                " AND ({}{}, n.id) {} (?{}, ?{})",
+                column,
+                collation(request.sort.key),
+                operator,
+                values.len() + 1,
+                values.len() + 2
+            ));
+            values.push(cursor.value.into());
+            values.push(Value::Text(cursor.id));
+        }
+        sql.push_str(&order_by(request.sort, position)?);
+        // One extra row tells whether there is a next page
+        sql.push_str(&format!(" LIMIT {}", limit.saturating_add(1)));
+
+        let mut stmt = conn.prepare(&sql)?;
+        let mut rows = stmt
+            .query_map(params_from_iter(values), |row| {
+                Ok((Self::row_to_note(row)?, row.get::<_, Value>(8)?))
+            })?
+            .collect::<rusqlite::Result<Vec<_>>>()?;
+        let has_more = rows.len() > limit;
+        rows.truncate(limit);
+        let next_cursor = match rows.last() {
+            Some((note, value)) if has_more => Some(
+                PageCursor::new(request.sort, value.clone(), note.id.clone(), total_estimate)?
+                    .encode()?,
+            ),
+            _ => None,
+        };
+
+        Ok(Page {
+            items: rows.into_iter().map(|(note, _)| note).collect(),
+            next_cursor,
+            total_estimate,
+        })
+    }
+
     fn row_to_note(row: &Row) -> rusqlite::Result<Note> {
         Ok(Note {
             id: row.get(0)?,
@@ -155,13 +481,190 @@ impl NoteDao {
             content_path: row.get(2)?,
             created_at: row.get(3)?,
             updated_at: row.get(4)?,
-            word_count: row.get(5)?,
+            word_count: row.get::<_, Option<i64>>(5)?.unwrap_or(0),
             is_deleted: row.get::<_, i32>(6)? != 0,
             deleted_at: row.get(7)?,
         })
     }
 }
 
+/// Column a listing of notes aliased as `n` is sorted by. `position` is the manual-order
+/// column of listings that have one. Nullable columns sort NULL as 0, so every row has a
+/// value a cursor can hold.
+fn sort_column(key: SortKey, position: Option<&'static str>) -> Result<&'static str, Error> {
+    Ok(match key {
+        SortKey::Title => "n.title",
+        SortKey::Created => "n.created_at",
+        SortKey::Updated => "n.updated_at",
+        SortKey::WordCount => "COALESCE(n.word_count, 0)",
+        SortKey::Position => position.ok_or_else(|| {
+            Error::InvalidInput("Manual order is only available inside a folder".to_string())
+        })?,
+    })
+}
+
+fn collation(key: SortKey) -> &'static str {
+    if key == SortKey::Title {
+        " COLLATE NOCASE"
+    } else {
+        ""
+    }
+}
+
+/// `ORDER BY` clause for notes aliased as `n`; ties are broken by ID so the order is stable
+fn order_by(sort: NoteSort, position: Option<&'static str>) -> Result<String, Error> {
+    let column = sort_column(sort.key, position)?;
+    let direction = if sort.descending { "DESC" } else { "ASC" };
+    Ok(format!(
+        " ORDER BY {}{} {direction}, n.id {direction}",
+        column,
+        collation(sort.key)
+    ))
+}
+
+/// Position in a keyset-paginated listing, handed out as an opaque hex string
+#[derive(Serialize, Deserialize)]
+pub(crate) struct PageCursor {
+    pub(crate) sort: NoteSort,
+    pub(crate) value: CursorValue,
+    pub(crate) id: NoteId,
+    /// `total_estimate` counted for the first page
+    pub(crate) total: u64,
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(untagged)]
+pub(crate) enum CursorValue {
+    Integer(i64),
+    Text(String),
+}
+
+impl From<CursorValue> for Value {
+    fn from(value: CursorValue) -> Self {
+        match value {
+            CursorValue::Integer(i) => Value::Integer(i),
+            CursorValue::Text(s) => Value::Text(s),
+        }
+    }
+}
+
+impl PageCursor {
+    fn new(sort: NoteSort, value: Value, id: NoteId, total: u64) -> Result<Self, Error> {
+        let value = match value {
+            Value::Integer(i) => CursorValue::Integer(i),
+            Value::Text(s) => CursorValue::Text(s),
+            other => {
+                return Err(Error::Storage(format!(
+                    "Unexpected sort value: {:?}",
+                    other
+                )));
+            }
+        };
+        Ok(Self {
+            sort,
+            value,
+            id,
+            total,
+        })
+    }
+
+    pub(crate) fn encode(&self) -> Result<String, Error> {
+        let json = serde_json::to_vec(self)?;
+        Ok(json.iter().map(|b| format!("{:02x}", b)).collect())
+    }
+
+    pub(crate) fn decode(cursor: &str) -> Result<Self, Error> {
+        let invalid = || Error::InvalidInput(format!("Invalid page cursor: {}", cursor));
+        if !cursor.len().is_multiple_of(2) || !cursor.is_ascii() {
+            return Err(invalid());
+        }
+        let bytes = (0..cursor.len())
+            .step_by(2)
+            .map(|i| u8::from_str_radix(&cursor[i..i + 2], 16))
+            .collect::<std::result::Result<Vec<u8>, _>>()
+            .map_err(|_| invalid())?;
+        serde_json::from_slice(&bytes).map_err(|_| invalid())
+    }
+}
+
+/// Note full-text index DAO. Rows and titles follow `notes` through triggers; content is
+/// written here because it lives in files.
+pub struct NoteFtsDao;
+
+impl NoteFtsDao {
+    /// Index the content of a note
+    pub fn index_content(conn: &Connection, note_id: &str, content: &str) -> Result<(), Error> {
+        conn.execute(
+            "UPDATE notes_fts SET content = ?2 WHERE rowid = (SELECT rowid FROM notes WHERE id = ?1)",
+            params![note_id, content],
+        )?;
+        Ok(())
+    }
+}
+
+/// Note property DAO (typed front matter values)
+pub struct NotePropertyDao;
+
+impl NotePropertyDao {
+    /// Replace all properties of a note
+    pub fn replace_for_note(
+        conn: &Connection,
+        note_id: &str,
+        properties: &[(String, PropertyValue)],
+    ) -> Result<(), Error> {
+        Self::delete_for_note(conn, note_id)?;
+        let mut stmt = conn.prepare(
+            "INSERT INTO note_properties (note_id, key, value_type, value, position) VALUES (?1, ?2, ?3, ?4, ?5)",
+        )?;
+        for (position, (key, value)) in properties.iter().enumerate() {
+            stmt.execute(params![
+                note_id,
+                key,
+                value.type_name(),
+                serde_json::to_string(value)?,
+                position as i64
+            ])?;
+        }
+        Ok(())
+    }
+
+    /// Get all properties of a note, in front matter order
+    pub fn get_for_note(conn: &Connection, note_id: &str) -> Result<Vec<NoteProperty>, Error> {
+        let mut stmt = conn.prepare(
+            "SELECT note_id, key, value, position FROM note_properties WHERE note_id = ?1 ORDER BY position",
+        )?;
+        let rows = stmt.query_map(params![note_id], |row| {
+            Ok((
+                row.get::<_, String>(0)?,
+                row.get::<_, String>(1)?,
+                row.get::<_, String>(2)?,
+                row.get::<_, i64>(3)?,
+            ))
+        })?;
+
+        let mut properties = Vec::new();
+        for row in rows {
+            let (note_id, key, value, position) = row?;
+            properties.push(NoteProperty {
+                note_id,
+                key,
+                value: serde_json::from_str(&value)?,
+                position,
+            });
+        }
+        Ok(properties)
+    }
+
+    /// Delete all properties of a note
+    pub fn delete_for_note(conn: &Connection, note_id: &str) -> Result<(), Error> {
+        conn.execute(
+            "DELETE FROM note_properties WHERE note_id = ?1",
+            params![note_id],
+        )?;
+        Ok(())
+    }
+}
+
 /// Block DAO
 pub struct BlockDao;
 
@@ -196,7 +699,7 @@ impl BlockDao {
         }
 
         let mut stmt = conn.prepare(&query)?;
-        let mut rows = stmt.query_map(params![id], |row| Self::row_to_block(row))?;
+        let mut rows = stmt.query_map(params![id], Self::row_to_block)?;
 
         match rows.next() {
             Some(Ok(block)) => Ok(Some(block)),
@@ -214,7 +717,7 @@ impl BlockDao {
         query.push_str(" ORDER BY position");
 
         let mut stmt = conn.prepare(&query)?;
-        let rows = stmt.query_map(params![note_id], |row| Self::row_to_block(row))?;
+        let rows = stmt.query_map(params![note_id], Self::row_to_block)?;
 
         let mut blocks = Vec::new();
         for row in rows {
@@ -263,6 +766,124 @@ impl BlockDao {
         Ok(())
     }
 
+    /// Get several blocks in one query, in the order of `ids`; unknown IDs are skipped
+    pub fn get_many(
+        conn: &Connection,
+        ids: &[String],
+        include_deleted: bool,
+    ) -> Result<Vec<Block>, Error> {
+        let mut query = r#"
+            SELECT b.id, b.note_id, b.block_type, b.content, b.position, b.created_at, b.updated_at, b.is_deleted, b.deleted_at
+            FROM json_each(?1) ids
+            INNER JOIN blocks b ON b.id = ids.value
+        "#
+        .to_string();
+        if !include_deleted {
+            query.push_str(" WHERE b.is_deleted = 0");
+        }
+        query.push_str(" ORDER BY ids.key");
+
+        let mut stmt = conn.prepare(&query)?;
+        let rows = stmt.query_map(params![serde_json::to_string(ids)?], Self::row_to_block)?;
+
+        let mut blocks = Vec::new();
+        for row in rows {
+            blocks.push(row?);
+        }
+        Ok(blocks)
+    }
+
+    /// Search block content by full text (FTS5 query syntax)
+    pub fn search_content(
+        conn: &Connection,
+        query: &str,
+        include_deleted: bool,
+    ) -> Result<Vec<Block>, Error> {
+        // FTS5 uses rowid to join with the source table
+        let mut sql = r#"
+            SELECT DISTINCT b.id, b.note_id, b.block_type, b.content, b.position, b.created_at, b.updated_at, b.is_deleted, b.deleted_at
+            FROM blocks_fts fts
+            INNER JOIN blocks b ON b.rowid = fts.rowid
+            WHERE blocks_fts MATCH ?1
+        "#
+        .to_string();
+        if !include_deleted {
+            sql.push_str(" AND b.is_deleted = 0");
+        }
+        sql.push_str(" ORDER BY b.position");
+
+        let mut stmt = conn.prepare(&sql)?;
+        let rows = stmt.query_map(params![query], Self::row_to_block)?;
+
+        let mut blocks = Vec::new();
+        for row in rows {
+            blocks.push(row?);
+        }
+        Ok(blocks)
+    }
+
+    /// Get the blocks that reference a block
+    pub fn get_referencing(
+        conn: &Connection,
+        block_id: &str,
+        include_deleted: bool,
+    ) -> Result<Vec<Block>, Error> {
+        Self::get_by_reference(
+            conn,
+            "br.source_block_id",
+            "br.target_block_id",
+            block_id,
+            include_deleted,
+        )
+    }
+
+    /// Get the blocks a block references
+    pub fn get_referenced(
+        conn: &Connection,
+        block_id: &str,
+        include_deleted: bool,
+    ) -> Result<Vec<Block>, Error> {
+        Self::get_by_reference(
+            conn,
+            "br.target_block_id",
+            "br.source_block_id",
+            block_id,
+            include_deleted,
+        )
+    }
+
+    /// Blocks at the `select` end of references whose `filter` end is `block_id`
+    fn get_by_reference(
+        conn: &Connection,
+        select: &str,
+        filter: &str,
+        block_id: &str,
+        include_deleted: bool,
+    ) -> Result<Vec<Block>, Error> {
+        let mut query = format!(
+            r#"
+            SELECT b.id, b.note_id, b.block_type, b.content, b.position, b.created_at, b.updated_at, b.is_deleted, b.deleted_at
+            FROM blocks b
+            INNER JOIN block_references br ON b.id = {}
+            WHERE {} = ?1
+            "#,
+            select, filter
+        );
+        if !include_deleted {
+            query.push_str(" AND b.is_deleted = 0");
+        }
+        query.push_str(" ORDER BY br.created_at, br.rowid");
+
+        let mut stmt = conn.prepare(&query)?;
+        let rows = stmt.query_map(params![block_id], Self::row_to_block)?;
+
+        let mut blocks = Vec::new();
+        for row in rows {
+            blocks.push(row?);
+        }
+        Ok(blocks)
+    }
+
     fn row_to_block(row: &Row) -> rusqlite::Result<Block> {
         Ok(Block {
             id: row.get(0)?,
@@ -307,7 +928,7 @@ impl FolderDao {
         let mut stmt = conn.prepare(
             "SELECT id, name, parent_id, path, created_at, updated_at, position FROM folders WHERE id = ?1"
         )?;
-        let mut rows = stmt.query_map(params![id], |row| Self::row_to_folder(row))?;
+        let mut rows = stmt.query_map(params![id], Self::row_to_folder)?;
 
         match rows.next() {
             Some(Ok(folder)) => Ok(Some(folder)),
@@ -321,7 +942,7 @@ impl FolderDao {
         let mut stmt = conn.prepare(
             "SELECT id, name, parent_id, path, created_at, updated_at, position FROM folders WHERE parent_id IS NULL ORDER BY position"
         )?;
-        let rows = stmt.query_map([], |row| Self::row_to_folder(row))?;
+        let rows = stmt.query_map([], Self::row_to_folder)?;
 
         let mut folders = Vec::new();
         for row in rows {
@@ -335,7 +956,21 @@ impl FolderDao {
         let mut stmt = conn.prepare(
             "SELECT id, name, parent_id, path, created_at, updated_at, position FROM folders WHERE parent_id = ?1 ORDER BY position"
         )?;
-        let rows = stmt.query_map(params![parent_id], |row| Self::row_to_folder(row))?;
+        let rows = stmt.query_map(params![parent_id], Self::row_to_folder)?;
+
+        let mut folders = Vec::new();
+        for row in rows {
+            folders.push(row?);
+        }
+        Ok(folders)
+    }
+
+    /// List all folders, siblings in position order
+    pub fn list(conn: &Connection) -> Result<Vec<Folder>, Error> {
+        let mut stmt = conn.prepare(
+            "SELECT id, name, parent_id, path, created_at, updated_at, position FROM folders ORDER BY position, path"
+        )?;
+        let rows = stmt.query_map([], Self::row_to_folder)?;
 
         let mut folders = Vec::new();
         for row in rows {
@@ -383,6 +1018,102 @@ impl FolderDao {
     }
 }
 
+/// Saved search DAO
+pub struct SavedSearchDao;
+
+impl SavedSearchDao {
+    /// Create a new saved search
+    pub fn create(conn: &Connection, search: &SavedSearch) -> Result<(), Error> {
+        conn.execute(
+            r#"
+            INSERT INTO saved_searches (id, name, query, sort_key, sort_desc, position, created_at, updated_at)
+            VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8)
+            "#,
+            params![
+                search.id,
+                search.name,
+                search.query,
+                search.sort.key.as_str(),
+                search.sort.descending as i32,
+                search.position,
+                search.created_at,
+                search.updated_at
+            ],
+        )?;
+        Ok(())
+    }
+
+    /// Get a saved search by ID
+    pub fn get_by_id(conn: &Connection, id: &str) -> Result<Option<SavedSearch>, Error> {
+        let mut stmt = conn.prepare(
+            "SELECT id, name, query, sort_key, sort_desc, position, created_at, updated_at FROM saved_searches WHERE id = ?1"
+        )?;
+        let mut rows = stmt.query_map(params![id], Self::row_to_saved_search)?;
+
+        match rows.next() {
+            Some(Ok(search)) => Ok(Some(search)),
+            Some(Err(e)) => Err(Error::Database(e)),
+            None => Ok(None),
+        }
+    }
+
+    /// List all saved searches
+    pub fn list(conn: &Connection) -> Result<Vec<SavedSearch>, Error> {
+        let mut stmt = conn.prepare(
+            "SELECT id, name, query, sort_key, sort_desc, position, created_at, updated_at FROM saved_searches ORDER BY position, name"
+        )?;
+        let rows = stmt.query_map([], Self::row_to_saved_search)?;
+
+        let mut searches = Vec::new();
+        for row in rows {
+            searches.push(row?);
+        }
+        Ok(searches)
+    }
+
+    /// Update a saved search
+    pub fn update(conn: &Connection, search: &SavedSearch) -> Result<(), Error> {
+        conn.execute(
+            r#"
+            UPDATE saved_searches
+            SET name = ?2, query = ?3, sort_key = ?4, sort_desc = ?5, position = ?6, updated_at = ?7
+            WHERE id = ?1
+            "#,
+            params![
+                search.id,
+                search.name,
+                search.query,
+                search.sort.key.as_str(),
+                search.sort.descending as i32,
+                search.position,
+                search.updated_at
+            ],
+        )?;
+        Ok(())
+    }
+
+    /// Delete a saved search
+    pub fn delete(conn: &Connection, id: &str) -> Result<(), Error> {
+        conn.execute("DELETE FROM saved_searches WHERE id = ?1", params![id])?;
+        Ok(())
+    }
+
+    fn row_to_saved_search(row: &Row) -> rusqlite::Result<SavedSearch> {
+        Ok(SavedSearch {
+            id: row.get(0)?,
+            name: row.get(1)?,
+            query: row.get(2)?,
+            sort: NoteSort {
+                key: SortKey::parse(&row.get::<_, String>(3)?).unwrap_or_default(),
+                descending: row.get::<_, i32>(4)? != 0,
+            },
+            position: row.get(5)?,
+            created_at: row.get(6)?,
+            updated_at: row.get(7)?,
+        })
+    }
+}
+
 /// Tag DAO
 pub struct TagDao;
 
@@ -402,7 +1133,7 @@ impl TagDao {
     /// Get a tag by ID
     pub fn get_by_id(conn: &Connection, id: &str) -> Result<Option<Tag>, Error> {
         let mut stmt = conn.prepare("SELECT id, name, color, icon, created_at FROM tags WHERE id = ?1")?;
-        let mut rows = stmt.query_map(params![id], |row| Self::row_to_tag(row))?;
+        let mut rows = stmt.query_map(params![id], Self::row_to_tag)?;
 
         match rows.next() {
             Some(Ok(tag)) => Ok(Some(tag)),
@@ -414,7 +1145,7 @@ impl TagDao {
     /// Get a tag by name
     pub fn get_by_name(conn: &Connection, name: &str) -> Result<Option<Tag>, Error> {
         let mut stmt = conn.prepare("SELECT id, name, color, icon, created_at FROM tags WHERE name = ?1")?;
-        let mut rows = stmt.query_map(params![name], |row| Self::row_to_tag(row))?;
+        let mut rows = stmt.query_map(params![name], Self::row_to_tag)?;
 
         match rows.next() {
             Some(Ok(tag)) => Ok(Some(tag)),
@@ -426,7 +1157,7 @@ impl TagDao {
     /// List all tags
     pub fn list(conn: &Connection) -> Result<Vec<Tag>, Error> {
         let mut stmt = conn.prepare("SELECT id, name, color, icon, created_at FROM tags ORDER BY name")?;
-        let rows = stmt.query_map([], |row| Self::row_to_tag(row))?;
+        let rows = stmt.query_map([], Self::row_to_tag)?;
 
         let mut tags = Vec::new();
         for row in rows {
@@ -450,6 +1181,45 @@ impl TagDao {
         Ok(())
     }
 
+    /// Get several tags in one query, in the order of `ids`; unknown IDs are skipped
+    pub fn get_many(conn: &Connection, ids: &[String]) -> Result<Vec<Tag>, Error> {
+        let mut stmt = conn.prepare(
+            r#"
+            SELECT t.id, t.name, t.color, t.icon, t.created_at
+            FROM json_each(?1) ids
+            INNER JOIN tags t ON t.id = ids.value
+            ORDER BY ids.key
+            "#,
+        )?;
+        let rows = stmt.query_map(params![serde_json::to_string(ids)?], Self::row_to_tag)?;
+
+        let mut tags = Vec::new();
+        for row in rows {
+            tags.push(row?);
+        }
+        Ok(tags)
+    }
+
+    /// Get the tags of a note, by name
+    pub fn get_for_note(conn: &Connection, note_id: &str) -> Result<Vec<Tag>, Error> {
+        let mut stmt = conn.prepare(
+            r#"
+            SELECT t.id, t.name, t.color, t.icon, t.created_at
+            FROM tags t
+            INNER JOIN note_tags nt ON t.id = nt.tag_id
+            WHERE nt.note_id = ?1
+            ORDER BY t.name
+            "#,
+        )?;
+        let rows = stmt.query_map(params![note_id], Self::row_to_tag)?;
+
+        let mut tags = Vec::new();
+        for row in rows {
+            tags.push(row?);
+        }
+        Ok(tags)
+    }
+
     fn row_to_tag(row: &Row) -> rusqlite::Result<Tag> {
         Ok(Tag {
             id: row.get(0)?,
@@ -494,7 +1264,7 @@ impl AttachmentDao {
         let mut stmt = conn.prepare(
             "SELECT id, file_name, file_path, file_type, mime_type, file_size, width, height, hash, created_at, updated_at FROM attachments WHERE id = ?1"
         )?;
-        let mut rows = stmt.query_map(params![id], |row| Self::row_to_attachment(row))?;
+        let mut rows = stmt.query_map(params![id], Self::row_to_attachment)?;
 
         match rows.next() {
             Some(Ok(attachment)) => Ok(Some(attachment)),
@@ -508,7 +1278,7 @@ impl AttachmentDao {
         let mut stmt = conn.prepare(
             "SELECT id, file_name, file_path, file_type, mime_type, file_size, width, height, hash, created_at, updated_at FROM attachments WHERE hash = ?1"
         )?;
-        let mut rows = stmt.query_map(params![hash], |row| Self::row_to_attachment(row))?;
+        let mut rows = stmt.query_map(params![hash], Self::row_to_attachment)?;
 
         match rows.next() {
             Some(Ok(attachment)) => Ok(Some(attachment)),
@@ -517,6 +1287,16 @@ impl AttachmentDao {
         }
     }
 
+    /// Count the attachments with a content hash
+    pub fn count_by_hash(conn: &Connection, hash: &str) -> Result<i64, Error> {
+        let count = conn.query_row(
+            "SELECT COUNT(*) FROM attachments WHERE hash = ?1",
+            params![hash],
+            |row| row.get(0),
+        )?;
+        Ok(count)
+    }
+
     /// Update an attachment
     pub fn update(conn: &Connection, attachment: &Attachment) -> Result<(), Error> {
         conn.execute(
@@ -546,6 +1326,68 @@ impl AttachmentDao {
         Ok(())
     }
 
+    /// Get several attachments in one query, in the order of `ids`; unknown IDs are skipped
+    pub fn get_many(conn: &Connection, ids: &[String]) -> Result<Vec<Attachment>, Error> {
+        let mut stmt = conn.prepare(
+            r#"
+            SELECT a.id, a.file_name, a.file_path, a.file_type, a.mime_type, a.file_size, a.width, a.height, a.hash, a.created_at, a.updated_at
+            FROM json_each(?1) ids
+            INNER JOIN attachments a ON a.id = ids.value
+            ORDER BY ids.key
+            "#,
+        )?;
+        let rows = stmt.query_map(
+            params![serde_json::to_string(ids)?],
+            Self::row_to_attachment,
+        )?;
+
+        let mut attachments = Vec::new();
+        for row in rows {
+            attachments.push(row?);
+        }
+        Ok(attachments)
+    }
+
+    /// Get the attachments of a note, in their order in the note
+    pub fn get_for_note(conn: &Connection, note_id: &str) -> Result<Vec<Attachment>, Error> {
+        let mut stmt = conn.prepare(
+            r#"
+            SELECT a.id, a.file_name, a.file_path, a.file_type, a.mime_type, a.file_size, a.width, a.height, a.hash, a.created_at, a.updated_at
+            FROM attachments a
+            INNER JOIN note_attachments na ON a.id = na.attachment_id
+            WHERE na.note_id = ?1
+            ORDER BY na.position
+            "#,
+        )?;
+        let rows = stmt.query_map(params![note_id], Self::row_to_attachment)?;
+
+        let mut attachments = Vec::new();
+        for row in rows {
+            attachments.push(row?);
+        }
+        Ok(attachments)
+    }
+
+    /// Get the attachments of a block
+    pub fn get_for_block(conn: &Connection, block_id: &str) -> Result<Vec<Attachment>, Error> {
+        let mut stmt = conn.prepare(
+            r#"
+            SELECT a.id, a.file_name, a.file_path, a.file_type, a.mime_type, a.file_size, a.width, a.height, a.hash, a.created_at, a.updated_at
+            FROM attachments a
+            INNER JOIN block_attachments ba ON a.id = ba.attachment_id
+            WHERE ba.block_id = ?1
+            ORDER BY ba.created_at, ba.rowid
+            "#,
+        )?;
+        let rows = stmt.query_map(params![block_id], Self::row_to_attachment)?;
+
+        let mut attachments = Vec::new();
+        for row in rows {
+            attachments.push(row?);
+        }
+        Ok(attachments)
+    }
+
     fn row_to_attachment(row: &Row) -> rusqlite::Result<Attachment> {
         Ok(Attachment {
             id: row.get(0)?,
@@ -593,7 +1435,7 @@ impl LinkDao {
         let mut stmt = conn.prepare(
             "SELECT id, source_note_id, target_note_id, source_block_id, target_block_id, link_type, link_text, created_at FROM links WHERE id = ?1"
         )?;
-        let mut rows = stmt.query_map(params![id], |row| Self::row_to_link(row))?;
+        let mut rows = stmt.query_map(params![id], Self::row_to_link)?;
 
         match rows.next() {
             Some(Ok(link)) => Ok(Some(link)),
@@ -607,7 +1449,7 @@ impl LinkDao {
         let mut stmt = conn.prepare(
             "SELECT id, source_note_id, target_note_id, source_block_id, target_block_id, link_type, link_text, created_at FROM links WHERE source_note_id = ?1"
         )?;
-        let rows = stmt.query_map(params![note_id], |row| Self::row_to_link(row))?;
+        let rows = stmt.query_map(params![note_id], Self::row_to_link)?;
 
         let mut links = Vec::new();
         for row in rows {
@@ -621,7 +1463,7 @@ impl LinkDao {
         let mut stmt = conn.prepare(
             "SELECT id, source_note_id, target_note_id, source_block_id, target_block_id, link_type, link_text, created_at FROM links WHERE target_note_id = ?1"
         )?;
-        let rows = stmt.query_map(params![note_id], |row| Self::row_to_link(row))?;
+        let rows = stmt.query_map(params![note_id], Self::row_to_link)?;
 
         let mut links = Vec::new();
         for row in rows {
@@ -635,7 +1477,7 @@ impl LinkDao {
         let mut stmt = conn.prepare(
             "SELECT id, source_note_id, target_note_id, source_block_id, target_block_id, link_type, link_text, created_at FROM links WHERE source_block_id = ?1"
         )?;
-        let rows = stmt.query_map(params![block_id], |row| Self::row_to_link(row))?;
+        let rows = stmt.query_map(params![block_id], Self::row_to_link)?;
 
         let mut links = Vec::new();
         for row in rows {
@@ -649,7 +1491,7 @@ impl LinkDao {
         let mut stmt = conn.prepare(
             "SELECT id, source_note_id, target_note_id, source_block_id, target_block_id, link_type, link_text, created_at FROM links WHERE target_block_id = ?1"
         )?;
-        let rows = stmt.query_map(params![block_id], |row| Self::row_to_link(row))?;
+        let rows = stmt.query_map(params![block_id], Self::row_to_link)?;
 
         let mut links = Vec::new();
         for row in rows {
@@ -732,6 +1574,27 @@ impl BlockReferenceDao {
         Ok(blocks)
     }
 
+    /// `(source, target)` pairs of the references from live blocks of a note to blocks of a type
+    pub fn get_in_note(
+        conn: &Connection,
+        note_id: &str,
+        target_type: &str,
+    ) -> Result<Vec<(String, String)>, Error> {
+        let mut stmt = conn.prepare(
+            r#"
+            SELECT br.source_block_id, br.target_block_id
+            FROM block_references br
+            INNER JOIN blocks s ON s.id = br.source_block_id
+            INNER JOIN blocks t ON t.id = br.target_block_id
+            WHERE s.note_id = ?1 AND s.is_deleted = 0 AND t.block_type = ?2
+            "#,
+        )?;
+        let rows = stmt.query_map(params![note_id, target_type], |row| {
+            Ok((row.get(0)?, row.get(1)?))
+        })?;
+        Ok(rows.collect::<rusqlite::Result<Vec<_>>>()?)
+    }
+
     /// Delete a block reference
     pub fn delete(conn: &Connection, source_block_id: &str, target_block_id: &str) -> Result<(), Error> {
         conn.execute(
@@ -760,6 +1623,71 @@ impl BlockReferenceDao {
     }
 }
 
+/// Blob DAO: content kept in the database by path, for single-file vaults
+pub struct BlobDao;
+
+impl BlobDao {
+    /// Get the blob stored at a path
+    pub fn get(conn: &Connection, path: &str) -> Result<Option<Vec<u8>>, Error> {
+        let mut stmt = conn.prepare("SELECT data FROM blobs WHERE path = ?1")?;
+        let mut rows = stmt.query_map(params![path], |row| row.get(0))?;
+
+        match rows.next() {
+            Some(Ok(data)) => Ok(Some(data)),
+            Some(Err(e)) => Err(Error::Database(e)),
+            None => Ok(None),
+        }
+    }
+
+    /// Store a blob at a path, replacing the one there
+    pub fn put(conn: &Connection, path: &str, data: &[u8]) -> Result<(), Error> {
+        let updated_at = chrono::Utc::now().timestamp();
+        conn.execute(
+            "INSERT OR REPLACE INTO blobs (path, data, updated_at) VALUES (?1, ?2, ?3)",
+            params![path, data, updated_at],
+        )?;
+        Ok(())
+    }
+
+    /// Delete the blob at a path
+    pub fn delete(conn: &Connection, path: &str) -> Result<(), Error> {
+        conn.execute("DELETE FROM blobs WHERE path = ?1", params![path])?;
+        Ok(())
+    }
+}
+
+/// Setting DAO: vault-wide settings by key
+pub struct SettingDao;
+
+impl SettingDao {
+    /// Get the value of a setting
+    pub fn get(conn: &Connection, key: &str) -> Result<Option<String>, Error> {
+        let mut stmt = conn.prepare("SELECT value FROM settings WHERE key = ?1")?;
+        let mut rows = stmt.query_map(params![key], |row| row.get(0))?;
+
+        match rows.next() {
+            Some(Ok(value)) => Ok(Some(value)),
+            Some(Err(e)) => Err(Error::Database(e)),
+            None => Ok(None),
+        }
+    }
+
+    /// Set a setting, replacing its value
+    pub fn set(conn: &Connection, key: &str, value: &str) -> Result<(), Error> {
+        conn.execute(
+            "INSERT OR REPLACE INTO settings (key, value) VALUES (?1, ?2)",
+            params![key, value],
+        )?;
+        Ok(())
+    }
+
+    /// Delete a setting
+    pub fn delete(conn: &Connection, key: &str) -> Result<(), Error> {
+        conn.execute("DELETE FROM settings WHERE key = ?1", params![key])?;
+        Ok(())
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -824,6 +1752,18 @@ mod tests {
         let referenced = BlockReferenceDao::get_referenced_blocks(conn, "block-1").unwrap();
         assert_eq!(referenced.len(), 1);
         assert_eq!(referenced[0], "block-2");
+
+        // References within a note, by target type
+        let in_note = BlockReferenceDao::get_in_note(conn, "note-1", "paragraph").unwrap();
+        assert_eq!(
+            in_note,
+            vec![("block-1".to_string(), "block-2".to_string())]
+        );
+        assert!(
+            BlockReferenceDao::get_in_note(conn, "note-1", "footnote")
+                .unwrap()
+                .is_empty()
+        );
     }
 
     #[test]
@@ -866,6 +1806,43 @@ mod tests {
         assert!(retrieved.is_some());
     }
 
+    #[test]
+    fn test_note_property_dao() {
+        let db = DatabaseManager::in_memory().unwrap();
+        let conn = db.conn();
+
+        let note = Note::new(
+            "note-1".to_string(),
+            "Test".to_string(),
+            "notes/test.md".to_string(),
+        );
+        NoteDao::create(conn, &note).unwrap();
+
+        let properties = vec![
+            (
+                "status".to_string(),
+                PropertyValue::Text("draft".to_string()),
+            ),
+            ("rating".to_string(), PropertyValue::Number(3.0)),
+            (
+                "tags".to_string(),
+                PropertyValue::List(vec![PropertyValue::Text("rust".to_string())]),
+            ),
+        ];
+        NotePropertyDao::replace_for_note(conn, "note-1", &properties).unwrap();
+
+        let stored = NotePropertyDao::get_for_note(conn, "note-1").unwrap();
+        assert_eq!(stored.len(), 3);
+        assert_eq!(stored[0].key, "status");
+        assert_eq!(stored[1].value, PropertyValue::Number(3.0));
+
+        NotePropertyDao::replace_for_note(conn, "note-1", &properties[..1]).unwrap();
+        assert_eq!(
+            NotePropertyDao::get_for_note(conn, "note-1").unwrap().len(),
+            1
+        );
+    }
+
     #[test]
     fn test_tag_dao() {
         let db = DatabaseManager::in_memory().unwrap();
@@ -885,4 +1862,397 @@ mod tests {
         assert!(retrieved.is_some());
         assert_eq!(retrieved.unwrap().id, "tag-123");
     }
+
+    #[test]
+    fn test_note_query_and_saved_search_dao() {
+        use crate::storage::{NoteFolderDao, NoteTagDao};
+
+        let db = DatabaseManager::in_memory().unwrap();
+        let conn = db.conn();
+
+        for (id, title, updated_at) in [
+            ("n1", "Alpha plan", 30),
+            ("n2", "beta 知识管理", 20),
+            ("n3", "Gamma", 10),
+        ] {
+            let mut note = Note::new(
+                id.to_string(),
+                title.to_string(),
+                format!("notes/{}.md", id),
+            );
+            note.updated_at = updated_at;
+            NoteDao::create(conn, &note).unwrap();
+        }
+        TagDao::create(conn, &Tag::new("t1".to_string(), "inbox".to_string())).unwrap();
+        TagDao::create(conn, &Tag::new("t2".to_string(), "done".to_string())).unwrap();
+        NoteTagDao::add(conn, "n1", "t1").unwrap();
+        NoteTagDao::add(conn, "n2", "t1").unwrap();
+        NoteTagDao::add(conn, "n2", "t2").unwrap();
+        FolderDao::create(
+            conn,
+            &Folder::new(
+                "f1".to_string(),
+                "Work".to_string(),
+                None,
+                "/Work".to_string(),
+            ),
+        )
+        .unwrap();
+        FolderDao::create(
+            conn,
+            &Folder::new(
+                "f2".to_string(),
+                "A".to_string(),
+                Some("f1".to_string()),
+                "/Work/A".to_string(),
+            ),
+        )
+        .unwrap();
+        FolderDao::create(
+            conn,
+            &Folder::new(
+                "f3".to_string(),
+                "Workshop".to_string(),
+                None,
+                "/Workshop".to_string(),
+            ),
+        )
+        .unwrap();
+        NoteFolderDao::add(conn, "n2", "f2", true, 0).unwrap();
+        NoteFolderDao::add(conn, "n3", "f3", true, 0).unwrap();
+
+        let ids = |query: &str, sort: NoteSort| -> Vec<String> {
+            NoteDao::query(conn, &Query::parse(query).unwrap(), sort, false)
+                .unwrap()
+                .into_iter()
+                .map(|n| n.id)
+                .collect()
+        };
+        let by_title = NoteSort {
+            key: SortKey::Title,
+            descending: false,
+        };
+        assert_eq!(ids("tag:INBOX -tag:done", by_title), vec!["n1"]);
+        assert_eq!(ids("tag:inbox", by_title), vec!["n1", "n2"]);
+        assert_eq!(ids("tag:inbox", NoteSort::default()), vec!["n1", "n2"]);
+        assert_eq!(
+            ids(
+                "tag:inbox",
+                NoteSort {
+                    key: SortKey::Updated,
+                    descending: false
+                }
+            ),
+            vec!["n2", "n1"]
+        );
+        assert_eq!(ids("folder:/Work", by_title), vec!["n2"]);
+        assert_eq!(ids("-folder:Work", by_title), vec!["n1", "n3"]);
+        assert_eq!(ids("知识", by_title), vec!["n2"]);
+        assert_eq!(ids("plan OR", by_title), Vec::<String>::new());
+        assert_eq!(ids("", by_title), vec!["n1", "n2", "n3"]);
+
+        let query = Query::parse("-tag:done").unwrap();
+        let first =
+            NoteDao::query_page(conn, &query, false, &PageRequest::new(by_title, 1)).unwrap();
+        assert_eq!(first.items[0].id, "n1");
+        assert_eq!(first.total_estimate, 2);
+        let request = PageRequest::new(by_title, 1).with_cursor(first.next_cursor);
+        let second = NoteDao::query_page(conn, &query, false, &request).unwrap();
+        assert_eq!(second.items[0].id, "n3");
+        assert_eq!(second.next_cursor, None);
+
+        let search = SavedSearch::new(
+            "s1".to_string(),
+            "Inbox".to_string(),
+            "tag:inbox -tag:done".to_string(),
+            by_title,
+        );
+        SavedSearchDao::create(conn, &search).unwrap();
+        let stored = SavedSearchDao::get_by_id(conn, "s1").unwrap().unwrap();
+        assert_eq!(stored.query, "tag:inbox -tag:done");
+        assert_eq!(stored.sort, by_title);
+        SavedSearchDao::delete(conn, "s1").unwrap();
+        assert!(SavedSearchDao::list(conn).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_keyset_pages() {
+        use crate::storage::{NoteFolderDao, NoteTagDao};
+
+        let db = DatabaseManager::in_memory().unwrap();
+        let conn = db.conn();
+        FolderDao::create(
+            conn,
+            &Folder::new(
+                "f1".to_string(),
+                "Work".to_string(),
+                None,
+                "/Work".to_string(),
+            ),
+        )
+        .unwrap();
+        TagDao::create(conn, &Tag::new("t1".to_string(), "rust".to_string())).unwrap();
+        let titles = [
+            "delta", "Alpha", "charlie", "bravo", "echo", "Foxtrot", "golf",
+        ];
+        for (i, title) in titles.iter().enumerate() {
+            let id = format!("n{}", i);
+            let mut note = Note::new(id.clone(), title.to_string(), format!("notes/{}.md", id));
+            // Ties on updated_at are broken by ID
+            note.updated_at = (i as i64) / 2;
+            note.word_count = 100 - i as i64;
+            NoteDao::create(conn, &note).unwrap();
+            NoteFolderDao::add(conn, &id, "f1", true, (titles.len() - i) as i64).unwrap();
+            if i % 2 == 0 {
+                NoteTagDao::add(conn, &id, "t1").unwrap();
+            }
+        }
+
+        let collect = |load: &dyn Fn(&PageRequest) -> Page<Note>, sort: NoteSort| -> Vec<String> {
+            let mut request = PageRequest::new(sort, 3);
+            let mut ids = Vec::new();
+            loop {
+                let page = load(&request);
+                assert!(page.items.len() <= page.total_estimate as usize);
+                ids.extend(page.items.into_iter().map(|n| n.id));
+                match page.next_cursor {
+                    Some(cursor) => request = request.with_cursor(Some(cursor)),
+                    None => return ids,
+                }
+            }
+        };
+        let list = |request: &PageRequest| NoteDao::list_page(conn, false, request).unwrap();
+        let sort = |key, descending| NoteSort { key, descending };
+
+        assert_eq!(
+            collect(&list, sort(SortKey::Title, false)),
+            vec!["n1", "n3", "n2", "n0", "n4", "n5", "n6"]
+        );
+        assert_eq!(
+            collect(&list, NoteSort::default()),
+            vec!["n6", "n5", "n4", "n3", "n2", "n1", "n0"]
+        );
+        assert_eq!(
+            collect(&list, sort(SortKey::WordCount, false)),
+            vec!["n6", "n5", "n4", "n3", "n2", "n1", "n0"]
+        );
+        let folder = |request: &PageRequest| {
+            NoteDao::get_by_folder_page(conn, "f1", false, request).unwrap()
+        };
+        assert_eq!(
+            collect(&folder, sort(SortKey::Position, false)),
+            vec!["n6", "n5", "n4", "n3", "n2", "n1", "n0"]
+        );
+        let tag =
+            |request: &PageRequest| NoteDao::get_by_tag_page(conn, "t1", false, request).unwrap();
+        assert_eq!(
+            collect(&tag, sort(SortKey::Created, false)),
+            vec!["n0", "n2", "n4", "n6"]
+        );
+
+        let first =
+            NoteDao::list_page(conn, false, &PageRequest::new(NoteSort::default(), 3)).unwrap();
+        assert_eq!(first.total_estimate, 7);
+        let mismatched =
+            PageRequest::new(sort(SortKey::Title, false), 3).with_cursor(first.next_cursor);
+        assert!(matches!(
+            NoteDao::list_page(conn, false, &mismatched),
+            Err(Error::InvalidInput(_))
+        ));
+        let garbage = PageRequest::default().with_cursor(Some("zz".to_string()));
+        assert!(matches!(
+            NoteDao::list_page(conn, false, &garbage),
+            Err(Error::InvalidInput(_))
+        ));
+        let manual = PageRequest::new(sort(SortKey::Position, false), 3);
+        assert!(matches!(
+            NoteDao::list_page(conn, false, &manual),
+            Err(Error::InvalidInput(_))
+        ));
+
+        // Oversized limits are clamped instead of overflowing
+        let huge = PageRequest {
+            limit: usize::MAX,
+            ..PageRequest::default()
+        };
+        let all = NoteDao::list_page(conn, false, &huge).unwrap();
+        assert_eq!(all.items.len(), 7);
+        assert!(all.next_cursor.is_none());
+        assert_eq!(
+            PageRequest::new(NoteSort::default(), usize::MAX).limit,
+            MAX_PAGE_SIZE
+        );
+
+        // NULL word counts and positions sort as 0
+        conn.execute(
+            "UPDATE notes SET word_count = NULL WHERE id IN ('n2', 'n4')",
+            [],
+        )
+        .unwrap();
+        conn.execute(
+            "UPDATE note_folders SET position = NULL WHERE note_id = 'n0'",
+            [],
+        )
+        .unwrap();
+        assert_eq!(
+            collect(&list, sort(SortKey::WordCount, false)),
+            vec!["n2", "n4", "n6", "n5", "n3", "n1", "n0"]
+        );
+        assert_eq!(
+            collect(&folder, sort(SortKey::Position, true)),
+            vec!["n1", "n2", "n3", "n4", "n5", "n6", "n0"]
+        );
+
+        // Later pages carry the first page's count instead of counting again
+        let first =
+            NoteDao::list_page(conn, false, &PageRequest::new(NoteSort::default(), 3)).unwrap();
+        NoteDao::create(
+            conn,
+            &Note::new(
+                "n7".to_string(),
+                "hotel".to_string(),
+                "notes/n7.md".to_string(),
+            ),
+        )
+        .unwrap();
+        let next = PageRequest::new(NoteSort::default(), 3).with_cursor(first.next_cursor);
+        assert_eq!(
+            NoteDao::list_page(conn, false, &next)
+                .unwrap()
+                .total_estimate,
+            7
+        );
+    }
+
+    #[test]
+    fn test_set_based_relations() {
+        use crate::storage::{BlockAttachmentDao, NoteAttachmentDao, NoteTagDao};
+
+        let db = DatabaseManager::in_memory().unwrap();
+        let conn = db.conn();
+        for id in ["n1", "n2", "n3"] {
+            NoteDao::create(
+                conn,
+                &Note::new(id.to_string(), id.to_string(), format!("notes/{}.md", id)),
+            )
+            .unwrap();
+        }
+        NoteDao::soft_delete(conn, "n3").unwrap();
+        for (id, name) in [("t1", "zeta"), ("t2", "alpha")] {
+            TagDao::create(conn, &Tag::new(id.to_string(), name.to_string())).unwrap();
+            NoteTagDao::add(conn, "n1", id).unwrap();
+        }
+        NoteTagDao::add(conn, "n3", "t1").unwrap();
+        for (id, position) in [("b1", 0), ("b2", 1), ("b3", 2)] {
+            BlockDao::create(
+                conn,
+                &Block::new(
+                    id.to_string(),
+                    "n1".to_string(),
+                    "paragraph".to_string(),
+                    id.to_string(),
+                    position,
+                ),
+            )
+            .unwrap();
+        }
+        BlockReferenceDao::create(conn, "r1", "b3", "b1").unwrap();
+        BlockReferenceDao::create(conn, "r2", "b2", "b1").unwrap();
+        for id in ["a1", "a2"] {
+            let attachment = Attachment::new(
+                id.to_string(),
+                id.to_string(),
+                id.to_string(),
+                "image".to_string(),
+                "image/png".to_string(),
+                1,
+                id.to_string(),
+            );
+            AttachmentDao::create(conn, &attachment).unwrap();
+        }
+        NoteAttachmentDao::add(conn, "n1", "a2", 0).unwrap();
+        NoteAttachmentDao::add(conn, "n1", "a1", 1).unwrap();
+        BlockAttachmentDao::add(conn, "b1", "a1").unwrap();
+        LinkDao::create(
+            conn,
+            &Link::new_note_link("l1".to_string(), "n2".to_string(), "n1".to_string(), None),
+        )
+        .unwrap();
+        LinkDao::create(
+            conn,
+            &Link::new_note_link("l2".to_string(), "n2".to_string(), "n1".to_string(), None),
+        )
+        .unwrap();
+        LinkDao::create(
+            conn,
+            &Link::new_note_link("l3".to_string(), "n3".to_string(), "n1".to_string(), None),
+        )
+        .unwrap();
+
+        let ids = |ids: &[&str]| -> Vec<String> { ids.iter().map(|id| id.to_string()).collect() };
+        let note_ids: Vec<String> =
+            NoteDao::get_many(conn, &ids(&["n2", "missing", "n3", "n1"]), false)
+                .unwrap()
+                .into_iter()
+                .map(|n| n.id)
+                .collect();
+        assert_eq!(note_ids, vec!["n2", "n1"]);
+        assert_eq!(
+            NoteDao::get_many(conn, &ids(&["n3"]), true).unwrap().len(),
+            1
+        );
+        assert!(NoteDao::get_many(conn, &[], false).unwrap().is_empty());
+        assert_eq!(
+            TagDao::get_many(conn, &ids(&["t1", "t2"])).unwrap()[0].name,
+            "zeta"
+        );
+        assert_eq!(
+            BlockDao::get_many(conn, &ids(&["b3", "b1"]), false).unwrap()[0].id,
+            "b3"
+        );
+        assert_eq!(
+            AttachmentDao::get_many(conn, &ids(&["a2"])).unwrap()[0].id,
+            "a2"
+        );
+
+        let names: Vec<String> = TagDao::get_for_note(conn, "n1")
+            .unwrap()
+            .into_iter()
+            .map(|t| t.name)
+            .collect();
+        assert_eq!(names, vec!["alpha", "zeta"]);
+        let tagged: Vec<String> = NoteDao::get_by_tag(conn, "t1", false)
+            .unwrap()
+            .into_iter()
+            .map(|n| n.id)
+            .collect();
+        assert_eq!(tagged, vec!["n1"]);
+        let referencing: Vec<String> = BlockDao::get_referencing(conn, "b1", false)
+            .unwrap()
+            .into_iter()
+            .map(|b| b.id)
+            .collect();
+        assert_eq!(referencing, vec!["b3", "b2"]);
+        assert_eq!(
+            BlockDao::get_referenced(conn, "b2", false).unwrap()[0].id,
+            "b1"
+        );
+        let attachments: Vec<String> = AttachmentDao::get_for_note(conn, "n1")
+            .unwrap()
+            .into_iter()
+            .map(|a| a.id)
+            .collect();
+        assert_eq!(attachments, vec!["a2", "a1"]);
+        assert_eq!(
+            AttachmentDao::get_for_block(conn, "b1").unwrap()[0].id,
+            "a1"
+        );
+        // Two links from the same note give one backlink; deleted notes are left out
+        let backlinks: Vec<String> = NoteDao::get_linking_to(conn, "n1", false)
+            .unwrap()
+            .into_iter()
+            .map(|n| n.id)
+            .collect();
+        assert_eq!(backlinks, vec!["n2"]);
+    }
 }
@@ -1,19 +1,46 @@
 //! Database schema and migration
 
-use rusqlite::{Connection, Result};
+use rusqlite::{Connection, OptionalExtension, Result};
+
+use super::tokenizer::{CJK_TOKENIZER, register_cjk_tokenizer};
 
 /// Current database schema version
-const SCHEMA_VERSION: u32 = 1;
+pub(crate) const SCHEMA_VERSION: u32 = 5;
+
+/// First schema version with typed note properties and aliases
+pub(crate) const PROPERTIES_VERSION: u32 = 2;
+
+/// First schema version whose full-text indexes use the CJK tokenizer
+pub(crate) const CJK_FTS_VERSION: u32 = 3;
+
+/// Setting present while note content still has to be reindexed after the full-text indexes
+/// were rebuilt. It is set with the rebuild and cleared when a reindex commits, so an
+/// interrupted reindex is retried on the next open.
+pub(crate) const REINDEX_PENDING: &str = "reindex_pending";
+
+/// Setting present while properties and aliases still have to be filled from front matter
+pub(crate) const FRONT_MATTER_PENDING: &str = "front_matter_pending";
 
-/// Initialize the database with all tables
+/// Initialize the database with all tables. The migration runs in one transaction, so an
+/// interrupted upgrade leaves the previous version behind.
 pub fn init_database(conn: &Connection) -> Result<()> {
     conn.execute("PRAGMA foreign_keys = ON", [])?;
+    register_cjk_tokenizer(conn)?;
+    let tx = conn.unchecked_transaction()?;
+    migrate(&tx)?;
+    tx.commit()
+}
+
+fn migrate(conn: &Connection) -> Result<()> {
+    let previous = schema_version(conn)?;
     create_notes_table(conn)?;
     create_blocks_table(conn)?;
     create_folders_table(conn)?;
     create_note_folders_table(conn)?;
     create_tags_table(conn)?;
     create_note_tags_table(conn)?;
+    create_note_properties_table(conn)?;
+    create_note_aliases_table(conn)?;
     create_links_table(conn)?;
     create_block_references_table(conn)?;
     create_databases_table(conn)?;
@@ -21,19 +48,65 @@ pub fn init_database(conn: &Connection) -> Result<()> {
     create_attachments_table(conn)?;
     create_note_attachments_table(conn)?;
     create_block_attachments_table(conn)?;
+    create_saved_searches_table(conn)?;
+    create_blobs_table(conn)?;
+    create_settings_table(conn)?;
+    let rebuild_fts = previous.is_some_and(|version| version < CJK_FTS_VERSION);
+    if rebuild_fts {
+        drop_fts_tables(conn)?;
+    }
     create_fts_tables(conn)?;
+    if rebuild_fts {
+        rebuild_fts_indexes(conn)?;
+    }
     create_indexes(conn)?;
+    // Leave the work that needs note content to the service layer
+    if rebuild_fts {
+        set_pending(conn, REINDEX_PENDING)?;
+    }
+    if previous.is_some_and(|version| version < PROPERTIES_VERSION) {
+        set_pending(conn, FRONT_MATTER_PENDING)?;
+    }
     conn.execute(
         "CREATE TABLE IF NOT EXISTS schema_version (version INTEGER PRIMARY KEY)",
         [],
     )?;
+    // A current database is left unwritten, so opening it does not wait for a writer
+    if previous != Some(SCHEMA_VERSION) {
+        conn.execute(
+            "INSERT OR REPLACE INTO schema_version (version) VALUES (?1)",
+            [SCHEMA_VERSION],
+        )?;
+    }
+    Ok(())
+}
+
+fn set_pending(conn: &Connection, key: &str) -> Result<()> {
     conn.execute(
-        "INSERT OR REPLACE INTO schema_version (version) VALUES (?1)",
-        [SCHEMA_VERSION],
+        "INSERT OR REPLACE INTO settings (key, value) VALUES (?1, '1')",
+        [key],
     )?;
     Ok(())
 }
 
+/// Schema version stored in the database, or `None` for a new database
+pub fn schema_version(conn: &Connection) -> Result<Option<u32>> {
+    let exists = conn
+        .query_row(
+            "SELECT 1 FROM sqlite_master WHERE type = 'table' AND name = 'schema_version'",
+            [],
+            |_| Ok(()),
+        )
+        .optional()?
+        .is_some();
+    if !exists {
+        return Ok(None);
+    }
+    conn.query_row("SELECT MAX(version) FROM schema_version", [], |row| {
+        row.get(0)
+    })
+}
+
 fn create_notes_table(conn: &Connection) -> Result<()> {
     conn.execute(
         r#"
@@ -145,6 +218,41 @@ fn create_note_tags_table(conn: &Connection) -> Result<()> {
     Ok(())
 }
 
+fn create_note_properties_table(conn: &Connection) -> Result<()> {
+    conn.execute(
+        r#"
+        CREATE TABLE IF NOT EXISTS note_properties (
+            note_id TEXT NOT NULL,
+            key TEXT NOT NULL,
+            value_type TEXT NOT NULL,
+            value TEXT NOT NULL,
+            position INTEGER DEFAULT 0,
+            PRIMARY KEY (note_id, key),
+            FOREIGN KEY (note_id) REFERENCES notes(id) ON DELETE CASCADE
+        )
+        "#,
+        [],
+    )?;
+    Ok(())
+}
+
+fn create_note_aliases_table(conn: &Connection) -> Result<()> {
+    conn.execute(
+        r#"
+        CREATE TABLE IF NOT EXISTS note_aliases (
+            note_id TEXT NOT NULL,
+            alias TEXT NOT NULL,
+            position INTEGER DEFAULT 0,
+            created_at INTEGER NOT NULL,
+            PRIMARY KEY (note_id, alias),
+            FOREIGN KEY (note_id) REFERENCES notes(id) ON DELETE CASCADE
+        )
+        "#,
+        [],
+    )?;
+    Ok(())
+}
+
 fn create_links_table(conn: &Connection) -> Result<()> {
     conn.execute(
         r#"
@@ -284,26 +392,46 @@ fn create_block_attachments_table(conn: &Connection) -> Result<()> {
     Ok(())
 }
 
-fn create_fts_tables(conn: &Connection) -> Result<()> {
+fn create_saved_searches_table(conn: &Connection) -> Result<()> {
+    conn.execute(
+        r#"
+        CREATE TABLE IF NOT EXISTS saved_searches (
+            id TEXT PRIMARY KEY,
+            name TEXT NOT NULL,
+            query TEXT NOT NULL,
+            sort_key TEXT NOT NULL DEFAULT 'updated',
+            sort_desc INTEGER NOT NULL DEFAULT 1,
+            position INTEGER DEFAULT 0,
+            created_at INTEGER NOT NULL,
+            updated_at INTEGER NOT NULL
+        )
+        "#,
+        [],
+    )?;
+    Ok(())
+}
+
+fn create_blobs_table(conn: &Connection) -> Result<()> {
     conn.execute(
         r#"
-        CREATE VIRTUAL TABLE IF NOT EXISTS notes_fts USING fts5(
-            note_id UNINDEXED,
-            title,
-            content,
-            content=notes,
-            content_rowid=rowid
+        CREATE TABLE IF NOT EXISTS blobs (
+            path TEXT PRIMARY KEY,
+            data BLOB NOT NULL,
+            updated_at INTEGER NOT NULL
         )
         "#,
         [],
     )?;
+    Ok(())
+}
+
+/// Vault-wide settings, such as where note content is kept
+fn create_settings_table(conn: &Connection) -> Result<()> {
     conn.execute(
         r#"
-        CREATE VIRTUAL TABLE IF NOT EXISTS blocks_fts USING fts5(
-            block_id UNINDEXED,
-            content,
-            content=blocks,
-            content_rowid=rowid
+        CREATE TABLE IF NOT EXISTS settings (
+            key TEXT PRIMARY KEY,
+            value TEXT NOT NULL
         )
         "#,
         [],
@@ -311,13 +439,108 @@ fn create_fts_tables(conn: &Connection) -> Result<()> {
     Ok(())
 }
 
+/// Full-text indexes. `notes_fts` stores its own text because note content lives in files:
+/// the triggers keep rows and titles in step with `notes`, and the note service writes the
+/// content. `blocks_fts` is an external-content index over `blocks`, kept current by triggers.
+fn create_fts_tables(conn: &Connection) -> Result<()> {
+    conn.execute(
+        &format!(
+            r#"
+            CREATE VIRTUAL TABLE IF NOT EXISTS notes_fts USING fts5(
+                note_id UNINDEXED,
+                title,
+                content,
+                tokenize = '{CJK_TOKENIZER} remove_diacritics 2'
+            )
+            "#
+        ),
+        [],
+    )?;
+    conn.execute(
+        &format!(
+            r#"
+            CREATE VIRTUAL TABLE IF NOT EXISTS blocks_fts USING fts5(
+                content,
+                content=blocks,
+                content_rowid=rowid,
+                tokenize = '{CJK_TOKENIZER} remove_diacritics 2'
+            )
+            "#
+        ),
+        [],
+    )?;
+    conn.execute_batch(
+        r#"
+        CREATE TRIGGER IF NOT EXISTS notes_fts_insert AFTER INSERT ON notes BEGIN
+            INSERT INTO notes_fts (rowid, note_id, title, content) VALUES (new.rowid, new.id, new.title, '');
+        END;
+        CREATE TRIGGER IF NOT EXISTS notes_fts_delete AFTER DELETE ON notes BEGIN
+            DELETE FROM notes_fts WHERE rowid = old.rowid;
+        END;
+        CREATE TRIGGER IF NOT EXISTS notes_fts_update_title AFTER UPDATE OF title ON notes BEGIN
+            UPDATE notes_fts SET title = new.title WHERE rowid = new.rowid;
+        END;
+        CREATE TRIGGER IF NOT EXISTS blocks_fts_insert AFTER INSERT ON blocks BEGIN
+            INSERT INTO blocks_fts (rowid, content) VALUES (new.rowid, new.content);
+        END;
+        CREATE TRIGGER IF NOT EXISTS blocks_fts_delete AFTER DELETE ON blocks BEGIN
+            INSERT INTO blocks_fts (blocks_fts, rowid, content) VALUES ('delete', old.rowid, old.content);
+        END;
+        CREATE TRIGGER IF NOT EXISTS blocks_fts_update AFTER UPDATE OF content ON blocks BEGIN
+            INSERT INTO blocks_fts (blocks_fts, rowid, content) VALUES ('delete', old.rowid, old.content);
+            INSERT INTO blocks_fts (rowid, content) VALUES (new.rowid, new.content);
+        END;
+        "#,
+    )?;
+    Ok(())
+}
+
+/// Drop the full-text indexes so they are recreated with the current tokenizer
+fn drop_fts_tables(conn: &Connection) -> Result<()> {
+    conn.execute_batch(
+        r#"
+        DROP TABLE IF EXISTS notes_fts;
+        DROP TABLE IF EXISTS blocks_fts;
+        "#,
+    )
+}
+
+/// Refill freshly created indexes from existing rows. Note content is read from its content
+/// store, so only titles are indexed here; `ServiceContext` reindexes the content while
+/// [`REINDEX_PENDING`] is set.
+fn rebuild_fts_indexes(conn: &Connection) -> Result<()> {
+    conn.execute_batch(
+        r#"
+        INSERT INTO notes_fts (rowid, note_id, title, content) SELECT rowid, id, title, '' FROM notes;
+        INSERT INTO blocks_fts (blocks_fts) VALUES ('rebuild');
+        "#,
+    )
+}
+
 fn create_indexes(conn: &Connection) -> Result<()> {
     conn.execute("CREATE INDEX IF NOT EXISTS idx_notes_created_at ON notes(created_at)", [])?;
     conn.execute("CREATE INDEX IF NOT EXISTS idx_notes_updated_at ON notes(updated_at)", [])?;
     conn.execute("CREATE INDEX IF NOT EXISTS idx_notes_title ON notes(title)", [])?;
-    conn.execute("CREATE INDEX IF NOT EXISTS idx_notes_deleted_at ON notes(deleted_at)", [])?;
-    conn.execute("CREATE INDEX IF NOT EXISTS idx_notes_is_deleted ON notes(is_deleted)", [])?;
-    conn.execute("CREATE INDEX IF NOT EXISTS idx_blocks_note_id ON blocks(note_id)", [])?;
+    conn.execute(
+        "CREATE INDEX IF NOT EXISTS idx_notes_title_nocase ON notes(title COLLATE NOCASE, id)",
+        [],
+    )?;
+    conn.execute(
+        "CREATE INDEX IF NOT EXISTS idx_notes_word_count ON notes(word_count)",
+        [],
+    )?;
+    conn.execute(
+        "CREATE INDEX IF NOT EXISTS idx_notes_deleted_at ON notes(deleted_at)",
+        [],
+    )?;
+    conn.execute(
+        "CREATE INDEX IF NOT EXISTS idx_notes_is_deleted ON notes(is_deleted)",
+        [],
+    )?;
+    conn.execute(
+        "CREATE INDEX IF NOT EXISTS idx_blocks_note_id ON blocks(note_id)",
+        [],
+    )?;
     conn.execute(
         "CREATE INDEX IF NOT EXISTS idx_blocks_position ON blocks(note_id, position)",
         [],
@@ -351,6 +574,14 @@ fn create_indexes(conn: &Connection) -> Result<()> {
         "CREATE INDEX IF NOT EXISTS idx_note_tags_tag_id ON note_tags(tag_id)",
         [],
     )?;
+    conn.execute(
+        "CREATE INDEX IF NOT EXISTS idx_note_properties_key ON note_properties(key)",
+        [],
+    )?;
+    conn.execute(
+        "CREATE INDEX IF NOT EXISTS idx_note_aliases_alias ON note_aliases(alias)",
+        [],
+    )?;
     conn.execute("CREATE INDEX IF NOT EXISTS idx_links_source_note ON links(source_note_id)", [])?;
     conn.execute("CREATE INDEX IF NOT EXISTS idx_links_target_note ON links(target_note_id)", [])?;
     conn.execute("CREATE INDEX IF NOT EXISTS idx_links_source_block ON links(source_block_id)", [])?;
@@ -415,5 +646,113 @@ mod tests {
             .unwrap();
         assert!(tables.contains(&"notes".to_string()));
         assert!(tables.contains(&"blocks".to_string()));
+        assert!(tables.contains(&"saved_searches".to_string()));
+        assert!(tables.contains(&"blobs".to_string()));
+        assert!(tables.contains(&"settings".to_string()));
+        assert_eq!(schema_version(&conn).unwrap(), Some(SCHEMA_VERSION));
+    }
+
+    #[test]
+    fn test_blocks_fts_follows_blocks() {
+        let conn = Connection::open_in_memory().unwrap();
+        init_database(&conn).unwrap();
+        conn.execute_batch(
+            r#"
+            INSERT INTO notes (id, title, content_path, created_at, updated_at) VALUES ('n1', '笔记', 'notes/n1.md', 0, 0);
+            INSERT INTO blocks (id, note_id, block_type, content, position, created_at, updated_at)
+                VALUES ('b1', 'n1', 'paragraph', '个人知识管理系统', 0, 0, 0);
+            "#,
+        )
+        .unwrap();
+        let count = |query: &str| -> i64 {
+            conn.query_row(
+                "SELECT COUNT(*) FROM blocks_fts WHERE blocks_fts MATCH ?1",
+                [query],
+                |row| row.get(0),
+            )
+            .unwrap()
+        };
+        assert_eq!(count("知识"), 1);
+        conn.execute(
+            "UPDATE blocks SET content = 'Knowledge base' WHERE id = 'b1'",
+            [],
+        )
+        .unwrap();
+        assert_eq!(count("知识"), 0);
+        assert_eq!(count("knowledge"), 1);
+
+        let title: String = conn
+            .query_row(
+                "SELECT title FROM notes_fts WHERE notes_fts MATCH '笔记'",
+                [],
+                |row| row.get(0),
+            )
+            .unwrap();
+        assert_eq!(title, "笔记");
+        conn.execute("DELETE FROM notes WHERE id = 'n1'", [])
+            .unwrap();
+        assert_eq!(count("knowledge"), 0);
+        let notes: i64 = conn
+            .query_row("SELECT COUNT(*) FROM notes_fts", [], |row| row.get(0))
+            .unwrap();
+        assert_eq!(notes, 0);
+    }
+
+    #[test]
+    fn test_upgrade_rebuilds_fts_with_cjk_tokenizer() {
+        let conn = Connection::open_in_memory().unwrap();
+        init_database(&conn).unwrap();
+        // Recreate the version 2 indexes, which used the default tokenizer
+        conn.execute_batch(
+            r#"
+            DROP TABLE notes_fts;
+            DROP TABLE blocks_fts;
+            DROP TRIGGER notes_fts_insert;
+            DROP TRIGGER blocks_fts_insert;
+            CREATE VIRTUAL TABLE notes_fts USING fts5(note_id UNINDEXED, title, content, content=notes, content_rowid=rowid);
+            CREATE VIRTUAL TABLE blocks_fts USING fts5(block_id UNINDEXED, content, content=blocks, content_rowid=rowid);
+            DELETE FROM schema_version;
+            INSERT INTO schema_version (version) VALUES (2);
+            INSERT INTO notes (id, title, content_path, created_at, updated_at) VALUES ('n1', '知识管理', 'notes/n1.md', 0, 0);
+            INSERT INTO blocks (id, note_id, block_type, content, position, created_at, updated_at)
+                VALUES ('b1', 'n1', 'paragraph', '中文分词', 0, 0, 0);
+            "#,
+        )
+        .unwrap();
+
+        init_database(&conn).unwrap();
+        assert_eq!(schema_version(&conn).unwrap(), Some(SCHEMA_VERSION));
+        let pending: String = conn
+            .query_row(
+                "SELECT value FROM settings WHERE key = ?1",
+                [REINDEX_PENDING],
+                |row| row.get(0),
+            )
+            .unwrap();
+        assert_eq!(pending, "1");
+        let sql: String = conn
+            .query_row(
+                "SELECT sql FROM sqlite_master WHERE name = 'blocks_fts'",
+                [],
+                |row| row.get(0),
+            )
+            .unwrap();
+        assert!(sql.contains(CJK_TOKENIZER));
+        let block: i64 = conn
+            .query_row(
+                "SELECT rowid FROM blocks_fts WHERE blocks_fts MATCH '分词'",
+                [],
+                |row| row.get(0),
+            )
+            .unwrap();
+        assert_eq!(block, 1);
+        let note: String = conn
+            .query_row(
+                "SELECT note_id FROM notes_fts WHERE notes_fts MATCH '管理'",
+                [],
+                |row| row.get(0),
+            )
+            .unwrap();
+        assert_eq!(note, "n1");
     }
 }
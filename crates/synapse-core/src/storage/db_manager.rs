@@ -3,23 +3,35 @@
 use rusqlite::{Connection, Result};
 use std::path::Path;
 
-use super::database::init_database;
+use super::database::{SCHEMA_VERSION, init_database, schema_version};
 
 pub struct DatabaseManager {
     conn: Connection,
+    upgraded_from: Option<u32>,
 }
 
 impl DatabaseManager {
     pub fn new<P: AsRef<Path>>(db_path: P) -> Result<Self> {
-        let conn = Connection::open(db_path)?;
-        init_database(&conn)?;
-        Ok(Self { conn })
+        Self::init(Connection::open(db_path)?)
     }
 
     pub fn in_memory() -> Result<Self> {
-        let conn = Connection::open_in_memory()?;
+        Self::init(Connection::open_in_memory()?)
+    }
+
+    fn init(conn: Connection) -> Result<Self> {
+        let previous = schema_version(&conn)?;
         init_database(&conn)?;
-        Ok(Self { conn })
+        let upgraded_from = previous.filter(|&version| version < SCHEMA_VERSION);
+        Ok(Self {
+            conn,
+            upgraded_from,
+        })
+    }
+
+    /// Schema version the database had before it was migrated on open, if it was
+    pub fn upgraded_from(&self) -> Option<u32> {
+        self.upgraded_from
     }
 
     pub fn conn(&self) -> &Connection {
@@ -29,6 +41,10 @@ impl DatabaseManager {
     pub fn conn_mut(&mut self) -> &mut Connection {
         &mut self.conn
     }
+
+    pub fn into_conn(self) -> Connection {
+        self.conn
+    }
 }
 
 #[cfg(test)]
@@ -41,4 +57,89 @@ mod tests {
         let count: i64 = db.conn().prepare("SELECT COUNT(*) FROM notes").unwrap().query_row([], |row| row.get(0)).unwrap();
         assert_eq!(count, 0);
     }
+
+    #[test]
+    fn test_upgrade_backfills_properties_from_front_matter() {
+        use crate::services::{NoteService, ServiceContext};
+
+        let dir = std::env::temp_dir().join(format!("synapse-upgrade-{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let db_path = dir.join("synapse.db");
+        let ctx = ServiceContext::new(&db_path, &dir).unwrap();
+        let content = "---\nrating: 4\naliases: [Plan]\n---\nBody".to_string();
+        let note = NoteService::create(&ctx, "Roadmap".to_string(), content).unwrap();
+        drop(ctx);
+
+        // Take the database back to version 1, before properties and aliases were stored
+        let conn = Connection::open(&db_path).unwrap();
+        conn.execute_batch(
+            r#"
+            DROP TABLE note_properties;
+            DROP TABLE note_aliases;
+            DELETE FROM schema_version;
+            INSERT INTO schema_version (version) VALUES (1);
+            "#,
+        )
+        .unwrap();
+        drop(conn);
+
+        let ctx = ServiceContext::new(&db_path, &dir).unwrap();
+        let properties = NoteService::get_properties(&ctx, &note.id).unwrap();
+        assert!(properties.iter().any(|property| property.key == "rating"));
+        assert_eq!(
+            NoteService::get_aliases(&ctx, &note.id).unwrap(),
+            vec!["Plan"]
+        );
+        drop(ctx);
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_interrupted_reindex_is_retried() {
+        use crate::services::{NoteService, SearchService, ServiceContext};
+
+        let dir = std::env::temp_dir().join(format!("synapse-reindex-{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let db_path = dir.join("synapse.db");
+        let ctx = ServiceContext::new(&db_path, &dir).unwrap();
+        NoteService::create(
+            &ctx,
+            "Roadmap".to_string(),
+            "Quarterly milestones".to_string(),
+        )
+        .unwrap();
+        drop(ctx);
+
+        // The upgrade committed but the process died before the content was reindexed
+        let conn = Connection::open(&db_path).unwrap();
+        crate::storage::register_cjk_tokenizer(&conn).unwrap();
+        conn.execute_batch(
+            r#"
+            UPDATE notes_fts SET content = '';
+            INSERT INTO settings (key, value) VALUES ('reindex_pending', '1');
+            "#,
+        )
+        .unwrap();
+        drop(conn);
+
+        let ctx = ServiceContext::new(&db_path, &dir).unwrap();
+        assert_eq!(
+            SearchService::search_notes(&ctx, "milestones", false)
+                .unwrap()
+                .len(),
+            1
+        );
+        drop(ctx);
+        let conn = Connection::open(&db_path).unwrap();
+        let pending: i64 = conn
+            .query_row(
+                "SELECT COUNT(*) FROM settings WHERE key LIKE '%_pending'",
+                [],
+                |row| row.get(0),
+            )
+            .unwrap();
+        assert_eq!(pending, 0);
+        drop(conn);
+        std::fs::remove_dir_all(dir).unwrap();
+    }
 }
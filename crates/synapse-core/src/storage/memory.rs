@@ -0,0 +1,1664 @@
+//! In-memory storage for tests, without SQLite.
+//!
+//! Tables are vectors of models. Readers share an immutable snapshot of the committed tables;
+//! a transaction works on its own copy, which replaces the snapshot when it commits, so
+//! readers never see uncommitted writes and a dropped transaction leaves nothing behind.
+//! Copying makes a transaction as expensive as the vault is large, which suits test vaults.
+//!
+//! The SQLite schema is mirrored where services rely on it: orderings, unique and foreign
+//! keys, cascading deletes and read-only readers. Full-text search is approximated: a note
+//! or block matches when each query term appears in it, ignoring case.
+
+use std::cmp::{Ordering, Reverse};
+use std::collections::{HashMap, HashSet};
+use std::sync::{Arc, Mutex, MutexGuard, RwLock, RwLockReadGuard, RwLockWriteGuard};
+
+use super::backend::*;
+use super::dao::{CursorValue, PageCursor};
+use crate::models::*;
+use crate::query::{Filter, Query};
+use crate::{Error, Result};
+
+/// Storage that lives in memory and is gone when dropped
+pub struct MemoryBackend {
+    committed: RwLock<Arc<MemoryStore>>,
+    writer: Mutex<()>,
+}
+
+/// A snapshot of the committed tables, or the working copy of a transaction
+pub struct MemoryStore {
+    tables: RwLock<Tables>,
+    read_only: bool,
+}
+
+/// A transaction on a copy of the committed tables
+pub struct MemoryTransaction<'a> {
+    backend: &'a MemoryBackend,
+    _writer: MutexGuard<'a, ()>,
+    store: MemoryStore,
+}
+
+#[derive(Clone, Default)]
+struct Tables {
+    notes: Vec<Note>,
+    /// Indexed content by note ID
+    note_content: HashMap<NoteId, String>,
+    note_properties: Vec<NoteProperty>,
+    note_aliases: Vec<NoteAlias>,
+    blocks: Vec<Block>,
+    block_references: Vec<BlockReference>,
+    tags: Vec<Tag>,
+    note_tags: Vec<(NoteId, TagId)>,
+    folders: Vec<Folder>,
+    note_folders: Vec<NoteFolder>,
+    links: Vec<Link>,
+    attachments: Vec<Attachment>,
+    note_attachments: Vec<NoteAttachment>,
+    block_attachments: Vec<(BlockId, AttachmentId)>,
+    saved_searches: Vec<SavedSearch>,
+    blobs: HashMap<String, Vec<u8>>,
+    settings: HashMap<String, String>,
+}
+
+#[derive(Clone)]
+struct NoteAlias {
+    note_id: NoteId,
+    alias: String,
+    position: i64,
+}
+
+#[derive(Clone)]
+struct BlockReference {
+    id: String,
+    source_block_id: BlockId,
+    target_block_id: BlockId,
+    created_at: i64,
+}
+
+#[derive(Clone)]
+struct NoteFolder {
+    note_id: NoteId,
+    folder_id: FolderId,
+    is_primary: bool,
+    position: i64,
+}
+
+#[derive(Clone)]
+struct NoteAttachment {
+    note_id: NoteId,
+    attachment_id: AttachmentId,
+    position: i64,
+}
+
+impl MemoryBackend {
+    pub fn new() -> Self {
+        Self {
+            committed: RwLock::new(Arc::new(MemoryStore::new(Tables::default(), true))),
+            writer: Mutex::new(()),
+        }
+    }
+}
+
+impl Default for MemoryBackend {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl StorageBackend for MemoryBackend {
+    type Store = MemoryStore;
+    type Reader<'a> = Arc<MemoryStore>;
+    type Transaction<'a> = MemoryTransaction<'a>;
+
+    fn reader(&self) -> Result<Arc<MemoryStore>> {
+        Ok(Arc::clone(&read_lock(&self.committed)))
+    }
+
+    fn begin(&self) -> Result<MemoryTransaction<'_>> {
+        let writer = lock(&self.writer);
+        let tables = read_lock(&self.committed).tables().clone();
+        Ok(MemoryTransaction {
+            backend: self,
+            _writer: writer,
+            store: MemoryStore::new(tables, false),
+        })
+    }
+}
+
+impl std::ops::Deref for MemoryTransaction<'_> {
+    type Target = MemoryStore;
+
+    fn deref(&self) -> &MemoryStore {
+        &self.store
+    }
+}
+
+impl StorageTransaction for MemoryTransaction<'_> {
+    fn commit(self) -> Result<()> {
+        let tables = self
+            .store
+            .tables
+            .into_inner()
+            .unwrap_or_else(|e| e.into_inner());
+        *write_lock(&self.backend.committed) = Arc::new(MemoryStore::new(tables, true));
+        Ok(())
+    }
+}
+
+impl MemoryStore {
+    fn new(tables: Tables, read_only: bool) -> Self {
+        Self {
+            tables: RwLock::new(tables),
+            read_only,
+        }
+    }
+
+    fn tables(&self) -> RwLockReadGuard<'_, Tables> {
+        read_lock(&self.tables)
+    }
+
+    /// The tables for writing; snapshots handed to readers refuse writes like SQLite's
+    /// `query_only` readers
+    fn tables_mut(&self) -> Result<RwLockWriteGuard<'_, Tables>> {
+        if self.read_only {
+            return Err(Error::Storage(
+                "attempt to write a readonly database".to_string(),
+            ));
+        }
+        Ok(write_lock(&self.tables))
+    }
+}
+
+impl Tables {
+    fn note(&self, id: &str) -> Option<&Note> {
+        self.notes.iter().find(|note| note.id == id)
+    }
+
+    fn has_note(&self, id: &str) -> bool {
+        self.note(id).is_some()
+    }
+
+    fn block(&self, id: &str) -> Option<&Block> {
+        self.blocks.iter().find(|block| block.id == id)
+    }
+
+    fn has_block(&self, id: &str) -> bool {
+        self.block(id).is_some()
+    }
+
+    fn tag(&self, id: &str) -> Option<&Tag> {
+        self.tags.iter().find(|tag| tag.id == id)
+    }
+
+    fn folder(&self, id: &str) -> Option<&Folder> {
+        self.folders.iter().find(|folder| folder.id == id)
+    }
+
+    fn attachment(&self, id: &str) -> Option<&Attachment> {
+        self.attachments
+            .iter()
+            .find(|attachment| attachment.id == id)
+    }
+
+    /// Notes with the given IDs that are visible, in table order and without duplicates
+    fn notes_in(&self, ids: &HashSet<&str>, include_deleted: bool) -> Vec<Note> {
+        self.notes
+            .iter()
+            .filter(|note| {
+                ids.contains(note.id.as_str()) && visible(note.is_deleted, include_deleted)
+            })
+            .cloned()
+            .collect()
+    }
+
+    /// Notes with a tag, with no position
+    fn tagged(&self, tag_id: &str, include_deleted: bool) -> Vec<Note> {
+        let ids = self
+            .note_tags
+            .iter()
+            .filter(|(_, t)| t == tag_id)
+            .map(|(note_id, _)| note_id.as_str())
+            .collect();
+        self.notes_in(&ids, include_deleted)
+    }
+
+    /// Notes in a folder, with their positions in it
+    fn in_folder(
+        &self,
+        folder_id: &str,
+        include_deleted: bool,
+    ) -> (Vec<Note>, HashMap<NoteId, i64>) {
+        let positions: HashMap<NoteId, i64> = self
+            .note_folders
+            .iter()
+            .filter(|nf| nf.folder_id == folder_id)
+            .map(|nf| (nf.note_id.clone(), nf.position))
+            .collect();
+        let ids = positions.keys().map(String::as_str).collect();
+        let notes = self.notes_in(&ids, include_deleted);
+        (notes, positions)
+    }
+
+    /// Whether a note passes one query filter, before negation
+    fn matches(&self, note: &Note, filter: &Filter) -> bool {
+        match filter {
+            Filter::Tag(name) => self.note_tags.iter().any(|(note_id, tag_id)| {
+                *note_id == note.id
+                    && self
+                        .tag(tag_id)
+                        .is_some_and(|tag| tag.name.eq_ignore_ascii_case(name))
+            }),
+            Filter::Folder(path) => self.note_folders.iter().any(|nf| {
+                nf.note_id == note.id
+                    && self.folder(&nf.folder_id).is_some_and(|folder| {
+                        folder.path == *path || folder.path.starts_with(&format!("{}/", path))
+                    })
+            }),
+            Filter::Text(text) => self.matches_text(note, &[text.to_lowercase()]),
+        }
+    }
+
+    /// Whether every term appears in the note's title or indexed content
+    fn matches_text(&self, note: &Note, terms: &[String]) -> bool {
+        let content = self
+            .note_content
+            .get(&note.id)
+            .map(String::as_str)
+            .unwrap_or_default();
+        contains_all(&format!("{}\n{}", note.title, content), terms)
+    }
+
+    fn referenced_blocks(
+        &self,
+        block_id: &str,
+        referencing: bool,
+        include_deleted: bool,
+    ) -> Vec<Block> {
+        let mut references: Vec<&BlockReference> = self
+            .block_references
+            .iter()
+            .filter(|r| {
+                let end = if referencing {
+                    &r.target_block_id
+                } else {
+                    &r.source_block_id
+                };
+                end == block_id
+            })
+            .collect();
+        references.sort_by_key(|r| r.created_at);
+        references
+            .into_iter()
+            .filter_map(|r| {
+                let other = if referencing {
+                    &r.source_block_id
+                } else {
+                    &r.target_block_id
+                };
+                self.block(other)
+            })
+            .filter(|block| visible(block.is_deleted, include_deleted))
+            .cloned()
+            .collect()
+    }
+}
+
+impl NoteStorage for MemoryStore {
+    fn create_note(&self, note: &Note) -> Result<()> {
+        let mut t = self.tables_mut()?;
+        if t.has_note(&note.id) {
+            return Err(unique("notes.id"));
+        }
+        t.notes.push(note.clone());
+        Ok(())
+    }
+
+    fn get_note(&self, id: &str, include_deleted: bool) -> Result<Option<Note>> {
+        let t = self.tables();
+        Ok(t.note(id)
+            .filter(|note| visible(note.is_deleted, include_deleted))
+            .cloned())
+    }
+
+    fn update_note(&self, note: &Note) -> Result<()> {
+        let mut t = self.tables_mut()?;
+        if let Some(row) = t.notes.iter_mut().find(|row| row.id == note.id) {
+            *row = Note {
+                created_at: row.created_at,
+                ..note.clone()
+            };
+        }
+        Ok(())
+    }
+
+    fn soft_delete_note(&self, id: &str) -> Result<()> {
+        let mut t = self.tables_mut()?;
+        if let Some(note) = t.notes.iter_mut().find(|note| note.id == id) {
+            note.is_deleted = true;
+            note.deleted_at = Some(chrono::Utc::now().timestamp());
+        }
+        Ok(())
+    }
+
+    fn restore_note(&self, id: &str) -> Result<()> {
+        let mut t = self.tables_mut()?;
+        if let Some(note) = t.notes.iter_mut().find(|note| note.id == id) {
+            note.is_deleted = false;
+            note.deleted_at = None;
+        }
+        Ok(())
+    }
+
+    fn list_notes(&self, include_deleted: bool) -> Result<Vec<Note>> {
+        let t = self.tables();
+        let mut notes: Vec<Note> = t
+            .notes
+            .iter()
+            .filter(|note| visible(note.is_deleted, include_deleted))
+            .cloned()
+            .collect();
+        by_recent_update(&mut notes);
+        Ok(notes)
+    }
+
+    fn list_notes_page(&self, include_deleted: bool, request: &PageRequest) -> Result<Page<Note>> {
+        let notes = self.list_notes(include_deleted)?;
+        page(notes, None, request)
+    }
+
+    fn search_notes_by_title(&self, query: &str, include_deleted: bool) -> Result<Vec<Note>> {
+        let mut notes = self.list_notes(include_deleted)?;
+        // LIKE is case-insensitive for ASCII only
+        let query = query.to_ascii_lowercase();
+        notes.retain(|note| note.title.to_ascii_lowercase().contains(&query));
+        Ok(notes)
+    }
+
+    fn query_notes(
+        &self,
+        query: &Query,
+        sort: NoteSort,
+        include_deleted: bool,
+    ) -> Result<Vec<Note>> {
+        if sort.key == SortKey::Position {
+            return Err(manual_order_unavailable());
+        }
+        let t = self.tables();
+        let mut notes: Vec<Note> = t
+            .notes
+            .iter()
+            .filter(|note| visible(note.is_deleted, include_deleted))
+            .filter(|note| {
+                query
+                    .terms
+                    .iter()
+                    .all(|term| t.matches(note, &term.filter) != term.negated)
+            })
+            .cloned()
+            .collect();
+        notes.sort_by(|a, b| {
+            compare_notes(
+                (&sort_value(a, sort.key, None), a),
+                (&sort_value(b, sort.key, None), b),
+                sort,
+            )
+        });
+        Ok(notes)
+    }
+
+    fn query_notes_page(
+        &self,
+        query: &Query,
+        include_deleted: bool,
+        request: &PageRequest,
+    ) -> Result<Page<Note>> {
+        let notes = self.query_notes(query, request.sort, include_deleted)?;
+        page(notes, None, request)
+    }
+
+    fn index_note_content(&self, note_id: &str, content: &str) -> Result<()> {
+        let mut t = self.tables_mut()?;
+        if t.has_note(note_id) {
+            t.note_content
+                .insert(note_id.to_string(), content.to_string());
+        }
+        Ok(())
+    }
+
+    fn search_note_content(&self, query: &str, include_deleted: bool) -> Result<Vec<Note>> {
+        let terms = fts_terms(query);
+        let mut notes = self.list_notes(include_deleted)?;
+        let t = self.tables();
+        notes.retain(|note| t.matches_text(note, &terms));
+        Ok(notes)
+    }
+
+    fn replace_note_properties(
+        &self,
+        note_id: &str,
+        properties: &[(String, PropertyValue)],
+    ) -> Result<()> {
+        let mut t = self.tables_mut()?;
+        t.note_properties.retain(|p| p.note_id != note_id);
+        if !properties.is_empty() && !t.has_note(note_id) {
+            return Err(foreign_key());
+        }
+        for (position, (key, value)) in properties.iter().enumerate() {
+            if t.note_properties
+                .iter()
+                .any(|p| p.note_id == note_id && p.key == *key)
+            {
+                return Err(unique("note_properties.note_id, note_properties.key"));
+            }
+            t.note_properties.push(NoteProperty {
+                note_id: note_id.to_string(),
+                key: key.clone(),
+                value: value.clone(),
+                position: position as i64,
+            });
+        }
+        Ok(())
+    }
+
+    fn note_properties(&self, note_id: &str) -> Result<Vec<NoteProperty>> {
+        let t = self.tables();
+        let mut properties: Vec<NoteProperty> = t
+            .note_properties
+            .iter()
+            .filter(|p| p.note_id == note_id)
+            .cloned()
+            .collect();
+        properties.sort_by_key(|p| p.position);
+        Ok(properties)
+    }
+
+    fn replace_note_aliases(&self, note_id: &str, aliases: &[String]) -> Result<()> {
+        let mut t = self.tables_mut()?;
+        t.note_aliases.retain(|a| a.note_id != note_id);
+        if !aliases.is_empty() && !t.has_note(note_id) {
+            return Err(foreign_key());
+        }
+        for (position, alias) in aliases.iter().enumerate() {
+            if !t
+                .note_aliases
+                .iter()
+                .any(|a| a.note_id == note_id && a.alias == *alias)
+            {
+                t.note_aliases.push(NoteAlias {
+                    note_id: note_id.to_string(),
+                    alias: alias.clone(),
+                    position: position as i64,
+                });
+            }
+        }
+        Ok(())
+    }
+
+    fn note_aliases(&self, note_id: &str) -> Result<Vec<String>> {
+        let t = self.tables();
+        let mut aliases: Vec<&NoteAlias> = t
+            .note_aliases
+            .iter()
+            .filter(|a| a.note_id == note_id)
+            .collect();
+        aliases.sort_by_key(|a| a.position);
+        Ok(aliases.into_iter().map(|a| a.alias.clone()).collect())
+    }
+
+    fn all_note_aliases(&self) -> Result<Vec<(NoteId, String)>> {
+        let t = self.tables();
+        let mut aliases: Vec<&NoteAlias> = t.note_aliases.iter().collect();
+        aliases.sort_by(|a, b| (&a.note_id, a.position).cmp(&(&b.note_id, b.position)));
+        Ok(aliases
+            .into_iter()
+            .map(|a| (a.note_id.clone(), a.alias.clone()))
+            .collect())
+    }
+}
+
+impl BlockStorage for MemoryStore {
+    fn create_block(&self, block: &Block) -> Result<()> {
+        let mut t = self.tables_mut()?;
+        if t.has_block(&block.id) {
+            return Err(unique("blocks.id"));
+        }
+        if !t.has_note(&block.note_id) {
+            return Err(foreign_key());
+        }
+        t.blocks.push(block.clone());
+        Ok(())
+    }
+
+    fn get_block(&self, id: &str, include_deleted: bool) -> Result<Option<Block>> {
+        let t = self.tables();
+        Ok(t.block(id)
+            .filter(|block| visible(block.is_deleted, include_deleted))
+            .cloned())
+    }
+
+    fn blocks_for_note(&self, note_id: &str, include_deleted: bool) -> Result<Vec<Block>> {
+        let t = self.tables();
+        let mut blocks: Vec<Block> = t
+            .blocks
+            .iter()
+            .filter(|block| block.note_id == note_id && visible(block.is_deleted, include_deleted))
+            .cloned()
+            .collect();
+        blocks.sort_by_key(|block| block.position);
+        Ok(blocks)
+    }
+
+    fn update_block(&self, block: &Block) -> Result<()> {
+        let mut t = self.tables_mut()?;
+        if let Some(row) = t.blocks.iter_mut().find(|row| row.id == block.id) {
+            *row = Block {
+                note_id: row.note_id.clone(),
+                created_at: row.created_at,
+                ..block.clone()
+            };
+        }
+        Ok(())
+    }
+
+    fn soft_delete_block(&self, id: &str) -> Result<()> {
+        let mut t = self.tables_mut()?;
+        if let Some(block) = t.blocks.iter_mut().find(|block| block.id == id) {
+            block.is_deleted = true;
+            block.deleted_at = Some(chrono::Utc::now().timestamp());
+        }
+        Ok(())
+    }
+
+    fn restore_block(&self, id: &str) -> Result<()> {
+        let mut t = self.tables_mut()?;
+        if let Some(block) = t.blocks.iter_mut().find(|block| block.id == id) {
+            block.is_deleted = false;
+            block.deleted_at = None;
+        }
+        Ok(())
+    }
+
+    fn blocks_referencing(&self, block_id: &str, include_deleted: bool) -> Result<Vec<Block>> {
+        Ok(self
+            .tables()
+            .referenced_blocks(block_id, true, include_deleted))
+    }
+
+    fn blocks_referenced_by(&self, block_id: &str, include_deleted: bool) -> Result<Vec<Block>> {
+        Ok(self
+            .tables()
+            .referenced_blocks(block_id, false, include_deleted))
+    }
+
+    fn create_block_reference(
+        &self,
+        id: &str,
+        source_block_id: &str,
+        target_block_id: &str,
+    ) -> Result<()> {
+        let mut t = self.tables_mut()?;
+        if t.block_references.iter().any(|r| r.id == id) {
+            return Err(unique("block_references.id"));
+        }
+        if !t.has_block(source_block_id) || !t.has_block(target_block_id) {
+            return Err(foreign_key());
+        }
+        t.block_references.push(BlockReference {
+            id: id.to_string(),
+            source_block_id: source_block_id.to_string(),
+            target_block_id: target_block_id.to_string(),
+            created_at: chrono::Utc::now().timestamp(),
+        });
+        Ok(())
+    }
+
+    fn delete_block_reference(&self, source_block_id: &str, target_block_id: &str) -> Result<()> {
+        let mut t = self.tables_mut()?;
+        t.block_references.retain(|r| {
+            r.source_block_id != source_block_id || r.target_block_id != target_block_id
+        });
+        Ok(())
+    }
+
+    fn block_references_in_note(
+        &self,
+        note_id: &str,
+        target_type: &str,
+    ) -> Result<Vec<(BlockId, BlockId)>> {
+        let t = self.tables();
+        Ok(t.block_references
+            .iter()
+            .filter(|r| {
+                t.block(&r.source_block_id)
+                    .is_some_and(|source| source.note_id == note_id && !source.is_deleted)
+                    && t.block(&r.target_block_id)
+                        .is_some_and(|target| target.block_type == target_type)
+            })
+            .map(|r| (r.source_block_id.clone(), r.target_block_id.clone()))
+            .collect())
+    }
+
+    fn search_block_content(&self, query: &str, include_deleted: bool) -> Result<Vec<Block>> {
+        let terms = fts_terms(query);
+        let t = self.tables();
+        let mut blocks: Vec<Block> = t
+            .blocks
+            .iter()
+            .filter(|block| visible(block.is_deleted, include_deleted))
+            .filter(|block| contains_all(&block.content, &terms))
+            .cloned()
+            .collect();
+        blocks.sort_by_key(|block| block.position);
+        Ok(blocks)
+    }
+}
+
+impl TagStorage for MemoryStore {
+    fn create_tag(&self, tag: &Tag) -> Result<()> {
+        let mut t = self.tables_mut()?;
+        if t.tag(&tag.id).is_some() {
+            return Err(unique("tags.id"));
+        }
+        if t.tags.iter().any(|other| other.name == tag.name) {
+            return Err(unique("tags.name"));
+        }
+        t.tags.push(tag.clone());
+        Ok(())
+    }
+
+    fn get_tag(&self, id: &str) -> Result<Option<Tag>> {
+        Ok(self.tables().tag(id).cloned())
+    }
+
+    fn get_tag_by_name(&self, name: &str) -> Result<Option<Tag>> {
+        Ok(self
+            .tables()
+            .tags
+            .iter()
+            .find(|tag| tag.name == name)
+            .cloned())
+    }
+
+    fn list_tags(&self) -> Result<Vec<Tag>> {
+        let mut tags = self.tables().tags.clone();
+        tags.sort_by(|a, b| a.name.cmp(&b.name));
+        Ok(tags)
+    }
+
+    fn update_tag(&self, tag: &Tag) -> Result<()> {
+        let mut t = self.tables_mut()?;
+        if t.tags
+            .iter()
+            .any(|other| other.id != tag.id && other.name == tag.name)
+        {
+            return Err(unique("tags.name"));
+        }
+        if let Some(row) = t.tags.iter_mut().find(|row| row.id == tag.id) {
+            row.name = tag.name.clone();
+            row.color = tag.color.clone();
+            row.icon = tag.icon.clone();
+        }
+        Ok(())
+    }
+
+    fn delete_tag(&self, id: &str) -> Result<()> {
+        let mut t = self.tables_mut()?;
+        t.tags.retain(|tag| tag.id != id);
+        t.note_tags.retain(|(_, tag_id)| tag_id != id);
+        Ok(())
+    }
+
+    fn tags_for_note(&self, note_id: &str) -> Result<Vec<Tag>> {
+        let t = self.tables();
+        let mut tags: Vec<Tag> = t
+            .note_tags
+            .iter()
+            .filter(|(n, _)| n == note_id)
+            .filter_map(|(_, tag_id)| t.tag(tag_id).cloned())
+            .collect();
+        tags.sort_by(|a, b| a.name.cmp(&b.name));
+        Ok(tags)
+    }
+
+    fn note_tag_ids(&self, note_id: &str) -> Result<Vec<TagId>> {
+        let t = self.tables();
+        Ok(t.note_tags
+            .iter()
+            .filter(|(n, _)| n == note_id)
+            .map(|(_, tag_id)| tag_id.clone())
+            .collect())
+    }
+
+    fn add_note_tag(&self, note_id: &str, tag_id: &str) -> Result<()> {
+        let mut t = self.tables_mut()?;
+        if t.note_tags
+            .iter()
+            .any(|(n, tag)| n == note_id && tag == tag_id)
+        {
+            return Err(unique("note_tags.note_id, note_tags.tag_id"));
+        }
+        if !t.has_note(note_id) || t.tag(tag_id).is_none() {
+            return Err(foreign_key());
+        }
+        t.note_tags.push((note_id.to_string(), tag_id.to_string()));
+        Ok(())
+    }
+
+    fn remove_note_tag(&self, note_id: &str, tag_id: &str) -> Result<()> {
+        let mut t = self.tables_mut()?;
+        t.note_tags.retain(|(n, tag)| n != note_id || tag != tag_id);
+        Ok(())
+    }
+
+    fn notes_with_tag(&self, tag_id: &str, include_deleted: bool) -> Result<Vec<Note>> {
+        let mut notes = self.tables().tagged(tag_id, include_deleted);
+        by_recent_update(&mut notes);
+        Ok(notes)
+    }
+
+    fn notes_with_tag_page(
+        &self,
+        tag_id: &str,
+        include_deleted: bool,
+        request: &PageRequest,
+    ) -> Result<Page<Note>> {
+        let notes = self.tables().tagged(tag_id, include_deleted);
+        page(notes, None, request)
+    }
+}
+
+impl FolderStorage for MemoryStore {
+    fn create_folder(&self, folder: &Folder) -> Result<()> {
+        let mut t = self.tables_mut()?;
+        if t.folder(&folder.id).is_some() {
+            return Err(unique("folders.id"));
+        }
+        if folder
+            .parent_id
+            .as_ref()
+            .is_some_and(|parent| t.folder(parent).is_none())
+        {
+            return Err(foreign_key());
+        }
+        t.folders.push(folder.clone());
+        Ok(())
+    }
+
+    fn get_folder(&self, id: &str) -> Result<Option<Folder>> {
+        Ok(self.tables().folder(id).cloned())
+    }
+
+    fn root_folders(&self) -> Result<Vec<Folder>> {
+        let mut folders: Vec<Folder> = self
+            .tables()
+            .folders
+            .iter()
+            .filter(|folder| folder.parent_id.is_none())
+            .cloned()
+            .collect();
+        folders.sort_by_key(|folder| folder.position);
+        Ok(folders)
+    }
+
+    fn child_folders(&self, parent_id: &str) -> Result<Vec<Folder>> {
+        let mut folders: Vec<Folder> = self
+            .tables()
+            .folders
+            .iter()
+            .filter(|folder| folder.parent_id.as_deref() == Some(parent_id))
+            .cloned()
+            .collect();
+        folders.sort_by_key(|folder| folder.position);
+        Ok(folders)
+    }
+
+    fn list_folders(&self) -> Result<Vec<Folder>> {
+        let mut folders = self.tables().folders.clone();
+        folders.sort_by(|a, b| (a.position, &a.path).cmp(&(b.position, &b.path)));
+        Ok(folders)
+    }
+
+    fn update_folder(&self, folder: &Folder) -> Result<()> {
+        let mut t = self.tables_mut()?;
+        if folder
+            .parent_id
+            .as_ref()
+            .is_some_and(|parent| t.folder(parent).is_none())
+        {
+            return Err(foreign_key());
+        }
+        if let Some(row) = t.folders.iter_mut().find(|row| row.id == folder.id) {
+            *row = Folder {
+                created_at: row.created_at,
+                ..folder.clone()
+            };
+        }
+        Ok(())
+    }
+
+    fn delete_folder(&self, id: &str) -> Result<()> {
+        let mut t = self.tables_mut()?;
+        // Children cascade, and so do theirs
+        let mut removed: HashSet<FolderId> = HashSet::new();
+        let mut pending = vec![id.to_string()];
+        while let Some(folder_id) = pending.pop() {
+            pending.extend(
+                t.folders
+                    .iter()
+                    .filter(|folder| folder.parent_id.as_ref() == Some(&folder_id))
+                    .map(|folder| folder.id.clone()),
+            );
+            removed.insert(folder_id);
+        }
+        t.folders.retain(|folder| !removed.contains(&folder.id));
+        t.note_folders.retain(|nf| !removed.contains(&nf.folder_id));
+        Ok(())
+    }
+
+    fn add_note_to_folder(
+        &self,
+        note_id: &str,
+        folder_id: &str,
+        is_primary: bool,
+        position: i64,
+    ) -> Result<()> {
+        let mut t = self.tables_mut()?;
+        if t.note_folders
+            .iter()
+            .any(|nf| nf.note_id == note_id && nf.folder_id == folder_id)
+        {
+            return Err(unique("note_folders.note_id, note_folders.folder_id"));
+        }
+        if !t.has_note(note_id) || t.folder(folder_id).is_none() {
+            return Err(foreign_key());
+        }
+        t.note_folders.push(NoteFolder {
+            note_id: note_id.to_string(),
+            folder_id: folder_id.to_string(),
+            is_primary,
+            position,
+        });
+        Ok(())
+    }
+
+    fn remove_note_from_folder(&self, note_id: &str, folder_id: &str) -> Result<()> {
+        let mut t = self.tables_mut()?;
+        t.note_folders
+            .retain(|nf| nf.note_id != note_id || nf.folder_id != folder_id);
+        Ok(())
+    }
+
+    fn notes_in_folder(&self, folder_id: &str, include_deleted: bool) -> Result<Vec<Note>> {
+        let (mut notes, positions) = self.tables().in_folder(folder_id, include_deleted);
+        by_recent_update(&mut notes);
+        notes.sort_by_key(|note| positions[&note.id]);
+        Ok(notes)
+    }
+
+    fn notes_in_folder_page(
+        &self,
+        folder_id: &str,
+        include_deleted: bool,
+        request: &PageRequest,
+    ) -> Result<Page<Note>> {
+        let (notes, positions) = self.tables().in_folder(folder_id, include_deleted);
+        page(notes, Some(&positions), request)
+    }
+
+    fn all_note_folder_paths(&self) -> Result<Vec<(NoteId, String)>> {
+        let t = self.tables();
+        let mut rows: Vec<&NoteFolder> = t.note_folders.iter().collect();
+        rows.sort_by(|a, b| {
+            (&a.note_id, !a.is_primary, a.position).cmp(&(&b.note_id, !b.is_primary, b.position))
+        });
+        Ok(rows
+            .into_iter()
+            .filter_map(|nf| {
+                let folder = t.folder(&nf.folder_id)?;
+                Some((nf.note_id.clone(), folder.path.clone()))
+            })
+            .collect())
+    }
+}
+
+impl LinkStorage for MemoryStore {
+    fn create_link(&self, link: &Link) -> Result<()> {
+        let mut t = self.tables_mut()?;
+        if t.links.iter().any(|other| other.id == link.id) {
+            return Err(unique("links.id"));
+        }
+        let has_target = match link.link_type.as_str() {
+            "note_link" | "database_relation" => link.target_note_id.is_some(),
+            "block_reference" => link.target_block_id.is_some(),
+            _ => false,
+        };
+        if !has_target {
+            return Err(Error::Storage("CHECK constraint failed: links".to_string()));
+        }
+        let note_exists = |id: &Option<NoteId>| id.as_ref().is_none_or(|id| t.has_note(id));
+        let block_exists = |id: &Option<BlockId>| id.as_ref().is_none_or(|id| t.has_block(id));
+        if !t.has_note(&link.source_note_id)
+            || !note_exists(&link.target_note_id)
+            || !block_exists(&link.source_block_id)
+            || !block_exists(&link.target_block_id)
+        {
+            return Err(foreign_key());
+        }
+        t.links.push(link.clone());
+        Ok(())
+    }
+
+    fn get_link(&self, id: &str) -> Result<Option<Link>> {
+        Ok(self
+            .tables()
+            .links
+            .iter()
+            .find(|link| link.id == id)
+            .cloned())
+    }
+
+    fn outgoing_links(&self, note_id: &str) -> Result<Vec<Link>> {
+        Ok(self.links_where(|link| link.source_note_id == note_id))
+    }
+
+    fn incoming_links(&self, note_id: &str) -> Result<Vec<Link>> {
+        Ok(self.links_where(|link| link.target_note_id.as_deref() == Some(note_id)))
+    }
+
+    fn links_from_block(&self, block_id: &str) -> Result<Vec<Link>> {
+        Ok(self.links_where(|link| link.source_block_id.as_deref() == Some(block_id)))
+    }
+
+    fn links_to_block(&self, block_id: &str) -> Result<Vec<Link>> {
+        Ok(self.links_where(|link| link.target_block_id.as_deref() == Some(block_id)))
+    }
+
+    fn delete_link(&self, id: &str) -> Result<()> {
+        let mut t = self.tables_mut()?;
+        t.links.retain(|link| link.id != id);
+        Ok(())
+    }
+
+    fn notes_linking_to(&self, note_id: &str, include_deleted: bool) -> Result<Vec<Note>> {
+        let t = self.tables();
+        let sources = t
+            .links
+            .iter()
+            .filter(|link| link.target_note_id.as_deref() == Some(note_id))
+            .map(|link| link.source_note_id.as_str())
+            .collect();
+        let mut notes = t.notes_in(&sources, include_deleted);
+        by_recent_update(&mut notes);
+        Ok(notes)
+    }
+}
+
+impl MemoryStore {
+    fn links_where(&self, predicate: impl Fn(&Link) -> bool) -> Vec<Link> {
+        self.tables()
+            .links
+            .iter()
+            .filter(|link| predicate(link))
+            .cloned()
+            .collect()
+    }
+}
+
+impl AttachmentStorage for MemoryStore {
+    fn create_attachment(&self, attachment: &Attachment) -> Result<()> {
+        let mut t = self.tables_mut()?;
+        if t.attachment(&attachment.id).is_some() {
+            return Err(unique("attachments.id"));
+        }
+        if t.attachments
+            .iter()
+            .any(|other| other.hash == attachment.hash)
+        {
+            return Err(unique("attachments.hash"));
+        }
+        t.attachments.push(attachment.clone());
+        Ok(())
+    }
+
+    fn get_attachment(&self, id: &str) -> Result<Option<Attachment>> {
+        Ok(self.tables().attachment(id).cloned())
+    }
+
+    fn get_attachment_by_hash(&self, hash: &str) -> Result<Option<Attachment>> {
+        let t = self.tables();
+        Ok(t.attachments
+            .iter()
+            .find(|attachment| attachment.hash == hash)
+            .cloned())
+    }
+
+    fn count_attachments_with_hash(&self, hash: &str) -> Result<i64> {
+        let t = self.tables();
+        Ok(t.attachments
+            .iter()
+            .filter(|attachment| attachment.hash == hash)
+            .count() as i64)
+    }
+
+    fn delete_attachment(&self, id: &str) -> Result<()> {
+        let mut t = self.tables_mut()?;
+        t.attachments.retain(|attachment| attachment.id != id);
+        t.note_attachments.retain(|na| na.attachment_id != id);
+        t.block_attachments
+            .retain(|(_, attachment_id)| attachment_id != id);
+        Ok(())
+    }
+
+    fn attachments_for_note(&self, note_id: &str) -> Result<Vec<Attachment>> {
+        let t = self.tables();
+        let mut rows: Vec<&NoteAttachment> = t
+            .note_attachments
+            .iter()
+            .filter(|na| na.note_id == note_id)
+            .collect();
+        rows.sort_by_key(|na| na.position);
+        Ok(rows
+            .into_iter()
+            .filter_map(|na| t.attachment(&na.attachment_id).cloned())
+            .collect())
+    }
+
+    fn attachments_for_block(&self, block_id: &str) -> Result<Vec<Attachment>> {
+        let t = self.tables();
+        Ok(t.block_attachments
+            .iter()
+            .filter(|(b, _)| b == block_id)
+            .filter_map(|(_, attachment_id)| t.attachment(attachment_id).cloned())
+            .collect())
+    }
+
+    fn add_note_attachment(&self, note_id: &str, attachment_id: &str, position: i64) -> Result<()> {
+        let mut t = self.tables_mut()?;
+        if t.note_attachments
+            .iter()
+            .any(|na| na.note_id == note_id && na.attachment_id == attachment_id)
+        {
+            return Err(unique(
+                "note_attachments.note_id, note_attachments.attachment_id",
+            ));
+        }
+        if !t.has_note(note_id) || t.attachment(attachment_id).is_none() {
+            return Err(foreign_key());
+        }
+        t.note_attachments.push(NoteAttachment {
+            note_id: note_id.to_string(),
+            attachment_id: attachment_id.to_string(),
+            position,
+        });
+        Ok(())
+    }
+
+    fn remove_note_attachment(&self, note_id: &str, attachment_id: &str) -> Result<()> {
+        let mut t = self.tables_mut()?;
+        t.note_attachments
+            .retain(|na| na.note_id != note_id || na.attachment_id != attachment_id);
+        Ok(())
+    }
+
+    fn add_block_attachment(&self, block_id: &str, attachment_id: &str) -> Result<()> {
+        let mut t = self.tables_mut()?;
+        if t.block_attachments
+            .iter()
+            .any(|(b, a)| b == block_id && a == attachment_id)
+        {
+            return Err(unique(
+                "block_attachments.block_id, block_attachments.attachment_id",
+            ));
+        }
+        if !t.has_block(block_id) || t.attachment(attachment_id).is_none() {
+            return Err(foreign_key());
+        }
+        t.block_attachments
+            .push((block_id.to_string(), attachment_id.to_string()));
+        Ok(())
+    }
+
+    fn remove_block_attachment(&self, block_id: &str, attachment_id: &str) -> Result<()> {
+        let mut t = self.tables_mut()?;
+        t.block_attachments
+            .retain(|(b, a)| b != block_id || a != attachment_id);
+        Ok(())
+    }
+}
+
+impl SavedSearchStorage for MemoryStore {
+    fn create_saved_search(&self, search: &SavedSearch) -> Result<()> {
+        let mut t = self.tables_mut()?;
+        if t.saved_searches.iter().any(|other| other.id == search.id) {
+            return Err(unique("saved_searches.id"));
+        }
+        t.saved_searches.push(search.clone());
+        Ok(())
+    }
+
+    fn get_saved_search(&self, id: &str) -> Result<Option<SavedSearch>> {
+        let t = self.tables();
+        Ok(t.saved_searches
+            .iter()
+            .find(|search| search.id == id)
+            .cloned())
+    }
+
+    fn list_saved_searches(&self) -> Result<Vec<SavedSearch>> {
+        let mut searches = self.tables().saved_searches.clone();
+        searches.sort_by(|a, b| (a.position, &a.name).cmp(&(b.position, &b.name)));
+        Ok(searches)
+    }
+
+    fn update_saved_search(&self, search: &SavedSearch) -> Result<()> {
+        let mut t = self.tables_mut()?;
+        if let Some(row) = t.saved_searches.iter_mut().find(|row| row.id == search.id) {
+            *row = SavedSearch {
+                created_at: row.created_at,
+                ..search.clone()
+            };
+        }
+        Ok(())
+    }
+
+    fn delete_saved_search(&self, id: &str) -> Result<()> {
+        let mut t = self.tables_mut()?;
+        t.saved_searches.retain(|search| search.id != id);
+        Ok(())
+    }
+}
+
+impl BlobStorage for MemoryStore {
+    fn get_blob(&self, path: &str) -> Result<Option<Vec<u8>>> {
+        Ok(self.tables().blobs.get(path).cloned())
+    }
+
+    fn put_blob(&self, path: &str, data: &[u8]) -> Result<()> {
+        let mut t = self.tables_mut()?;
+        t.blobs.insert(path.to_string(), data.to_vec());
+        Ok(())
+    }
+
+    fn delete_blob(&self, path: &str) -> Result<()> {
+        let mut t = self.tables_mut()?;
+        t.blobs.remove(path);
+        Ok(())
+    }
+}
+
+impl SettingStorage for MemoryStore {
+    fn get_setting(&self, key: &str) -> Result<Option<String>> {
+        Ok(self.tables().settings.get(key).cloned())
+    }
+
+    fn set_setting(&self, key: &str, value: &str) -> Result<()> {
+        let mut t = self.tables_mut()?;
+        t.settings.insert(key.to_string(), value.to_string());
+        Ok(())
+    }
+
+    fn delete_setting(&self, key: &str) -> Result<()> {
+        let mut t = self.tables_mut()?;
+        t.settings.remove(key);
+        Ok(())
+    }
+}
+
+/// The page after `request.cursor` of `notes`, keyset-paginated like the SQLite listings.
+/// `positions` holds the manual order of a folder listing.
+fn page(
+    notes: Vec<Note>,
+    positions: Option<&HashMap<NoteId, i64>>,
+    request: &PageRequest,
+) -> Result<Page<Note>> {
+    let limit = request.page_size();
+    if limit == 0 {
+        return Err(Error::InvalidInput(
+            "Page limit must be positive".to_string(),
+        ));
+    }
+    if request.sort.key == SortKey::Position && positions.is_none() {
+        return Err(manual_order_unavailable());
+    }
+    let cursor = request
+        .cursor
+        .as_deref()
+        .map(PageCursor::decode)
+        .transpose()?;
+    if cursor.as_ref().is_some_and(|c| c.sort != request.sort) {
+        return Err(Error::InvalidInput(
+            "Cursor belongs to a different sort order".to_string(),
+        ));
+    }
+    // Like the SQLite listings, later pages report the count taken for the first one
+    let total_estimate = cursor
+        .as_ref()
+        .map_or(notes.len() as u64, |cursor| cursor.total);
+
+    let mut rows: Vec<(CursorValue, Note)> = notes
+        .into_iter()
+        .map(|note| (sort_value(&note, request.sort.key, positions), note))
+        .collect();
+    rows.sort_by(|a, b| compare_notes((&a.0, &a.1), (&b.0, &b.1), request.sort));
+    if let Some(cursor) = cursor {
+        rows.retain(|(value, note)| {
+            let order = compare_values(value, &cursor.value).then_with(|| note.id.cmp(&cursor.id));
+            order
+                == if request.sort.descending {
+                    Ordering::Less
+                } else {
+                    Ordering::Greater
+                }
+        });
+    }
+
+    let has_more = rows.len() > limit;
+    rows.truncate(limit);
+    let next_cursor = match rows.last() {
+        Some((value, note)) if has_more => Some(
+            PageCursor {
+                sort: request.sort,
+                value: value.clone(),
+                id: note.id.clone(),
+                total: total_estimate,
+            }
+            .encode()?,
+        ),
+        _ => None,
+    };
+
+    Ok(Page {
+        items: rows.into_iter().map(|(_, note)| note).collect(),
+        next_cursor,
+        total_estimate,
+    })
+}
+
+/// Value a note is sorted by; `positions` holds the manual order of a folder listing
+fn sort_value(note: &Note, key: SortKey, positions: Option<&HashMap<NoteId, i64>>) -> CursorValue {
+    match key {
+        SortKey::Title => CursorValue::Text(note.title.clone()),
+        SortKey::Created => CursorValue::Integer(note.created_at),
+        SortKey::Updated => CursorValue::Integer(note.updated_at),
+        SortKey::WordCount => CursorValue::Integer(note.word_count),
+        SortKey::Position => CursorValue::Integer(
+            positions
+                .and_then(|p| p.get(&note.id).copied())
+                .unwrap_or_default(),
+        ),
+    }
+}
+
+/// Order of two notes in a listing; ties are broken by ID so the order is stable
+fn compare_notes(a: (&CursorValue, &Note), b: (&CursorValue, &Note), sort: NoteSort) -> Ordering {
+    let order = compare_values(a.0, b.0).then_with(|| a.1.id.cmp(&b.1.id));
+    if sort.descending {
+        order.reverse()
+    } else {
+        order
+    }
+}
+
+/// SQLite's order of sort values: integers before text, text compared like `COLLATE NOCASE`
+fn compare_values(a: &CursorValue, b: &CursorValue) -> Ordering {
+    match (a, b) {
+        (CursorValue::Integer(a), CursorValue::Integer(b)) => a.cmp(b),
+        (CursorValue::Text(a), CursorValue::Text(b)) => {
+            a.to_ascii_lowercase().cmp(&b.to_ascii_lowercase())
+        }
+        (CursorValue::Integer(_), CursorValue::Text(_)) => Ordering::Less,
+        (CursorValue::Text(_), CursorValue::Integer(_)) => Ordering::Greater,
+    }
+}
+
+/// Most recently updated first
+fn by_recent_update(notes: &mut [Note]) {
+    notes.sort_by_key(|note| Reverse(note.updated_at));
+}
+
+/// Words of a full-text query, lowercased, without quotes, prefix stars and operators
+fn fts_terms(query: &str) -> Vec<String> {
+    query
+        .split_whitespace()
+        .filter(|word| !matches!(*word, "AND" | "OR" | "NOT"))
+        .map(|word| word.trim_matches(['"', '*']).to_lowercase())
+        .filter(|word| !word.is_empty())
+        .collect()
+}
+
+fn contains_all(text: &str, terms: &[String]) -> bool {
+    let text = text.to_lowercase();
+    terms.iter().all(|term| text.contains(term.as_str()))
+}
+
+fn visible(is_deleted: bool, include_deleted: bool) -> bool {
+    include_deleted || !is_deleted
+}
+
+fn unique(columns: &str) -> Error {
+    Error::Storage(format!("UNIQUE constraint failed: {}", columns))
+}
+
+fn foreign_key() -> Error {
+    Error::Storage("FOREIGN KEY constraint failed".to_string())
+}
+
+fn manual_order_unavailable() -> Error {
+    Error::InvalidInput("Manual order is only available inside a folder".to_string())
+}
+
+/// Lock a mutex, ignoring poisoning: transactions only replace the committed tables as a whole
+fn lock<T>(mutex: &Mutex<T>) -> MutexGuard<'_, T> {
+    mutex.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+fn read_lock<T>(lock: &RwLock<T>) -> RwLockReadGuard<'_, T> {
+    lock.read().unwrap_or_else(|e| e.into_inner())
+}
+
+fn write_lock<T>(lock: &RwLock<T>) -> RwLockWriteGuard<'_, T> {
+    lock.write().unwrap_or_else(|e| e.into_inner())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+    use std::path::PathBuf;
+
+    use super::*;
+    use crate::services::{
+        AttachmentService, FolderService, NoteService, SearchService, ServiceContext, TagService,
+    };
+    use crate::storage::SqliteBackend;
+
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir =
+            std::env::temp_dir().join(format!("synapse-memory-{}-{}", name, uuid::Uuid::new_v4()));
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    fn titles(notes: Vec<Note>) -> String {
+        notes
+            .into_iter()
+            .map(|note| note.title)
+            .collect::<Vec<_>>()
+            .join(",")
+    }
+
+    /// Write a small vault through `backend` and describe what reading it back returns
+    fn scenario<B: StorageBackend>(backend: &B) -> Vec<String> {
+        let unit = backend.begin().unwrap();
+        let store = &*unit;
+        for (i, title) in ["beta", "Alpha", "gamma", "alpha two"].iter().enumerate() {
+            let id = format!("n{}", i);
+            let mut note = Note::new(id.clone(), title.to_string(), format!("notes/{}.md", id));
+            note.updated_at = 100 + i as i64;
+            note.word_count = (i % 2) as i64;
+            store.create_note(&note).unwrap();
+            store
+                .index_note_content(&id, &format!("body {} shared", title))
+                .unwrap();
+        }
+        store
+            .create_tag(&Tag::new("t1".to_string(), "rust".to_string()))
+            .unwrap();
+        store
+            .create_tag(&Tag::new("t2".to_string(), "done".to_string()))
+            .unwrap();
+        store.add_note_tag("n0", "t1").unwrap();
+        store.add_note_tag("n2", "t1").unwrap();
+        store.add_note_tag("n2", "t2").unwrap();
+        let work = Folder::new(
+            "f1".to_string(),
+            "Work".to_string(),
+            None,
+            "/Work".to_string(),
+        );
+        let sub = Folder::new(
+            "f2".to_string(),
+            "Sub".to_string(),
+            Some("f1".to_string()),
+            "/Work/Sub".to_string(),
+        );
+        store.create_folder(&work).unwrap();
+        store.create_folder(&sub).unwrap();
+        store.add_note_to_folder("n1", "f1", true, 1).unwrap();
+        store.add_note_to_folder("n0", "f1", true, 0).unwrap();
+        store.add_note_to_folder("n3", "f2", true, 0).unwrap();
+        store.add_note_to_folder("n3", "f1", false, 2).unwrap();
+        let aliases = ["b".to_string(), "b".to_string(), "a".to_string()];
+        store.replace_note_aliases("n0", &aliases).unwrap();
+        for (id, source) in [("l1", "n1"), ("l2", "n3")] {
+            let link =
+                Link::new_note_link(id.to_string(), source.to_string(), "n0".to_string(), None);
+            store.create_link(&link).unwrap();
+        }
+        for i in 0..2 {
+            let block = Block::new(
+                format!("b{}", i),
+                "n0".to_string(),
+                "paragraph".to_string(),
+                format!("block {}", i),
+                i,
+            );
+            store.create_block(&block).unwrap();
+        }
+        store.create_block_reference("r1", "b1", "b0").unwrap();
+        let attachment = Attachment::new(
+            "a1".to_string(),
+            "cat.png".to_string(),
+            "attachments/cat.png".to_string(),
+            "image".to_string(),
+            "image/png".to_string(),
+            3,
+            "h1".to_string(),
+        );
+        store.create_attachment(&attachment).unwrap();
+        store.add_note_attachment("n0", "a1", 0).unwrap();
+        unit.commit().unwrap();
+
+        let store = backend.reader().unwrap();
+        let by_title = NoteSort {
+            key: SortKey::Title,
+            descending: false,
+        };
+        let query = |input: &str| {
+            titles(
+                store
+                    .query_notes(&Query::parse(input).unwrap(), by_title, false)
+                    .unwrap(),
+            )
+        };
+        let mut log = vec![
+            titles(store.list_notes(false).unwrap()),
+            titles(store.search_notes_by_title("ALPHA", false).unwrap()),
+            titles(store.notes_in_folder("f1", false).unwrap()),
+            titles(store.notes_with_tag("t1", false).unwrap()),
+            titles(store.notes_linking_to("n0", false).unwrap()),
+            titles(store.search_note_content("shared", false).unwrap()),
+            query("tag:RUST -tag:done"),
+            query("folder:/Work"),
+            query("body -gamma"),
+            format!("{:?}", store.note_aliases("n0").unwrap()),
+            format!("{:?}", store.all_note_folder_paths().unwrap()),
+            format!(
+                "{:?}",
+                store
+                    .tags_for_note("n2")
+                    .unwrap()
+                    .iter()
+                    .map(|t| &t.name)
+                    .collect::<Vec<_>>()
+            ),
+            format!(
+                "{:?}",
+                store
+                    .blocks_referencing("b0", false)
+                    .unwrap()
+                    .iter()
+                    .map(|b| &b.id)
+                    .collect::<Vec<_>>()
+            ),
+            format!("{}", store.count_attachments_with_hash("h1").unwrap()),
+        ];
+        let mut request = PageRequest::new(by_title, 3);
+        loop {
+            let page = store.list_notes_page(false, &request).unwrap();
+            log.push(format!("{} of {}", titles(page.items), page.total_estimate));
+            match page.next_cursor {
+                Some(cursor) => request = request.with_cursor(Some(cursor)),
+                None => break,
+            }
+        }
+        let query = Query::parse("-tag:done").unwrap();
+        let mut request = PageRequest::new(by_title, 2);
+        loop {
+            let page = store.query_notes_page(&query, false, &request).unwrap();
+            log.push(format!("{} of {}", titles(page.items), page.total_estimate));
+            match page.next_cursor {
+                Some(cursor) => request = request.with_cursor(Some(cursor)),
+                None => break,
+            }
+        }
+        let huge = PageRequest {
+            limit: usize::MAX,
+            ..PageRequest::new(by_title, 3)
+        };
+        let page = store.list_notes_page(false, &huge).unwrap();
+        log.push(format!("{} {:?}", titles(page.items), page.next_cursor));
+        drop(store);
+
+        // Constraint violations fail the statement, not the transaction
+        let unit = backend.begin().unwrap();
+        let store = &*unit;
+        log.push(format!(
+            "{}",
+            store
+                .create_tag(&Tag::new("t3".to_string(), "rust".to_string()))
+                .is_err()
+        ));
+        log.push(format!("{}", store.add_note_tag("n0", "t1").is_err()));
+        log.push(format!("{}", store.add_note_tag("missing", "t1").is_err()));
+        store.delete_folder("f1").unwrap();
+        store.delete_tag("t1").unwrap();
+        store.delete_attachment("a1").unwrap();
+        unit.commit().unwrap();
+
+        let store = backend.reader().unwrap();
+        log.push(format!("{:?}", store.all_note_folder_paths().unwrap()));
+        log.push(format!("{}", store.list_folders().unwrap().len()));
+        log.push(titles(store.notes_with_tag("t1", false).unwrap()));
+        log.push(format!(
+            "{}",
+            store.attachments_for_note("n0").unwrap().len()
+        ));
+        log
+    }
+
+    #[test]
+    fn test_matches_sqlite() {
+        let dir = temp_dir("parity");
+        let sqlite = scenario(&SqliteBackend::open(dir.join("synapse.db")).unwrap());
+        let memory = scenario(&MemoryBackend::new());
+        assert_eq!(memory, sqlite);
+        assert_eq!(memory[0], "alpha two,gamma,Alpha,beta");
+        assert_eq!(memory[6], "beta");
+        fs::remove_dir_all(&dir).ok();
+    }
+
+    #[test]
+    fn test_transactions_are_isolated() {
+        let backend = MemoryBackend::new();
+        let tag = Tag::new("t1".to_string(), "rust".to_string());
+
+        let unit = backend.begin().unwrap();
+        unit.create_tag(&tag).unwrap();
+        assert!(backend.reader().unwrap().list_tags().unwrap().is_empty());
+        drop(unit);
+        assert!(backend.reader().unwrap().get_tag("t1").unwrap().is_none());
+
+        let unit = backend.begin().unwrap();
+        unit.create_tag(&tag).unwrap();
+        let before = backend.reader().unwrap();
+        unit.commit().unwrap();
+        // A reader keeps the snapshot it was taken from
+        assert!(before.list_tags().unwrap().is_empty());
+        let reader = backend.reader().unwrap();
+        assert_eq!(reader.list_tags().unwrap().len(), 1);
+        assert!(reader.delete_tag("t1").is_err());
+    }
+
+    #[test]
+    fn test_services_on_memory_backend() {
+        let ctx = ServiceContext::in_memory();
+        let content = "---\ntags: [work]\naliases: [Roadmap]\n---\nShip it".to_string();
+        let note = NoteService::create(&ctx, "Plan".to_string(), content).unwrap();
+        let read = NoteService::get_by_id(&ctx, &note.id, false)
+            .unwrap()
+            .unwrap();
+        assert!(read.content.ends_with("Ship it"));
+
+        assert_eq!(
+            NoteService::get_tags(&ctx, &note.id).unwrap()[0].name,
+            "work"
+        );
+        assert_eq!(
+            SearchService::search_notes(&ctx, "ship", false)
+                .unwrap()
+                .len(),
+            1
+        );
+        assert_eq!(
+            SearchService::fuzzy_titles(&ctx, "roadmap", 5).unwrap()[0].title,
+            "Plan"
+        );
+        assert!(TagService::create(&ctx, "work".to_string()).is_err());
+
+        // Property edits rewrite the front matter and keep the body
+        NoteService::set_property(
+            &ctx,
+            &note.id,
+            "status",
+            PropertyValue::Text("done".to_string()),
+        )
+        .unwrap();
+        NoteService::remove_property(&ctx, &note.id, "aliases").unwrap();
+        let read = NoteService::get_by_id(&ctx, &note.id, false)
+            .unwrap()
+            .unwrap();
+        assert!(read.content.contains("status: done"));
+        assert!(read.content.ends_with("Ship it"));
+        let properties = NoteService::get_properties(&ctx, &note.id).unwrap();
+        assert!(properties.iter().any(|property| property.key == "status"));
+        assert!(!properties.iter().any(|property| property.key == "aliases"));
+
+        // Tags added or removed directly go into the `tags:` front matter, so saving the
+        // note keeps them
+        let idea = TagService::create(&ctx, "idea".to_string()).unwrap();
+        NoteService::add_tag(&ctx, &note.id, &idea.id).unwrap();
+        let work = NoteService::get_tags(&ctx, &note.id)
+            .unwrap()
+            .into_iter()
+            .find(|tag| tag.name == "work")
+            .unwrap();
+        NoteService::remove_tag(&ctx, &note.id, &work.id).unwrap();
+        let read = NoteService::get_by_id(&ctx, &note.id, false)
+            .unwrap()
+            .unwrap();
+        assert!(read.content.contains("tags:\n  - idea\n"));
+        NoteService::update(&ctx, &note.id, None, Some(read.content)).unwrap();
+        let tags = NoteService::get_tags(&ctx, &note.id).unwrap();
+        assert_eq!(
+            tags.iter().map(|tag| tag.name.as_str()).collect::<Vec<_>>(),
+            vec!["idea"]
+        );
+
+        FolderService::create(&ctx, "Work".to_string(), None).unwrap();
+        assert_eq!(FolderService::tree(&ctx).unwrap().len(), 1);
+        assert!(matches!(
+            AttachmentService::upload_from_bytes(&ctx, b"data", "a.txt"),
+            Err(Error::InvalidInput(_))
+        ));
+    }
+}
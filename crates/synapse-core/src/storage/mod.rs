@@ -1,17 +1,30 @@
-//! Storage abstraction and SQLite implementation.
+//! Storage abstraction with SQLite and in-memory implementations.
 //!
-//! Storage is abstracted behind [`StorageBackend`] so the implementation can be swapped
-//! (e.g. for testing or alternative backends). Per-entity storage traits (NoteStorage,
-//! BlockStorage, etc.) may be introduced in a later refinement.
+//! Storage is abstracted behind [`StorageBackend`] and the per-entity storage traits
+//! ([`NoteStorage`], [`BlockStorage`], ...) so the implementation can be swapped:
+//! [`SqliteBackend`] persists to SQLite through the DAOs, [`MemoryBackend`] keeps a vault in
+//! memory for tests.
 
 mod backend;
 mod database;
 mod db_manager;
 mod dao;
+mod memory;
+mod pool;
 mod relation_dao;
+mod sqlite;
+mod tokenizer;
 
-pub use backend::{StorageBackend, SqliteBackend};
-pub use database::init_database;
+pub use backend::{
+    AttachmentStorage, BlobStorage, BlockStorage, FolderStorage, LinkStorage, NoteStorage,
+    SavedSearchStorage, SettingStorage, Storage, StorageBackend, StorageTransaction, TagStorage,
+};
+pub(crate) use database::{FRONT_MATTER_PENDING, REINDEX_PENDING};
+pub use database::{init_database, schema_version};
 pub use db_manager::DatabaseManager;
 pub use dao::*;
+pub use memory::{MemoryBackend, MemoryStore, MemoryTransaction};
+pub use pool::{ConnectionPool, PooledConnection};
 pub use relation_dao::*;
+pub use sqlite::{SqliteBackend, SqliteTransaction};
+pub use tokenizer::{CJK_TOKENIZER, register_cjk_tokenizer};
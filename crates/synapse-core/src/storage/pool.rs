@@ -0,0 +1,224 @@
+//! Connection pool: one writer and any number of readers over a WAL database.
+//!
+//! SQLite allows a single writer at a time, so the pool keeps exactly one write connection
+//! behind a mutex; units of work hold it for their whole transaction. Readers never block
+//! on the writer in WAL mode. They are opened on demand, returned to the pool on drop and
+//! marked `query_only`, so a write that bypasses the writer fails instead of waiting on
+//! the database lock.
+
+use std::ops::Deref;
+use std::path::{Path, PathBuf};
+use std::sync::{Mutex, MutexGuard};
+use std::time::Duration;
+
+use rusqlite::{Connection, Result};
+
+use super::DatabaseManager;
+use super::tokenizer::register_cjk_tokenizer;
+
+/// Idle readers kept open for reuse; more are opened when several threads read at once
+const MAX_IDLE_READERS: usize = 4;
+
+/// How long a connection waits for another process holding the database lock
+const BUSY_TIMEOUT: Duration = Duration::from_secs(5);
+
+pub struct ConnectionPool {
+    path: PathBuf,
+    writer: Mutex<Connection>,
+    readers: Mutex<Vec<Connection>>,
+    upgraded_from: Option<u32>,
+}
+
+/// A reader borrowed from the pool, returned to it on drop
+pub struct PooledConnection<'a> {
+    pool: &'a ConnectionPool,
+    conn: Option<Connection>,
+}
+
+impl ConnectionPool {
+    /// Open the database at `db_path`, migrating it and switching it to WAL mode. The pool
+    /// needs a file: each connection to an in-memory or temporary database would get a
+    /// private one, so readers would never see what the writer commits.
+    pub fn open<P: AsRef<Path>>(db_path: P) -> Result<Self> {
+        let path = db_path.as_ref().to_path_buf();
+        if is_private_database(&path) {
+            return Err(rusqlite::Error::SqliteFailure(
+                rusqlite::ffi::Error::new(rusqlite::ffi::SQLITE_CANTOPEN),
+                Some(format!(
+                    "A connection pool needs a database file, not {:?}; use \
+                     ServiceContext::in_memory for storage in memory",
+                    path
+                )),
+            ));
+        }
+        let db = DatabaseManager::new(&path)?;
+        let upgraded_from = db.upgraded_from();
+
+        let writer = db.into_conn();
+        writer.pragma_update(None, "journal_mode", "WAL")?;
+        writer.busy_timeout(BUSY_TIMEOUT)?;
+
+        Ok(Self {
+            path,
+            writer: Mutex::new(writer),
+            readers: Mutex::new(Vec::new()),
+            upgraded_from,
+        })
+    }
+
+    /// Schema version the database had before it was migrated on open, if it was
+    pub fn upgraded_from(&self) -> Option<u32> {
+        self.upgraded_from
+    }
+
+    /// Borrow a reader. The most recently returned one is reused first.
+    pub fn reader(&self) -> Result<PooledConnection<'_>> {
+        let idle = lock(&self.readers).pop();
+        let conn = match idle {
+            Some(conn) => conn,
+            None => self.open_reader()?,
+        };
+        Ok(PooledConnection {
+            pool: self,
+            conn: Some(conn),
+        })
+    }
+
+    /// Take the write connection, waiting for the current writer to finish
+    pub fn writer(&self) -> MutexGuard<'_, Connection> {
+        lock(&self.writer)
+    }
+
+    fn open_reader(&self) -> Result<Connection> {
+        let conn = Connection::open(&self.path)?;
+        conn.busy_timeout(BUSY_TIMEOUT)?;
+        conn.pragma_update(None, "query_only", true)?;
+        register_cjk_tokenizer(&conn)?;
+        Ok(conn)
+    }
+}
+
+impl Deref for PooledConnection<'_> {
+    type Target = Connection;
+
+    fn deref(&self) -> &Connection {
+        self.conn
+            .as_ref()
+            .expect("pooled connection is only taken on drop")
+    }
+}
+
+impl Drop for PooledConnection<'_> {
+    fn drop(&mut self) {
+        if let Some(conn) = self.conn.take() {
+            let mut idle = lock(&self.pool.readers);
+            if idle.len() < MAX_IDLE_READERS {
+                idle.push(conn);
+            }
+        }
+    }
+}
+
+/// Whether connections to `path` each get their own database: `:memory:`, an empty path
+/// (a temporary database) or a `mode=memory` URI
+fn is_private_database(path: &Path) -> bool {
+    let path = path.to_string_lossy();
+    path.is_empty()
+        || path == ":memory:"
+        || (path.starts_with("file:") && path.contains("mode=memory"))
+}
+
+/// Lock a mutex, ignoring poisoning: a unit of work rolls back before it releases the
+/// writer, even when it panics, so the connection is always usable
+fn lock<T>(mutex: &Mutex<T>) -> MutexGuard<'_, T> {
+    mutex.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn temp_db(name: &str) -> PathBuf {
+        let dir =
+            std::env::temp_dir().join(format!("synapse-pool-{}-{}", name, uuid::Uuid::new_v4()));
+        std::fs::create_dir_all(&dir).unwrap();
+        dir.join("synapse.db")
+    }
+
+    #[test]
+    fn test_readers_see_committed_writes_only() {
+        let path = temp_db("readers");
+        let pool = ConnectionPool::open(&path).unwrap();
+        let mode: String = pool
+            .writer()
+            .query_row("PRAGMA journal_mode", [], |row| row.get(0))
+            .unwrap();
+        assert_eq!(mode, "wal");
+
+        let count = |conn: &Connection| -> i64 {
+            conn.query_row("SELECT COUNT(*) FROM tags", [], |row| row.get(0))
+                .unwrap()
+        };
+        {
+            let writer = pool.writer();
+            writer
+                .execute_batch(
+                    "BEGIN; INSERT INTO tags (id, name, created_at) VALUES ('t1', 'rust', 0);",
+                )
+                .unwrap();
+            // The reader neither blocks nor sees the open transaction
+            assert_eq!(count(&pool.reader().unwrap()), 0);
+            writer.execute_batch("COMMIT").unwrap();
+        }
+        assert_eq!(count(&pool.reader().unwrap()), 1);
+
+        // Readers are reused and refuse writes
+        let reader = pool.reader().unwrap();
+        assert!(reader.execute("DELETE FROM tags", []).is_err());
+        let second = pool.reader().unwrap();
+        drop((reader, second));
+        assert_eq!(lock(&pool.readers).len(), 2);
+
+        drop(pool);
+        std::fs::remove_dir_all(path.parent().unwrap()).unwrap();
+    }
+
+    #[test]
+    fn test_pool_refuses_private_databases() {
+        for path in ["", ":memory:", "file:vault?mode=memory&cache=shared"] {
+            assert!(ConnectionPool::open(path).is_err(), "{:?}", path);
+        }
+    }
+
+    #[test]
+    fn test_pool_is_shared_across_threads() {
+        let path = temp_db("threads");
+        let pool = ConnectionPool::open(&path).unwrap();
+        std::thread::scope(|scope| {
+            for i in 0..4 {
+                let pool = &pool;
+                scope.spawn(move || {
+                    pool.writer()
+                        .execute(
+                            "INSERT INTO tags (id, name, created_at) VALUES (?1, ?1, 0)",
+                            [format!("tag-{}", i)],
+                        )
+                        .unwrap();
+                    pool.reader()
+                        .unwrap()
+                        .query_row("SELECT COUNT(*) FROM tags", [], |row| row.get::<_, i64>(0))
+                        .unwrap();
+                });
+            }
+        });
+        let count: i64 = pool
+            .reader()
+            .unwrap()
+            .query_row("SELECT COUNT(*) FROM tags", [], |row| row.get(0))
+            .unwrap();
+        assert_eq!(count, 4);
+
+        drop(pool);
+        std::fs::remove_dir_all(path.parent().unwrap()).unwrap();
+    }
+}
@@ -75,6 +75,24 @@ impl NoteFolderDao {
         Ok(notes)
     }
 
+    /// Get the folder path of every note-folder pair, as `(note_id, path)`
+    pub fn get_all_paths(conn: &Connection) -> Result<Vec<(String, String)>, Error> {
+        let mut stmt = conn.prepare(
+            r#"
+            SELECT nf.note_id, f.path FROM note_folders nf
+            INNER JOIN folders f ON f.id = nf.folder_id
+            ORDER BY nf.note_id, nf.is_primary DESC, nf.position
+            "#,
+        )?;
+        let rows = stmt.query_map([], |row| Ok((row.get(0)?, row.get(1)?)))?;
+
+        let mut paths = Vec::new();
+        for row in rows {
+            paths.push(row?);
+        }
+        Ok(paths)
+    }
+
     /// Update note position in folder
     pub fn update_position(conn: &Connection, note_id: &str, folder_id: &str, position: i64) -> Result<(), Error> {
         conn.execute(
@@ -137,6 +155,70 @@ impl NoteTagDao {
     }
 }
 
+/// Note-Alias DAO (alternative titles from front matter)
+pub struct NoteAliasDao;
+
+impl NoteAliasDao {
+    /// Replace all aliases of a note
+    pub fn replace_for_note(
+        conn: &Connection,
+        note_id: &str,
+        aliases: &[String],
+    ) -> Result<(), Error> {
+        conn.execute(
+            "DELETE FROM note_aliases WHERE note_id = ?1",
+            params![note_id],
+        )?;
+        let created_at = chrono::Utc::now().timestamp();
+        let mut stmt = conn.prepare(
+            "INSERT OR IGNORE INTO note_aliases (note_id, alias, position, created_at) VALUES (?1, ?2, ?3, ?4)"
+        )?;
+        for (position, alias) in aliases.iter().enumerate() {
+            stmt.execute(params![note_id, alias, position as i64, created_at])?;
+        }
+        Ok(())
+    }
+
+    /// Get all aliases of a note
+    pub fn get_aliases_for_note(conn: &Connection, note_id: &str) -> Result<Vec<String>, Error> {
+        let mut stmt =
+            conn.prepare("SELECT alias FROM note_aliases WHERE note_id = ?1 ORDER BY position")?;
+        let rows = stmt.query_map(params![note_id], |row| row.get(0))?;
+
+        let mut aliases = Vec::new();
+        for row in rows {
+            aliases.push(row?);
+        }
+        Ok(aliases)
+    }
+
+    /// Get every alias of every note, as `(note_id, alias)`
+    pub fn get_all(conn: &Connection) -> Result<Vec<(String, String)>, Error> {
+        let mut stmt =
+            conn.prepare("SELECT note_id, alias FROM note_aliases ORDER BY note_id, position")?;
+        let rows = stmt.query_map([], |row| Ok((row.get(0)?, row.get(1)?)))?;
+
+        let mut aliases = Vec::new();
+        for row in rows {
+            aliases.push(row?);
+        }
+        Ok(aliases)
+    }
+
+    /// Get all notes with an alias (case-insensitive)
+    pub fn get_notes_with_alias(conn: &Connection, alias: &str) -> Result<Vec<String>, Error> {
+        let mut stmt =
+            conn.prepare("SELECT note_id FROM note_aliases WHERE alias = ?1 COLLATE NOCASE")?;
+        let rows = stmt.query_map(params![alias], |row| row.get(0))?;
+
+        let mut notes = Vec::new();
+        for row in rows {
+            notes.push(row?);
+        }
+        Ok(notes)
+    }
+}
+
 /// Note-Attachment relation DAO
 pub struct NoteAttachmentDao;
 
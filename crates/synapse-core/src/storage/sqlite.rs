@@ -0,0 +1,472 @@
+//! SQLite storage: the entity traits on a [`Connection`], delegating to the DAOs.
+//!
+//! [`SqliteBackend`] reads from the pool's readers and writes in `BEGIN IMMEDIATE`
+//! transactions on its single writer.
+
+use std::ops::Deref;
+use std::path::Path;
+use std::sync::MutexGuard;
+
+use rusqlite::Connection;
+
+use super::backend::*;
+use super::*;
+use crate::Result;
+use crate::models::*;
+use crate::query::Query;
+
+/// SQLite-backed storage (default implementation)
+pub struct SqliteBackend {
+    pool: ConnectionPool,
+}
+
+/// A transaction on the pool's write connection
+pub struct SqliteTransaction<'a> {
+    conn: MutexGuard<'a, Connection>,
+}
+
+impl SqliteBackend {
+    /// Open the database at `db_path`; see [`ConnectionPool::open`]
+    pub fn open<P: AsRef<Path>>(db_path: P) -> Result<Self> {
+        Ok(Self::new(ConnectionPool::open(db_path)?))
+    }
+
+    pub fn new(pool: ConnectionPool) -> Self {
+        Self { pool }
+    }
+
+    pub fn pool(&self) -> &ConnectionPool {
+        &self.pool
+    }
+}
+
+impl StorageBackend for SqliteBackend {
+    type Store = Connection;
+    type Reader<'a> = PooledConnection<'a>;
+    type Transaction<'a> = SqliteTransaction<'a>;
+
+    fn reader(&self) -> Result<PooledConnection<'_>> {
+        Ok(self.pool.reader()?)
+    }
+
+    /// The write lock is taken immediately, so the transaction never fails later for lack
+    /// of it
+    fn begin(&self) -> Result<SqliteTransaction<'_>> {
+        let conn = self.pool.writer();
+        conn.execute_batch("BEGIN IMMEDIATE")?;
+        Ok(SqliteTransaction { conn })
+    }
+}
+
+impl Deref for SqliteTransaction<'_> {
+    type Target = Connection;
+
+    fn deref(&self) -> &Connection {
+        &self.conn
+    }
+}
+
+impl StorageTransaction for SqliteTransaction<'_> {
+    fn commit(self) -> Result<()> {
+        self.conn.execute_batch("COMMIT")?;
+        Ok(())
+    }
+}
+
+impl Drop for SqliteTransaction<'_> {
+    fn drop(&mut self) {
+        // Still in the transaction unless COMMIT succeeded
+        if !self.conn.is_autocommit() {
+            let _ = self.conn.execute_batch("ROLLBACK");
+        }
+    }
+}
+
+impl NoteStorage for Connection {
+    fn create_note(&self, note: &Note) -> Result<()> {
+        NoteDao::create(self, note)
+    }
+
+    fn get_note(&self, id: &str, include_deleted: bool) -> Result<Option<Note>> {
+        NoteDao::get_by_id(self, id, include_deleted)
+    }
+
+    fn update_note(&self, note: &Note) -> Result<()> {
+        NoteDao::update(self, note)
+    }
+
+    fn soft_delete_note(&self, id: &str) -> Result<()> {
+        NoteDao::soft_delete(self, id)
+    }
+
+    fn restore_note(&self, id: &str) -> Result<()> {
+        NoteDao::restore(self, id)
+    }
+
+    fn list_notes(&self, include_deleted: bool) -> Result<Vec<Note>> {
+        NoteDao::list(self, include_deleted)
+    }
+
+    fn list_notes_page(&self, include_deleted: bool, request: &PageRequest) -> Result<Page<Note>> {
+        NoteDao::list_page(self, include_deleted, request)
+    }
+
+    fn search_notes_by_title(&self, query: &str, include_deleted: bool) -> Result<Vec<Note>> {
+        NoteDao::search_by_title(self, query, include_deleted)
+    }
+
+    fn query_notes(
+        &self,
+        query: &Query,
+        sort: NoteSort,
+        include_deleted: bool,
+    ) -> Result<Vec<Note>> {
+        NoteDao::query(self, query, sort, include_deleted)
+    }
+
+    fn query_notes_page(
+        &self,
+        query: &Query,
+        include_deleted: bool,
+        request: &PageRequest,
+    ) -> Result<Page<Note>> {
+        NoteDao::query_page(self, query, include_deleted, request)
+    }
+
+    fn index_note_content(&self, note_id: &str, content: &str) -> Result<()> {
+        NoteFtsDao::index_content(self, note_id, content)
+    }
+
+    fn search_note_content(&self, query: &str, include_deleted: bool) -> Result<Vec<Note>> {
+        NoteDao::search_content(self, query, include_deleted)
+    }
+
+    fn replace_note_properties(
+        &self,
+        note_id: &str,
+        properties: &[(String, PropertyValue)],
+    ) -> Result<()> {
+        NotePropertyDao::replace_for_note(self, note_id, properties)
+    }
+
+    fn note_properties(&self, note_id: &str) -> Result<Vec<NoteProperty>> {
+        NotePropertyDao::get_for_note(self, note_id)
+    }
+
+    fn replace_note_aliases(&self, note_id: &str, aliases: &[String]) -> Result<()> {
+        NoteAliasDao::replace_for_note(self, note_id, aliases)
+    }
+
+    fn note_aliases(&self, note_id: &str) -> Result<Vec<String>> {
+        NoteAliasDao::get_aliases_for_note(self, note_id)
+    }
+
+    fn all_note_aliases(&self) -> Result<Vec<(NoteId, String)>> {
+        NoteAliasDao::get_all(self)
+    }
+}
+
+impl BlockStorage for Connection {
+    fn create_block(&self, block: &Block) -> Result<()> {
+        BlockDao::create(self, block)
+    }
+
+    fn get_block(&self, id: &str, include_deleted: bool) -> Result<Option<Block>> {
+        BlockDao::get_by_id(self, id, include_deleted)
+    }
+
+    fn blocks_for_note(&self, note_id: &str, include_deleted: bool) -> Result<Vec<Block>> {
+        BlockDao::get_by_note(self, note_id, include_deleted)
+    }
+
+    fn update_block(&self, block: &Block) -> Result<()> {
+        BlockDao::update(self, block)
+    }
+
+    fn soft_delete_block(&self, id: &str) -> Result<()> {
+        BlockDao::soft_delete(self, id)
+    }
+
+    fn restore_block(&self, id: &str) -> Result<()> {
+        BlockDao::restore(self, id)
+    }
+
+    fn blocks_referencing(&self, block_id: &str, include_deleted: bool) -> Result<Vec<Block>> {
+        BlockDao::get_referencing(self, block_id, include_deleted)
+    }
+
+    fn blocks_referenced_by(&self, block_id: &str, include_deleted: bool) -> Result<Vec<Block>> {
+        BlockDao::get_referenced(self, block_id, include_deleted)
+    }
+
+    fn create_block_reference(
+        &self,
+        id: &str,
+        source_block_id: &str,
+        target_block_id: &str,
+    ) -> Result<()> {
+        BlockReferenceDao::create(self, id, source_block_id, target_block_id)
+    }
+
+    fn delete_block_reference(&self, source_block_id: &str, target_block_id: &str) -> Result<()> {
+        BlockReferenceDao::delete(self, source_block_id, target_block_id)
+    }
+
+    fn block_references_in_note(
+        &self,
+        note_id: &str,
+        target_type: &str,
+    ) -> Result<Vec<(BlockId, BlockId)>> {
+        BlockReferenceDao::get_in_note(self, note_id, target_type)
+    }
+
+    fn search_block_content(&self, query: &str, include_deleted: bool) -> Result<Vec<Block>> {
+        BlockDao::search_content(self, query, include_deleted)
+    }
+}
+
+impl TagStorage for Connection {
+    fn create_tag(&self, tag: &Tag) -> Result<()> {
+        TagDao::create(self, tag)
+    }
+
+    fn get_tag(&self, id: &str) -> Result<Option<Tag>> {
+        TagDao::get_by_id(self, id)
+    }
+
+    fn get_tag_by_name(&self, name: &str) -> Result<Option<Tag>> {
+        TagDao::get_by_name(self, name)
+    }
+
+    fn list_tags(&self) -> Result<Vec<Tag>> {
+        TagDao::list(self)
+    }
+
+    fn update_tag(&self, tag: &Tag) -> Result<()> {
+        TagDao::update(self, tag)
+    }
+
+    fn delete_tag(&self, id: &str) -> Result<()> {
+        TagDao::delete(self, id)
+    }
+
+    fn tags_for_note(&self, note_id: &str) -> Result<Vec<Tag>> {
+        TagDao::get_for_note(self, note_id)
+    }
+
+    fn note_tag_ids(&self, note_id: &str) -> Result<Vec<TagId>> {
+        NoteTagDao::get_tags_for_note(self, note_id)
+    }
+
+    fn add_note_tag(&self, note_id: &str, tag_id: &str) -> Result<()> {
+        NoteTagDao::add(self, note_id, tag_id)
+    }
+
+    fn remove_note_tag(&self, note_id: &str, tag_id: &str) -> Result<()> {
+        NoteTagDao::remove(self, note_id, tag_id)
+    }
+
+    fn notes_with_tag(&self, tag_id: &str, include_deleted: bool) -> Result<Vec<Note>> {
+        NoteDao::get_by_tag(self, tag_id, include_deleted)
+    }
+
+    fn notes_with_tag_page(
+        &self,
+        tag_id: &str,
+        include_deleted: bool,
+        request: &PageRequest,
+    ) -> Result<Page<Note>> {
+        NoteDao::get_by_tag_page(self, tag_id, include_deleted, request)
+    }
+}
+
+impl FolderStorage for Connection {
+    fn create_folder(&self, folder: &Folder) -> Result<()> {
+        FolderDao::create(self, folder)
+    }
+
+    fn get_folder(&self, id: &str) -> Result<Option<Folder>> {
+        FolderDao::get_by_id(self, id)
+    }
+
+    fn root_folders(&self) -> Result<Vec<Folder>> {
+        FolderDao::get_roots(self)
+    }
+
+    fn child_folders(&self, parent_id: &str) -> Result<Vec<Folder>> {
+        FolderDao::get_children(self, parent_id)
+    }
+
+    fn list_folders(&self) -> Result<Vec<Folder>> {
+        FolderDao::list(self)
+    }
+
+    fn update_folder(&self, folder: &Folder) -> Result<()> {
+        FolderDao::update(self, folder)
+    }
+
+    fn delete_folder(&self, id: &str) -> Result<()> {
+        FolderDao::delete(self, id)
+    }
+
+    fn add_note_to_folder(
+        &self,
+        note_id: &str,
+        folder_id: &str,
+        is_primary: bool,
+        position: i64,
+    ) -> Result<()> {
+        NoteFolderDao::add(self, note_id, folder_id, is_primary, position)
+    }
+
+    fn remove_note_from_folder(&self, note_id: &str, folder_id: &str) -> Result<()> {
+        NoteFolderDao::remove(self, note_id, folder_id)
+    }
+
+    fn notes_in_folder(&self, folder_id: &str, include_deleted: bool) -> Result<Vec<Note>> {
+        NoteDao::get_by_folder(self, folder_id, include_deleted)
+    }
+
+    fn notes_in_folder_page(
+        &self,
+        folder_id: &str,
+        include_deleted: bool,
+        request: &PageRequest,
+    ) -> Result<Page<Note>> {
+        NoteDao::get_by_folder_page(self, folder_id, include_deleted, request)
+    }
+
+    fn all_note_folder_paths(&self) -> Result<Vec<(NoteId, String)>> {
+        NoteFolderDao::get_all_paths(self)
+    }
+}
+
+impl LinkStorage for Connection {
+    fn create_link(&self, link: &Link) -> Result<()> {
+        LinkDao::create(self, link)
+    }
+
+    fn get_link(&self, id: &str) -> Result<Option<Link>> {
+        LinkDao::get_by_id(self, id)
+    }
+
+    fn outgoing_links(&self, note_id: &str) -> Result<Vec<Link>> {
+        LinkDao::get_outgoing_links(self, note_id)
+    }
+
+    fn incoming_links(&self, note_id: &str) -> Result<Vec<Link>> {
+        LinkDao::get_incoming_links(self, note_id)
+    }
+
+    fn links_from_block(&self, block_id: &str) -> Result<Vec<Link>> {
+        LinkDao::get_links_from_block(self, block_id)
+    }
+
+    fn links_to_block(&self, block_id: &str) -> Result<Vec<Link>> {
+        LinkDao::get_links_to_block(self, block_id)
+    }
+
+    fn delete_link(&self, id: &str) -> Result<()> {
+        LinkDao::delete(self, id)
+    }
+
+    fn notes_linking_to(&self, note_id: &str, include_deleted: bool) -> Result<Vec<Note>> {
+        NoteDao::get_linking_to(self, note_id, include_deleted)
+    }
+}
+
+impl AttachmentStorage for Connection {
+    fn create_attachment(&self, attachment: &Attachment) -> Result<()> {
+        AttachmentDao::create(self, attachment)
+    }
+
+    fn get_attachment(&self, id: &str) -> Result<Option<Attachment>> {
+        AttachmentDao::get_by_id(self, id)
+    }
+
+    fn get_attachment_by_hash(&self, hash: &str) -> Result<Option<Attachment>> {
+        AttachmentDao::get_by_hash(self, hash)
+    }
+
+    fn count_attachments_with_hash(&self, hash: &str) -> Result<i64> {
+        AttachmentDao::count_by_hash(self, hash)
+    }
+
+    fn delete_attachment(&self, id: &str) -> Result<()> {
+        AttachmentDao::delete(self, id)
+    }
+
+    fn attachments_for_note(&self, note_id: &str) -> Result<Vec<Attachment>> {
+        AttachmentDao::get_for_note(self, note_id)
+    }
+
+    fn attachments_for_block(&self, block_id: &str) -> Result<Vec<Attachment>> {
+        AttachmentDao::get_for_block(self, block_id)
+    }
+
+    fn add_note_attachment(&self, note_id: &str, attachment_id: &str, position: i64) -> Result<()> {
+        NoteAttachmentDao::add(self, note_id, attachment_id, position)
+    }
+
+    fn remove_note_attachment(&self, note_id: &str, attachment_id: &str) -> Result<()> {
+        NoteAttachmentDao::remove(self, note_id, attachment_id)
+    }
+
+    fn add_block_attachment(&self, block_id: &str, attachment_id: &str) -> Result<()> {
+        BlockAttachmentDao::add(self, block_id, attachment_id)
+    }
+
+    fn remove_block_attachment(&self, block_id: &str, attachment_id: &str) -> Result<()> {
+        BlockAttachmentDao::remove(self, block_id, attachment_id)
+    }
+}
+
+impl SavedSearchStorage for Connection {
+    fn create_saved_search(&self, search: &SavedSearch) -> Result<()> {
+        SavedSearchDao::create(self, search)
+    }
+
+    fn get_saved_search(&self, id: &str) -> Result<Option<SavedSearch>> {
+        SavedSearchDao::get_by_id(self, id)
+    }
+
+    fn list_saved_searches(&self) -> Result<Vec<SavedSearch>> {
+        SavedSearchDao::list(self)
+    }
+
+    fn update_saved_search(&self, search: &SavedSearch) -> Result<()> {
+        SavedSearchDao::update(self, search)
+    }
+
+    fn delete_saved_search(&self, id: &str) -> Result<()> {
+        SavedSearchDao::delete(self, id)
+    }
+}
+
+impl BlobStorage for Connection {
+    fn get_blob(&self, path: &str) -> Result<Option<Vec<u8>>> {
+        BlobDao::get(self, path)
+    }
+
+    fn put_blob(&self, path: &str, data: &[u8]) -> Result<()> {
+        BlobDao::put(self, path, data)
+    }
+
+    fn delete_blob(&self, path: &str) -> Result<()> {
+        BlobDao::delete(self, path)
+    }
+}
+
+impl SettingStorage for Connection {
+    fn get_setting(&self, key: &str) -> Result<Option<String>> {
+        SettingDao::get(self, key)
+    }
+
+    fn set_setting(&self, key: &str, value: &str) -> Result<()> {
+        SettingDao::set(self, key, value)
+    }
+
+    fn delete_setting(&self, key: &str) -> Result<()> {
+        SettingDao::delete(self, key)
+    }
+}
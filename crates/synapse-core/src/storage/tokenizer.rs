@@ -0,0 +1,380 @@
+//! `synapse_cjk`: an FTS5 tokenizer for mixed CJK and Latin text.
+//!
+//! Runs of Chinese and Japanese characters are indexed as overlapping bigrams, with each
+//! character also indexed as a colocated unigram, so that `知识` matches `知识管理系统`
+//! and a single-character query still finds something. Everything else is handed to the
+//! built-in `unicode61` tokenizer, which receives the tokenizer arguments (e.g.
+//! `tokenize = 'synapse_cjk remove_diacritics 2'`).
+//!
+//! Queries are tokenized as bigrams only: a query word of several characters becomes a
+//! phrase of consecutive bigrams, which matches exactly where the characters are adjacent.
+
+use std::ffi::{c_char, c_int, c_void};
+use std::ops::Range;
+use std::ptr;
+
+use rusqlite::Connection;
+use rusqlite::ffi;
+
+use crate::stats::is_cjk;
+
+/// Name to use in `tokenize = '...'`
+pub const CJK_TOKENIZER: &str = "synapse_cjk";
+
+/// Register the tokenizer on a connection; needed on every connection that reads or writes
+/// the full-text indexes
+pub fn register_cjk_tokenizer(conn: &Connection) -> rusqlite::Result<()> {
+    let api = fts5_api(conn)?;
+    let mut tokenizer = ffi::fts5_tokenizer {
+        xCreate: Some(x_create),
+        xDelete: Some(x_delete),
+        xTokenize: Some(x_tokenize),
+    };
+    // SAFETY: `api` is the connection's FTS5 API, valid while the connection is open. FTS5
+    // copies the function table; the user data is the API itself, passed back to `x_create`.
+    let rc = unsafe {
+        let create = (*api)
+            .xCreateTokenizer
+            .ok_or_else(|| failure("FTS5 API without xCreateTokenizer"))?;
+        create(
+            api,
+            c"synapse_cjk".as_ptr(),
+            api.cast(),
+            &mut tokenizer,
+            None,
+        )
+    };
+    check(conn, rc)
+}
+
+/// The FTS5 API of a connection, fetched with `SELECT fts5(?1)`
+fn fts5_api(conn: &Connection) -> rusqlite::Result<*mut ffi::fts5_api> {
+    let mut api: *mut ffi::fts5_api = ptr::null_mut();
+    // SAFETY: a plain prepare/bind/step/finalize on the connection's handle; the bound pointer
+    // lives until the statement is finalized.
+    let rc = unsafe {
+        let db = conn.handle();
+        let mut stmt = ptr::null_mut();
+        let rc = ffi::sqlite3_prepare_v2(
+            db,
+            c"SELECT fts5(?1)".as_ptr(),
+            -1,
+            &mut stmt,
+            ptr::null_mut(),
+        );
+        if rc != ffi::SQLITE_OK {
+            return check(conn, rc).map(|_| ptr::null_mut());
+        }
+        ffi::sqlite3_bind_pointer(
+            stmt,
+            1,
+            (&mut api as *mut *mut ffi::fts5_api).cast(),
+            c"fts5_api_ptr".as_ptr(),
+            None,
+        );
+        ffi::sqlite3_step(stmt);
+        ffi::sqlite3_finalize(stmt)
+    };
+    check(conn, rc)?;
+    if api.is_null() {
+        return Err(failure("FTS5 is not available"));
+    }
+    Ok(api)
+}
+
+fn check(conn: &Connection, rc: c_int) -> rusqlite::Result<()> {
+    if rc == ffi::SQLITE_OK {
+        return Ok(());
+    }
+    // SAFETY: reading the error message of an open connection
+    let message = unsafe {
+        let message = ffi::sqlite3_errmsg(conn.handle());
+        (!message.is_null()).then(|| {
+            std::ffi::CStr::from_ptr(message)
+                .to_string_lossy()
+                .into_owned()
+        })
+    };
+    Err(rusqlite::Error::SqliteFailure(ffi::Error::new(rc), message))
+}
+
+fn failure(message: &str) -> rusqlite::Error {
+    rusqlite::Error::SqliteFailure(
+        ffi::Error::new(ffi::SQLITE_ERROR),
+        Some(message.to_string()),
+    )
+}
+
+/// Tokenizer instance: the wrapped `unicode61` tokenizer
+struct CjkTokenizer {
+    inner: *mut ffi::Fts5Tokenizer,
+    functions: ffi::fts5_tokenizer,
+}
+
+type TokenCallback = unsafe extern "C" fn(
+    ctx: *mut c_void,
+    flags: c_int,
+    token: *const c_char,
+    len: c_int,
+    start: c_int,
+    end: c_int,
+) -> c_int;
+
+unsafe extern "C" fn x_create(
+    user_data: *mut c_void,
+    args: *mut *const c_char,
+    arg_count: c_int,
+    out: *mut *mut ffi::Fts5Tokenizer,
+) -> c_int {
+    let api: *mut ffi::fts5_api = user_data.cast();
+    let mut inner_data = ptr::null_mut();
+    let mut functions = ffi::fts5_tokenizer {
+        xCreate: None,
+        xDelete: None,
+        xTokenize: None,
+    };
+    // SAFETY: `api` was registered as user data and outlives its tokenizers
+    unsafe {
+        let Some(find) = (*api).xFindTokenizer else {
+            return ffi::SQLITE_ERROR;
+        };
+        let rc = find(api, c"unicode61".as_ptr(), &mut inner_data, &mut functions);
+        if rc != ffi::SQLITE_OK {
+            return rc;
+        }
+        let Some(create) = functions.xCreate else {
+            return ffi::SQLITE_ERROR;
+        };
+        let mut inner = ptr::null_mut();
+        let rc = create(inner_data, args, arg_count, &mut inner);
+        if rc != ffi::SQLITE_OK {
+            return rc;
+        }
+        *out = Box::into_raw(Box::new(CjkTokenizer { inner, functions })).cast();
+    }
+    ffi::SQLITE_OK
+}
+
+unsafe extern "C" fn x_delete(tokenizer: *mut ffi::Fts5Tokenizer) {
+    // SAFETY: `tokenizer` was created by `x_create` and is deleted once
+    unsafe {
+        let tokenizer = Box::from_raw(tokenizer.cast::<CjkTokenizer>());
+        if let Some(delete) = tokenizer.functions.xDelete {
+            delete(tokenizer.inner);
+        }
+    }
+}
+
+/// Forwards tokens of a non-CJK run to FTS5, moving offsets from the run to the whole text
+struct Forward {
+    ctx: *mut c_void,
+    token: TokenCallback,
+    offset: c_int,
+}
+
+unsafe extern "C" fn forward_token(
+    ctx: *mut c_void,
+    flags: c_int,
+    token: *const c_char,
+    len: c_int,
+    start: c_int,
+    end: c_int,
+) -> c_int {
+    // SAFETY: `ctx` is the `Forward` passed to the inner tokenizer below
+    unsafe {
+        let forward = &*ctx.cast::<Forward>();
+        (forward.token)(
+            forward.ctx,
+            flags,
+            token,
+            len,
+            start + forward.offset,
+            end + forward.offset,
+        )
+    }
+}
+
+unsafe extern "C" fn x_tokenize(
+    tokenizer: *mut ffi::Fts5Tokenizer,
+    ctx: *mut c_void,
+    flags: c_int,
+    text: *const c_char,
+    len: c_int,
+    token: Option<TokenCallback>,
+) -> c_int {
+    let Some(token) = token else {
+        return ffi::SQLITE_ERROR;
+    };
+    // SAFETY: FTS5 passes `len` bytes of UTF-8 text and a tokenizer made by `x_create`
+    unsafe {
+        let bytes = if len > 0 {
+            std::slice::from_raw_parts(text.cast::<u8>(), len as usize)
+        } else {
+            &[]
+        };
+        let Ok(text) = std::str::from_utf8(bytes) else {
+            return ffi::SQLITE_ERROR;
+        };
+        let tokenizer = &*tokenizer.cast::<CjkTokenizer>();
+        let query = flags & ffi::FTS5_TOKENIZE_QUERY != 0;
+
+        for run in runs(text) {
+            let rc = match run {
+                Run::Other(range) => {
+                    let Some(tokenize) = tokenizer.functions.xTokenize else {
+                        return ffi::SQLITE_ERROR;
+                    };
+                    let mut forward = Forward {
+                        ctx,
+                        token,
+                        offset: range.start as c_int,
+                    };
+                    tokenize(
+                        tokenizer.inner,
+                        (&mut forward as *mut Forward).cast(),
+                        flags,
+                        text[range.clone()].as_ptr().cast(),
+                        range.len() as c_int,
+                        Some(forward_token),
+                    )
+                }
+                Run::Cjk(range) => cjk_tokens(&text[range.clone()], query)
+                    .into_iter()
+                    .map(|(span, colocated)| {
+                        let (start, end) = (range.start + span.start, range.start + span.end);
+                        let flags = if colocated {
+                            ffi::FTS5_TOKEN_COLOCATED
+                        } else {
+                            0
+                        };
+                        token(
+                            ctx,
+                            flags,
+                            text[start..end].as_ptr().cast(),
+                            (end - start) as c_int,
+                            start as c_int,
+                            end as c_int,
+                        )
+                    })
+                    .find(|&rc| rc != ffi::SQLITE_OK)
+                    .unwrap_or(ffi::SQLITE_OK),
+            };
+            if rc != ffi::SQLITE_OK {
+                return rc;
+            }
+        }
+    }
+    ffi::SQLITE_OK
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Run {
+    Cjk(Range<usize>),
+    Other(Range<usize>),
+}
+
+/// Split text into alternating runs of CJK and other characters (byte ranges)
+fn runs(text: &str) -> Vec<Run> {
+    let mut runs: Vec<Run> = Vec::new();
+    for (i, c) in text.char_indices() {
+        let end = i + c.len_utf8();
+        match (runs.last_mut(), is_cjk(c)) {
+            (Some(Run::Cjk(range)), true) | (Some(Run::Other(range)), false) => range.end = end,
+            (_, true) => runs.push(Run::Cjk(i..end)),
+            (_, false) => runs.push(Run::Other(i..end)),
+        }
+    }
+    runs
+}
+
+/// Tokens of a CJK run as (byte range, colocated with the previous token). Documents get a
+/// bigram at each character plus the character itself at the same position; queries get
+/// bigrams only, or the character for a one-character run.
+fn cjk_tokens(run: &str, query: bool) -> Vec<(Range<usize>, bool)> {
+    let chars: Vec<(usize, char)> = run.char_indices().collect();
+    let char_range = |i: usize| chars[i].0..chars[i].0 + chars[i].1.len_utf8();
+    if chars.len() == 1 {
+        return vec![(char_range(0), false)];
+    }
+    let mut tokens = Vec::new();
+    for i in 0..chars.len() {
+        if i + 1 < chars.len() {
+            tokens.push((chars[i].0..char_range(i + 1).end, false));
+            if !query {
+                tokens.push((char_range(i), true));
+            }
+        } else if !query {
+            tokens.push((char_range(i), false));
+        }
+    }
+    tokens
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn texts<'a>(run: &'a str, tokens: &[(Range<usize>, bool)]) -> Vec<(&'a str, bool)> {
+        tokens
+            .iter()
+            .map(|(range, colocated)| (&run[range.clone()], *colocated))
+            .collect()
+    }
+
+    #[test]
+    fn test_runs_and_bigrams() {
+        assert_eq!(
+            runs("Rust编程 ok"),
+            vec![Run::Other(0..4), Run::Cjk(4..10), Run::Other(10..13)]
+        );
+        let run = "知识库";
+        assert_eq!(
+            texts(run, &cjk_tokens(run, false)),
+            vec![
+                ("知识", false),
+                ("知", true),
+                ("识库", false),
+                ("识", true),
+                ("库", false)
+            ]
+        );
+        assert_eq!(
+            texts(run, &cjk_tokens(run, true)),
+            vec![("知识", false), ("识库", false)]
+        );
+        assert_eq!(texts("知", &cjk_tokens("知", true)), vec![("知", false)]);
+    }
+
+    #[test]
+    fn test_fts5_matches_cjk_substrings() {
+        let conn = Connection::open_in_memory().unwrap();
+        register_cjk_tokenizer(&conn).unwrap();
+        conn.execute_batch(
+            "CREATE VIRTUAL TABLE docs USING fts5(body, tokenize = 'synapse_cjk remove_diacritics 2');
+             INSERT INTO docs(rowid, body) VALUES (1, '知识管理系统 for Café notes');
+             INSERT INTO docs(rowid, body) VALUES (2, '管理知识');",
+        )
+        .unwrap();
+        let search = |query: &str| -> Vec<i64> {
+            let mut stmt = conn
+                .prepare("SELECT rowid FROM docs WHERE docs MATCH ?1 ORDER BY rowid")
+                .unwrap();
+            stmt.query_map([query], |row| row.get(0))
+                .unwrap()
+                .collect::<rusqlite::Result<_>>()
+                .unwrap()
+        };
+        assert_eq!(search("知识"), vec![1, 2]);
+        assert_eq!(search("知识管理"), vec![1]);
+        assert_eq!(search("识"), vec![1, 2]);
+        assert_eq!(search("系管"), Vec::<i64>::new());
+        assert_eq!(search("cafe"), vec![1]);
+        assert_eq!(search("管理 NOTES"), vec![1]);
+
+        let mut stmt = conn
+            .prepare("SELECT highlight(docs, 0, '[', ']') FROM docs WHERE docs MATCH '管理系统'")
+            .unwrap();
+        let highlighted: String = stmt.query_row([], |row| row.get(0)).unwrap();
+        assert_eq!(highlighted, "知识[管理系统] for Café notes");
+    }
+}
@@ -0,0 +1,313 @@
+//! Units of work: a storage transaction together with the file writes that belong to it.
+//!
+//! Notes and attachments keep their content outside their rows, so most changes touch
+//! both. A unit stages each file write in a temporary file next to its target and only renames
+//! it into place on commit. The file it replaces is kept aside until the database commit
+//! succeeds, so a failure on either side leaves rows and files as they were. Note content goes
+//! through the context's [`ContentStore`], whose staged writes follow the same steps.
+//!
+//! A unit holds the backend's single transaction until it commits or is dropped, so units
+//! on different threads run one after another.
+
+use std::fs::{self, File};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+use crate::Result;
+use crate::content::{ContentChanges, ContentStore};
+use crate::storage::{SqliteBackend, StorageBackend, StorageTransaction};
+
+/// A database transaction with staged file and content writes; dropped without
+/// [`UnitOfWork::commit`], all of them are rolled back
+pub struct UnitOfWork<'a, B: StorageBackend + 'a = SqliteBackend> {
+    transaction: B::Transaction<'a>,
+    files: StagedFiles,
+    content: Box<dyn ContentChanges + 'a>,
+}
+
+/// File changes waiting for a unit to commit. Temporary files still staged when this is
+/// dropped are removed.
+#[derive(Default)]
+pub(crate) struct StagedFiles {
+    writes: Vec<StagedWrite>,
+    removals: Vec<PathBuf>,
+    /// Writes moved into place by [`StagedFiles::apply`], until they are finished or reverted
+    applied: Vec<AppliedWrite>,
+}
+
+struct StagedWrite {
+    target: PathBuf,
+    temp: PathBuf,
+}
+
+/// A write moved into place, with the copy of the file it replaced
+struct AppliedWrite {
+    target: PathBuf,
+    backup: Option<PathBuf>,
+}
+
+impl<'a, B: StorageBackend> UnitOfWork<'a, B> {
+    /// Begin a transaction on `storage`, staging note content for `content`
+    pub(crate) fn begin(storage: &'a B, content: &'a dyn ContentStore) -> Result<Self> {
+        Ok(Self {
+            transaction: storage.begin()?,
+            files: StagedFiles::default(),
+            content: content.changes(),
+        })
+    }
+
+    /// Store to run the unit's reads and writes on
+    pub fn store(&self) -> &B::Store {
+        &self.transaction
+    }
+
+    /// Stage `content` to replace the file at `path` on commit. The content is written to
+    /// disk now, beside the target, so commit only has to rename it.
+    pub fn write_file(&mut self, path: impl AsRef<Path>, content: &[u8]) -> Result<()> {
+        self.files.write(path.as_ref().to_path_buf(), content)
+    }
+
+    /// Delete the file at `path` once the unit has committed
+    pub fn remove_file(&mut self, path: impl AsRef<Path>) {
+        self.files.remove(path.as_ref().to_path_buf());
+    }
+
+    /// Stage note content to replace what the content store holds at `path`
+    pub fn write_content(&mut self, path: &str, content: &str) -> Result<()> {
+        self.content.write(&*self.transaction, path, content)
+    }
+
+    /// Move staged files and content into place, then commit the transaction. If any step
+    /// fails the replaced files and content are put back and the transaction is rolled back.
+    pub fn commit(self) -> Result<()> {
+        let Self {
+            transaction,
+            mut files,
+            mut content,
+        } = self;
+        files.apply()?;
+        if let Err(e) = content.apply() {
+            files.revert();
+            return Err(e);
+        }
+        if let Err(e) = transaction.commit() {
+            content.revert();
+            files.revert();
+            return Err(e);
+        }
+        content.finish();
+        files.finish();
+        Ok(())
+    }
+}
+
+impl StagedFiles {
+    /// Stage `content` to replace the file at `target`, writing it beside the target now and
+    /// creating its directory if it is the first file there
+    pub(crate) fn write(&mut self, target: PathBuf, content: &[u8]) -> Result<()> {
+        if let Some(i) = self.writes.iter().position(|w| w.target == target) {
+            let earlier = self.writes.remove(i);
+            let _ = fs::remove_file(earlier.temp);
+        }
+        if let Some(parent) = target.parent() {
+            fs::create_dir_all(parent)?;
+        }
+
+        let temp = sibling(&target, "tmp");
+        let mut file = File::create(&temp)?;
+        self.writes.push(StagedWrite { target, temp });
+        file.write_all(content)?;
+        file.sync_all()?;
+        Ok(())
+    }
+
+    /// Delete the file at `path` on [`StagedFiles::finish`]
+    pub(crate) fn remove(&mut self, path: PathBuf) {
+        self.removals.push(path);
+    }
+
+    /// Move staged writes into place, keeping the files they replace aside. If a write
+    /// fails, the ones already moved are reverted.
+    pub(crate) fn apply(&mut self) -> Result<()> {
+        while !self.writes.is_empty() {
+            let write = self.writes.remove(0);
+            let backup = match keep_aside(&write.target) {
+                Ok(backup) => backup,
+                Err(e) => {
+                    let _ = fs::remove_file(&write.temp);
+                    self.revert();
+                    return Err(e);
+                }
+            };
+            // Renaming over the target replaces it atomically
+            if let Err(e) = fs::rename(&write.temp, &write.target) {
+                let _ = fs::remove_file(&write.temp);
+                if let Some(backup) = backup {
+                    let _ = fs::remove_file(backup);
+                }
+                self.revert();
+                return Err(e.into());
+            }
+            self.applied.push(AppliedWrite {
+                target: write.target,
+                backup,
+            });
+        }
+        Ok(())
+    }
+
+    /// Put replaced files back and remove files that did not exist before, newest first
+    pub(crate) fn revert(&mut self) {
+        for write in self.applied.drain(..).rev() {
+            let _ = match &write.backup {
+                Some(backup) => fs::rename(backup, &write.target),
+                None => fs::remove_file(&write.target),
+            };
+        }
+    }
+
+    /// Drop the replaced files and carry out staged removals. The unit has committed by
+    /// now, so failures only leave stray files behind.
+    pub(crate) fn finish(&mut self) {
+        for write in self.applied.drain(..) {
+            if let Some(backup) = &write.backup {
+                let _ = fs::remove_file(backup);
+            }
+        }
+        for path in self.removals.drain(..) {
+            let _ = fs::remove_file(path);
+        }
+    }
+}
+
+impl Drop for StagedFiles {
+    fn drop(&mut self) {
+        for write in &self.writes {
+            let _ = fs::remove_file(&write.temp);
+        }
+    }
+}
+
+/// Keep a copy of `target`, if it exists, under a temporary name. The copy is a hard link
+/// where possible so the target itself never goes missing.
+fn keep_aside(target: &Path) -> Result<Option<PathBuf>> {
+    if !target.exists() {
+        return Ok(None);
+    }
+    let backup = sibling(target, "bak");
+    if fs::hard_link(target, &backup).is_err() {
+        fs::copy(target, &backup)?;
+    }
+    Ok(Some(backup))
+}
+
+/// Hidden, unique path in the same directory as `path`, so renames stay on one file system
+fn sibling(path: &Path, suffix: &str) -> PathBuf {
+    let name = path
+        .file_name()
+        .map(|name| name.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    path.with_file_name(format!(".{}.{}.{}", name, uuid::Uuid::new_v4(), suffix))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::content::MemoryContent;
+    use rusqlite::Connection;
+
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir =
+            std::env::temp_dir().join(format!("synapse-unit-{}-{}", name, uuid::Uuid::new_v4()));
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    /// Files in `dir` other than the database
+    fn entries(dir: &Path) -> Vec<String> {
+        let mut names: Vec<String> = fs::read_dir(dir)
+            .unwrap()
+            .map(|e| e.unwrap().file_name().to_string_lossy().into_owned())
+            .filter(|name| !name.starts_with("synapse.db"))
+            .collect();
+        names.sort();
+        names
+    }
+
+    fn tag_count(conn: &Connection) -> i64 {
+        conn.query_row("SELECT COUNT(*) FROM tags", [], |row| row.get(0))
+            .unwrap()
+    }
+
+    #[test]
+    fn test_commit_and_rollback() {
+        let dir = temp_dir("commit");
+        let storage = SqliteBackend::open(dir.join("synapse.db")).unwrap();
+        let content = MemoryContent::default();
+        let insert = "INSERT INTO tags (id, name, created_at) VALUES ('t1', 'rust', 0)";
+
+        let mut unit = UnitOfWork::begin(&storage, &content).unwrap();
+        unit.store().execute(insert, []).unwrap();
+        unit.write_file(dir.join("a.md"), b"first").unwrap();
+        unit.write_file(dir.join("a.md"), b"second").unwrap();
+        assert!(!dir.join("a.md").exists());
+        drop(unit);
+        assert_eq!(tag_count(&storage.reader().unwrap()), 0);
+        assert!(entries(&dir).is_empty());
+
+        let mut unit = UnitOfWork::begin(&storage, &content).unwrap();
+        unit.store().execute(insert, []).unwrap();
+        unit.write_file(dir.join("a.md"), b"first").unwrap();
+        unit.write_file(dir.join("a.md"), b"second").unwrap();
+        unit.commit().unwrap();
+        assert_eq!(tag_count(&storage.reader().unwrap()), 1);
+        assert_eq!(fs::read_to_string(dir.join("a.md")).unwrap(), "second");
+        assert_eq!(entries(&dir), vec!["a.md"]);
+
+        let mut unit = UnitOfWork::begin(&storage, &content).unwrap();
+        unit.remove_file(dir.join("a.md"));
+        drop(unit);
+        assert!(dir.join("a.md").exists());
+        let mut unit = UnitOfWork::begin(&storage, &content).unwrap();
+        unit.remove_file(dir.join("a.md"));
+        unit.commit().unwrap();
+        assert!(entries(&dir).is_empty());
+
+        drop(storage);
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_failed_database_commit_restores_files() {
+        let dir = temp_dir("restore");
+        let storage = SqliteBackend::open(dir.join("synapse.db")).unwrap();
+        let content = MemoryContent::default();
+        fs::write(dir.join("old.md"), "old").unwrap();
+
+        let mut unit = UnitOfWork::begin(&storage, &content).unwrap();
+        // A deferred foreign key violation only fails at COMMIT, after the files moved
+        unit.store()
+            .execute_batch(
+                r#"
+                PRAGMA defer_foreign_keys = ON;
+                INSERT INTO note_tags (note_id, tag_id, created_at) VALUES ('missing', 'missing', 0);
+                "#,
+            )
+            .unwrap();
+        unit.write_file(dir.join("old.md"), b"new").unwrap();
+        unit.write_file(dir.join("new.md"), b"new").unwrap();
+        assert!(unit.commit().is_err());
+
+        assert_eq!(fs::read_to_string(dir.join("old.md")).unwrap(), "old");
+        assert_eq!(entries(&dir), vec!["old.md"]);
+        let links: i64 = storage
+            .reader()
+            .unwrap()
+            .query_row("SELECT COUNT(*) FROM note_tags", [], |row| row.get(0))
+            .unwrap();
+        assert_eq!(links, 0);
+
+        drop(storage);
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,77 @@
+//! EditorCore benchmarks on large documents (~2 MB meeting log).
+
+use criterion::{BatchSize, Criterion, criterion_group, criterion_main};
+use synapse_editor::EditorCore;
+
+/// About 2 MB of mixed ASCII/CJK/emoji lines
+fn large_document() -> String {
+    let line = "- 10:42 讨论发布计划 with the team 👍 and follow up on action items\n";
+    line.repeat(2 * 1024 * 1024 / line.len())
+}
+
+fn bench_edits(c: &mut Criterion) {
+    let content = large_document();
+    let editor = EditorCore::new(content.clone());
+    let middle = editor.len() / 2;
+
+    c.bench_function("open_2mb", |b| b.iter(|| EditorCore::new(content.clone())));
+
+    c.bench_function("insert_middle_2mb", |b| {
+        b.iter_batched_ref(
+            || {
+                let mut editor = EditorCore::new(content.clone());
+                editor.set_cursor(middle).unwrap();
+                editor
+            },
+            |editor| editor.insert_at_cursor("x").unwrap(),
+            BatchSize::LargeInput,
+        )
+    });
+
+    c.bench_function("type_1000_chars_2mb", |b| {
+        b.iter_batched_ref(
+            || {
+                let mut editor = EditorCore::new(content.clone());
+                editor.set_cursor(middle).unwrap();
+                editor
+            },
+            |editor| {
+                for _ in 0..1000 {
+                    editor.insert_at_cursor("a").unwrap();
+                }
+            },
+            BatchSize::LargeInput,
+        )
+    });
+
+    c.bench_function("delete_backward_middle_2mb", |b| {
+        b.iter_batched_ref(
+            || {
+                let mut editor = EditorCore::new(content.clone());
+                editor.set_cursor(middle).unwrap();
+                editor
+            },
+            |editor| editor.delete_backward(1).unwrap(),
+            BatchSize::LargeInput,
+        )
+    });
+}
+
+fn bench_lookups(c: &mut Criterion) {
+    let editor = EditorCore::new(large_document());
+    let last_line = editor.line_count() - 1;
+    let middle = editor.len() / 2;
+
+    c.bench_function("line_range_2mb", |b| {
+        b.iter(|| editor.line_range(std::hint::black_box(last_line / 2)))
+    });
+    c.bench_function("grapheme_to_utf16_2mb", |b| {
+        b.iter(|| editor.grapheme_to_utf16(std::hint::black_box(middle)))
+    });
+    c.bench_function("utf16_to_grapheme_2mb", |b| {
+        b.iter(|| editor.utf16_to_grapheme(std::hint::black_box(middle)))
+    });
+}
+
+criterion_group!(benches, bench_edits, bench_lookups);
+criterion_main!(benches);
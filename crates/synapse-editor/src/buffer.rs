@@ -0,0 +1,539 @@
+//! Rope text buffer used by [`crate::EditorCore`].
+//!
+//! Text is stored as chunks of at most [`MAX_CHUNK`] bytes in a treap (a randomized balanced
+//! binary tree). Chunks are always cut at grapheme cluster boundaries, and every node caches
+//! the byte, grapheme, UTF-16 and line break counts of its subtree, so positions in any of
+//! those units are found in O(log n). Nodes are immutable and shared through `Arc`: an edit
+//! copies only the path it touches, and cloning a buffer is O(1).
+
+use std::ops::Range;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use unicode_segmentation::{GraphemeCursor, UnicodeSegmentation};
+
+/// Upper bound for chunk size in bytes (a single longer grapheme gets its own chunk)
+const MAX_CHUNK: usize = 256;
+
+/// Sizes of a piece of text in the units the editor cares about
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+struct TextInfo {
+    bytes: usize,
+    graphemes: usize,
+    utf16: usize,
+    line_breaks: usize,
+}
+
+impl TextInfo {
+    fn of(text: &str) -> Self {
+        let mut info = Self {
+            bytes: text.len(),
+            utf16: text.encode_utf16().count(),
+            ..Self::default()
+        };
+        for g in text.graphemes(true) {
+            info.graphemes += 1;
+            if is_line_break(g) {
+                info.line_breaks += 1;
+            }
+        }
+        info
+    }
+
+    fn add(self, other: Self) -> Self {
+        Self {
+            bytes: self.bytes + other.bytes,
+            graphemes: self.graphemes + other.graphemes,
+            utf16: self.utf16 + other.utf16,
+            line_breaks: self.line_breaks + other.line_breaks,
+        }
+    }
+}
+
+pub(crate) fn is_line_break(grapheme: &str) -> bool {
+    grapheme == "\n" || grapheme == "\r\n"
+}
+
+type Link = Option<Arc<Node>>;
+
+#[derive(Debug)]
+struct Node {
+    chunk: Arc<str>,
+    info: TextInfo,
+    /// Info of the whole subtree, including this chunk
+    sum: TextInfo,
+    priority: u64,
+    left: Link,
+    right: Link,
+}
+
+fn sum(link: &Link) -> TextInfo {
+    link.as_ref().map(|n| n.sum).unwrap_or_default()
+}
+
+fn node(chunk: Arc<str>, info: TextInfo, priority: u64, left: Link, right: Link) -> Link {
+    let sum = sum(&left).add(info).add(sum(&right));
+    Some(Arc::new(Node {
+        chunk,
+        info,
+        sum,
+        priority,
+        left,
+        right,
+    }))
+}
+
+/// Copy of `n` with new children
+fn rebuild(n: &Node, left: Link, right: Link) -> Link {
+    node(n.chunk.clone(), n.info, n.priority, left, right)
+}
+
+fn next_priority() -> u64 {
+    // splitmix64 over a global counter: cheap, deterministic-enough randomness for balancing
+    static COUNTER: AtomicU64 = AtomicU64::new(0);
+    let mut z = COUNTER
+        .fetch_add(0x9E37_79B9_7F4A_7C15, Ordering::Relaxed)
+        .wrapping_add(0x9E37_79B9_7F4A_7C15);
+    z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+    z ^ (z >> 31)
+}
+
+fn leaf(chunk: &str) -> Link {
+    if chunk.is_empty() {
+        return None;
+    }
+    node(
+        chunk.into(),
+        TextInfo::of(chunk),
+        next_priority(),
+        None,
+        None,
+    )
+}
+
+fn merge(a: Link, b: Link) -> Link {
+    match (a, b) {
+        (None, x) | (x, None) => x,
+        (Some(a), Some(b)) => {
+            if a.priority > b.priority {
+                rebuild(&a, a.left.clone(), merge(a.right.clone(), Some(b)))
+            } else {
+                rebuild(&b, merge(Some(a), b.left.clone()), b.right.clone())
+            }
+        }
+    }
+}
+
+/// Split into (first `k` bytes, rest). `k` must be a char boundary; a chunk cut in the
+/// middle of a cluster is re-chunked by the caller.
+fn split(link: &Link, k: usize) -> (Link, Link) {
+    let Some(n) = link else {
+        return (None, None);
+    };
+    let left_bytes = sum(&n.left).bytes;
+    if k <= left_bytes {
+        let (l, r) = split(&n.left, k);
+        (l, rebuild(n, r, n.right.clone()))
+    } else if k >= left_bytes + n.info.bytes {
+        let (l, r) = split(&n.right, k - left_bytes - n.info.bytes);
+        (rebuild(n, n.left.clone(), l), r)
+    } else {
+        let (a, b) = n.chunk.split_at(k - left_bytes);
+        (
+            merge(n.left.clone(), leaf(a)),
+            merge(leaf(b), n.right.clone()),
+        )
+    }
+}
+
+/// Remove the last chunk: (rest, chunk)
+fn pop_last(link: &Link) -> (Link, Option<Arc<str>>) {
+    let Some(n) = link else {
+        return (None, None);
+    };
+    if n.right.is_none() {
+        return (n.left.clone(), Some(n.chunk.clone()));
+    }
+    let (right, chunk) = pop_last(&n.right);
+    (rebuild(n, n.left.clone(), right), chunk)
+}
+
+/// Remove the first chunk: (chunk, rest)
+fn pop_first(link: &Link) -> (Option<Arc<str>>, Link) {
+    let Some(n) = link else {
+        return (None, None);
+    };
+    if n.left.is_none() {
+        return (Some(n.chunk.clone()), n.right.clone());
+    }
+    let (chunk, left) = pop_first(&n.left);
+    (chunk, rebuild(n, left, n.right.clone()))
+}
+
+/// Cut text into chunks at grapheme boundaries
+fn build(text: &str) -> Link {
+    let mut root = None;
+    let mut start = 0;
+    let mut end = 0;
+    for (offset, g) in text.grapheme_indices(true) {
+        if end > start && offset + g.len() - start > MAX_CHUNK {
+            root = merge(root, leaf(&text[start..end]));
+            start = end;
+        }
+        end = offset + g.len();
+    }
+    merge(root, leaf(&text[start..]))
+}
+
+/// Find the chunk where `metric` crosses `target`: the first chunk whose end lies beyond
+/// `target`, or the last chunk. Returns the chunk and the info of everything before it.
+fn find(
+    mut link: &Link,
+    mut target: usize,
+    metric: fn(&TextInfo) -> usize,
+) -> Option<(&Node, TextInfo)> {
+    let mut prefix = TextInfo::default();
+    while let Some(n) = link {
+        let left = sum(&n.left);
+        if target < metric(&left) {
+            link = &n.left;
+        } else if target < metric(&left) + metric(&n.info) || n.right.is_none() {
+            return Some((n, prefix.add(left)));
+        } else {
+            target -= metric(&left) + metric(&n.info);
+            prefix = prefix.add(left).add(n.info);
+            link = &n.right;
+        }
+    }
+    None
+}
+
+fn is_boundary(text: &str, offset: usize) -> bool {
+    GraphemeCursor::new(offset, text.len(), true)
+        .is_boundary(text, 0)
+        .unwrap_or(true)
+}
+
+fn grapheme_byte_offset(chunk: &str, index: usize) -> usize {
+    chunk
+        .grapheme_indices(true)
+        .nth(index)
+        .map(|(offset, _)| offset)
+        .unwrap_or(chunk.len())
+}
+
+/// Persistent rope of grapheme-aligned chunks
+#[derive(Debug, Clone, Default)]
+pub(crate) struct TextBuffer {
+    root: Link,
+}
+
+impl TextBuffer {
+    pub(crate) fn new(text: &str) -> Self {
+        Self { root: build(text) }
+    }
+
+    /// Length in grapheme clusters
+    pub(crate) fn len(&self) -> usize {
+        sum(&self.root).graphemes
+    }
+
+    pub(crate) fn line_breaks(&self) -> usize {
+        sum(&self.root).line_breaks
+    }
+
+    pub(crate) fn len_bytes(&self) -> usize {
+        sum(&self.root).bytes
+    }
+
+    /// Replace a byte range (on char boundaries) with text
+    pub(crate) fn replace(&mut self, range: Range<usize>, text: &str) {
+        let len = self.len_bytes();
+        let start = range.start.min(len);
+        let end = range.end.clamp(start, len);
+        let (left, rest) = split(&self.root, start);
+        let (_, right) = split(&rest, end - start);
+
+        // Re-chunk the edited neighbourhood so clusters spanning the edit stay whole. The
+        // edit can also move cluster boundaries further on (an inserted regional indicator
+        // re-pairs every flag after it), so keep taking chunks until an old chunk boundary
+        // is still a cluster boundary.
+        let (left, before) = pop_last(&left);
+        let mut joined = format!("{}{}", before.as_deref().unwrap_or(""), text);
+        let mut right = right;
+        while let (Some(after), rest) = pop_first(&right) {
+            let junction = joined.len();
+            joined.push_str(&after);
+            right = rest;
+            if is_boundary(&joined, junction) {
+                break;
+            }
+        }
+        self.root = merge(merge(left, build(&joined)), right);
+    }
+
+    /// Byte offset of a grapheme index
+    pub(crate) fn grapheme_to_byte(&self, index: usize) -> usize {
+        let index = index.min(self.len());
+        let Some((n, prefix)) = find(&self.root, index, |i| i.graphemes) else {
+            return 0;
+        };
+        prefix.bytes + grapheme_byte_offset(&n.chunk, index - prefix.graphemes)
+    }
+
+    /// Grapheme index of the first cluster boundary at or after a byte offset
+    pub(crate) fn byte_to_grapheme(&self, byte: usize) -> usize {
+        let Some((n, prefix)) = find(&self.root, byte, |i| i.bytes) else {
+            return 0;
+        };
+        let local = byte.saturating_sub(prefix.bytes);
+        prefix.graphemes
+            + n.chunk
+                .grapheme_indices(true)
+                .take_while(|(offset, _)| *offset < local)
+                .count()
+    }
+
+    /// Text of a grapheme range
+    pub(crate) fn slice(&self, range: Range<usize>) -> String {
+        let mut out = String::new();
+        collect(&self.root, range.start, range.end.min(self.len()), &mut out);
+        out
+    }
+
+    /// Grapheme index of the start of a line (`None` past the last line)
+    pub(crate) fn line_start(&self, line: usize) -> Option<usize> {
+        if line == 0 {
+            return Some(0);
+        }
+        if line > self.line_breaks() {
+            return None;
+        }
+        let (n, prefix) = find(&self.root, line - 1, |i| i.line_breaks)?;
+        let mut breaks = prefix.line_breaks;
+        for (i, g) in n.chunk.graphemes(true).enumerate() {
+            if is_line_break(g) {
+                breaks += 1;
+                if breaks == line {
+                    return Some(prefix.graphemes + i + 1);
+                }
+            }
+        }
+        None
+    }
+
+    /// Line containing a grapheme index (number of line breaks before it)
+    pub(crate) fn line_of(&self, index: usize) -> usize {
+        let index = index.min(self.len());
+        let Some((n, prefix)) = find(&self.root, index, |i| i.graphemes) else {
+            return 0;
+        };
+        prefix.line_breaks
+            + n.chunk
+                .graphemes(true)
+                .take(index - prefix.graphemes)
+                .filter(|g| is_line_break(g))
+                .count()
+    }
+
+    pub(crate) fn grapheme_to_utf16(&self, index: usize) -> usize {
+        let index = index.min(self.len());
+        let Some((n, prefix)) = find(&self.root, index, |i| i.graphemes) else {
+            return 0;
+        };
+        prefix.utf16
+            + n.chunk
+                .graphemes(true)
+                .take(index - prefix.graphemes)
+                .map(|g| g.encode_utf16().count())
+                .sum::<usize>()
+    }
+
+    /// Offsets inside a cluster resolve to the start of that cluster
+    pub(crate) fn utf16_to_grapheme(&self, offset: usize) -> usize {
+        let Some((n, prefix)) = find(&self.root, offset, |i| i.utf16) else {
+            return 0;
+        };
+        let mut units = prefix.utf16;
+        for (i, g) in n.chunk.graphemes(true).enumerate() {
+            units += g.encode_utf16().count();
+            if units > offset {
+                return prefix.graphemes + i;
+            }
+        }
+        self.len()
+    }
+}
+
+impl std::fmt::Display for TextBuffer {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&self.slice(0..self.len()))
+    }
+}
+
+/// Append the graphemes in `start..end` (relative to `link`) to `out`
+fn collect(link: &Link, start: usize, end: usize, out: &mut String) {
+    let Some(n) = link else { return };
+    if start >= end {
+        return;
+    }
+    let left = sum(&n.left).graphemes;
+    if start < left {
+        collect(&n.left, start, end.min(left), out);
+    }
+    let chunk_end = left + n.info.graphemes;
+    if start < chunk_end && end > left {
+        let from = start.saturating_sub(left);
+        let to = end.min(chunk_end) - left;
+        if from == 0 && to == n.info.graphemes {
+            out.push_str(&n.chunk);
+        } else {
+            let a = grapheme_byte_offset(&n.chunk, from);
+            let b = grapheme_byte_offset(&n.chunk, to);
+            out.push_str(&n.chunk[a..b]);
+        }
+    }
+    if end > chunk_end {
+        collect(
+            &n.right,
+            start.saturating_sub(chunk_end),
+            end - chunk_end,
+            out,
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Compare against a plain `String` under pseudo-random edits
+    #[test]
+    fn test_matches_string_model() {
+        let pieces = [
+            "a", "bc\n", "é", "e\u{301}", "👍🏽", "\r\n", "🇫🇷", " word ", "\u{301}",
+        ];
+        let mut seed = 42u64;
+        let mut rand = move |n: usize| {
+            seed = seed
+                .wrapping_mul(6364136223846793005)
+                .wrapping_add(1442695040888963407);
+            ((seed >> 33) as usize) % n.max(1)
+        };
+
+        let mut buffer = TextBuffer::new(&"line of text\n".repeat(300));
+        let mut model = buffer.to_string();
+        for _ in 0..2000 {
+            let len = model.graphemes(true).count();
+            let at = rand(len + 1);
+            let end = if rand(3) == 0 {
+                (at + rand(40)).min(len)
+            } else {
+                at
+            };
+            let text = if rand(2) == 0 {
+                pieces[rand(pieces.len())]
+            } else {
+                ""
+            };
+            let (a, b) = (byte_at(&model, at), byte_at(&model, end));
+            assert_eq!(
+                (buffer.grapheme_to_byte(at), buffer.grapheme_to_byte(end)),
+                (a, b)
+            );
+            model.replace_range(a..b, text);
+            buffer.replace(a..b, text);
+            assert_eq!(buffer.len(), model.graphemes(true).count());
+        }
+        assert_eq!(buffer.to_string(), model);
+        assert_eq!(buffer.len_bytes(), model.len());
+        assert_eq!(
+            buffer.line_breaks(),
+            model.graphemes(true).filter(|g| is_line_break(g)).count()
+        );
+    }
+
+    /// Cluster boundaries match a fresh segmentation after edits that re-pair flags
+    #[test]
+    fn test_matches_segmentation() {
+        let mut buffer = TextBuffer::new(&"🇺🇸".repeat(200));
+        buffer.replace(0..0, "\u{1F1EB}");
+        assert_eq!(buffer.slice(100..101), "🇸🇺");
+        assert_eq!(buffer.slice(200..201), "🇸");
+
+        let pieces = [
+            "\u{1F1EB}",
+            "\u{1F1F7}",
+            "🇺🇸",
+            "\u{200D}",
+            "👍",
+            "\u{1F3FD}",
+            "e",
+            "\u{301}",
+            "\r",
+            "\n",
+        ];
+        let mut seed = 7u64;
+        let mut rand = move |n: usize| {
+            seed = seed
+                .wrapping_mul(6364136223846793005)
+                .wrapping_add(1442695040888963407);
+            ((seed >> 33) as usize) % n.max(1)
+        };
+
+        let mut buffer = TextBuffer::new(&"🇺🇸".repeat(100));
+        let mut model = buffer.to_string();
+        for step in 0..1000 {
+            let chars: Vec<usize> = model
+                .char_indices()
+                .map(|(offset, _)| offset)
+                .chain([model.len()])
+                .collect();
+            let a = chars[rand(chars.len())];
+            let b = if rand(4) == 0 {
+                chars[rand(chars.len())].max(a)
+            } else {
+                a
+            };
+            let text = pieces[rand(pieces.len())];
+            model.replace_range(a..b, text);
+            buffer.replace(a..b, text);
+            if step % 50 == 0 {
+                let expected: Vec<&str> = model.graphemes(true).collect();
+                assert_eq!(buffer.len(), expected.len());
+                for (i, g) in expected.iter().enumerate() {
+                    assert_eq!(buffer.slice(i..i + 1), *g);
+                }
+            }
+        }
+        assert_eq!(buffer.to_string(), model);
+        assert_eq!(buffer.len(), model.graphemes(true).count());
+    }
+
+    #[test]
+    fn test_lookups_across_chunks() {
+        let text = "😀 x\n".repeat(1000);
+        let buffer = TextBuffer::new(&text);
+        assert_eq!(buffer.line_start(500), Some(2000));
+        assert_eq!(buffer.line_of(2001), 500);
+        assert_eq!(buffer.line_start(1001), None);
+        // Each line is 5 UTF-16 units: the emoji is a surrogate pair
+        assert_eq!(buffer.grapheme_to_utf16(2000), 2500);
+        assert_eq!(buffer.utf16_to_grapheme(2500), 2000);
+        assert_eq!(buffer.utf16_to_grapheme(2501), 2000);
+        assert_eq!(buffer.slice(1999..2004), "\n😀 x\n");
+        assert_eq!(buffer.grapheme_to_byte(4), 7);
+        // Inside the emoji resolves to the next boundary
+        assert_eq!(buffer.byte_to_grapheme(2), 1);
+        assert_eq!(buffer.byte_to_grapheme(5), 2);
+        assert_eq!(buffer.byte_to_grapheme(7), 4);
+    }
+
+    fn byte_at(text: &str, index: usize) -> usize {
+        text.grapheme_indices(true)
+            .nth(index)
+            .map(|(offset, _)| offset)
+            .unwrap_or(text.len())
+    }
+}
@@ -0,0 +1,226 @@
+//! Obsidian-style callouts: `> [!kind]± Title` block quotes.
+//!
+//! Callout blocks keep their Markdown source as content, so the syntax survives a round trip;
+//! [`Callout::parse`] exposes the kind, title and fold state for the UI.
+
+use std::ops::Range;
+
+use pulldown_cmark::{Event, Parser, Tag, TagEnd, html};
+
+use crate::html::escape;
+use crate::renderer::render_markdown_to_html;
+
+/// Fold state of a callout (`+` expanded, `-` collapsed)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CalloutFold {
+    Expanded,
+    Collapsed,
+}
+
+/// A parsed callout
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Callout {
+    /// Lowercased kind, e.g. `warning`, `note`, `tip`
+    pub kind: String,
+    pub title: Option<String>,
+    /// `None` if the callout is not foldable
+    pub fold: Option<CalloutFold>,
+    /// Markdown body without the `> ` prefixes
+    pub body: String,
+}
+
+impl Callout {
+    /// Parse the Markdown source of a block quote. Returns `None` if it is not a callout.
+    pub fn parse(source: &str) -> Option<Self> {
+        let mut lines = source.lines();
+        let header = strip_quote_marker(lines.next()?)?;
+        let rest = header.strip_prefix("[!")?;
+        let end = rest.find(']')?;
+        let kind = &rest[..end];
+        if kind.is_empty()
+            || !kind
+                .chars()
+                .all(|c| c.is_alphanumeric() || c == '-' || c == '_')
+        {
+            return None;
+        }
+
+        let mut after = &rest[end + 1..];
+        let fold = match after.chars().next() {
+            Some('+') => Some(CalloutFold::Expanded),
+            Some('-') => Some(CalloutFold::Collapsed),
+            _ => None,
+        };
+        if fold.is_some() {
+            after = &after[1..];
+        }
+        let title = after.trim();
+
+        let body = lines
+            .map(|line| strip_quote_marker(line).unwrap_or(line))
+            .collect::<Vec<_>>()
+            .join("\n");
+
+        Some(Self {
+            kind: kind.to_lowercase(),
+            title: (!title.is_empty()).then(|| title.to_string()),
+            fold,
+            body: body.trim_end().to_string(),
+        })
+    }
+
+    /// Title shown when none is given: the capitalized kind
+    pub fn display_title(&self) -> String {
+        match &self.title {
+            Some(title) => title.clone(),
+            None => {
+                let mut chars = self.kind.chars();
+                chars
+                    .next()
+                    .map(|c| c.to_uppercase().chain(chars).collect())
+                    .unwrap_or_default()
+            }
+        }
+    }
+
+    /// Serialize back to `> [!kind]± Title` Markdown
+    pub fn to_markdown(&self) -> String {
+        let mut out = format!("> [!{}]", self.kind);
+        match self.fold {
+            Some(CalloutFold::Expanded) => out.push('+'),
+            Some(CalloutFold::Collapsed) => out.push('-'),
+            None => {}
+        }
+        if let Some(title) = &self.title {
+            out.push(' ');
+            out.push_str(title);
+        }
+        for line in self.body.lines() {
+            out.push('\n');
+            if line.is_empty() {
+                out.push('>');
+            } else {
+                out.push_str("> ");
+                out.push_str(line);
+            }
+        }
+        out
+    }
+
+    /// Render as an `<aside>`; foldable callouts use `<details>` for the title and body
+    pub fn to_html(&self) -> String {
+        let kind = escape(&self.kind);
+        let title = render_inline(&self.display_title());
+        let body = render_markdown_to_html(&self.body);
+
+        let mut out = format!(
+            "<aside class=\"callout callout-{}\" data-callout=\"{}\">\n",
+            kind, kind
+        );
+        match self.fold {
+            Some(fold) => {
+                let open = if fold == CalloutFold::Expanded { " open" } else { "" };
+                out.push_str(&format!(
+                    "<details class=\"callout-fold\"{}>\n<summary class=\"callout-title\">{}</summary>\n<div class=\"callout-content\">\n{}</div>\n</details>\n",
+                    open, title, body
+                ));
+            }
+            None => out.push_str(&format!(
+                "<div class=\"callout-title\">{}</div>\n<div class=\"callout-content\">\n{}</div>\n",
+                title, body
+            )),
+        }
+        out.push_str("</aside>\n");
+        out
+    }
+}
+
+/// Strip indentation, `>` and one following space
+fn strip_quote_marker(line: &str) -> Option<&str> {
+    let rest = line.trim_start().strip_prefix('>')?;
+    Some(rest.strip_prefix(' ').unwrap_or(rest))
+}
+
+/// Render inline Markdown without the surrounding paragraph
+fn render_inline(text: &str) -> String {
+    let events = Parser::new(text).filter(|event| {
+        !matches!(
+            event,
+            Event::Start(Tag::Paragraph) | Event::End(TagEnd::Paragraph)
+        )
+    });
+    let mut out = String::new();
+    html::push_html(&mut out, events);
+    out.trim_end().to_string()
+}
+
+/// Event adapter that replaces callout block quotes with rendered `<aside>` HTML
+pub(crate) struct RenderCallouts<'a, I> {
+    source: &'a str,
+    inner: I,
+}
+
+impl<'a, I: Iterator<Item = (Event<'a>, Range<usize>)>> RenderCallouts<'a, I> {
+    pub(crate) fn new(source: &'a str, inner: I) -> Self {
+        Self { source, inner }
+    }
+
+    /// Skip the events of the block quote that was just started
+    fn skip_block_quote(&mut self) {
+        let mut depth = 1;
+        for (event, _) in self.inner.by_ref() {
+            match event {
+                Event::Start(Tag::BlockQuote(_)) => depth += 1,
+                Event::End(TagEnd::BlockQuote(_)) => {
+                    depth -= 1;
+                    if depth == 0 {
+                        break;
+                    }
+                }
+                _ => {}
+            }
+        }
+    }
+}
+
+impl<'a, I: Iterator<Item = (Event<'a>, Range<usize>)>> Iterator for RenderCallouts<'a, I> {
+    type Item = Event<'a>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let (event, range) = self.inner.next()?;
+        if let Event::Start(Tag::BlockQuote(_)) = event
+            && let Some(callout) = Callout::parse(&self.source[range])
+        {
+            self.skip_block_quote();
+            return Some(Event::Html(callout.to_html().into()));
+        }
+        Some(event)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_and_round_trip_callout() {
+        let source = "> [!Warning]- Mind the **gap**\n> First line\n>\n> Second paragraph";
+        let callout = Callout::parse(source).unwrap();
+        assert_eq!(callout.kind, "warning");
+        assert_eq!(callout.title.as_deref(), Some("Mind the **gap**"));
+        assert_eq!(callout.fold, Some(CalloutFold::Collapsed));
+        assert_eq!(callout.body, "First line\n\nSecond paragraph");
+        assert_eq!(
+            callout.to_markdown(),
+            "> [!warning]- Mind the **gap**\n> First line\n>\n> Second paragraph"
+        );
+
+        let plain = Callout::parse("> [!note]\n> Body").unwrap();
+        assert_eq!(plain.title, None);
+        assert_eq!(plain.fold, None);
+        assert_eq!(plain.display_title(), "Note");
+
+        assert!(Callout::parse("> Just a quote").is_none());
+        assert!(Callout::parse("> [!] empty kind").is_none());
+    }
+}
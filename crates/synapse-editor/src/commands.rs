@@ -0,0 +1,680 @@
+//! Markdown editing commands on [`EditorCore`]: inline styles, headings, lists, tasks and
+//! auto-paired brackets.
+//!
+//! Every command applies at each selection and is undone as a single step.
+
+use std::collections::HashSet;
+use std::ops::{Range, RangeInclusive};
+
+use synapse_core::Result;
+
+use crate::EditorCore;
+use crate::selection::Selection;
+
+/// Inline style toggled by [`EditorCore::toggle_inline_style`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum InlineStyle {
+    Bold,
+    Italic,
+    Code,
+}
+
+impl InlineStyle {
+    fn marker(self) -> &'static str {
+        match self {
+            InlineStyle::Bold => "**",
+            InlineStyle::Italic => "*",
+            InlineStyle::Code => "`",
+        }
+    }
+
+    /// Whether a run of `n` marker characters next to the text belongs to this style
+    /// (`***text***` is both bold and italic)
+    fn matches_run(self, n: usize) -> bool {
+        match self {
+            InlineStyle::Bold => n >= 2,
+            InlineStyle::Italic => n == 1 || n >= 3,
+            InlineStyle::Code => n == 1,
+        }
+    }
+}
+
+/// Indentation added or removed per list nesting level
+const LIST_INDENT: &str = "    ";
+
+/// Brackets closed automatically by [`EditorCore::type_char`]
+const PAIRS: [(char, char); 4] = [('(', ')'), ('[', ']'), ('{', '}'), ('`', '`')];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum ListMarker {
+    Bullet(char),
+    Ordered { number: u64, delimiter: char },
+}
+
+/// List item prefix of a line; offsets are bytes, which equal graphemes for the ASCII prefix
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct ListItem {
+    indent: usize,
+    marker: ListMarker,
+    /// Start of the text after the marker
+    content_start: usize,
+    /// Checkbox state and the position of the `x` or space inside `[ ]`
+    task: Option<(bool, usize)>,
+    /// Start of the text after the marker and checkbox
+    text_start: usize,
+}
+
+fn parse_list_item(line: &str) -> Option<ListItem> {
+    let indent = line.len() - line.trim_start_matches([' ', '\t']).len();
+    let rest = &line[indent..];
+    let (marker, marker_len) = match rest.chars().next()? {
+        c @ ('-' | '*' | '+') => (ListMarker::Bullet(c), 1),
+        _ => {
+            let digits = rest
+                .find(|c: char| !c.is_ascii_digit())
+                .unwrap_or(rest.len());
+            let delimiter = rest[digits..].chars().next()?;
+            if digits == 0 || digits > 9 || !matches!(delimiter, '.' | ')') {
+                return None;
+            }
+            let number = rest[..digits].parse().ok()?;
+            (ListMarker::Ordered { number, delimiter }, digits + 1)
+        }
+    };
+    let after = &rest[marker_len..];
+    let spaces = after.len() - after.trim_start_matches([' ', '\t']).len();
+    // `**bold**` and `---` are not list items
+    if spaces == 0 && !after.is_empty() {
+        return None;
+    }
+    let content_start = indent + marker_len + spaces;
+    let content = &line[content_start..];
+    let task = ["[ ]", "[x]", "[X]"]
+        .iter()
+        .find(|checkbox| content.starts_with(*checkbox))
+        .filter(|_| content[3..].is_empty() || content[3..].starts_with(' '))
+        .map(|checkbox| (checkbox != &"[ ]", content_start + 1));
+    let text_start = match task {
+        Some(_) => content_start + 3 + (content.len() > 3) as usize,
+        None => content_start,
+    };
+    Some(ListItem {
+        indent,
+        marker,
+        content_start,
+        task,
+        text_start,
+    })
+}
+
+fn is_blank(line: &str) -> bool {
+    line.trim().is_empty()
+}
+
+/// `selection` with a new range, keeping its direction
+fn reselect(selection: Selection, start: usize, end: usize) -> Selection {
+    if selection.head < selection.anchor {
+        Selection::new(end, start)
+    } else {
+        Selection::new(start, end)
+    }
+}
+
+impl EditorCore {
+    /// Toggle bold, italic or code around every selection. An empty selection inserts a
+    /// pair of markers around the cursor (or removes an empty pair).
+    pub fn toggle_inline_style(&mut self, style: InlineStyle) -> Result<()> {
+        let marker = style.marker();
+        let m = marker.len();
+        let c = &marker[..1];
+        self.change_selections(|editor, i, tracked| {
+            let selection = tracked[i];
+            let (start, end) = (selection.start(), selection.end());
+
+            let before = editor.marker_run(start, c, false);
+            let after = editor.marker_run(end, c, true);
+            if style.matches_run(before) && style.matches_run(after) {
+                editor.edit(end..end + m, "", tracked)?;
+                editor.edit(start - m..start, "", tracked)?;
+                tracked[i] = reselect(selection, start - m, end - m);
+                return Ok(());
+            }
+
+            if end - start >= 2 * m {
+                let leading = editor.marker_run(start, c, true).min(end - start);
+                let trailing = editor.marker_run(end, c, false).min(end - start);
+                if style.matches_run(leading) && style.matches_run(trailing) {
+                    editor.edit(end - m..end, "", tracked)?;
+                    editor.edit(start..start + m, "", tracked)?;
+                    tracked[i] = reselect(selection, start, end - 2 * m);
+                    return Ok(());
+                }
+            }
+
+            editor.edit(end..end, marker, tracked)?;
+            editor.edit(start..start, marker, tracked)?;
+            tracked[i] = reselect(selection, start + m, end + m);
+            Ok(())
+        })
+    }
+
+    /// Cycle the heading level of the line at each cursor: text, `#`, `##`, … `######`, text
+    pub fn cycle_heading(&mut self) -> Result<()> {
+        let mut done = HashSet::new();
+        self.change_selections(|editor, i, tracked| {
+            let line = editor.line_index_of(tracked[i].head);
+            if !done.insert(line) {
+                return Ok(());
+            }
+            let start = editor
+                .line_range(line)
+                .map_or(editor.len(), |span| span.start);
+            let content = editor.line_content(line).unwrap_or_default();
+            let hashes = content.chars().take_while(|&c| c == '#').count();
+            let is_heading = (1..=6).contains(&hashes)
+                && content[hashes..].chars().next().is_none_or(|c| c == ' ');
+            let (level, prefix_len) = if is_heading {
+                let spaces = content[hashes..].chars().take_while(|&c| c == ' ').count();
+                (hashes, hashes + spaces)
+            } else {
+                (0, 0)
+            };
+            let prefix = match (level + 1) % 7 {
+                0 => String::new(),
+                next => format!("{} ", "#".repeat(next)),
+            };
+            editor.edit(start..start + prefix_len, &prefix, tracked)
+        })
+    }
+
+    /// Insert a line break at every cursor. In a list item the new line continues the list
+    /// (unchecked for tasks, renumbering ordered lists); Enter on an empty item ends the list.
+    pub fn insert_newline(&mut self) -> Result<()> {
+        self.change_selections(|editor, i, tracked| {
+            if !tracked[i].is_empty() {
+                editor.edit(tracked[i].range(), "", tracked)?;
+            }
+            let pos = tracked[i].head;
+            let line = editor.line_index_of(pos);
+            let span = editor.line_range(line).unwrap_or(pos..pos);
+            let content = editor.line_content(line).unwrap_or_default();
+
+            let item = parse_list_item(&content).filter(|item| pos >= span.start + item.text_start);
+            let Some(item) = item else {
+                editor.edit(pos..pos, "\n", tracked)?;
+                return Ok(());
+            };
+            if is_blank(&content[item.text_start..]) {
+                editor.edit(span, "", tracked)?;
+                return Ok(());
+            }
+
+            let marker = match item.marker {
+                ListMarker::Bullet(c) => c.to_string(),
+                ListMarker::Ordered { number, delimiter } => format!("{}{}", number + 1, delimiter),
+            };
+            let checkbox = if item.task.is_some() { "[ ] " } else { "" };
+            let text = format!("\n{}{} {}", &content[..item.indent], marker, checkbox);
+            editor.edit(pos..pos, &text, tracked)?;
+            if matches!(item.marker, ListMarker::Ordered { .. }) {
+                editor.renumber_list(line + 1, tracked)?;
+            }
+            Ok(())
+        })
+    }
+
+    /// Indent the list items touched by each selection by one level
+    pub fn indent_list_items(&mut self) -> Result<()> {
+        self.shift_list_items(true)
+    }
+
+    /// Outdent the list items touched by each selection by one level
+    pub fn outdent_list_items(&mut self) -> Result<()> {
+        self.shift_list_items(false)
+    }
+
+    /// Check or uncheck the task on each selected line. List items become tasks and other
+    /// lines become task items.
+    pub fn toggle_task(&mut self) -> Result<()> {
+        let mut done = HashSet::new();
+        self.change_selections(|editor, i, tracked| {
+            for line in editor.selected_lines(tracked[i]) {
+                if !done.insert(line) {
+                    continue;
+                }
+                let Some(start) = editor.line_range(line).map(|span| span.start) else {
+                    continue;
+                };
+                let content = editor.line_content(line).unwrap_or_default();
+                match parse_list_item(&content) {
+                    Some(ListItem {
+                        task: Some((checked, mark)),
+                        ..
+                    }) => {
+                        let at = start + mark;
+                        editor.edit(at..at + 1, if checked { " " } else { "x" }, tracked)?;
+                    }
+                    Some(item) => {
+                        let at = start + item.content_start;
+                        editor.edit(at..at, "[ ] ", tracked)?;
+                    }
+                    None => {
+                        let indent = content.len() - content.trim_start_matches([' ', '\t']).len();
+                        editor.edit(start + indent..start + indent, "- [ ] ", tracked)?;
+                    }
+                }
+            }
+            Ok(())
+        })
+    }
+
+    /// Type a character with bracket and backtick auto-pairing: an opening bracket also
+    /// inserts its closing bracket (or wraps the selection), and typing a closing bracket
+    /// in front of the same one steps over it. Other characters are inserted as usual.
+    pub fn type_char(&mut self, c: char) -> Result<()> {
+        let close = PAIRS
+            .iter()
+            .find(|(open, _)| *open == c)
+            .map(|&(_, close)| close);
+        let is_close = PAIRS.iter().any(|&(_, close)| close == c);
+        if close.is_none() && !is_close {
+            return self.insert_at_cursor(&c.to_string());
+        }
+        let typed = c.to_string();
+        self.change_selections(|editor, i, tracked| {
+            let selection = tracked[i];
+            let head = selection.head;
+            let next = editor.grapheme_at(head);
+            if selection.is_empty() && is_close && next.as_deref() == Some(typed.as_str()) {
+                tracked[i] = Selection::cursor(head + 1);
+                return Ok(());
+            }
+            if let Some(close) = close {
+                let (start, end) = (selection.start(), selection.end());
+                if !selection.is_empty() {
+                    editor.edit(end..end, &close.to_string(), tracked)?;
+                    editor.edit(start..start, &typed, tracked)?;
+                    tracked[i] = reselect(selection, start + 1, end + 1);
+                    return Ok(());
+                }
+                let before_space = next.as_deref().is_none_or(|g| {
+                    g.chars().all(char::is_whitespace)
+                        || PAIRS.iter().any(|&(_, c)| g == c.to_string())
+                });
+                if before_space {
+                    editor.edit(head..head, &format!("{}{}", c, close), tracked)?;
+                    tracked[i] = Selection::cursor(head + 1);
+                    return Ok(());
+                }
+            }
+            editor.edit(selection.range(), &typed, tracked)
+        })
+    }
+
+    /// Backspace that also removes the closing half of an empty auto-paired bracket
+    pub fn backspace(&mut self) -> Result<()> {
+        self.change_selections(|editor, i, tracked| {
+            let selection = tracked[i];
+            let head = selection.head;
+            if !selection.is_empty() {
+                return editor.edit(selection.range(), "", tracked);
+            }
+            if head == 0 {
+                return Ok(());
+            }
+            let (before, after) = (editor.grapheme_at(head - 1), editor.grapheme_at(head));
+            let empty_pair = PAIRS.iter().any(|&(open, close)| {
+                before.as_deref() == Some(open.to_string().as_str())
+                    && after.as_deref() == Some(close.to_string().as_str())
+            });
+            let end = if empty_pair { head + 1 } else { head };
+            editor.edit(head - 1..end, "", tracked)
+        })
+    }
+
+    fn shift_list_items(&mut self, indent: bool) -> Result<()> {
+        let mut done = HashSet::new();
+        self.change_selections(|editor, i, tracked| {
+            let lines = editor.selected_lines(tracked[i]);
+            let first = *lines.start();
+            let mut ordered = false;
+            for line in lines {
+                if !done.insert(line) {
+                    continue;
+                }
+                let content = editor.line_content(line).unwrap_or_default();
+                let Some(item) = parse_list_item(&content) else {
+                    continue;
+                };
+                ordered |= matches!(item.marker, ListMarker::Ordered { .. });
+                let start = editor.line_range(line).map_or(0, |span| span.start);
+                if indent {
+                    editor.edit(start..start, LIST_INDENT, tracked)?;
+                } else {
+                    let remove = if content.starts_with('\t') {
+                        1
+                    } else {
+                        item.indent.min(LIST_INDENT.len())
+                    };
+                    editor.edit(start..start + remove, "", tracked)?;
+                }
+            }
+            if ordered {
+                editor.renumber_list(first, tracked)?;
+            }
+            Ok(())
+        })
+    }
+
+    /// Renumber the ordered lists (at every nesting level) of the list block containing
+    /// `line`: the block of non-blank lines around it
+    fn renumber_list(&mut self, line: usize, tracked: &mut [Selection]) -> Result<()> {
+        let non_blank = |editor: &Self, line: usize| {
+            editor
+                .line_content(line)
+                .is_some_and(|content| !is_blank(&content))
+        };
+        let mut first = line;
+        while first > 0 && non_blank(self, first - 1) {
+            first -= 1;
+        }
+
+        // Open lists by indentation, with the next number for ordered ones
+        let mut levels: Vec<(usize, Option<u64>)> = Vec::new();
+        let mut line = first;
+        while non_blank(self, line) {
+            let content = self.line_content(line).unwrap_or_default();
+            if let Some(item) = parse_list_item(&content) {
+                while levels
+                    .last()
+                    .is_some_and(|&(indent, _)| indent > item.indent)
+                {
+                    levels.pop();
+                }
+                let continues = levels
+                    .last()
+                    .is_some_and(|&(indent, _)| indent == item.indent);
+                match item.marker {
+                    ListMarker::Ordered { number, .. } => {
+                        let expected = match levels.last() {
+                            Some(&(_, Some(next))) if continues => next,
+                            // A nested list starts at one; the outermost keeps its start
+                            Some(_) if !continues => 1,
+                            _ => number,
+                        };
+                        if continues {
+                            levels.pop();
+                        }
+                        levels.push((item.indent, Some(expected + 1)));
+                        if expected != number {
+                            let start = self.line_range(line).map_or(0, |span| span.start);
+                            let at = start + item.indent;
+                            let digits = content[item.indent..]
+                                .chars()
+                                .take_while(char::is_ascii_digit)
+                                .count();
+                            self.edit(at..at + digits, &expected.to_string(), tracked)?;
+                        }
+                    }
+                    ListMarker::Bullet(_) => {
+                        if continues {
+                            levels.pop();
+                        }
+                        levels.push((item.indent, None));
+                    }
+                }
+            }
+            line += 1;
+        }
+        Ok(())
+    }
+
+    /// Lines touched by a selection; a selection ending at the start of a line does not
+    /// include that line
+    fn selected_lines(&self, selection: Selection) -> RangeInclusive<usize> {
+        let first = self.line_index_of(selection.start());
+        let mut last = self.line_index_of(selection.end());
+        if last > first
+            && self
+                .line_range(last)
+                .is_some_and(|span| span.start == selection.end())
+        {
+            last -= 1;
+        }
+        first..=last
+    }
+
+    /// Number (up to 3) of `marker` graphemes directly after (`forward`) or before `pos`
+    fn marker_run(&self, pos: usize, marker: &str, forward: bool) -> usize {
+        (0..3)
+            .take_while(|&k| {
+                let at = if forward {
+                    pos + k
+                } else {
+                    pos.wrapping_sub(k + 1)
+                };
+                self.grapheme_at(at).as_deref() == Some(marker)
+            })
+            .count()
+    }
+
+    /// Run `f` for each selection, from the last to the first, as one undo step. `f` gets the
+    /// index of its selection in `tracked`, edits with [`EditorCore::edit`] so that the
+    /// tracked selections follow the text, and may set its own new selection.
+    fn change_selections(
+        &mut self,
+        mut f: impl FnMut(&mut Self, usize, &mut [Selection]) -> Result<()>,
+    ) -> Result<()> {
+        let mut tracked = self.selections().to_vec();
+        let primary = self.primary_selection_index();
+        self.begin_group();
+        let result = (0..tracked.len())
+            .rev()
+            .try_for_each(|i| f(self, i, &mut tracked));
+        let selected = self.set_selections(tracked, primary);
+        self.end_group();
+        result.and(selected)
+    }
+
+    /// Replace a grapheme range and move the tracked selections along with the text;
+    /// positions at an insertion point end up after the inserted text
+    fn edit(&mut self, range: Range<usize>, text: &str, tracked: &mut [Selection]) -> Result<()> {
+        let inserted = self.replace(range.clone(), text)?;
+        let map = |pos: usize| {
+            if pos >= range.end {
+                pos - range.end + inserted.end
+            } else if pos > range.start {
+                inserted.end
+            } else {
+                pos
+            }
+        };
+        for selection in tracked {
+            *selection = Selection::new(map(selection.anchor), map(selection.head));
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn editor(content: &str, anchor: usize, head: usize) -> EditorCore {
+        let mut editor = EditorCore::new(content);
+        editor.set_selection(anchor, head).unwrap();
+        editor
+    }
+
+    #[test]
+    fn test_parse_list_item() {
+        let item = parse_list_item("  12) [x] done").unwrap();
+        assert_eq!(item.indent, 2);
+        assert_eq!(
+            item.marker,
+            ListMarker::Ordered {
+                number: 12,
+                delimiter: ')'
+            }
+        );
+        assert_eq!(item.task, Some((true, 7)));
+        assert_eq!(item.text_start, 10);
+        assert_eq!(parse_list_item("-").unwrap().content_start, 1);
+        assert!(parse_list_item("**bold**").is_none());
+        assert!(parse_list_item("---").is_none());
+        assert!(parse_list_item("2024 was").is_none());
+    }
+
+    #[test]
+    fn test_toggle_inline_style() {
+        let mut e = editor("make it bold", 8, 12);
+        e.toggle_inline_style(InlineStyle::Bold).unwrap();
+        assert_eq!(e.buffer_content(), "make it **bold**");
+        assert_eq!(e.selection(), Some((10, 14)));
+        e.toggle_inline_style(InlineStyle::Bold).unwrap();
+        assert_eq!(e.buffer_content(), "make it bold");
+        assert_eq!(e.selection(), Some((8, 12)));
+
+        // Italic inside bold, and unwrapping a selection that includes its markers
+        let mut e = editor("**both**", 2, 6);
+        e.toggle_inline_style(InlineStyle::Italic).unwrap();
+        assert_eq!(e.buffer_content(), "***both***");
+        e.toggle_inline_style(InlineStyle::Bold).unwrap();
+        assert_eq!(e.buffer_content(), "*both*");
+        let mut e = editor("*both*", 6, 0);
+        e.toggle_inline_style(InlineStyle::Italic).unwrap();
+        assert_eq!(e.buffer_content(), "both");
+        assert_eq!(e.selection(), Some((4, 0)));
+
+        let mut e = editor("run ", 4, 4);
+        e.toggle_inline_style(InlineStyle::Code).unwrap();
+        assert_eq!(e.buffer_content(), "run ``");
+        assert_eq!(e.cursor_position(), 5);
+        e.toggle_inline_style(InlineStyle::Code).unwrap();
+        assert_eq!(e.buffer_content(), "run ");
+
+        // One undo step per toggle
+        let mut e = editor("a b", 0, 1);
+        e.add_selection(Selection::new(2, 3));
+        e.toggle_inline_style(InlineStyle::Bold).unwrap();
+        assert_eq!(e.buffer_content(), "**a** **b**");
+        e.undo().unwrap();
+        assert_eq!(e.buffer_content(), "a b");
+    }
+
+    #[test]
+    fn test_cycle_heading() {
+        let mut e = editor("Title\nbody", 2, 2);
+        e.cycle_heading().unwrap();
+        assert_eq!(e.buffer_content(), "# Title\nbody");
+        assert_eq!(e.cursor_position(), 4);
+        e.cycle_heading().unwrap();
+        assert_eq!(e.buffer_content(), "## Title\nbody");
+        for _ in 0..4 {
+            e.cycle_heading().unwrap();
+        }
+        assert_eq!(e.buffer_content(), "###### Title\nbody");
+        e.cycle_heading().unwrap();
+        assert_eq!(e.buffer_content(), "Title\nbody");
+        assert_eq!(e.cursor_position(), 2);
+        e.undo().unwrap();
+        assert_eq!(e.buffer_content(), "###### Title\nbody");
+        // `#tag` is not a heading
+        let mut e = editor("#tag", 0, 0);
+        e.cycle_heading().unwrap();
+        assert_eq!(e.buffer_content(), "# #tag");
+    }
+
+    #[test]
+    fn test_insert_newline_continues_lists() {
+        let mut e = editor("- one", 5, 5);
+        e.insert_newline().unwrap();
+        assert_eq!(e.buffer_content(), "- one\n- ");
+        assert_eq!(e.cursor_position(), 8);
+        // Enter on an empty item ends the list
+        e.insert_newline().unwrap();
+        assert_eq!(e.buffer_content(), "- one\n");
+        assert_eq!(e.cursor_position(), 6);
+
+        let mut e = editor("  - [x] done", 12, 12);
+        e.insert_newline().unwrap();
+        assert_eq!(e.buffer_content(), "  - [x] done\n  - [ ] ");
+
+        let mut e = editor("1. a\n2. b\n3. c", 4, 4);
+        e.insert_newline().unwrap();
+        assert_eq!(e.buffer_content(), "1. a\n2. \n3. b\n4. c");
+        assert_eq!(e.cursor_position(), 8);
+        e.undo().unwrap();
+        assert_eq!(e.buffer_content(), "1. a\n2. b\n3. c");
+
+        // Splitting an item moves the rest of the text to the new item
+        let mut e = editor("9) ab", 4, 4);
+        e.insert_newline().unwrap();
+        assert_eq!(e.buffer_content(), "9) a\n10) b");
+
+        let mut e = editor("plain", 5, 5);
+        e.insert_newline().unwrap();
+        assert_eq!(e.buffer_content(), "plain\n");
+    }
+
+    #[test]
+    fn test_indent_and_outdent_list_items() {
+        let mut e = editor("1. a\n2. b\n3. c\ntext", 6, 6);
+        e.indent_list_items().unwrap();
+        assert_eq!(e.buffer_content(), "1. a\n    1. b\n2. c\ntext");
+        assert_eq!(e.cursor_position(), 10);
+        e.outdent_list_items().unwrap();
+        assert_eq!(e.buffer_content(), "1. a\n2. b\n3. c\ntext");
+        e.undo().unwrap();
+        assert_eq!(e.buffer_content(), "1. a\n    1. b\n2. c\ntext");
+
+        // Lines that are not list items are left alone
+        let mut e = editor("- a\ntext\n- b", 0, 12);
+        e.indent_list_items().unwrap();
+        assert_eq!(e.buffer_content(), "    - a\ntext\n    - b");
+    }
+
+    #[test]
+    fn test_toggle_task() {
+        let mut e = editor("- [ ] todo\n- item\nplain\n  - [x] done", 0, 34);
+        e.toggle_task().unwrap();
+        assert_eq!(
+            e.buffer_content(),
+            "- [x] todo\n- [ ] item\n- [ ] plain\n  - [ ] done"
+        );
+        e.undo().unwrap();
+        assert_eq!(
+            e.buffer_content(),
+            "- [ ] todo\n- item\nplain\n  - [x] done"
+        );
+    }
+
+    #[test]
+    fn test_auto_pairs() {
+        let mut e = editor("", 0, 0);
+        for c in "f(x".chars() {
+            e.type_char(c).unwrap();
+        }
+        assert_eq!(e.buffer_content(), "f(x)");
+        e.type_char(')').unwrap();
+        assert_eq!(e.buffer_content(), "f(x)");
+        assert_eq!(e.cursor_position(), 4);
+
+        e.type_char(' ').unwrap();
+        e.type_char('`').unwrap();
+        assert_eq!(e.buffer_content(), "f(x) ``");
+        e.backspace().unwrap();
+        assert_eq!(e.buffer_content(), "f(x) ");
+
+        // No pairing in front of a word; wrapping a selection
+        let mut e = editor("word", 0, 0);
+        e.type_char('[').unwrap();
+        assert_eq!(e.buffer_content(), "[word");
+        let mut e = editor("link", 0, 4);
+        e.type_char('[').unwrap();
+        assert_eq!(e.buffer_content(), "[link]");
+        assert_eq!(e.selection(), Some((1, 5)));
+        e.undo().unwrap();
+        assert_eq!(e.buffer_content(), "link");
+    }
+}
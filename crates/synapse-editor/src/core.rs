@@ -1,188 +1,896 @@
-//! Editor core: buffer, cursor, and commands (minimal stub).
+//! Editor core: a grapheme-indexed rope buffer with multiple selections, grouped undo,
+//! search and replace, text statistics and change tracking for incremental parsing.
 //!
-//! Index and selection use grapheme clusters. See interface doc for full API.
+//! Index and selection use grapheme clusters: an emoji ZWJ sequence, a letter with combining
+//! accents or a flag is one position, and `\r\n` counts as a single line break. The CodeMirror
+//! frontend works in UTF-16 code units; use [`EditorCore::grapheme_to_utf16`] and
+//! [`EditorCore::utf16_to_grapheme`] to convert.
+//!
+//! The editor holds a set of selections (multiple cursors) with one primary selection. The
+//! single-cursor methods (`cursor_position`, `set_cursor`, `selection`, `set_selection`) read
+//! and write the primary one; `insert_at_cursor`, `delete_backward` and `delete_forward` edit
+//! at every selection and are undone as one step. Markdown editing commands (inline styles,
+//! headings, lists, tasks, auto-pairs) live in `commands.rs`.
 
 use std::ops::Range;
+use std::time::Duration;
+
+use regex::{Captures, Regex};
+use synapse_core::{Error, Result, TextStats};
 
-use synapse_core::Result;
+use crate::buffer::TextBuffer;
+use crate::history::{Edit, History};
+use crate::incremental::TextChange;
+use crate::search::{FindOptions, grapheme_matches};
+use crate::selection::{Selection, SelectionSet};
 
-/// Editor core: buffer + cursor + basic edit/undo.
+/// Editor core: buffer, selections, undo history and changes not yet taken.
 /// Full API per docs/02-架构设计/接口定义-core-editor.md.
 pub struct EditorCore {
-    buffer: String,
-    cursor: usize,
-    /// (anchor, head); no selection when anchor == head
-    selection: Option<(usize, usize)>,
-    undo_stack: Vec<String>,
-    redo_stack: Vec<String>,
+    buffer: TextBuffer,
+    selections: SelectionSet,
+    history: History,
+    /// Changes since the last [`EditorCore::take_changes`]
+    changes: Vec<TextChange>,
 }
 
+/// Pending changes beyond this are composed into one, so that an editor nobody takes changes
+/// from does not grow without bound
+const MAX_PENDING_CHANGES: usize = 1024;
+
 impl EditorCore {
     pub fn new(initial_content: impl Into<String>) -> Self {
-        let buffer = initial_content.into();
-        let len = buffer.chars().count();
+        let buffer = TextBuffer::new(&initial_content.into());
         Self {
-            buffer: buffer.clone(),
-            cursor: len.min(len),
-            selection: None,
-            undo_stack: vec![buffer],
-            redo_stack: Vec::new(),
+            selections: SelectionSet::single(Selection::cursor(buffer.len())),
+            buffer,
+            history: History::default(),
+            changes: Vec::new(),
         }
     }
 
     pub fn buffer_content(&self) -> String {
-        self.buffer.clone()
+        self.buffer.to_string()
+    }
+
+    /// Length of the buffer in grapheme clusters
+    pub fn len(&self) -> usize {
+        self.buffer.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.buffer.len() == 0
     }
 
     pub fn line_count(&self) -> usize {
-        self.buffer.lines().count().max(1)
+        // Like `str::lines`: a trailing line break does not start another line
+        let breaks = self.buffer.line_breaks();
+        let ends_with_break = breaks > 0 && self.buffer.line_start(breaks) == Some(self.len());
+        (breaks + 1 - usize::from(ends_with_break)).max(1)
     }
 
+    /// Head of the primary selection
     pub fn cursor_position(&self) -> usize {
-        self.cursor
+        self.selections.primary().head
     }
 
+    /// Place a single cursor, dropping all other selections
     pub fn set_cursor(&mut self, index: usize) -> Result<()> {
-        let len = self.buffer.chars().count();
-        self.cursor = index.min(len);
+        self.select(SelectionSet::single(Selection::cursor(
+            index.min(self.len()),
+        )));
         Ok(())
     }
 
+    /// Primary selection as (anchor, head), if it is not empty
     pub fn selection(&self) -> Option<(usize, usize)> {
-        self.selection
+        let primary = self.selections.primary();
+        (!primary.is_empty()).then_some((primary.anchor, primary.head))
     }
 
+    /// Select a single range, dropping all other selections; the cursor goes to `head`
     pub fn set_selection(&mut self, anchor: usize, head: usize) -> Result<()> {
-        let len = self.buffer.chars().count();
-        self.selection = Some((anchor.min(len), head.min(len)));
+        let len = self.len();
+        let selection = Selection::new(anchor.min(len), head.min(len));
+        self.select(SelectionSet::single(selection));
         Ok(())
     }
 
-    pub fn insert_at_cursor(&mut self, text: &str) -> Result<()> {
-        let byte_pos = self.char_offset_to_byte(self.cursor);
-        self.buffer.insert_str(byte_pos, text);
-        self.cursor += text.chars().count();
-        self.undo_stack.push(self.buffer.clone());
-        self.redo_stack.clear();
+    /// Collapse every selection to a cursor at its head
+    pub fn clear_selection(&mut self) {
+        let ranges = self
+            .selections
+            .ranges()
+            .iter()
+            .map(|s| Selection::cursor(s.head));
+        let set = SelectionSet::new(
+            ranges.collect(),
+            self.selections.primary_index(),
+            self.len(),
+        );
+        self.select(set);
+    }
+
+    /// All selections in document order
+    pub fn selections(&self) -> &[Selection] {
+        self.selections.ranges()
+    }
+
+    /// Index of the primary selection in [`EditorCore::selections`]
+    pub fn primary_selection_index(&self) -> usize {
+        self.selections.primary_index()
+    }
+
+    /// Replace all selections; overlapping ones are merged
+    pub fn set_selections(&mut self, selections: Vec<Selection>, primary: usize) -> Result<()> {
+        if primary >= selections.len() {
+            return Err(Error::InvalidInput(format!(
+                "Primary selection {} out of {} selections",
+                primary,
+                selections.len()
+            )));
+        }
+        let set = SelectionSet::new(selections, primary, self.len());
+        self.select(set);
         Ok(())
     }
 
-    pub fn delete_backward(&mut self, n: usize) -> Result<usize> {
-        let byte_pos = self.char_offset_to_byte(self.cursor);
-        let start = (0..self.buffer.len())
-            .rev()
-            .filter(|&i| self.buffer.is_char_boundary(i))
-            .nth(n)
+    /// Add a selection (or cursor) and make it the primary one
+    pub fn add_selection(&mut self, selection: Selection) {
+        let mut ranges = self.selections.ranges().to_vec();
+        ranges.push(selection);
+        let primary = ranges.len() - 1;
+        let set = SelectionSet::new(ranges, primary, self.len());
+        self.select(set);
+    }
+
+    /// Keep only the primary selection
+    pub fn collapse_selections(&mut self) {
+        self.select(SelectionSet::single(self.selections.primary()));
+    }
+
+    /// Select the next occurrence of the primary selection's text (wrapping around the
+    /// buffer) as a new primary selection. With an empty primary selection, select the word
+    /// at the cursor instead. Returns false when there is nothing more to select.
+    pub fn add_next_occurrence(&mut self) -> bool {
+        let primary = self.selections.primary();
+        if primary.is_empty() {
+            let Some(word) = self.word_at(primary.head) else {
+                return false;
+            };
+            let mut ranges = self.selections.ranges().to_vec();
+            ranges[self.selections.primary_index()] = Selection::new(word.start, word.end);
+            let set = SelectionSet::new(ranges, self.selections.primary_index(), self.len());
+            self.select(set);
+            return true;
+        }
+
+        let Ok(regex) = Regex::new(&regex::escape(&self.text_range(primary.range()))) else {
+            return false;
+        };
+        let matches = self.find_regex(&regex);
+        let first_after = matches
+            .iter()
+            .position(|m| m.start >= primary.end())
             .unwrap_or(0);
-        let removed = if start >= byte_pos {
-            0
-        } else {
-            self.buffer[start..byte_pos].chars().count()
+        let selected = |m: &Range<usize>| {
+            self.selections
+                .ranges()
+                .iter()
+                .any(|s| s.start() < m.end && m.start < s.end())
         };
-        if removed > 0 {
-            self.buffer.drain(start..byte_pos);
-            self.cursor = self.cursor.saturating_sub(removed);
-            self.undo_stack.push(self.buffer.clone());
-            self.redo_stack.clear();
+        let next = matches
+            .iter()
+            .cycle()
+            .skip(first_after)
+            .take(matches.len())
+            .find(|m| !selected(m))
+            .cloned();
+        match next {
+            Some(m) => {
+                self.add_selection(Selection::new(m.start, m.end));
+                true
+            }
+            None => false,
         }
-        Ok(removed)
     }
 
-    pub fn delete_forward(&mut self, n: usize) -> Result<usize> {
-        let byte_pos = self.char_offset_to_byte(self.cursor);
-        let end_byte = (byte_pos..=self.buffer.len())
-            .filter(|&i| self.buffer.is_char_boundary(i))
-            .nth(n)
-            .unwrap_or(self.buffer.len());
-        let removed = self.buffer[byte_pos..end_byte].chars().count();
-        if removed > 0 {
-            self.buffer.drain(byte_pos..end_byte);
-            self.undo_stack.push(self.buffer.clone());
-            self.redo_stack.clear();
+    /// Split every selection that spans several lines into one selection per line
+    pub fn split_selection_into_lines(&mut self) {
+        let mut ranges = Vec::new();
+        let mut primary = 0;
+        for (i, selection) in self.selections.ranges().iter().enumerate() {
+            let first = self.buffer.line_of(selection.start());
+            let last = self.buffer.line_of(selection.end());
+            if first == last {
+                ranges.push(*selection);
+            } else {
+                for line in first..=last {
+                    let Some(span) = self.line_range(line) else {
+                        break;
+                    };
+                    let start = span.start.max(selection.start());
+                    let end = span.end.min(selection.end());
+                    // A selection ending at the start of a line does not select that line
+                    if line == last && start == end {
+                        break;
+                    }
+                    ranges.push(Selection::new(start, end));
+                }
+            }
+            if i == self.selections.primary_index() {
+                primary = ranges.len() - 1;
+            }
+        }
+        let set = SelectionSet::new(ranges, primary, self.len());
+        self.select(set);
+    }
+
+    /// Text of a grapheme range
+    pub fn text_range(&self, range: Range<usize>) -> String {
+        self.buffer.slice(range)
+    }
+
+    /// Grapheme cluster at an index
+    pub fn grapheme_at(&self, index: usize) -> Option<String> {
+        (index < self.len()).then(|| self.buffer.slice(index..index + 1))
+    }
+
+    /// Replace a grapheme range with text as one undoable edit; returns the grapheme range of
+    /// the inserted text. The cursor and selection keep their place in the surrounding text.
+    pub fn replace(&mut self, range: Range<usize>, text: &str) -> Result<Range<usize>> {
+        let len = self.len();
+        let start = range.start.min(len);
+        let end = range.end.clamp(start, len);
+        if start == end && text.is_empty() {
+            return Ok(start..start);
+        }
+
+        let before = self.selections.clone();
+        let edit = self.replace_range(start..end, text);
+        let inserted = self.buffer.byte_to_grapheme(edit.at)
+            ..self.buffer.byte_to_grapheme(edit.at + edit.inserted.len());
+        self.selections = self.selections.map(self.len(), |pos| {
+            if pos >= end {
+                pos - end + inserted.end
+            } else if pos > start {
+                inserted.end
+            } else {
+                pos
+            }
+        });
+        self.history
+            .record(vec![edit], before, self.selections.clone());
+        Ok(inserted)
+    }
+
+    /// Insert text at every cursor, replacing selected text
+    pub fn insert_at_cursor(&mut self, text: &str) -> Result<()> {
+        if text.is_empty() {
+            return Ok(());
         }
-        Ok(removed)
+        let ranges = self
+            .selections
+            .ranges()
+            .iter()
+            .map(Selection::range)
+            .collect();
+        self.edit_selections(ranges, text);
+        Ok(())
     }
 
+    /// Delete up to `n` grapheme clusters before every cursor, or the selected text of
+    /// non-empty selections; returns how many were removed at the primary selection
+    pub fn delete_backward(&mut self, n: usize) -> Result<usize> {
+        let ranges = self
+            .selections
+            .ranges()
+            .iter()
+            .map(|s| {
+                if s.is_empty() {
+                    s.head.saturating_sub(n)..s.head
+                } else {
+                    s.range()
+                }
+            })
+            .collect();
+        Ok(self.edit_selections(ranges, ""))
+    }
+
+    /// Delete up to `n` grapheme clusters after every cursor, or the selected text of
+    /// non-empty selections; returns how many were removed at the primary selection
+    pub fn delete_forward(&mut self, n: usize) -> Result<usize> {
+        let len = self.len();
+        let ranges = self
+            .selections
+            .ranges()
+            .iter()
+            .map(|s| {
+                if s.is_empty() {
+                    s.head..s.head.saturating_add(n).min(len)
+                } else {
+                    s.range()
+                }
+            })
+            .collect();
+        Ok(self.edit_selections(ranges, ""))
+    }
+
+    /// Undo the last step, restoring the selections from before it
     pub fn undo(&mut self) -> Result<bool> {
-        if self.undo_stack.len() <= 1 {
+        let Some((selections, edits)) = self.history.undo(&mut self.buffer) else {
             return Ok(false);
-        }
-        self.redo_stack.push(self.undo_stack.pop().unwrap());
-        self.buffer = self.undo_stack.last().cloned().unwrap_or_default();
-        let len = self.buffer.chars().count();
-        self.cursor = self.cursor.min(len);
+        };
+        edits.iter().for_each(|edit| self.push_change(edit));
+        self.selections = selections.clamp(self.len());
         Ok(true)
     }
 
+    /// Redo the last undone step, restoring the selections from after it
     pub fn redo(&mut self) -> Result<bool> {
-        let Some(prev) = self.redo_stack.pop() else {
+        let Some((selections, edits)) = self.history.redo(&mut self.buffer) else {
             return Ok(false);
         };
-        self.undo_stack.push(self.buffer.clone());
-        self.buffer = prev;
-        let len = self.buffer.chars().count();
-        self.cursor = self.cursor.min(len);
+        edits.iter().for_each(|edit| self.push_change(edit));
+        self.selections = selections.clamp(self.len());
         Ok(true)
     }
 
     pub fn can_undo(&self) -> bool {
-        self.undo_stack.len() > 1
+        self.history.can_undo()
     }
 
     pub fn can_redo(&self) -> bool {
-        !self.redo_stack.is_empty()
+        self.history.can_redo()
+    }
+
+    /// Start an undo group: all edits until the matching [`EditorCore::end_group`] are
+    /// undone and redone as one step. Groups may nest.
+    pub fn begin_group(&mut self) {
+        self.history.begin_group(self.selections.clone());
+    }
+
+    /// End an undo group; returns false if no group was open
+    pub fn end_group(&mut self) -> bool {
+        self.history.end_group()
+    }
+
+    /// Maximum pause between keystrokes that are still merged into one undo step
+    pub fn set_undo_coalesce_timeout(&mut self, timeout: Duration) {
+        self.history.set_coalesce_timeout(timeout);
     }
 
     pub fn cursor_line_index(&self) -> usize {
-        self.buffer
-            .chars()
-            .take(self.cursor)
-            .filter(|&c| c == '\n')
-            .count()
+        self.buffer.line_of(self.cursor_position())
     }
 
+    /// Line containing a grapheme index
+    pub fn line_index_of(&self, index: usize) -> usize {
+        self.buffer.line_of(index)
+    }
+
+    /// Grapheme range of a line, excluding its line break
     pub fn line_range(&self, line_index: usize) -> Option<Range<usize>> {
-        let mut start = 0usize;
-        let mut idx = 0usize;
-        let char_count = self.buffer.chars().count();
-        for (i, c) in self.buffer.chars().enumerate() {
-            if idx == line_index {
-                let end = self
-                    .buffer
-                    .chars()
-                    .skip(i)
-                    .position(|ch| ch == '\n')
-                    .map(|j| i + j)
-                    .unwrap_or(char_count);
-                return Some(start..end);
-            }
-            if c == '\n' {
-                start = i + 1;
-                idx += 1;
+        let start = self.buffer.line_start(line_index)?;
+        let end = match self.buffer.line_start(line_index + 1) {
+            Some(next) => next - 1,
+            None => self.len(),
+        };
+        Some(start..end)
+    }
+
+    pub fn line_content(&self, line_index: usize) -> Option<String> {
+        self.line_range(line_index).map(|r| self.buffer.slice(r))
+    }
+
+    /// Grapheme ranges of the regex matches in the buffer; matches that start or end inside
+    /// a grapheme cluster are skipped
+    pub fn find_regex(&self, regex: &Regex) -> Vec<Range<usize>> {
+        let content = self.buffer_content();
+        grapheme_matches(regex, &content)
+            .into_iter()
+            .map(|(_, range)| range)
+            .collect()
+    }
+
+    /// Grapheme ranges of the matches of `pattern` in the buffer
+    pub fn find_all(&self, pattern: &str, options: &FindOptions) -> Result<Vec<Range<usize>>> {
+        let regex = options.build_regex(pattern)?;
+        let content = self.buffer_content();
+        Ok(self
+            .matches(&regex, &content, options)
+            .into_iter()
+            .map(|(_, range)| range)
+            .collect())
+    }
+
+    /// Replace every match of `pattern` as one undo step; returns the number of replacements.
+    /// With [`FindOptions::regex`] the replacement expands capture groups (`$1`, `${name}`).
+    pub fn replace_all(
+        &mut self,
+        pattern: &str,
+        replacement: &str,
+        options: &FindOptions,
+    ) -> Result<usize> {
+        let regex = options.build_regex(pattern)?;
+        let content = self.buffer_content();
+        let matches = self.matches(&regex, &content, options);
+        self.begin_group();
+        let result = matches.iter().rev().try_for_each(|(caps, range)| {
+            let text = if options.regex {
+                let mut text = String::new();
+                caps.expand(replacement, &mut text);
+                text
+            } else {
+                replacement.to_string()
+            };
+            self.replace(range.clone(), &text).map(|_| ())
+        });
+        self.end_group();
+        result.map(|()| matches.len())
+    }
+
+    /// Statistics of the document, front matter excluded
+    pub fn statistics(&self) -> TextStats {
+        TextStats::of_note(&self.buffer_content())
+    }
+
+    /// Statistics of the selected text summed over all non-empty selections; `None` when
+    /// nothing is selected
+    pub fn selection_statistics(&self) -> Option<TextStats> {
+        self.selections
+            .ranges()
+            .iter()
+            .filter(|s| !s.is_empty())
+            .map(|s| TextStats::of(&self.text_range(s.range())))
+            .reduce(|a, b| a + b)
+    }
+
+    /// Take the changes made to the text (edits, undo and redo) since the last call, in
+    /// order, e.g. to feed an [`crate::IncrementalParser`]
+    pub fn take_changes(&mut self) -> Vec<TextChange> {
+        std::mem::take(&mut self.changes)
+    }
+
+    /// Convert a grapheme index to a UTF-16 code unit offset (for CodeMirror)
+    pub fn grapheme_to_utf16(&self, index: usize) -> usize {
+        self.buffer.grapheme_to_utf16(index)
+    }
+
+    /// Convert a UTF-16 code unit offset to a grapheme index.
+    /// Offsets inside a cluster resolve to the start of that cluster.
+    pub fn utf16_to_grapheme(&self, offset: usize) -> usize {
+        self.buffer.utf16_to_grapheme(offset)
+    }
+
+    /// Matches of `regex` in `content` (the buffer text) that pass the whole-word and
+    /// in-selection options
+    fn matches<'t>(
+        &self,
+        regex: &Regex,
+        content: &'t str,
+        options: &FindOptions,
+    ) -> Vec<(Captures<'t>, Range<usize>)> {
+        let selected: Vec<Range<usize>> = self
+            .selections
+            .ranges()
+            .iter()
+            .filter(|s| !s.is_empty())
+            .map(Selection::range)
+            .collect();
+        grapheme_matches(regex, content)
+            .into_iter()
+            .filter(|(caps, range)| {
+                let in_selection = !options.in_selection
+                    || selected
+                        .iter()
+                        .any(|s| s.start <= range.start && range.end <= s.end);
+                in_selection
+                    && caps
+                        .get(0)
+                        .is_some_and(|m| options.accepts(content, m.range()))
+            })
+            .collect()
+    }
+
+    fn select(&mut self, selections: SelectionSet) {
+        self.selections = selections;
+        self.history.seal();
+    }
+
+    /// Replace one range per selection (aligned with the selections) with `text` and leave a
+    /// cursor after each insertion, as one undo step. Returns the number of graphemes removed
+    /// at the primary selection.
+    fn edit_selections(&mut self, ranges: Vec<Range<usize>>, text: &str) -> usize {
+        // Ranges of neighbouring cursors may overlap (e.g. deleting back past each other)
+        let mut previous_end = 0;
+        let ranges: Vec<Range<usize>> = ranges
+            .into_iter()
+            .map(|range| {
+                let start = range.start.max(previous_end);
+                let end = range.end.max(start);
+                previous_end = end;
+                start..end
+            })
+            .collect();
+
+        // Edit from the last selection to the first so that earlier offsets stay valid
+        let before = self.selections.clone();
+        let mut edits = Vec::with_capacity(ranges.len());
+        let mut ends = vec![(0, 0isize); ranges.len()];
+        for (i, range) in ranges.iter().enumerate().rev() {
+            if range.is_empty() && text.is_empty() {
+                ends[i] = (self.buffer.grapheme_to_byte(range.start), 0);
+                continue;
             }
+            let edit = self.replace_range(range.clone(), text);
+            let delta = edit.inserted.len() as isize - edit.deleted.len() as isize;
+            ends[i] = (edit.at + edit.inserted.len(), delta);
+            edits.push(edit);
         }
-        if idx == line_index {
-            Some(start..char_count)
+
+        // Each cursor shifts by the edits before it. The inserted text may merge with its
+        // neighbours (e.g. a combining accent), so cursors go to the next cluster boundary.
+        let mut shift = 0isize;
+        let cursors = ends
+            .iter()
+            .map(|&(end, delta)| {
+                let cursor = self
+                    .buffer
+                    .byte_to_grapheme((end as isize + shift) as usize);
+                shift += delta;
+                Selection::cursor(cursor)
+            })
+            .collect();
+        let primary = self.selections.primary_index();
+        self.selections = SelectionSet::new(cursors, primary, self.len());
+        self.history.record(edits, before, self.selections.clone());
+        ranges[primary].len()
+    }
+
+    /// Grapheme range of the word at or just before `index`
+    fn word_at(&self, index: usize) -> Option<Range<usize>> {
+        let is_word = |i: usize| {
+            self.grapheme_at(i)
+                .and_then(|g| g.chars().next())
+                .is_some_and(|c| c.is_alphanumeric() || c == '_')
+        };
+        let mut start = if is_word(index) {
+            index
+        } else if index > 0 && is_word(index - 1) {
+            index - 1
         } else {
-            None
+            return None;
+        };
+        let mut end = start + 1;
+        while start > 0 && is_word(start - 1) {
+            start -= 1;
         }
+        while is_word(end) {
+            end += 1;
+        }
+        Some(start..end)
     }
 
-    pub fn line_content(&self, line_index: usize) -> Option<String> {
-        self.line_range(line_index).map(|r| {
-            self.buffer
-                .chars()
-                .skip(r.start)
-                .take(r.end - r.start)
-                .collect()
-        })
-    }
-
-    fn char_offset_to_byte(&self, char_offset: usize) -> usize {
-        self.buffer
-            .chars()
-            .take(char_offset)
-            .map(char::len_utf8)
-            .sum()
+    /// Replace a grapheme range; returns the edit for the undo history
+    fn replace_range(&mut self, range: Range<usize>, text: &str) -> Edit {
+        let start = self.buffer.grapheme_to_byte(range.start);
+        let end = self.buffer.grapheme_to_byte(range.end);
+        let deleted = self.buffer.slice(range);
+        self.buffer.replace(start..end, text);
+        let edit = Edit {
+            at: start,
+            deleted,
+            inserted: text.to_string(),
+        };
+        self.push_change(&edit);
+        edit
+    }
+
+    fn push_change(&mut self, edit: &Edit) {
+        if self.changes.len() == MAX_PENDING_CHANGES
+            && let Some(change) = TextChange::compose(&self.changes)
+        {
+            self.changes = vec![change];
+        }
+        self.changes.push(TextChange {
+            start: edit.at,
+            old_end: edit.at + edit.deleted.len(),
+            new_end: edit.at + edit.inserted.len(),
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_grapheme_deletion() {
+        // family emoji (ZWJ sequence), e + combining acute, and a flag
+        let mut editor = EditorCore::new("a👨\u{200d}👩\u{200d}👧e\u{301}🇫🇷");
+        assert_eq!(editor.len(), 4);
+        assert_eq!(editor.cursor_position(), 4);
+
+        assert_eq!(editor.delete_backward(1).unwrap(), 1);
+        assert_eq!(editor.buffer_content(), "a👨\u{200d}👩\u{200d}👧e\u{301}");
+        editor.set_cursor(1).unwrap();
+        assert_eq!(editor.delete_forward(1).unwrap(), 1);
+        assert_eq!(editor.buffer_content(), "ae\u{301}");
+
+        editor.set_cursor(1).unwrap();
+        editor.insert_at_cursor("x").unwrap();
+        assert_eq!(editor.cursor_position(), 2);
+        assert_eq!(editor.delete_backward(5).unwrap(), 2);
+        assert_eq!(editor.buffer_content(), "e\u{301}");
+
+        // Counts past the end of the text stop there
+        editor.set_cursor(0).unwrap();
+        assert_eq!(editor.delete_forward(usize::MAX).unwrap(), 1);
+        assert_eq!(editor.buffer_content(), "");
+        assert_eq!(editor.delete_backward(usize::MAX).unwrap(), 0);
+    }
+
+    #[test]
+    fn test_lines_and_utf16() {
+        let editor = EditorCore::new("one\r\n😀 two\nthree");
+        assert_eq!(editor.line_range(0), Some(0..3));
+        assert_eq!(editor.line_range(1), Some(4..9));
+        assert_eq!(editor.line_content(1).as_deref(), Some("😀 two"));
+        assert_eq!(editor.line_range(2), Some(10..15));
+        assert_eq!(editor.line_range(3), None);
+
+        // "one\r\n" is 5 UTF-16 units, the emoji is a surrogate pair
+        assert_eq!(editor.grapheme_to_utf16(5), 7);
+        assert_eq!(editor.utf16_to_grapheme(7), 5);
+        assert_eq!(editor.utf16_to_grapheme(6), 4);
+        assert_eq!(editor.utf16_to_grapheme(1000), editor.len());
+    }
+
+    #[test]
+    fn test_replace_keeps_cursor_in_place() {
+        let mut editor = EditorCore::new("hello world");
+        editor.set_cursor(8).unwrap();
+        assert_eq!(editor.replace(0..5, "hi").unwrap(), 0..2);
+        assert_eq!(editor.buffer_content(), "hi world");
+        assert_eq!(editor.cursor_position(), 5);
+        assert_eq!(editor.text_range(3..8), "world");
+        assert_eq!(editor.grapheme_at(0).as_deref(), Some("h"));
+        assert_eq!(editor.grapheme_at(8), None);
+
+        editor.undo().unwrap();
+        assert_eq!(editor.buffer_content(), "hello world");
+        assert_eq!(editor.cursor_position(), 8);
+    }
+
+    #[test]
+    fn test_undo_coalesces_words() {
+        let mut editor = EditorCore::new("");
+        for c in ["h", "i", " ", "y", "o", "u"] {
+            editor.insert_at_cursor(c).unwrap();
+        }
+        editor.delete_backward(1).unwrap();
+        editor.delete_backward(1).unwrap();
+        assert_eq!(editor.buffer_content(), "hi y");
+
+        assert!(editor.undo().unwrap());
+        assert_eq!(editor.buffer_content(), "hi you");
+        assert!(editor.undo().unwrap());
+        assert_eq!(editor.buffer_content(), "hi ");
+        assert_eq!(editor.cursor_position(), 3);
+        assert!(editor.undo().unwrap());
+        assert_eq!(editor.buffer_content(), "");
+        assert!(!editor.undo().unwrap());
+
+        assert!(editor.redo().unwrap());
+        assert_eq!(editor.buffer_content(), "hi ");
+        assert_eq!(editor.cursor_position(), 3);
+
+        // Without time coalescing every keystroke is its own step
+        editor.set_undo_coalesce_timeout(Duration::ZERO);
+        editor.insert_at_cursor("a").unwrap();
+        editor.insert_at_cursor("b").unwrap();
+        editor.undo().unwrap();
+        assert_eq!(editor.buffer_content(), "hi a");
+    }
+
+    #[test]
+    fn test_undo_group_restores_cursor_and_selection() {
+        let mut editor = EditorCore::new("e");
+        editor.set_selection(0, 1).unwrap();
+        editor.begin_group();
+        editor.set_cursor(1).unwrap();
+        editor.insert_at_cursor("\u{301}").unwrap();
+        editor.set_cursor(0).unwrap();
+        editor.insert_at_cursor("> ").unwrap();
+        assert!(editor.end_group());
+        assert!(!editor.end_group());
+        assert_eq!(editor.buffer_content(), "> e\u{301}");
+
+        assert!(editor.undo().unwrap());
+        assert_eq!(editor.buffer_content(), "e");
+        assert_eq!(editor.cursor_position(), 1);
+        assert_eq!(editor.selection(), Some((0, 1)));
+        assert!(!editor.can_undo());
+
+        assert!(editor.redo().unwrap());
+        assert_eq!(editor.buffer_content(), "> e\u{301}");
+        assert_eq!(editor.cursor_position(), 2);
+    }
+
+    #[test]
+    fn test_multi_cursor_edits() {
+        let mut editor = EditorCore::new("one\ntwo\nthree");
+        editor
+            .set_selections(
+                vec![
+                    Selection::cursor(0),
+                    Selection::cursor(4),
+                    Selection::cursor(8),
+                ],
+                1,
+            )
+            .unwrap();
+        for c in ["-", " "] {
+            editor.insert_at_cursor(c).unwrap();
+        }
+        assert_eq!(editor.buffer_content(), "- one\n- two\n- three");
+        assert_eq!(
+            editor.selections(),
+            &[
+                Selection::cursor(2),
+                Selection::cursor(8),
+                Selection::cursor(14)
+            ]
+        );
+        assert_eq!(editor.cursor_position(), 8);
+
+        assert_eq!(editor.delete_backward(1).unwrap(), 1);
+        assert_eq!(editor.buffer_content(), "-one\n-two\n-three");
+        // Cursors that meet are merged
+        editor.delete_backward(1).unwrap();
+        editor.delete_forward(10).unwrap();
+        assert_eq!(editor.buffer_content(), "");
+        assert_eq!(editor.selections(), &[Selection::cursor(0)]);
+
+        // The multi-cursor typing and the deletes are one undo step each
+        editor.undo().unwrap();
+        assert_eq!(editor.buffer_content(), "one\ntwo\nthree");
+        editor.undo().unwrap();
+        assert_eq!(editor.buffer_content(), "- one\n- two\n- three");
+        editor.undo().unwrap();
+        assert_eq!(editor.buffer_content(), "one\ntwo\nthree");
+        assert_eq!(editor.selections().len(), 3);
+        assert_eq!(editor.primary_selection_index(), 1);
+    }
+
+    #[test]
+    fn test_typing_replaces_selections() {
+        let mut editor = EditorCore::new("a😀b😀c");
+        editor
+            .set_selections(vec![Selection::new(1, 2), Selection::new(4, 3)], 0)
+            .unwrap();
+        editor.insert_at_cursor("e\u{301}").unwrap();
+        assert_eq!(editor.buffer_content(), "ae\u{301}be\u{301}c");
+        assert_eq!(
+            editor.selections(),
+            &[Selection::cursor(2), Selection::cursor(4)]
+        );
+        assert!(editor.set_selections(Vec::new(), 0).is_err());
+    }
+
+    #[test]
+    fn test_add_next_occurrence() {
+        let mut editor = EditorCore::new("foo bar foo_x foo");
+        editor.set_cursor(9).unwrap();
+        assert!(editor.add_next_occurrence());
+        assert_eq!(editor.selection(), Some((8, 13)));
+
+        editor.set_selection(0, 3).unwrap();
+        assert!(editor.add_next_occurrence());
+        assert!(editor.add_next_occurrence());
+        assert!(!editor.add_next_occurrence());
+        let ranges: Vec<_> = editor.selections().iter().map(Selection::range).collect();
+        assert_eq!(ranges, vec![0..3, 8..11, 14..17]);
+        assert_eq!(editor.primary_selection_index(), 2);
+
+        editor.insert_at_cursor("baz").unwrap();
+        assert_eq!(editor.buffer_content(), "baz bar baz_x baz");
+    }
+
+    #[test]
+    fn test_split_selection_into_lines() {
+        let mut editor = EditorCore::new("ab\ncd\n\nef\ngh");
+        editor.set_selection(1, 10).unwrap();
+        editor.split_selection_into_lines();
+        let ranges: Vec<_> = editor.selections().iter().map(Selection::range).collect();
+        assert_eq!(ranges, vec![1..2, 3..5, 6..6, 7..9]);
+        assert_eq!(editor.primary_selection_index(), 3);
+
+        editor.insert_at_cursor("*").unwrap();
+        assert_eq!(editor.buffer_content(), "a*\n*\n*\n*\ngh");
+    }
+
+    #[test]
+    fn test_find_all_options() {
+        let editor = EditorCore::new("Cat cat concat #cat\ncat.");
+        let find =
+            |pattern: &str, options: FindOptions| editor.find_all(pattern, &options).unwrap();
+        assert_eq!(
+            find("cat", FindOptions::default()),
+            vec![0..3, 4..7, 11..14, 16..19, 20..23]
+        );
+        let options = FindOptions {
+            case_sensitive: true,
+            whole_word: true,
+            ..Default::default()
+        };
+        assert_eq!(find("cat", options), vec![4..7, 16..19, 20..23]);
+        assert_eq!(find("#cat", options), vec![15..19]);
+        // Literal patterns are escaped; regex ones are not
+        assert_eq!(find("cat.", FindOptions::default()), vec![20..24]);
+        let regex = FindOptions {
+            regex: true,
+            ..Default::default()
+        };
+        assert_eq!(find("^cat", regex), vec![0..3, 20..23]);
+        assert!(editor.find_all("(", &regex).is_err());
+        assert!(editor.find_all("", &FindOptions::default()).is_err());
+
+        // Matches come back as grapheme ranges
+        let editor = EditorCore::new("👨‍👩‍👧 café");
+        assert_eq!(
+            editor.find_all("CAFÉ", &FindOptions::default()).unwrap(),
+            vec![2..6]
+        );
+    }
+
+    #[test]
+    fn test_replace_all() {
+        let mut editor = EditorCore::new("2024-01-31, 2023-12-01 and 2022-06-15");
+        editor.set_selection(12, 37).unwrap();
+        let options = FindOptions {
+            regex: true,
+            in_selection: true,
+            ..Default::default()
+        };
+        let count = editor
+            .replace_all(r"(?<y>\d{4})-(\d{2})-(\d{2})", "$3.$2.${y}", &options)
+            .unwrap();
+        assert_eq!(count, 2);
+        assert_eq!(
+            editor.buffer_content(),
+            "2024-01-31, 01.12.2023 and 15.06.2022"
+        );
+        assert_eq!(editor.selection(), Some((12, 37)));
+
+        // One undo step for all replacements
+        editor.undo().unwrap();
+        assert_eq!(
+            editor.buffer_content(),
+            "2024-01-31, 2023-12-01 and 2022-06-15"
+        );
+
+        // Literal replacements keep `$` as is
+        let mut editor = EditorCore::new("a b a");
+        let count = editor
+            .replace_all("a", "$1", &FindOptions::default())
+            .unwrap();
+        assert_eq!(count, 2);
+        assert_eq!(editor.buffer_content(), "$1 b $1");
+    }
+
+    #[test]
+    fn test_statistics() {
+        let mut editor = EditorCore::new("---\ntags: [a]\n---\nHello world 你好");
+        assert_eq!(editor.statistics().words, 4);
+        assert_eq!(editor.selection_statistics(), None);
+        editor.set_selection(18, 23).unwrap();
+        editor.add_selection(Selection::new(30, 32));
+        let selected = editor.selection_statistics().unwrap();
+        assert_eq!(
+            (selected.words, selected.cjk_chars, selected.chars),
+            (3, 2, 7)
+        );
     }
 }
@@ -0,0 +1,77 @@
+//! Footnote rendering: numbered references and definitions that link to each other.
+//!
+//! Footnote parsing is shared with the core, which stores the links between blocks; see
+//! [`synapse_core::footnote`].
+
+use std::collections::{HashMap, HashSet};
+
+use pulldown_cmark::{CowStr, Event, Tag, TagEnd};
+
+use crate::html::escape;
+
+/// Event adapter that numbers footnotes and links references and definitions both ways
+pub(crate) struct LinkFootnotes<'a, I> {
+    inner: I,
+    numbers: HashMap<CowStr<'a>, usize>,
+    /// Labels already referenced; only the first reference gets the id the backref targets
+    referenced: HashSet<CowStr<'a>>,
+    current_definition: Option<CowStr<'a>>,
+}
+
+impl<'a, I: Iterator<Item = Event<'a>>> LinkFootnotes<'a, I> {
+    pub(crate) fn new(inner: I) -> Self {
+        Self {
+            inner,
+            numbers: HashMap::new(),
+            referenced: HashSet::new(),
+            current_definition: None,
+        }
+    }
+
+    fn number(&mut self, label: &CowStr<'a>) -> usize {
+        let next = self.numbers.len() + 1;
+        *self.numbers.entry(label.clone()).or_insert(next)
+    }
+}
+
+impl<'a, I: Iterator<Item = Event<'a>>> Iterator for LinkFootnotes<'a, I> {
+    type Item = Event<'a>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let event = self.inner.next()?;
+        let html = match event {
+            Event::FootnoteReference(label) => {
+                let number = self.number(&label);
+                let id = escape(&label);
+                let anchor = if self.referenced.insert(label) {
+                    format!(" id=\"fnref-{}\"", id)
+                } else {
+                    String::new()
+                };
+                format!(
+                    "<sup class=\"footnote-reference\"{}><a href=\"#fn-{}\">{}</a></sup>",
+                    anchor, id, number
+                )
+            }
+            Event::Start(Tag::FootnoteDefinition(label)) => {
+                let number = self.number(&label);
+                let html = format!(
+                    "<div class=\"footnote-definition\" id=\"fn-{}\"><sup class=\"footnote-definition-label\">{}</sup>\n",
+                    escape(&label),
+                    number
+                );
+                self.current_definition = Some(label);
+                html
+            }
+            Event::End(TagEnd::FootnoteDefinition) => match self.current_definition.take() {
+                Some(label) => format!(
+                    "<a href=\"#fnref-{}\" class=\"footnote-backref\">↩</a></div>\n",
+                    escape(&label)
+                ),
+                None => "</div>\n".to_string(),
+            },
+            event => return Some(event),
+        };
+        Some(Event::Html(html.into()))
+    }
+}
@@ -0,0 +1,167 @@
+//! Syntax highlighting for fenced code blocks.
+//!
+//! Highlighting is class-based: code is wrapped in `<span class="hl-…">` elements named
+//! after syntax scopes, and colors come from a stylesheet generated by
+//! [`highlight_theme_css`]. Switching themes only means swapping that stylesheet.
+
+use std::sync::OnceLock;
+
+use pulldown_cmark::{CodeBlockKind, CowStr, Event, Tag, TagEnd};
+use syntect::highlighting::ThemeSet;
+use syntect::html::{ClassStyle, ClassedHTMLGenerator, css_for_theme_with_class_style};
+use syntect::parsing::{SyntaxReference, SyntaxSet};
+use syntect::util::LinesWithEndings;
+
+/// Class style shared by the generated spans and the generated theme CSS.
+const CLASS_STYLE: ClassStyle = ClassStyle::SpacedPrefixed { prefix: "hl-" };
+
+fn syntax_set() -> &'static SyntaxSet {
+    static SYNTAX_SET: OnceLock<SyntaxSet> = OnceLock::new();
+    SYNTAX_SET.get_or_init(SyntaxSet::load_defaults_newlines)
+}
+
+fn theme_set() -> &'static ThemeSet {
+    static THEME_SET: OnceLock<ThemeSet> = OnceLock::new();
+    THEME_SET.get_or_init(ThemeSet::load_defaults)
+}
+
+/// Language token of a fence info string (`rust,ignore` -> `rust`)
+fn fence_language(info: &str) -> &str {
+    info.split(|c: char| c.is_whitespace() || c == ',' || c == '{')
+        .next()
+        .unwrap_or("")
+}
+
+/// Resolve a fence info string (e.g. `rust`, `rust,ignore`, `shell`) to a syntax
+fn find_syntax(lang: &str) -> Option<&'static SyntaxReference> {
+    let token = fence_language(lang);
+    if token.is_empty() {
+        return None;
+    }
+    let token = match token.to_ascii_lowercase().as_str() {
+        "shell" | "console" | "zsh" | "sh" => "bash".to_string(),
+        "psql" | "sqlite" | "postgres" | "postgresql" => "sql".to_string(),
+        other => other.to_string(),
+    };
+    syntax_set().find_syntax_by_token(&token)
+}
+
+/// Highlight code as class-based HTML spans (without the surrounding `<pre><code>`).
+///
+/// Returns `None` if the language is unknown, so callers can fall back to plain output.
+pub fn highlight_code(code: &str, lang: &str) -> Option<String> {
+    let syntax = find_syntax(lang)?;
+    let mut generator =
+        ClassedHTMLGenerator::new_with_class_style(syntax, syntax_set(), CLASS_STYLE);
+    for line in LinesWithEndings::from(code) {
+        generator
+            .parse_html_for_line_which_includes_newline(line)
+            .ok()?;
+    }
+    Some(generator.finalize())
+}
+
+/// Names of the built-in highlight themes accepted by [`highlight_theme_css`]
+pub fn highlight_themes() -> Vec<String> {
+    theme_set().themes.keys().cloned().collect()
+}
+
+/// Generate the stylesheet for a built-in highlight theme (e.g. `InspiredGitHub`,
+/// `base16-ocean.dark`). Returns `None` for unknown theme names.
+pub fn highlight_theme_css(theme_name: &str) -> Option<String> {
+    let theme = theme_set().themes.get(theme_name)?;
+    css_for_theme_with_class_style(theme, CLASS_STYLE).ok()
+}
+
+/// Event adapter that replaces fenced code blocks with highlighted HTML.
+///
+/// Code blocks without a known language are passed through unchanged.
+pub(crate) struct HighlightCodeBlocks<'a, I> {
+    inner: I,
+    pending: std::vec::IntoIter<Event<'a>>,
+}
+
+impl<'a, I: Iterator<Item = Event<'a>>> HighlightCodeBlocks<'a, I> {
+    pub(crate) fn new(inner: I) -> Self {
+        Self {
+            inner,
+            pending: Vec::new().into_iter(),
+        }
+    }
+
+    fn highlight_block(&mut self, lang: CowStr<'a>) -> Vec<Event<'a>> {
+        let mut buffered = Vec::new();
+        let mut code = String::new();
+        for event in self.inner.by_ref() {
+            match event {
+                Event::End(TagEnd::CodeBlock) => break,
+                Event::Text(ref text) => {
+                    code.push_str(text);
+                    buffered.push(event);
+                }
+                other => buffered.push(other),
+            }
+        }
+
+        match highlight_code(&code, &lang) {
+            Some(spans) => {
+                // Keep the class attribute safe without pulling in an HTML escaper
+                let class: String = fence_language(&lang)
+                    .chars()
+                    .filter(|c| {
+                        c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '+' | '#' | '.')
+                    })
+                    .collect();
+                let html = format!(
+                    "<pre class=\"hl-code\"><code class=\"language-{}\">{}</code></pre>\n",
+                    class, spans
+                );
+                vec![Event::Html(html.into())]
+            }
+            None => {
+                let mut events = vec![Event::Start(Tag::CodeBlock(CodeBlockKind::Fenced(lang)))];
+                events.extend(buffered);
+                events.push(Event::End(TagEnd::CodeBlock));
+                events
+            }
+        }
+    }
+}
+
+impl<'a, I: Iterator<Item = Event<'a>>> Iterator for HighlightCodeBlocks<'a, I> {
+    type Item = Event<'a>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if let Some(event) = self.pending.next() {
+            return Some(event);
+        }
+        match self.inner.next()? {
+            Event::Start(Tag::CodeBlock(CodeBlockKind::Fenced(lang))) if !lang.is_empty() => {
+                self.pending = self.highlight_block(lang).into_iter();
+                self.pending.next()
+            }
+            event => Some(event),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_highlight_known_languages() {
+        let rust = highlight_code("fn main() {}\n", "rust").unwrap();
+        assert!(rust.contains("<span class=\"hl-"));
+        assert!(highlight_code("SELECT 1;\n", "sql").is_some());
+        assert!(highlight_code("echo hi\n", "shell").is_some());
+        assert!(highlight_code("x\n", "no-such-language").is_none());
+    }
+
+    #[test]
+    fn test_theme_css_uses_class_prefix() {
+        let css = highlight_theme_css("InspiredGitHub").unwrap();
+        assert!(css.contains(".hl-"));
+        assert!(highlight_theme_css("missing-theme").is_none());
+    }
+}
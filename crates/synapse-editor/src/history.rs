@@ -0,0 +1,241 @@
+//! Undo/redo history made of invertible edit operations.
+//!
+//! Each undo step holds the edits it made plus the selections before and after. Consecutive
+//! typing or deleting is coalesced into one step while it stays contiguous and within
+//! [`DEFAULT_COALESCE_TIMEOUT`] of the previous edit; typing also starts a new step at each new
+//! word. A multi-cursor edit (one edit per cursor) is recorded as a single step and coalesces
+//! the same way when every cursor continues its own run. `begin_group`/`end_group` make
+//! everything in between a single step.
+
+use std::time::{Duration, Instant};
+
+use crate::buffer::TextBuffer;
+use crate::selection::SelectionSet;
+
+/// Edits further apart than this start a new undo step
+pub const DEFAULT_COALESCE_TIMEOUT: Duration = Duration::from_millis(1000);
+
+/// A replacement of `deleted` by `inserted` at byte offset `at`.
+///
+/// Offsets are in bytes rather than graphemes so that inverting an edit is exact even when
+/// the inserted text merged with a neighbouring cluster. Within a step, each edit's offset is
+/// relative to the buffer after the edits before it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct Edit {
+    pub(crate) at: usize,
+    pub(crate) deleted: String,
+    pub(crate) inserted: String,
+}
+
+impl Edit {
+    fn apply(&self, buffer: &mut TextBuffer) {
+        buffer.replace(self.at..self.at + self.deleted.len(), &self.inserted);
+    }
+
+    fn revert(&self, buffer: &mut TextBuffer) {
+        buffer.replace(self.at..self.at + self.inserted.len(), &self.deleted);
+    }
+
+    fn inverse(&self) -> Edit {
+        Edit {
+            at: self.at,
+            deleted: self.inserted.clone(),
+            inserted: self.deleted.clone(),
+        }
+    }
+
+    fn is_insert(&self) -> bool {
+        self.deleted.is_empty() && !self.inserted.is_empty()
+    }
+
+    fn is_delete(&self) -> bool {
+        self.inserted.is_empty() && !self.deleted.is_empty()
+    }
+
+    /// Change in buffer length, in bytes
+    fn delta(&self) -> isize {
+        self.inserted.len() as isize - self.deleted.len() as isize
+    }
+
+    /// Whether `next` continues this edit as part of the same typing/deleting run
+    fn continues_with(&self, next: &Edit) -> bool {
+        if self.is_insert() && next.is_insert() {
+            let contiguous = next.at == self.at + self.inserted.len();
+            let starts_word = self.inserted.ends_with(char::is_whitespace)
+                && !next.inserted.starts_with(char::is_whitespace);
+            return contiguous && !starts_word && !next.inserted.contains('\n');
+        }
+        if self.is_delete() && next.is_delete() {
+            // Backspace runs move left, forward-delete runs stay in place
+            let backward = next.at + next.deleted.len() == self.at;
+            let forward = next.at == self.at;
+            return (backward || forward) && !next.deleted.contains('\n');
+        }
+        false
+    }
+}
+
+/// Whether the edits of a multi-cursor edit each continue the matching edit of the previous
+/// one. Multi-cursor edits are applied from the last cursor to the first, so an edit of
+/// `previous` has since moved by the edits listed after it (at lower offsets).
+fn continues_all(previous: &[Edit], next: &[Edit]) -> bool {
+    if previous.len() != next.len() {
+        return false;
+    }
+    let mut shift = 0isize;
+    previous.iter().zip(next).rev().all(|(previous, next)| {
+        let moved = Edit {
+            at: (previous.at as isize + shift) as usize,
+            ..previous.clone()
+        };
+        shift += previous.delta();
+        moved.continues_with(next)
+    })
+}
+
+#[derive(Debug, Clone)]
+struct Step {
+    edits: Vec<Edit>,
+    /// Edits per recorded change (the number of cursors), used for coalescing
+    width: usize,
+    before: SelectionSet,
+    after: SelectionSet,
+}
+
+#[derive(Debug)]
+pub(crate) struct History {
+    undo: Vec<Step>,
+    redo: Vec<Step>,
+    group_depth: usize,
+    /// Selections when the open group began, restored by undoing the group
+    group_before: Option<SelectionSet>,
+    /// The last step accepts no more edits; the next edit starts a new step
+    sealed: bool,
+    last_edit_at: Option<Instant>,
+    coalesce_timeout: Duration,
+}
+
+impl Default for History {
+    fn default() -> Self {
+        Self {
+            undo: Vec::new(),
+            redo: Vec::new(),
+            group_depth: 0,
+            group_before: None,
+            sealed: true,
+            last_edit_at: None,
+            coalesce_timeout: DEFAULT_COALESCE_TIMEOUT,
+        }
+    }
+}
+
+impl History {
+    pub(crate) fn set_coalesce_timeout(&mut self, timeout: Duration) {
+        self.coalesce_timeout = timeout;
+    }
+
+    /// Record edits that have already been applied to the buffer, one per cursor
+    pub(crate) fn record(&mut self, edits: Vec<Edit>, before: SelectionSet, after: SelectionSet) {
+        if edits.is_empty() {
+            return;
+        }
+        let now = Instant::now();
+        let in_time = self
+            .last_edit_at
+            .is_some_and(|at| now.duration_since(at) <= self.coalesce_timeout);
+        self.last_edit_at = Some(now);
+        self.redo.clear();
+
+        let extend = match self.undo.last() {
+            Some(_) if self.sealed => false,
+            Some(_) if self.group_depth > 0 => true,
+            Some(step) => {
+                in_time
+                    && step.width == edits.len()
+                    && continues_all(&step.edits[step.edits.len() - step.width..], &edits)
+            }
+            None => false,
+        };
+        if extend && let Some(step) = self.undo.last_mut() {
+            if step.width != edits.len() {
+                step.width = 0;
+            }
+            step.edits.extend(edits);
+            step.after = after;
+        } else {
+            self.undo.push(Step {
+                width: edits.len(),
+                edits,
+                before: self.group_before.take().unwrap_or(before),
+                after,
+            });
+        }
+        self.sealed = false;
+    }
+
+    /// Start a transaction; nested groups join the outermost one
+    pub(crate) fn begin_group(&mut self, selections: SelectionSet) {
+        if self.group_depth == 0 {
+            self.sealed = true;
+            self.group_before = Some(selections);
+        }
+        self.group_depth += 1;
+    }
+
+    /// End a transaction; returns false if no group was open
+    pub(crate) fn end_group(&mut self) -> bool {
+        if self.group_depth == 0 {
+            return false;
+        }
+        self.group_depth -= 1;
+        if self.group_depth == 0 {
+            self.sealed = true;
+            self.group_before = None;
+        }
+        true
+    }
+
+    /// Stop coalescing into the current step (e.g. after the cursor was moved)
+    pub(crate) fn seal(&mut self) {
+        if self.group_depth == 0 {
+            self.sealed = true;
+        }
+    }
+
+    /// Revert the last step; returns the selections to restore and the edits applied to the
+    /// buffer, in order
+    pub(crate) fn undo(&mut self, buffer: &mut TextBuffer) -> Option<(SelectionSet, Vec<Edit>)> {
+        let step = self.undo.pop()?;
+        let mut applied = Vec::with_capacity(step.edits.len());
+        for edit in step.edits.iter().rev() {
+            edit.revert(buffer);
+            applied.push(edit.inverse());
+        }
+        let state = step.before.clone();
+        self.redo.push(step);
+        self.sealed = true;
+        Some((state, applied))
+    }
+
+    /// Re-apply the last undone step; returns the selections to restore and the edits
+    /// applied to the buffer, in order
+    pub(crate) fn redo(&mut self, buffer: &mut TextBuffer) -> Option<(SelectionSet, Vec<Edit>)> {
+        let step = self.redo.pop()?;
+        for edit in &step.edits {
+            edit.apply(buffer);
+        }
+        let state = step.after.clone();
+        let applied = step.edits.clone();
+        self.undo.push(step);
+        self.sealed = true;
+        Some((state, applied))
+    }
+
+    pub(crate) fn can_undo(&self) -> bool {
+        !self.undo.is_empty()
+    }
+
+    pub(crate) fn can_redo(&self) -> bool {
+        !self.redo.is_empty()
+    }
+}
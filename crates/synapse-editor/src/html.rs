@@ -0,0 +1,9 @@
+//! HTML helpers shared by the renderers.
+
+/// Escape text for use in HTML content and double-quoted attributes
+pub(crate) fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
@@ -0,0 +1,634 @@
+//! Incremental reparsing: keep a note's blocks in sync with editor changes by reparsing only
+//! the top-level Markdown blocks around each change.
+//!
+//! The note is split into segments, one per top-level block (a paragraph, a whole list, a
+//! quote, a code fence …), each holding the blocks parsed from it. A change reparses the
+//! segments it touches plus one on each side, and keeps extending the window while the text
+//! after it parses differently (an opened code fence swallows the rest of the note). Link
+//! reference and footnote definitions apply to the whole note, so a note that has any is
+//! kept as one segment; changes near the head, where front matter can open or close, also
+//! reparse the whole note. Blocks that survive keep their IDs, so the resulting
+//! [`BlockDiff`] can be persisted with [`synapse_core::BlockService::apply_diff`].
+
+use std::ops::Range;
+
+use chrono::Utc;
+use pulldown_cmark::{Event, Parser, Tag};
+use synapse_core::{Block, BlockDiff, Error, Result};
+
+use crate::parser::{front_matter_len, parse_blocks, parser_options};
+
+/// A change to the editor text in byte offsets: `start..old_end` of the previous text became
+/// `start..new_end`. In a sequence, each change is relative to the text after the previous one.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TextChange {
+    pub start: usize,
+    pub old_end: usize,
+    pub new_end: usize,
+}
+
+impl TextChange {
+    /// The single change covering `self` followed by `next`
+    pub fn then(self, next: TextChange) -> TextChange {
+        // Map an offset of the intermediate text back to the original, or on to the final text
+        let to_old = |x: usize| {
+            if x <= self.start {
+                x
+            } else if x >= self.new_end {
+                x - self.new_end + self.old_end
+            } else {
+                self.old_end
+            }
+        };
+        let to_new = |x: usize| {
+            if x <= next.start {
+                x
+            } else if x >= next.old_end {
+                x - next.old_end + next.new_end
+            } else {
+                next.new_end
+            }
+        };
+        TextChange {
+            start: self.start.min(next.start),
+            old_end: self.old_end.max(to_old(next.old_end)),
+            new_end: to_new(self.new_end).max(next.new_end),
+        }
+    }
+
+    /// The single change covering a sequence of changes
+    pub fn compose(changes: &[TextChange]) -> Option<TextChange> {
+        changes.iter().copied().reduce(TextChange::then)
+    }
+}
+
+/// A top-level block of the source and the blocks parsed from it
+#[derive(Debug, Clone)]
+struct Segment {
+    range: Range<usize>,
+    blocks: Vec<Block>,
+}
+
+/// Blocks of a note, updated incrementally from [`TextChange`]s
+#[derive(Debug, Clone)]
+pub struct IncrementalParser {
+    note_id: String,
+    segments: Vec<Segment>,
+    /// The note defines link references or footnotes, which any segment may use
+    definitions: bool,
+}
+
+impl IncrementalParser {
+    /// Parse a note from scratch; all blocks get new IDs
+    pub fn new(content: &str, note_id: &str) -> Result<Self> {
+        let (mut segments, definitions) = parse_segments(content, 0..content.len(), note_id)?;
+        for (position, block) in segments
+            .iter_mut()
+            .flat_map(|segment| &mut segment.blocks)
+            .enumerate()
+        {
+            block.position = position as i64;
+        }
+        Ok(Self {
+            note_id: note_id.to_string(),
+            segments,
+            definitions,
+        })
+    }
+
+    /// Parse a note whose blocks were stored before: blocks that still match take over the
+    /// stored IDs. Returns the parser and the diff that brings `stored` up to date.
+    pub fn with_blocks(
+        content: &str,
+        note_id: &str,
+        mut stored: Vec<Block>,
+    ) -> Result<(Self, BlockDiff)> {
+        let mut parser = Self::new(content, note_id)?;
+        stored.sort_by_key(|block| block.position);
+        let mut parsed: Vec<Block> = parser.blocks().cloned().collect();
+        let mut diff = BlockDiff::default();
+        reconcile(&stored, &mut parsed, 0, &mut diff);
+
+        let mut parsed = parsed.into_iter();
+        for segment in &mut parser.segments {
+            for block in &mut segment.blocks {
+                *block = parsed.next().expect("one parsed block per segment block");
+            }
+        }
+        Ok((parser, diff))
+    }
+
+    /// Current blocks in order
+    pub fn blocks(&self) -> impl Iterator<Item = &Block> {
+        self.segments.iter().flat_map(|segment| &segment.blocks)
+    }
+
+    /// Update the blocks after `changes` turned the previous text into `content`; returns
+    /// what changed
+    pub fn apply(&mut self, content: &str, changes: &[TextChange]) -> Result<BlockDiff> {
+        let Some(change) = TextChange::compose(changes) else {
+            return Ok(BlockDiff::default());
+        };
+        let old_len = self.segments.last().map_or(0, |segment| segment.range.end);
+        if change.old_end > old_len || old_len - change.old_end + change.new_end != content.len() {
+            return Err(Error::InvalidInput(
+                "Changes do not match the content".to_string(),
+            ));
+        }
+        let shift = |pos: usize| pos - change.old_end + change.new_end;
+
+        // Segment holding an offset of the old text
+        let n = self.segments.len();
+        let index_of = |pos: usize| {
+            self.segments
+                .partition_point(|segment| segment.range.end <= pos)
+                .min(n - 1)
+        };
+        let mut first = index_of(change.start).saturating_sub(1);
+        let mut last = (index_of(change.old_end) + 1).min(n - 1);
+        // A note opening with `---` that is not front matter yet becomes front matter once a
+        // closing `---` appears anywhere below
+        let open_head = content.starts_with("---")
+            && self.segments[0]
+                .blocks
+                .first()
+                .is_none_or(|block| block.block_type != "front_matter");
+        let mut whole = first == 0 || open_head || self.definitions;
+        let (parsed, definitions) = loop {
+            if whole {
+                (first, last) = (0, n - 1);
+            }
+            let window = self.segments[first].range.start..shift(self.segments[last].range.end);
+            if !content.is_char_boundary(window.start) || !content.is_char_boundary(window.end) {
+                return Err(Error::InvalidInput(
+                    "Change is not on a character boundary".to_string(),
+                ));
+            }
+            let (parsed, definitions) = parse_segments(content, window, &self.note_id)?;
+            if definitions && !whole {
+                whole = true;
+                continue;
+            }
+            // In sync once the last (unchanged) segment parses exactly as before
+            let old = &self.segments[last];
+            let synced = last == n - 1
+                || parsed.last().is_some_and(|segment| {
+                    let moved = shift(old.range.start)..shift(old.range.end);
+                    segment.range == moved && same_blocks(&segment.blocks, &old.blocks)
+                });
+            if synced {
+                break (parsed, definitions);
+            }
+            last = (2 * last - first + 1).min(n - 1);
+        };
+
+        let mut diff = BlockDiff::default();
+        let position = self.segments[..first]
+            .iter()
+            .map(|segment| segment.blocks.len())
+            .sum::<usize>() as i64;
+        let old: Vec<Block> = self.segments[first..=last]
+            .iter()
+            .flat_map(|segment| segment.blocks.iter().cloned())
+            .collect();
+        let mut new: Vec<Block> = parsed
+            .iter()
+            .flat_map(|segment| segment.blocks.iter().cloned())
+            .collect();
+        reconcile(&old, &mut new, position, &mut diff);
+
+        let mut new = new.into_iter();
+        let parsed: Vec<Segment> = parsed
+            .into_iter()
+            .map(|segment| Segment {
+                blocks: segment
+                    .blocks
+                    .iter()
+                    .map(|_| new.next().expect("one block each"))
+                    .collect(),
+                range: segment.range,
+            })
+            .collect();
+        let inserted = parsed.len();
+        self.segments.splice(first..=last, parsed);
+        self.definitions = definitions;
+
+        // Later segments move in the text, and in position if the block count changed
+        let mut position = self.segments[..first + inserted]
+            .iter()
+            .map(|segment| segment.blocks.len())
+            .sum::<usize>() as i64;
+        for segment in &mut self.segments[first + inserted..] {
+            segment.range = shift(segment.range.start)..shift(segment.range.end);
+            for block in &mut segment.blocks {
+                if block.position != position {
+                    block.position = position;
+                    block.updated_at = Utc::now().timestamp();
+                    diff.updated.push(block.clone());
+                }
+                position += 1;
+            }
+        }
+        Ok(diff)
+    }
+}
+
+fn same_blocks(a: &[Block], b: &[Block]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).all(|(a, b)| same_block(a, b))
+}
+
+fn same_block(a: &Block, b: &Block) -> bool {
+    a.block_type == b.block_type && a.content == b.content
+}
+
+/// Match freshly parsed blocks against the old blocks they replace, numbering them from
+/// `position`. Unchanged blocks at either end keep their old block; the blocks in between
+/// are paired in order (updated, keeping the old ID), and the rest are inserted or removed.
+fn reconcile(old: &[Block], new: &mut [Block], position: i64, diff: &mut BlockDiff) {
+    let prefix = old
+        .iter()
+        .zip(new.iter())
+        .take_while(|(old, new)| same_block(old, new))
+        .count();
+    let suffix = old[prefix..]
+        .iter()
+        .rev()
+        .zip(new[prefix..].iter().rev())
+        .take_while(|(old, new)| same_block(old, new))
+        .count();
+    let (old_changed, new_changed) = (old.len() - suffix, new.len() - suffix);
+
+    let now = Utc::now().timestamp();
+    for (i, block) in new.iter_mut().enumerate() {
+        let expected = position + i as i64;
+        let kept = if i < prefix {
+            Some(&old[i])
+        } else if i >= new_changed {
+            Some(&old[i - new_changed + old_changed])
+        } else {
+            None
+        };
+        if let Some(kept) = kept {
+            *block = kept.clone();
+            if block.position != expected {
+                block.position = expected;
+                block.updated_at = now;
+                diff.updated.push(block.clone());
+            }
+        } else if let Some(previous) = old[prefix..old_changed].get(i - prefix) {
+            if same_block(previous, block) && previous.position == expected {
+                *block = previous.clone();
+                continue;
+            }
+            block.id = previous.id.clone();
+            block.created_at = previous.created_at;
+            block.position = expected;
+            diff.updated.push(block.clone());
+        } else {
+            block.position = expected;
+            diff.inserted.push(block.clone());
+        }
+    }
+    let paired = new_changed - prefix;
+    diff.removed.extend(
+        old[prefix..old_changed]
+            .iter()
+            .skip(paired)
+            .map(|block| block.id.clone()),
+    );
+}
+
+/// Split `window` of `content` (starting at a top-level block boundary) into top-level
+/// segments and parse each; also tells whether the window defines link references or
+/// footnotes
+fn parse_segments(
+    content: &str,
+    window: Range<usize>,
+    note_id: &str,
+) -> Result<(Vec<Segment>, bool)> {
+    let text = &content[window.clone()];
+    let front_matter = window.start == 0;
+    // Front matter is a segment of its own
+    let head = if front_matter {
+        front_matter_len(text)
+    } else {
+        0
+    };
+    let mut starts = Vec::new();
+    if head > 0 {
+        starts.push(0);
+    }
+    let mut depth = 0usize;
+    let events = Parser::new_ext(&text[head..], parser_options()).into_offset_iter();
+    let mut definitions = events.reference_definitions().iter().next().is_some();
+    for (event, range) in events {
+        let top_level = depth == 0;
+        match event {
+            Event::Start(Tag::FootnoteDefinition(_)) => {
+                definitions = true;
+                depth += 1;
+            }
+            Event::Start(_) => depth += 1,
+            Event::End(_) => {
+                depth -= 1;
+                continue;
+            }
+            _ => {}
+        }
+        if top_level {
+            // Blocks start at the beginning of a line, including their indentation
+            let start = text[..head + range.start].rfind('\n').map_or(0, |i| i + 1);
+            if starts.last() != Some(&start) {
+                starts.push(start);
+            }
+        }
+    }
+    match starts.first_mut() {
+        Some(first) => *first = 0,
+        None => starts.push(0),
+    }
+    // References and footnotes resolve against definitions anywhere in the text, so text
+    // with definitions stays in one segment
+    if definitions {
+        starts.truncate(1);
+    }
+
+    let ends = starts.iter().skip(1).copied().chain([text.len()]);
+    let segments = starts
+        .iter()
+        .zip(ends)
+        .map(|(&start, end)| {
+            let blocks = parse_blocks(&text[start..end], note_id, front_matter && start == 0)?;
+            Ok(Segment {
+                range: window.start + start..window.start + end,
+                blocks,
+            })
+        })
+        .collect::<Result<_>>()?;
+    Ok((segments, definitions))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::EditorCore;
+
+    fn contents(parser: &IncrementalParser) -> Vec<(String, i64)> {
+        parser
+            .blocks()
+            .map(|block| (block.content.clone(), block.position))
+            .collect()
+    }
+
+    fn ids(parser: &IncrementalParser) -> Vec<String> {
+        parser.blocks().map(|block| block.id.clone()).collect()
+    }
+
+    /// Apply an edit through the editor and check the result against a full parse
+    fn edit(
+        editor: &mut EditorCore,
+        parser: &mut IncrementalParser,
+        f: impl FnOnce(&mut EditorCore),
+    ) -> BlockDiff {
+        f(editor);
+        let content = editor.buffer_content();
+        let diff = parser.apply(&content, &editor.take_changes()).unwrap();
+        let full = parse_markdown(&content);
+        assert_eq!(
+            parser
+                .blocks()
+                .map(|b| (&b.block_type, &b.content))
+                .collect::<Vec<_>>(),
+            full.iter()
+                .map(|b| (&b.block_type, &b.content))
+                .collect::<Vec<_>>(),
+        );
+        diff
+    }
+
+    fn parse_markdown(content: &str) -> Vec<Block> {
+        crate::parse_markdown_to_blocks(content, "note-1").unwrap()
+    }
+
+    #[test]
+    fn test_compose_changes() {
+        // Insert 3 bytes at 10, then delete byte 2
+        let insert = TextChange {
+            start: 10,
+            old_end: 10,
+            new_end: 13,
+        };
+        let delete = TextChange {
+            start: 2,
+            old_end: 3,
+            new_end: 2,
+        };
+        assert_eq!(
+            insert.then(delete),
+            TextChange {
+                start: 2,
+                old_end: 10,
+                new_end: 12,
+            }
+        );
+        assert_eq!(TextChange::compose(&[]), None);
+    }
+
+    #[test]
+    fn test_segments_match_full_parse() {
+        let content =
+            "---\ntitle: x\n---\n# Title\n\n    code\n\nPara\n\n- a\n- b\n\n---\n\n> quote";
+        let parser = IncrementalParser::new(content, "note-1").unwrap();
+        let full = parse_markdown(content);
+        assert_eq!(
+            parser.blocks().map(|b| &b.content).collect::<Vec<_>>(),
+            full.iter().map(|b| &b.content).collect::<Vec<_>>()
+        );
+        assert_eq!(parser.segments.len(), 7);
+    }
+
+    #[test]
+    fn test_typing_updates_one_block() {
+        let mut editor = EditorCore::new("# Title\n\nFirst\n\nSecond\n");
+        let mut parser = IncrementalParser::new(&editor.buffer_content(), "note-1").unwrap();
+        let before = ids(&parser);
+
+        let diff = edit(&mut editor, &mut parser, |e| {
+            e.set_cursor(14).unwrap();
+            e.insert_at_cursor(" line").unwrap();
+        });
+        assert!(diff.inserted.is_empty() && diff.removed.is_empty());
+        assert_eq!(diff.updated.len(), 1);
+        assert_eq!(diff.updated[0].id, before[1]);
+        assert_eq!(diff.updated[0].content, "First line");
+        assert_eq!(ids(&parser), before);
+    }
+
+    #[test]
+    fn test_split_and_merge_blocks() {
+        let mut editor = EditorCore::new("Intro\n\nOne two\n\nOutro");
+        let mut parser = IncrementalParser::new(&editor.buffer_content(), "note-1").unwrap();
+        let before = ids(&parser);
+
+        let diff = edit(&mut editor, &mut parser, |e| {
+            e.set_cursor(10).unwrap();
+            e.insert_at_cursor("\n\n").unwrap();
+        });
+        assert_eq!(diff.inserted.len(), 1);
+        assert_eq!(diff.inserted[0].content, "two");
+        assert_eq!(diff.updated.len(), 2);
+        assert!(diff.removed.is_empty());
+        let after = ids(&parser);
+        assert_eq!(
+            (&after[0], &after[1], &after[3]),
+            (&before[0], &before[1], &before[2])
+        );
+        assert_eq!(
+            contents(&parser),
+            vec![
+                ("Intro".to_string(), 0),
+                ("One".to_string(), 1),
+                ("two".to_string(), 2),
+                ("Outro".to_string(), 3)
+            ]
+        );
+
+        // Undo merges them again
+        let diff = edit(&mut editor, &mut parser, |e| {
+            e.undo().unwrap();
+        });
+        assert_eq!(diff.removed, vec![after[2].clone()]);
+        assert_eq!(ids(&parser), before);
+    }
+
+    #[test]
+    fn test_code_fence_reparses_rest_of_note() {
+        let mut editor = EditorCore::new("Start\n\nA\n\nB\n\nC\n\nD\n\nE");
+        let mut parser = IncrementalParser::new(&editor.buffer_content(), "note-1").unwrap();
+        edit(&mut editor, &mut parser, |e| {
+            e.set_cursor(0).unwrap();
+            e.insert_at_cursor("```\n").unwrap();
+        });
+        assert_eq!(parser.blocks().count(), 1);
+        edit(&mut editor, &mut parser, |e| {
+            e.undo().unwrap();
+        });
+        assert_eq!(parser.blocks().count(), 6);
+
+        // Lists and headings around an edit
+        let mut editor = EditorCore::new("- a\n- b\n\ntext\n===\n\nmore");
+        let mut parser = IncrementalParser::new(&editor.buffer_content(), "note-1").unwrap();
+        edit(&mut editor, &mut parser, |e| {
+            e.set_cursor(7).unwrap();
+            e.insert_at_cursor("\n- c").unwrap();
+        });
+        edit(&mut editor, &mut parser, |e| {
+            let start = e.line_range(5).unwrap().start;
+            e.replace(start..start + 3, "").unwrap();
+        });
+    }
+
+    #[test]
+    fn test_definitions_and_front_matter() {
+        // A reference definition far below the edit turns `[foo]` into a link
+        let mut editor = EditorCore::new("[foo]\n\nA\n\nB\n\nC\n\n[foo]: http://x\n");
+        let mut parser = IncrementalParser::new(&editor.buffer_content(), "note-1").unwrap();
+        edit(&mut editor, &mut parser, |e| {
+            e.set_cursor(0).unwrap();
+            e.insert_at_cursor("x").unwrap();
+        });
+
+        // Breaking the closing `---` of the front matter, then undoing it
+        let mut editor = EditorCore::new("---\nt: 1\n---\nA\n\nB\n\n[^1]: n\n\nC[^1]\n");
+        let mut parser = IncrementalParser::new(&editor.buffer_content(), "note-1").unwrap();
+        edit(&mut editor, &mut parser, |e| {
+            e.replace(9..22, "- ").unwrap();
+        });
+        edit(&mut editor, &mut parser, |e| {
+            e.undo().unwrap();
+        });
+        assert_eq!(parser.blocks().next().unwrap().block_type, "front_matter");
+
+        // Closing `---` typed far below the opening one
+        let mut editor = EditorCore::new("---\na: 1\n\nA\n\nB\n\nC\n\nD\n");
+        let mut parser = IncrementalParser::new(&editor.buffer_content(), "note-1").unwrap();
+        edit(&mut editor, &mut parser, |e| {
+            let end = e.len();
+            e.replace(end..end, "---\n").unwrap();
+        });
+        assert_eq!(parser.blocks().count(), 1);
+    }
+
+    /// Compare against a full parse under pseudo-random edits and undos
+    #[test]
+    fn test_random_edits_match_full_parse() {
+        let pieces = [
+            "\n",
+            "\n\n",
+            "---\n",
+            "```\n",
+            "- ",
+            "> ",
+            "# ",
+            "[foo]",
+            "\n[foo]: /x\n",
+            "[^1]",
+            "\n[^1]: n\n",
+            "word ",
+            "t: 1",
+            "    ",
+            "",
+        ];
+        let mut seed = 7u64;
+        let mut rand = move |n: usize| {
+            seed = seed
+                .wrapping_mul(6364136223846793005)
+                .wrapping_add(1442695040888963407);
+            ((seed >> 33) as usize) % n.max(1)
+        };
+
+        for _ in 0..20 {
+            let mut editor = EditorCore::new(
+                "---\nt: 1\n---\n# Title\n\nA [foo]\n\n- a\n- b\n\n> quote\n\nB\n\n    code\n\nC\n",
+            );
+            let mut parser = IncrementalParser::new(&editor.buffer_content(), "note-1").unwrap();
+            for _ in 0..40 {
+                let (choice, at, width, piece) = (rand(4), rand(100), rand(8), rand(pieces.len()));
+                edit(&mut editor, &mut parser, |e| {
+                    if choice == 0 {
+                        e.undo().unwrap();
+                    } else {
+                        let at = at.min(e.len());
+                        let end = (at + width).min(e.len());
+                        e.replace(at..end, pieces[piece]).unwrap();
+                    }
+                });
+            }
+        }
+    }
+
+    #[test]
+    fn test_with_blocks_keeps_stored_ids() {
+        let stored = IncrementalParser::new("A\n\nB\n\nC", "note-1")
+            .unwrap()
+            .blocks()
+            .cloned()
+            .collect::<Vec<_>>();
+        let (parser, diff) =
+            IncrementalParser::with_blocks("A\n\nB changed\n\nC\n\nD", "note-1", stored.clone())
+                .unwrap();
+        let ids = ids(&parser);
+        assert_eq!(
+            &ids[..3],
+            &[
+                stored[0].id.clone(),
+                stored[1].id.clone(),
+                stored[2].id.clone()
+            ]
+        );
+        assert_eq!(diff.updated.len(), 1);
+        assert_eq!(diff.inserted.len(), 1);
+        assert_eq!(diff.inserted[0].content, "D");
+        assert!(diff.removed.is_empty());
+    }
+}
@@ -1,9 +1,35 @@
 //! Synapse Editor: parse/render and EditorCore.
 
+mod buffer;
+mod callout;
+mod commands;
 mod core;
+mod footnote;
+mod highlight;
+mod history;
+mod html;
+mod incremental;
+mod math;
 mod parser;
 mod renderer;
+mod search;
+mod selection;
+mod serializer;
+#[cfg(feature = "vim")]
+mod vim;
 
+pub use callout::{Callout, CalloutFold};
+pub use commands::InlineStyle;
 pub use core::EditorCore;
+pub use highlight::{highlight_code, highlight_theme_css, highlight_themes};
+pub use history::DEFAULT_COALESCE_TIMEOUT;
+pub use incremental::{IncrementalParser, TextChange};
+pub use math::tex_to_mathml;
 pub use parser::parse_markdown_to_blocks;
 pub use renderer::render_markdown_to_html;
+pub use search::FindOptions;
+pub use selection::Selection;
+pub use serializer::{block_to_markdown, blocks_to_markdown};
+pub use synapse_core::footnote::{footnote_label, footnote_links, footnote_references};
+#[cfg(feature = "vim")]
+pub use vim::{Key, Register, VimEngine, VimMode};
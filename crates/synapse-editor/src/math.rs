@@ -0,0 +1,1125 @@
+//! TeX math -> MathML conversion.
+//!
+//! Covers the subset of LaTeX math used in notes: scripts, fractions, roots, Greek letters
+//! and symbols, big operators, functions, accents, font commands, `\left...\right`
+//! delimiters, spacing and matrix-like environments. Unknown commands are rendered as
+//! `<merror>` so a formula never disappears silently.
+
+use pulldown_cmark::Event;
+
+use crate::html::escape;
+
+const MATHML_NS: &str = "http://www.w3.org/1998/Math/MathML";
+
+/// Deepest nesting of groups and commands that is parsed; anything deeper is rendered as
+/// `<merror>` instead of growing the stack
+const MAX_DEPTH: usize = 64;
+
+/// Convert a TeX formula to a `<math>` element. `display` selects block layout
+/// (`$$...$$`) over inline layout (`$...$`).
+pub fn tex_to_mathml(tex: &str, display: bool) -> String {
+    let mut parser = MathParser::new(tex, display);
+    let body = parser.parse_sequence(Stop::End);
+    format!(
+        "<math xmlns=\"{}\"{}><semantics><mrow>{}</mrow><annotation encoding=\"application/x-tex\">{}</annotation></semantics></math>",
+        MATHML_NS,
+        if display { " display=\"block\"" } else { "" },
+        body,
+        escape(tex.trim())
+    )
+}
+
+/// Renderer hook: replace math events with inline MathML
+pub(crate) fn render_math_event(event: Event<'_>) -> Event<'_> {
+    match event {
+        Event::InlineMath(tex) => Event::InlineHtml(tex_to_mathml(&tex, false).into()),
+        Event::DisplayMath(tex) => Event::InlineHtml(tex_to_mathml(&tex, true).into()),
+        other => other,
+    }
+}
+
+/// Where a sequence of atoms ends
+#[derive(Clone, Copy, PartialEq)]
+enum Stop {
+    End,
+    Brace,
+    Bracket,
+    Right,
+    Cell,
+}
+
+/// Font selected by `\mathbf`, `\mathbb`, ...
+#[derive(Clone, Copy, PartialEq)]
+enum Variant {
+    Normal,
+    Bold,
+    DoubleStruck,
+    Script,
+    Fraktur,
+    SansSerif,
+    Monospace,
+}
+
+/// A parsed atom before sub/superscripts are attached
+struct Atom {
+    xml: String,
+    /// Scripts go under/over instead of beside (`\sum` in display mode, `\lim`, braces)
+    limits: bool,
+    /// Append a function application operator after the scripts (`\sin x`)
+    apply_function: bool,
+}
+
+impl Atom {
+    fn new(xml: String) -> Self {
+        Self {
+            xml,
+            limits: false,
+            apply_function: false,
+        }
+    }
+}
+
+struct MathParser {
+    chars: Vec<char>,
+    pos: usize,
+    display: bool,
+    variant: Option<Variant>,
+    /// Sequences and atoms being parsed, up to [`MAX_DEPTH`]
+    depth: usize,
+}
+
+impl MathParser {
+    fn new(tex: &str, display: bool) -> Self {
+        Self {
+            chars: tex.chars().collect(),
+            pos: 0,
+            display,
+            variant: None,
+            depth: 0,
+        }
+    }
+
+    fn peek(&self) -> Option<char> {
+        self.chars.get(self.pos).copied()
+    }
+
+    fn skip_whitespace(&mut self) {
+        while self.peek().is_some_and(char::is_whitespace) {
+            self.pos += 1;
+        }
+    }
+
+    /// Name of the command at the cursor (`\frac` -> `frac`, `\{` -> `{`), without consuming it
+    fn peek_command(&self) -> Option<String> {
+        if self.peek() != Some('\\') {
+            return None;
+        }
+        let rest = &self.chars[self.pos + 1..];
+        let letters: String = rest
+            .iter()
+            .take_while(|c| c.is_ascii_alphabetic())
+            .collect();
+        if letters.is_empty() {
+            rest.first().map(|c| c.to_string())
+        } else {
+            Some(letters)
+        }
+    }
+
+    fn read_command(&mut self) -> String {
+        let name = self.peek_command().unwrap_or_default();
+        self.pos += 1 + name.chars().count();
+        name
+    }
+
+    fn at_stop(&self, stop: Stop) -> bool {
+        match (stop, self.peek()) {
+            (_, None) => true,
+            (Stop::Brace, Some('}')) | (Stop::Bracket, Some(']')) | (Stop::Cell, Some('&')) => true,
+            (Stop::Right, Some('\\')) => self.peek_command().as_deref() == Some("right"),
+            (Stop::Cell, Some('\\')) => {
+                matches!(self.peek_command().as_deref(), Some("\\") | Some("end"))
+            }
+            _ => false,
+        }
+    }
+
+    fn parse_sequence(&mut self, stop: Stop) -> String {
+        if self.depth >= MAX_DEPTH {
+            return self.skip_sequence(stop);
+        }
+        self.depth += 1;
+        let out = self.parse_atoms(stop);
+        self.depth -= 1;
+        out
+    }
+
+    fn parse_atoms(&mut self, stop: Stop) -> String {
+        let mut out = String::new();
+        loop {
+            self.skip_whitespace();
+            if self.at_stop(stop) {
+                break;
+            }
+            let atom = match self.peek() {
+                Some('^') | Some('_') | Some('\'') => Atom::new("<mrow></mrow>".to_string()),
+                _ => match self.parse_atom() {
+                    Some(atom) => atom,
+                    None => continue,
+                },
+            };
+            out.push_str(&self.parse_scripts(atom));
+        }
+        out
+    }
+
+    /// Skip a sequence nested too deeply to parse, up to its stop, and show it as an error
+    fn skip_sequence(&mut self, stop: Stop) -> String {
+        let start = self.pos;
+        let mut braces = 0usize;
+        while self.peek().is_some() && !(braces == 0 && self.at_stop(stop)) {
+            match self.peek() {
+                Some('{') => braces += 1,
+                Some('}') => braces = braces.saturating_sub(1),
+                _ => {}
+            }
+            self.pos += 1;
+        }
+        self.source_error(start)
+    }
+
+    /// `<merror>` for the source from `start` to the cursor
+    fn source_error(&self, start: usize) -> String {
+        let source: String = self.chars[start..self.pos].iter().collect();
+        format!("<merror><mtext>{}</mtext></merror>", escape(&source))
+    }
+
+    /// Parse a `{...}` group, consuming the closing brace if present
+    fn parse_group(&mut self) -> String {
+        self.pos += 1;
+        let inner = self.parse_sequence(Stop::Brace);
+        if self.peek() == Some('}') {
+            self.pos += 1;
+        }
+        format!("<mrow>{}</mrow>", inner)
+    }
+
+    /// Parse a command argument: a group, a single digit or a single atom
+    fn parse_arg(&mut self) -> String {
+        self.skip_whitespace();
+        match self.peek() {
+            Some('{') => self.parse_group(),
+            Some(c) if c.is_ascii_digit() => {
+                self.pos += 1;
+                format!("<mn>{}</mn>", self.styled(c))
+            }
+            Some(_) => self
+                .parse_atom()
+                .map(|atom| atom.xml)
+                .unwrap_or_else(|| "<mrow></mrow>".to_string()),
+            None => "<mrow></mrow>".to_string(),
+        }
+    }
+
+    /// Raw text of a `{...}` group (for `\text`, `\begin`, ...)
+    fn parse_raw_group(&mut self) -> String {
+        self.skip_whitespace();
+        if self.peek() != Some('{') {
+            // Single-token argument, e.g. `\text x`
+            let c = self.peek();
+            if c.is_some() {
+                self.pos += 1;
+            }
+            return c.map(String::from).unwrap_or_default();
+        }
+        self.pos += 1;
+        let mut depth = 0usize;
+        let mut text = String::new();
+        while let Some(c) = self.peek() {
+            self.pos += 1;
+            match c {
+                '{' => depth += 1,
+                '}' if depth == 0 => break,
+                '}' => depth -= 1,
+                _ => {}
+            }
+            text.push(c);
+        }
+        text
+    }
+
+    /// Parse an argument with a font variant applied to its letters and digits
+    fn parse_arg_with_variant(&mut self, variant: Variant) -> String {
+        let saved = self.variant.replace(variant);
+        let xml = self.parse_arg();
+        self.variant = saved;
+        xml
+    }
+
+    fn styled(&self, c: char) -> char {
+        match self.variant {
+            Some(variant) => styled_char(c, variant),
+            None => c,
+        }
+    }
+
+    fn identifier(&self, c: char) -> String {
+        if self.variant == Some(Variant::Normal) {
+            format!("<mi mathvariant=\"normal\">{}</mi>", escape_char(c))
+        } else {
+            format!("<mi>{}</mi>", escape_char(self.styled(c)))
+        }
+    }
+
+    fn parse_atom(&mut self) -> Option<Atom> {
+        if self.depth >= MAX_DEPTH {
+            // Skip one token: a command, a group or a character
+            let start = self.pos;
+            match self.peek()? {
+                '\\' => {
+                    self.read_command();
+                }
+                '{' => {
+                    self.parse_raw_group();
+                }
+                _ => self.pos += 1,
+            }
+            return Some(Atom::new(self.source_error(start)));
+        }
+        self.depth += 1;
+        let atom = self.parse_one_atom();
+        self.depth -= 1;
+        atom
+    }
+
+    fn parse_one_atom(&mut self) -> Option<Atom> {
+        let c = self.peek()?;
+        match c {
+            '{' => Some(Atom::new(self.parse_group())),
+            '}' | '&' => {
+                // Stray closer or alignment mark outside its context: skip it
+                self.pos += 1;
+                None
+            }
+            '\\' => self.parse_command(),
+            '~' => {
+                self.pos += 1;
+                Some(Atom::new("<mtext>&#xA0;</mtext>".to_string()))
+            }
+            c if c.is_ascii_digit() || (c == '.' && self.next_is_digit()) => {
+                let mut number = String::new();
+                while let Some(d) = self.peek() {
+                    if d.is_ascii_digit() || (d == '.' && self.next_is_digit()) {
+                        number.push(self.styled(d));
+                        self.pos += 1;
+                    } else {
+                        break;
+                    }
+                }
+                Some(Atom::new(format!("<mn>{}</mn>", number)))
+            }
+            c if c.is_alphabetic() => {
+                self.pos += 1;
+                Some(Atom::new(self.identifier(c)))
+            }
+            '(' | ')' | '[' | ']' | '|' => {
+                self.pos += 1;
+                Some(Atom::new(format!("<mo stretchy=\"false\">{}</mo>", c)))
+            }
+            _ => {
+                self.pos += 1;
+                Some(Atom::new(format!("<mo>{}</mo>", escape_char(c))))
+            }
+        }
+    }
+
+    fn next_is_digit(&self) -> bool {
+        self.chars
+            .get(self.pos + 1)
+            .is_some_and(char::is_ascii_digit)
+    }
+
+    fn parse_scripts(&mut self, mut base: Atom) -> String {
+        let mut sub: Option<String> = None;
+        let mut sup = String::new();
+        let mut has_sup = false;
+        loop {
+            self.skip_whitespace();
+            match self.peek() {
+                Some('_') if sub.is_none() => {
+                    self.pos += 1;
+                    sub = Some(self.parse_arg());
+                }
+                Some('^') => {
+                    self.pos += 1;
+                    sup.push_str(&self.parse_arg());
+                    has_sup = true;
+                }
+                Some('\'') => {
+                    self.pos += 1;
+                    sup.push_str("<mo>&#x2032;</mo>");
+                    has_sup = true;
+                }
+                Some('\\') => match self.peek_command().as_deref() {
+                    Some("limits") => {
+                        self.read_command();
+                        base.limits = true;
+                    }
+                    Some("nolimits") => {
+                        self.read_command();
+                        base.limits = false;
+                    }
+                    _ => break,
+                },
+                _ => break,
+            }
+        }
+
+        let sup = has_sup.then(|| format!("<mrow>{}</mrow>", sup));
+        let (under, over, both) = if base.limits {
+            ("munder", "mover", "munderover")
+        } else {
+            ("msub", "msup", "msubsup")
+        };
+        let mut xml = match (sub, sup) {
+            (None, None) => base.xml,
+            (Some(sub), None) => format!("<{0}>{1}{2}</{0}>", under, base.xml, sub),
+            (None, Some(sup)) => format!("<{0}>{1}{2}</{0}>", over, base.xml, sup),
+            (Some(sub), Some(sup)) => format!("<{0}>{1}{2}{3}</{0}>", both, base.xml, sub, sup),
+        };
+        if base.apply_function {
+            xml.push_str("<mo>&#x2061;</mo>");
+        }
+        xml
+    }
+
+    fn parse_command(&mut self) -> Option<Atom> {
+        let start = self.pos;
+        let name = self.read_command();
+        let name = name.as_str();
+
+        if let Some(letter) = greek(name) {
+            let xml = if letter.is_uppercase() {
+                format!("<mi mathvariant=\"normal\">{}</mi>", letter)
+            } else {
+                format!("<mi>{}</mi>", letter)
+            };
+            return Some(Atom::new(xml));
+        }
+        if let Some(symbol) = identifier_symbol(name) {
+            return Some(Atom::new(format!("<mi>{}</mi>", symbol)));
+        }
+        if let Some(op) = operator_symbol(name) {
+            return Some(Atom::new(format!("<mo>{}</mo>", escape(op))));
+        }
+        if let Some((op, limits)) = big_operator(name) {
+            return Some(Atom {
+                xml: format!("<mo largeop=\"true\" movablelimits=\"true\">{}</mo>", op),
+                limits: limits && self.display,
+                apply_function: false,
+            });
+        }
+        if let Some(limits) = function_name(name) {
+            return Some(Atom {
+                xml: format!("<mi>{}</mi>", name),
+                limits: limits && self.display,
+                apply_function: true,
+            });
+        }
+        if let Some(width) = space_width(name) {
+            return Some(Atom::new(format!("<mspace width=\"{}\"></mspace>", width)));
+        }
+        if let Some(variant) = font_variant(name) {
+            return Some(Atom::new(self.parse_arg_with_variant(variant)));
+        }
+        if let Some((accent, over)) = accent(name) {
+            let base = self.parse_arg();
+            let xml = if over {
+                format!("<mover accent=\"true\">{}<mo>{}</mo></mover>", base, accent)
+            } else {
+                format!(
+                    "<munder accentunder=\"true\">{}<mo>{}</mo></munder>",
+                    base, accent
+                )
+            };
+            return Some(Atom::new(xml));
+        }
+        if let Some(size) = delimiter_size(name) {
+            let delim = self.parse_delimiter();
+            return Some(Atom::new(format!(
+                "<mo minsize=\"{0}\" maxsize=\"{0}\">{1}</mo>",
+                size, delim
+            )));
+        }
+
+        let xml = match name {
+            "frac" | "dfrac" | "tfrac" | "cfrac" => {
+                let num = self.parse_arg();
+                let den = self.parse_arg();
+                format!("<mfrac>{}{}</mfrac>", num, den)
+            }
+            "binom" | "dbinom" | "tbinom" => {
+                let top = self.parse_arg();
+                let bottom = self.parse_arg();
+                format!(
+                    "<mrow><mo>(</mo><mfrac linethickness=\"0\">{}{}</mfrac><mo>)</mo></mrow>",
+                    top, bottom
+                )
+            }
+            "sqrt" => {
+                self.skip_whitespace();
+                if self.peek() == Some('[') {
+                    self.pos += 1;
+                    let index = self.parse_sequence(Stop::Bracket);
+                    if self.peek() == Some(']') {
+                        self.pos += 1;
+                    }
+                    let radicand = self.parse_arg();
+                    format!("<mroot>{}<mrow>{}</mrow></mroot>", radicand, index)
+                } else {
+                    format!("<msqrt>{}</msqrt>", self.parse_arg())
+                }
+            }
+            "text" | "textrm" | "textnormal" | "mbox" | "textit" | "textbf" => {
+                let text = self.parse_raw_group();
+                format!("<mtext>{}</mtext>", escape(&text))
+            }
+            "operatorname" => {
+                let text = self.parse_raw_group();
+                return Some(Atom {
+                    xml: format!("<mi>{}</mi>", escape(text.trim())),
+                    limits: false,
+                    apply_function: true,
+                });
+            }
+            "overbrace" | "underbrace" => {
+                let base = self.parse_arg();
+                let xml = if name == "overbrace" {
+                    format!("<mover>{}<mo>&#x23DE;</mo></mover>", base)
+                } else {
+                    format!("<munder>{}<mo>&#x23DF;</mo></munder>", base)
+                };
+                return Some(Atom {
+                    xml: format!("<mrow>{}</mrow>", xml),
+                    limits: true,
+                    apply_function: false,
+                });
+            }
+            "left" => {
+                let open = self.parse_delimiter();
+                let inner = self.parse_sequence(Stop::Right);
+                let close = if self.peek_command().as_deref() == Some("right") {
+                    self.read_command();
+                    self.parse_delimiter()
+                } else {
+                    String::new()
+                };
+                format!("<mrow>{}{}{}</mrow>", fence(&open), inner, fence(&close))
+            }
+            "middle" => {
+                let delim = self.parse_delimiter();
+                format!("<mo stretchy=\"true\">{}</mo>", delim)
+            }
+            "not" => {
+                self.skip_whitespace();
+                if self.peek() == Some('=') {
+                    self.pos += 1;
+                    "<mo>&#x2260;</mo>".to_string()
+                } else {
+                    let negated = self.parse_atom().map(|atom| atom.xml).unwrap_or_default();
+                    match negated.strip_suffix("</mo>") {
+                        Some(op) => format!("{}&#x338;</mo>", op),
+                        None => negated,
+                    }
+                }
+            }
+            "begin" => self.parse_environment(),
+            "displaystyle" | "textstyle" | "scriptstyle" | "limits" | "nolimits" | "\\" => {
+                return None;
+            }
+            // Closers without their opener
+            "right" => {
+                self.parse_delimiter();
+                self.source_error(start)
+            }
+            "end" => {
+                self.parse_raw_group();
+                self.source_error(start)
+            }
+            "{" | "}" | "$" | "%" | "#" | "&" | "_" => {
+                format!("<mo>{}</mo>", escape(name))
+            }
+            "|" => "<mo>&#x2016;</mo>".to_string(),
+            _ => format!("<merror><mtext>\\{}</mtext></merror>", escape(name)),
+        };
+        Some(Atom::new(xml))
+    }
+
+    /// Read a delimiter after `\left`, `\right`, `\big`...; `.` means "no delimiter"
+    fn parse_delimiter(&mut self) -> String {
+        self.skip_whitespace();
+        match self.peek() {
+            Some('\\') => {
+                let name = self.read_command();
+                match name.as_str() {
+                    "{" | "lbrace" => "{".to_string(),
+                    "}" | "rbrace" => "}".to_string(),
+                    "|" | "Vert" | "lVert" | "rVert" => "&#x2016;".to_string(),
+                    "vert" | "lvert" | "rvert" | "mid" => "|".to_string(),
+                    other => operator_symbol(other).map(escape).unwrap_or_default(),
+                }
+            }
+            Some('.') => {
+                self.pos += 1;
+                String::new()
+            }
+            Some(c) => {
+                self.pos += 1;
+                escape_char(c)
+            }
+            None => String::new(),
+        }
+    }
+
+    fn parse_environment(&mut self) -> String {
+        let env = self.parse_raw_group();
+        if env == "array" {
+            // Column spec is not needed for MathML layout
+            self.parse_raw_group();
+        }
+
+        let mut rows: Vec<Vec<String>> = vec![Vec::new()];
+        loop {
+            let cell = self.parse_sequence(Stop::Cell);
+            if let Some(row) = rows.last_mut() {
+                row.push(cell);
+            }
+            match self.peek() {
+                Some('&') => self.pos += 1,
+                Some('\\') if self.peek_command().as_deref() == Some("\\") => {
+                    self.read_command();
+                    rows.push(Vec::new());
+                }
+                Some('\\') => {
+                    // \end{env}
+                    self.read_command();
+                    self.parse_raw_group();
+                    break;
+                }
+                _ => break,
+            }
+        }
+        if rows.len() > 1
+            && rows
+                .last()
+                .is_some_and(|row| row.iter().all(String::is_empty))
+        {
+            rows.pop();
+        }
+
+        let env = env.trim_end_matches('*');
+        let column_align = match env {
+            "cases" => " columnalign=\"left left\"",
+            "aligned" | "align" | "split" | "alignat" => " columnalign=\"right left\"",
+            _ => "",
+        };
+        let mut table = format!("<mtable{}>", column_align);
+        for row in rows {
+            table.push_str("<mtr>");
+            for cell in row {
+                table.push_str(&format!("<mtd><mrow>{}</mrow></mtd>", cell));
+            }
+            table.push_str("</mtr>");
+        }
+        table.push_str("</mtable>");
+
+        let (open, close) = match env {
+            "pmatrix" => ("(", ")"),
+            "bmatrix" => ("[", "]"),
+            "Bmatrix" => ("{", "}"),
+            "vmatrix" => ("|", "|"),
+            "Vmatrix" => ("&#x2016;", "&#x2016;"),
+            "cases" => ("{", ""),
+            _ => ("", ""),
+        };
+        if open.is_empty() && close.is_empty() {
+            table
+        } else {
+            format!("<mrow>{}{}{}</mrow>", fence(open), table, fence(close))
+        }
+    }
+}
+
+fn fence(delim: &str) -> String {
+    if delim.is_empty() {
+        String::new()
+    } else {
+        format!("<mo fence=\"true\" stretchy=\"true\">{}</mo>", delim)
+    }
+}
+
+fn escape_char(c: char) -> String {
+    match c {
+        '&' => "&amp;".to_string(),
+        '<' => "&lt;".to_string(),
+        '>' => "&gt;".to_string(),
+        '"' => "&quot;".to_string(),
+        c => c.to_string(),
+    }
+}
+
+fn greek(name: &str) -> Option<char> {
+    let c = match name {
+        "alpha" => 'α',
+        "beta" => 'β',
+        "gamma" => 'γ',
+        "delta" => 'δ',
+        "epsilon" => 'ϵ',
+        "varepsilon" => 'ε',
+        "zeta" => 'ζ',
+        "eta" => 'η',
+        "theta" => 'θ',
+        "vartheta" => 'ϑ',
+        "iota" => 'ι',
+        "kappa" => 'κ',
+        "lambda" => 'λ',
+        "mu" => 'μ',
+        "nu" => 'ν',
+        "xi" => 'ξ',
+        "pi" => 'π',
+        "varpi" => 'ϖ',
+        "rho" => 'ρ',
+        "varrho" => 'ϱ',
+        "sigma" => 'σ',
+        "varsigma" => 'ς',
+        "tau" => 'τ',
+        "upsilon" => 'υ',
+        "phi" => 'ϕ',
+        "varphi" => 'φ',
+        "chi" => 'χ',
+        "psi" => 'ψ',
+        "omega" => 'ω',
+        "Gamma" => 'Γ',
+        "Delta" => 'Δ',
+        "Theta" => 'Θ',
+        "Lambda" => 'Λ',
+        "Xi" => 'Ξ',
+        "Pi" => 'Π',
+        "Sigma" => 'Σ',
+        "Upsilon" => 'Υ',
+        "Phi" => 'Φ',
+        "Psi" => 'Ψ',
+        "Omega" => 'Ω',
+        _ => return None,
+    };
+    Some(c)
+}
+
+/// Symbols that behave like identifiers
+fn identifier_symbol(name: &str) -> Option<&'static str> {
+    let s = match name {
+        "infty" => "∞",
+        "partial" => "∂",
+        "nabla" => "∇",
+        "emptyset" | "varnothing" => "∅",
+        "ell" => "ℓ",
+        "hbar" => "ℏ",
+        "aleph" => "ℵ",
+        "Re" => "ℜ",
+        "Im" => "ℑ",
+        "imath" => "ı",
+        "jmath" => "ȷ",
+        "wp" => "℘",
+        "top" => "⊤",
+        "bot" => "⊥",
+        "angle" => "∠",
+        "triangle" => "△",
+        "prime" => "′",
+        _ => return None,
+    };
+    Some(s)
+}
+
+/// Binary operators, relations, arrows and punctuation
+fn operator_symbol(name: &str) -> Option<&'static str> {
+    let s = match name {
+        "times" => "×",
+        "cdot" => "⋅",
+        "pm" => "±",
+        "mp" => "∓",
+        "div" => "÷",
+        "ast" => "∗",
+        "star" => "⋆",
+        "circ" => "∘",
+        "bullet" => "∙",
+        "oplus" => "⊕",
+        "ominus" => "⊖",
+        "otimes" => "⊗",
+        "odot" => "⊙",
+        "wedge" | "land" => "∧",
+        "vee" | "lor" => "∨",
+        "neg" | "lnot" => "¬",
+        "cap" => "∩",
+        "cup" => "∪",
+        "setminus" => "∖",
+        "leq" | "le" => "≤",
+        "geq" | "ge" => "≥",
+        "neq" | "ne" => "≠",
+        "approx" => "≈",
+        "equiv" => "≡",
+        "sim" => "∼",
+        "simeq" => "≃",
+        "cong" => "≅",
+        "propto" => "∝",
+        "ll" => "≪",
+        "gg" => "≫",
+        "prec" => "≺",
+        "succ" => "≻",
+        "in" => "∈",
+        "notin" => "∉",
+        "ni" => "∋",
+        "subset" => "⊂",
+        "supset" => "⊃",
+        "subseteq" => "⊆",
+        "supseteq" => "⊇",
+        "perp" => "⊥",
+        "parallel" => "∥",
+        "mid" => "∣",
+        "forall" => "∀",
+        "exists" => "∃",
+        "nexists" => "∄",
+        "to" | "rightarrow" => "→",
+        "leftarrow" | "gets" => "←",
+        "leftrightarrow" => "↔",
+        "Rightarrow" | "implies" => "⇒",
+        "Leftarrow" => "⇐",
+        "Leftrightarrow" | "iff" => "⇔",
+        "mapsto" => "↦",
+        "uparrow" => "↑",
+        "downarrow" => "↓",
+        "longrightarrow" => "⟶",
+        "longleftarrow" => "⟵",
+        "Longrightarrow" => "⟹",
+        "ldots" | "dots" => "…",
+        "cdots" => "⋯",
+        "vdots" => "⋮",
+        "ddots" => "⋱",
+        "langle" => "⟨",
+        "rangle" => "⟩",
+        "lfloor" => "⌊",
+        "rfloor" => "⌋",
+        "lceil" => "⌈",
+        "rceil" => "⌉",
+        "colon" => ":",
+        "vert" => "|",
+        "Vert" => "‖",
+        "backslash" => "\\",
+        "therefore" => "∴",
+        "because" => "∵",
+        _ => return None,
+    };
+    Some(s)
+}
+
+/// Big operators; the flag tells whether limits go under/over in display mode
+fn big_operator(name: &str) -> Option<(&'static str, bool)> {
+    let op = match name {
+        "sum" => ("∑", true),
+        "prod" => ("∏", true),
+        "coprod" => ("∐", true),
+        "bigcup" => ("⋃", true),
+        "bigcap" => ("⋂", true),
+        "bigoplus" => ("⨁", true),
+        "bigotimes" => ("⨂", true),
+        "bigvee" => ("⋁", true),
+        "bigwedge" => ("⋀", true),
+        "int" => ("∫", false),
+        "iint" => ("∬", false),
+        "iiint" => ("∭", false),
+        "oint" => ("∮", false),
+        _ => return None,
+    };
+    Some(op)
+}
+
+/// Named functions; the flag tells whether limits go under/over in display mode
+fn function_name(name: &str) -> Option<bool> {
+    match name {
+        "lim" | "liminf" | "limsup" | "max" | "min" | "sup" | "inf" | "det" | "gcd" | "Pr"
+        | "argmax" | "argmin" => Some(true),
+        "sin" | "cos" | "tan" | "cot" | "sec" | "csc" | "sinh" | "cosh" | "tanh" | "coth"
+        | "arcsin" | "arccos" | "arctan" | "log" | "ln" | "lg" | "exp" | "arg" | "deg" | "dim"
+        | "ker" | "hom" => Some(false),
+        _ => None,
+    }
+}
+
+fn space_width(name: &str) -> Option<&'static str> {
+    let width = match name {
+        "," | "thinspace" => "0.1667em",
+        ":" | ">" | "medspace" => "0.2222em",
+        ";" | "thickspace" => "0.2778em",
+        "!" | "negthinspace" => "-0.1667em",
+        " " => "0.25em",
+        "enspace" => "0.5em",
+        "quad" => "1em",
+        "qquad" => "2em",
+        _ => return None,
+    };
+    Some(width)
+}
+
+fn font_variant(name: &str) -> Option<Variant> {
+    let variant = match name {
+        "mathrm" | "mathup" => Variant::Normal,
+        "mathbf" | "boldsymbol" | "bm" => Variant::Bold,
+        "mathbb" => Variant::DoubleStruck,
+        "mathcal" | "mathscr" => Variant::Script,
+        "mathfrak" => Variant::Fraktur,
+        "mathsf" => Variant::SansSerif,
+        "mathtt" => Variant::Monospace,
+        _ => return None,
+    };
+    Some(variant)
+}
+
+/// Accent character and whether it goes over (true) or under (false) the base
+fn accent(name: &str) -> Option<(&'static str, bool)> {
+    let accent = match name {
+        "hat" | "widehat" => ("^", true),
+        "bar" => ("¯", true),
+        "overline" => ("‾", true),
+        "vec" | "overrightarrow" => ("→", true),
+        "overleftarrow" => ("←", true),
+        "dot" => ("˙", true),
+        "ddot" => ("¨", true),
+        "tilde" | "widetilde" => ("~", true),
+        "check" => ("ˇ", true),
+        "breve" => ("˘", true),
+        "acute" => ("´", true),
+        "grave" => ("`", true),
+        "mathring" => ("˚", true),
+        "underline" => ("_", false),
+        _ => return None,
+    };
+    Some(accent)
+}
+
+fn delimiter_size(name: &str) -> Option<&'static str> {
+    let size = match name {
+        "big" | "bigl" | "bigr" | "bigm" => "1.2em",
+        "Big" | "Bigl" | "Bigr" | "Bigm" => "1.623em",
+        "bigg" | "biggl" | "biggr" | "biggm" => "2.047em",
+        "Bigg" | "Biggl" | "Biggr" | "Biggm" => "2.470em",
+        _ => return None,
+    };
+    Some(size)
+}
+
+/// Map a Latin letter or digit to its Unicode Mathematical Alphanumeric Symbol
+fn styled_char(c: char, variant: Variant) -> char {
+    // Letters that live in the Letterlike Symbols block instead of the math block
+    let exception = match (variant, c) {
+        (Variant::DoubleStruck, 'C') => Some('ℂ'),
+        (Variant::DoubleStruck, 'H') => Some('ℍ'),
+        (Variant::DoubleStruck, 'N') => Some('ℕ'),
+        (Variant::DoubleStruck, 'P') => Some('ℙ'),
+        (Variant::DoubleStruck, 'Q') => Some('ℚ'),
+        (Variant::DoubleStruck, 'R') => Some('ℝ'),
+        (Variant::DoubleStruck, 'Z') => Some('ℤ'),
+        (Variant::Script, 'B') => Some('ℬ'),
+        (Variant::Script, 'E') => Some('ℰ'),
+        (Variant::Script, 'F') => Some('ℱ'),
+        (Variant::Script, 'H') => Some('ℋ'),
+        (Variant::Script, 'I') => Some('ℐ'),
+        (Variant::Script, 'L') => Some('ℒ'),
+        (Variant::Script, 'M') => Some('ℳ'),
+        (Variant::Script, 'R') => Some('ℛ'),
+        (Variant::Script, 'e') => Some('ℯ'),
+        (Variant::Script, 'g') => Some('ℊ'),
+        (Variant::Script, 'o') => Some('ℴ'),
+        (Variant::Fraktur, 'C') => Some('ℭ'),
+        (Variant::Fraktur, 'H') => Some('ℌ'),
+        (Variant::Fraktur, 'I') => Some('ℑ'),
+        (Variant::Fraktur, 'R') => Some('ℜ'),
+        (Variant::Fraktur, 'Z') => Some('ℨ'),
+        _ => None,
+    };
+    if let Some(mapped) = exception {
+        return mapped;
+    }
+
+    // (upper, lower, digit) base code points per variant
+    let (upper, lower, digit) = match variant {
+        Variant::Normal => return c,
+        Variant::Bold => (0x1D400, 0x1D41A, Some(0x1D7CE)),
+        Variant::DoubleStruck => (0x1D538, 0x1D552, Some(0x1D7D8)),
+        Variant::Script => (0x1D49C, 0x1D4B6, None),
+        Variant::Fraktur => (0x1D504, 0x1D51E, None),
+        Variant::SansSerif => (0x1D5A0, 0x1D5BA, Some(0x1D7E2)),
+        Variant::Monospace => (0x1D670, 0x1D68A, Some(0x1D7F6)),
+    };
+    let code = match c {
+        'A'..='Z' => Some(upper + (c as u32 - 'A' as u32)),
+        'a'..='z' => Some(lower + (c as u32 - 'a' as u32)),
+        '0'..='9' => digit.map(|base| base + (c as u32 - '0' as u32)),
+        _ => None,
+    };
+    code.and_then(char::from_u32).unwrap_or(c)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_scripts_and_fractions() {
+        let xml = tex_to_mathml(r"x_i^2 + \frac{a}{b}", false);
+        assert!(xml.contains("<msubsup><mi>x</mi><mi>i</mi><mrow><mn>2</mn></mrow></msubsup>"));
+        assert!(xml.contains("<mfrac><mrow><mi>a</mi></mrow><mrow><mi>b</mi></mrow></mfrac>"));
+        assert!(!xml.contains("display=\"block\""));
+    }
+
+    #[test]
+    fn test_display_limits_and_environments() {
+        let xml = tex_to_mathml(
+            r"\sum_{k=1}^{n} k = \begin{pmatrix} a & b \\ c & d \end{pmatrix}",
+            true,
+        );
+        assert!(xml.contains("display=\"block\""));
+        assert!(xml.contains("<munderover><mo largeop=\"true\""));
+        assert_eq!(xml.matches("<mtr>").count(), 2);
+        assert_eq!(xml.matches("<mtd>").count(), 4);
+    }
+
+    #[test]
+    fn test_symbols_and_unknown_commands() {
+        let xml = tex_to_mathml(r"\alpha \leq \sqrt[3]{\mathbb{R}} \foo", false);
+        assert!(xml.contains("<mi>α</mi>"));
+        assert!(xml.contains("<mo>≤</mo>"));
+        assert!(xml.contains("<mroot>"));
+        assert!(xml.contains("ℝ"));
+        assert!(xml.contains("<merror><mtext>\\foo</mtext></merror>"));
+
+        let interval = tex_to_mathml("[a, b]", false);
+        assert!(interval.contains("<mo stretchy=\"false\">]</mo>"));
+    }
+
+    /// (TeX, display mode, fragment the MathML must contain)
+    #[test]
+    fn test_conversions() {
+        let cases = [
+            // Fractions
+            (r"\frac12", false, "<mfrac><mn>1</mn><mn>2</mn></mfrac>"),
+            (
+                r"\dfrac{a+b}{c}",
+                false,
+                "<mfrac><mrow><mi>a</mi><mo>+</mo><mi>b</mi></mrow><mrow><mi>c</mi></mrow></mfrac>",
+            ),
+            // Scripts
+            (
+                r"x'",
+                false,
+                "<msup><mi>x</mi><mrow><mo>&#x2032;</mo></mrow></msup>",
+            ),
+            (
+                r"e^{-x^2}",
+                false,
+                "<msup><mi>x</mi><mrow><mn>2</mn></mrow></msup>",
+            ),
+            (r"\sum_{k=1}^n k", true, "<munderover><mo largeop=\"true\""),
+            (r"\sum_{k=1}^n k", false, "<msubsup><mo largeop=\"true\""),
+            // \left...\right
+            (
+                r"\left( x \right)",
+                false,
+                "<mrow><mo fence=\"true\" stretchy=\"true\">(</mo><mi>x</mi><mo fence=\"true\" stretchy=\"true\">)</mo></mrow>",
+            ),
+            (
+                r"\left\{ x \right.",
+                false,
+                "<mrow><mo fence=\"true\" stretchy=\"true\">{</mo><mi>x</mi></mrow>",
+            ),
+            (
+                r"\left| x \middle| y \right|",
+                false,
+                "<mo stretchy=\"true\">|</mo>",
+            ),
+            // Environments
+            (
+                r"\begin{matrix} a \end{matrix}",
+                false,
+                "<mtable><mtr><mtd><mrow><mi>a</mi></mrow></mtd></mtr></mtable>",
+            ),
+            (
+                r"\begin{cases} 1 & x \\ 0 & y \end{cases}",
+                true,
+                "<mtable columnalign=\"left left\">",
+            ),
+            // Accents
+            (
+                r"\hat{x}",
+                false,
+                "<mover accent=\"true\"><mrow><mi>x</mi></mrow><mo>^</mo></mover>",
+            ),
+            (
+                r"\vec v",
+                false,
+                "<mover accent=\"true\"><mi>v</mi><mo>→</mo></mover>",
+            ),
+            // Error recovery: closers without openers and unknown commands stay visible
+            (
+                r"a \right) b",
+                false,
+                "<mi>a</mi><merror><mtext>\\right)</mtext></merror><mi>b</mi>",
+            ),
+            (
+                r"a \end{matrix} b",
+                false,
+                "<mi>a</mi><merror><mtext>\\end{matrix}</mtext></merror><mi>b</mi>",
+            ),
+            (r"\foo{x}", false, "<merror><mtext>\\foo</mtext></merror>"),
+            (
+                r"\left( x",
+                false,
+                "<mo fence=\"true\" stretchy=\"true\">(</mo><mi>x</mi></mrow>",
+            ),
+            (
+                r"\frac{a}",
+                false,
+                "<mfrac><mrow><mi>a</mi></mrow><mrow></mrow></mfrac>",
+            ),
+        ];
+        for (tex, display, fragment) in cases {
+            let xml = tex_to_mathml(tex, display);
+            assert!(xml.contains(fragment), "{tex}: {xml}");
+        }
+    }
+
+    #[test]
+    fn test_deep_nesting_is_an_error() {
+        let n = 100_000;
+        let nested = format!("{}x{}", "{".repeat(n), "}".repeat(n));
+        // Run on a small stack so unbounded recursion would overflow it
+        let xml = std::thread::Builder::new()
+            .stack_size(2 << 20)
+            .spawn(move || tex_to_mathml(&nested, false))
+            .unwrap()
+            .join()
+            .unwrap();
+        assert!(xml.contains("<merror>"));
+        assert!(xml.matches("<mrow>").count() <= 2 * MAX_DEPTH);
+
+        let commands = format!("{}x", r"\sqrt".repeat(n));
+        let xml = std::thread::Builder::new()
+            .stack_size(2 << 20)
+            .spawn(move || tex_to_mathml(&commands, false))
+            .unwrap()
+            .join()
+            .unwrap();
+        assert!(xml.contains("<merror>"));
+
+        let shallow = tex_to_mathml("{{{x}}}", false);
+        assert!(!shallow.contains("<merror>"));
+    }
+}
@@ -1,22 +1,95 @@
 //! Markdown parser: content -> Block list (Block from synapse-core).
 
-use pulldown_cmark::{Event, Parser, Tag, TagEnd};
+use pulldown_cmark::{Event, Options, Parser, Tag, TagEnd};
 use uuid::Uuid;
 
 use synapse_core::Block;
 use synapse_core::Result;
+use synapse_core::footnote::FOOTNOTE_BLOCK_TYPE;
+use synapse_core::frontmatter::split_front_matter;
+
+use crate::callout::Callout;
+
+/// Block type of an ordered list item, whose content starts with its marker (`1.`)
+pub(crate) const ORDERED_ITEM_BLOCK_TYPE: &str = "ordered_list_item";
 
 /// Parse Markdown content into blocks
 pub fn parse_markdown_to_blocks(content: &str, note_id: &str) -> Result<Vec<Block>> {
-    let parser = Parser::new(content);
+    parse_blocks(content, note_id, true)
+}
+
+/// Parser options for note bodies. Front matter is split off first with
+/// [`split_front_matter`]: pulldown-cmark would take a metadata block at any block start.
+pub(crate) fn parser_options() -> Options {
+    Options::ENABLE_MATH | Options::ENABLE_FOOTNOTES
+}
+
+/// Length of the front matter block opening `content`, or 0 without one
+pub(crate) fn front_matter_len(content: &str) -> usize {
+    match split_front_matter(content) {
+        (Some(_), body) => content.len() - body.len(),
+        (None, _) => 0,
+    }
+}
+
+/// Parse Markdown into blocks; `front_matter` is false for text that does not start the note
+pub(crate) fn parse_blocks(content: &str, note_id: &str, front_matter: bool) -> Result<Vec<Block>> {
+    let (yaml, body) = if front_matter {
+        split_front_matter(content)
+    } else {
+        (None, content)
+    };
+    let mut blocks = Vec::new();
+    if let Some(yaml) = yaml {
+        let yaml = yaml.trim_matches(['\r', '\n']);
+        let block_id = format!("block-{}", Uuid::new_v4());
+        blocks.push(Block::new(
+            block_id,
+            note_id.to_string(),
+            "front_matter".to_string(),
+            if yaml.is_empty() {
+                "---\n---".to_string()
+            } else {
+                format!("---\n{}\n---", yaml)
+            },
+            0,
+        ));
+    }
+    for mut block in parse_events(body, note_id)? {
+        block.position = blocks.len() as i64;
+        blocks.push(block);
+    }
+    Ok(blocks)
+}
+
+fn parse_events(content: &str, note_id: &str) -> Result<Vec<Block>> {
+    let parser = Parser::new_ext(content, parser_options());
     let mut blocks = Vec::new();
     let mut position = 0i64;
     let mut current_block_type = "paragraph".to_string();
     let mut current_content = String::new();
     let mut in_code_block = false;
     let mut code_block_lang = String::new();
+    // Nesting depth of the callout whose events are being skipped
+    let mut callout_depth = 0usize;
+    let mut in_footnote_definition = false;
+    // Whether each enclosing list is ordered, innermost last
+    let mut ordered_lists = Vec::new();
+
+    for (event, range) in parser.into_offset_iter() {
+        if callout_depth > 0 {
+            match event {
+                Event::Start(Tag::BlockQuote(_)) => callout_depth += 1,
+                Event::End(TagEnd::BlockQuote(_)) => callout_depth -= 1,
+                _ => {}
+            }
+            continue;
+        }
+        if in_footnote_definition {
+            in_footnote_definition = !matches!(event, Event::End(TagEnd::FootnoteDefinition));
+            continue;
+        }
 
-    for event in parser {
         match event {
             Event::Start(tag) => {
                 if !current_content.trim().is_empty() && !in_code_block {
@@ -45,16 +118,40 @@ pub fn parse_markdown_to_blocks(content: &str, note_id: &str) -> Result<Vec<Bloc
                         current_block_type = "code_block".to_string();
                     }
                     Tag::List(Some(_)) => {
+                        ordered_lists.push(true);
                         current_block_type = "ordered_list".to_string();
                     }
                     Tag::List(None) => {
+                        ordered_lists.push(false);
                         current_block_type = "unordered_list".to_string();
                     }
+                    Tag::Item if ordered_lists.last() == Some(&true) => {
+                        // Ordered items keep their marker (`3.`, `1)`) so numbering round-trips
+                        let marker = content[range].split_whitespace().next().unwrap_or("1.");
+                        current_content.push_str(marker);
+                        current_content.push(' ');
+                        current_block_type = ORDERED_ITEM_BLOCK_TYPE.to_string();
+                    }
                     Tag::Item => {
                         current_block_type = "list_item".to_string();
                     }
                     Tag::BlockQuote(_) => {
-                        current_block_type = "quote".to_string();
+                        // Callouts keep their Markdown source so the syntax round-trips
+                        let source = content[range].trim_end();
+                        if Callout::parse(source).is_some() {
+                            let block_id = format!("block-{}", Uuid::new_v4());
+                            blocks.push(Block::new(
+                                block_id,
+                                note_id.to_string(),
+                                "callout".to_string(),
+                                source.to_string(),
+                                position,
+                            ));
+                            position += 1;
+                            callout_depth = 1;
+                        } else {
+                            current_block_type = "quote".to_string();
+                        }
                     }
                     Tag::Table(_) => {
                         current_block_type = "table".to_string();
@@ -65,59 +162,74 @@ pub fn parse_markdown_to_blocks(content: &str, note_id: &str) -> Result<Vec<Bloc
                     Tag::TableCell => {
                         current_block_type = "table_cell".to_string();
                     }
+                    Tag::FootnoteDefinition(_) => {
+                        // Definitions keep their `[^label]: ...` source
+                        let block_id = format!("block-{}", Uuid::new_v4());
+                        blocks.push(Block::new(
+                            block_id,
+                            note_id.to_string(),
+                            FOOTNOTE_BLOCK_TYPE.to_string(),
+                            content[range].trim_end().to_string(),
+                            position,
+                        ));
+                        position += 1;
+                        in_footnote_definition = true;
+                    }
                     _ => {}
                 }
             }
-            Event::End(tag_end) => {
-                match tag_end {
-                    TagEnd::CodeBlock => {
-                        if !current_content.trim().is_empty() {
-                            let block_id = format!("block-{}", Uuid::new_v4());
-                            let mut block = Block::new(
-                                block_id,
-                                note_id.to_string(),
-                                "code_block".to_string(),
-                                current_content.trim().to_string(),
-                                position,
-                            );
-                            if !code_block_lang.is_empty() {
-                                block.content =
-                                    format!("```{}\n{}\n```", code_block_lang, block.content);
-                            } else {
-                                block.content = format!("```\n{}\n```", block.content);
-                            }
-                            blocks.push(block);
-                            position += 1;
-                            current_content.clear();
+            Event::End(tag_end) => match tag_end {
+                TagEnd::CodeBlock => {
+                    if !current_content.trim().is_empty() {
+                        let block_id = format!("block-{}", Uuid::new_v4());
+                        let mut block = Block::new(
+                            block_id,
+                            note_id.to_string(),
+                            "code_block".to_string(),
+                            current_content.trim().to_string(),
+                            position,
+                        );
+                        if !code_block_lang.is_empty() {
+                            block.content =
+                                format!("```{}\n{}\n```", code_block_lang, block.content);
+                        } else {
+                            block.content = format!("```\n{}\n```", block.content);
                         }
-                        in_code_block = false;
-                        code_block_lang.clear();
+                        blocks.push(block);
+                        position += 1;
+                        current_content.clear();
                     }
-                    TagEnd::Heading(_)
-                    | TagEnd::Paragraph
-                    | TagEnd::List(_)
-                    | TagEnd::Item
-                    | TagEnd::BlockQuote(_)
-                    | TagEnd::Table
-                    | TagEnd::TableRow
-                    | TagEnd::TableCell => {
-                        if !current_content.trim().is_empty() {
-                            let block_id = format!("block-{}", Uuid::new_v4());
-                            blocks.push(Block::new(
-                                block_id,
-                                note_id.to_string(),
-                                current_block_type.clone(),
-                                current_content.trim().to_string(),
-                                position,
-                            ));
-                            position += 1;
-                            current_content.clear();
-                        }
-                        current_block_type = "paragraph".to_string();
+                    in_code_block = false;
+                    code_block_lang.clear();
+                    current_block_type = "paragraph".to_string();
+                }
+                TagEnd::Heading(_)
+                | TagEnd::Paragraph
+                | TagEnd::List(_)
+                | TagEnd::Item
+                | TagEnd::BlockQuote(_)
+                | TagEnd::Table
+                | TagEnd::TableRow
+                | TagEnd::TableCell => {
+                    if matches!(tag_end, TagEnd::List(_)) {
+                        ordered_lists.pop();
                     }
-                    _ => {}
+                    if !current_content.trim().is_empty() {
+                        let block_id = format!("block-{}", Uuid::new_v4());
+                        blocks.push(Block::new(
+                            block_id,
+                            note_id.to_string(),
+                            current_block_type.clone(),
+                            current_content.trim().to_string(),
+                            position,
+                        ));
+                        position += 1;
+                        current_content.clear();
+                    }
+                    current_block_type = "paragraph".to_string();
                 }
-            }
+                _ => {}
+            },
             Event::Text(text) => {
                 current_content.push_str(&text);
             }
@@ -160,12 +272,12 @@ pub fn parse_markdown_to_blocks(content: &str, note_id: &str) -> Result<Vec<Bloc
                 let marker = if checked { "- [x]" } else { "- [ ]" };
                 current_content.push_str(marker);
             }
-            Event::FootnoteReference(_) => {}
-            Event::InlineMath(math) => {
-                current_content.push_str(&format!("${}$", math));
+            Event::FootnoteReference(label) => {
+                current_content.push_str(&format!("[^{}]", label));
             }
-            Event::DisplayMath(math) => {
-                current_content.push_str(&format!("$${}\n$$", math));
+            // Math keeps its source: `$$...$$` may sit inside a paragraph
+            Event::InlineMath(_) | Event::DisplayMath(_) => {
+                current_content.push_str(&content[range]);
             }
             Event::InlineHtml(html) => {
                 current_content.push_str(&html);
@@ -200,4 +312,111 @@ mod tests {
         assert_eq!(blocks[0].block_type, "heading_h1");
         assert_eq!(blocks[1].block_type, "paragraph");
     }
+
+    #[test]
+    fn test_parse_keeps_inline_math() {
+        let blocks = parse_markdown_to_blocks("Area is $a_1 * b_1$.", "note-123").unwrap();
+        assert_eq!(blocks.len(), 1);
+        assert_eq!(blocks[0].content, "Area is $a_1 * b_1$.");
+    }
+
+    #[test]
+    fn test_parse_callout_block() {
+        let content = "> [!warning]+ Title\n> Body line\n> > nested\n\nAfter";
+        let blocks = parse_markdown_to_blocks(content, "note-123").unwrap();
+        assert_eq!(blocks.len(), 2);
+        assert_eq!(blocks[0].block_type, "callout");
+        assert_eq!(
+            blocks[0].content,
+            "> [!warning]+ Title\n> Body line\n> > nested"
+        );
+        assert_eq!(blocks[1].content, "After");
+
+        let callout = Callout::parse(&blocks[0].content).unwrap();
+        assert_eq!(callout.kind, "warning");
+        assert_eq!(callout.title.as_deref(), Some("Title"));
+        assert_eq!(callout.to_markdown(), blocks[0].content);
+
+        let quote = parse_markdown_to_blocks("> plain quote", "note-123").unwrap();
+        assert_eq!(quote[0].block_type, "quote");
+    }
+
+    #[test]
+    fn test_parse_footnotes() {
+        let content = "Claim[^src] here.\n\n[^src]: Source, *page 3*.\n";
+        let blocks = parse_markdown_to_blocks(content, "note-123").unwrap();
+        assert_eq!(blocks.len(), 2);
+        assert_eq!(blocks[0].content, "Claim[^src] here.");
+        assert_eq!(blocks[1].block_type, "footnote");
+        assert_eq!(blocks[1].content, "[^src]: Source, *page 3*.");
+
+        let links = crate::footnote_links(&blocks);
+        assert_eq!(links, vec![(blocks[0].id.clone(), blocks[1].id.clone())]);
+    }
+
+    #[test]
+    fn test_parse_front_matter_block() {
+        let content = "---\ntitle: Meeting\ntags: [work]\n---\n# Heading\n";
+        let blocks = parse_markdown_to_blocks(content, "note-123").unwrap();
+        assert_eq!(blocks.len(), 2);
+        assert_eq!(blocks[0].block_type, "front_matter");
+        assert_eq!(blocks[0].content, "---\ntitle: Meeting\ntags: [work]\n---");
+        assert_eq!(blocks[1].block_type, "heading_h1");
+    }
+
+    /// The parser, the renderer and `synapse_core::frontmatter` agree on where front matter ends
+    #[test]
+    fn test_front_matter_detection() {
+        let cases: [(&str, Option<&str>, &str); 11] = [
+            ("---\ntitle: a\n---\nbody", Some("title: a\n"), "body"),
+            ("---\n---\nbody", Some(""), "body"),
+            ("---\n\ntitle: a\n---\nbody", Some("\ntitle: a\n"), "body"),
+            ("--- \ntitle: a\n---\nbody", Some("title: a\n"), "body"),
+            ("---\ntitle: a\n---   \nbody", Some("title: a\n"), "body"),
+            ("---\ntitle: a\n...\nbody", Some("title: a\n"), "body"),
+            (
+                "---\r\ntitle: a\r\n---\r\nbody",
+                Some("title: a\r\n"),
+                "body",
+            ),
+            ("---\ntitle: a\nbody", None, "---\ntitle: a\nbody"),
+            (
+                "\n---\ntitle: a\n---\nbody",
+                None,
+                "\n---\ntitle: a\n---\nbody",
+            ),
+            (
+                "body\n\n---\ntitle: a\n---\n",
+                None,
+                "body\n\n---\ntitle: a\n---\n",
+            ),
+            ("---", None, "---"),
+        ];
+        for (content, yaml, body) in cases {
+            assert_eq!(split_front_matter(content), (yaml, body), "{content:?}");
+
+            let blocks = parse_markdown_to_blocks(content, "n").unwrap();
+            let body_blocks = parse_markdown_to_blocks(body, "n").unwrap();
+            let has_front_matter =
+                blocks.first().map(|b| b.block_type.as_str()) == Some("front_matter");
+            assert_eq!(has_front_matter, yaml.is_some(), "{content:?}");
+            let rest: Vec<_> = blocks[usize::from(has_front_matter)..]
+                .iter()
+                .map(|b| (&b.block_type, &b.content))
+                .collect();
+            let expected: Vec<_> = body_blocks
+                .iter()
+                .map(|b| (&b.block_type, &b.content))
+                .collect();
+            assert_eq!(rest, expected, "{content:?}");
+
+            if yaml.is_some() {
+                assert_eq!(
+                    crate::render_markdown_to_html(content),
+                    crate::render_markdown_to_html(body),
+                    "{content:?}"
+                );
+            }
+        }
+    }
 }
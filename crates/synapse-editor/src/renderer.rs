@@ -1,7 +1,20 @@
 //! Markdown renderer: content -> HTML.
+//!
+//! Fenced code blocks with a known language are syntax highlighted (see [`crate::highlight`]),
+//! and `$...$` / `$$...$$` math is converted to MathML (see [`crate::math`]).
+//! `> [!kind]` callouts become `<aside>` elements (see [`crate::callout`]), footnote references
+//! and definitions link to each other (see [`crate::footnote`]), and YAML front matter is not
+//! rendered.
 
 use pulldown_cmark::{Options, Parser, html};
 
+use synapse_core::frontmatter::split_front_matter;
+
+use crate::callout::RenderCallouts;
+use crate::footnote::LinkFootnotes;
+use crate::highlight::HighlightCodeBlocks;
+use crate::math::render_math_event;
+
 /// Render Markdown content to HTML
 pub fn render_markdown_to_html(content: &str) -> String {
     let mut options = Options::empty();
@@ -10,10 +23,15 @@ pub fn render_markdown_to_html(content: &str) -> String {
     options.insert(Options::ENABLE_FOOTNOTES);
     options.insert(Options::ENABLE_TASKLISTS);
     options.insert(Options::ENABLE_SMART_PUNCTUATION);
+    options.insert(Options::ENABLE_MATH);
 
+    // Front matter is not rendered
+    let (_, content) = split_front_matter(content);
     let parser = Parser::new_ext(content, options);
     let mut html_output = String::new();
-    html::push_html(&mut html_output, parser);
+    let parser = RenderCallouts::new(content, parser.into_offset_iter());
+    let events = HighlightCodeBlocks::new(LinkFootnotes::new(parser.map(render_math_event)));
+    html::push_html(&mut html_output, events);
     html_output
 }
 
@@ -26,4 +44,61 @@ mod tests {
         let html = render_markdown_to_html("# Heading");
         assert!(html.contains("<h1>"));
     }
+
+    #[test]
+    fn test_render_highlighted_code_block() {
+        let html = render_markdown_to_html("```rust\nlet x = 1;\n```");
+        assert!(html.contains("<code class=\"language-rust\">"));
+        assert!(html.contains("<span class=\"hl-"));
+
+        let plain = render_markdown_to_html("```\nlet x = 1;\n```");
+        assert!(plain.contains("<pre><code>let x = 1;"));
+    }
+
+    #[test]
+    fn test_render_math_as_mathml() {
+        let html = render_markdown_to_html("Inline $x^2$ and\n\n$$\\frac{1}{2}$$");
+        assert!(html.contains("<math xmlns=\"http://www.w3.org/1998/Math/MathML\"><semantics>"));
+        assert!(html.contains("<msup><mi>x</mi>"));
+        assert!(html.contains("display=\"block\""));
+        assert!(!html.contains("$x^2$"));
+    }
+
+    #[test]
+    fn test_render_callout_as_aside() {
+        let html = render_markdown_to_html("> [!warning] Careful\n> Body *text*");
+        assert!(
+            html.contains("<aside class=\"callout callout-warning\" data-callout=\"warning\">")
+        );
+        assert!(html.contains("<div class=\"callout-title\">Careful</div>"));
+        assert!(html.contains("<p>Body <em>text</em></p>"));
+        assert!(!html.contains("<blockquote>"));
+
+        let folded = render_markdown_to_html("> [!tip]-\n> Hidden");
+        assert!(folded.contains("<details class=\"callout-fold\">"));
+        assert!(folded.contains("<summary class=\"callout-title\">Tip</summary>"));
+    }
+
+    #[test]
+    fn test_render_linked_footnotes() {
+        let html = render_markdown_to_html("Claim[^src].\n\n[^src]: Source.\n");
+        assert!(html.contains(
+            "<sup class=\"footnote-reference\" id=\"fnref-src\"><a href=\"#fn-src\">1</a></sup>"
+        ));
+        assert!(html.contains("<div class=\"footnote-definition\" id=\"fn-src\">"));
+        assert!(html.contains("<a href=\"#fnref-src\" class=\"footnote-backref\">"));
+
+        // A footnote cited twice keeps its ids unique
+        let html = render_markdown_to_html("One[^src], two[^src].\n\n[^src]: Source.\n");
+        assert_eq!(html.matches("id=\"fnref-src\"").count(), 1);
+        assert_eq!(html.matches("<a href=\"#fn-src\">1</a>").count(), 2);
+        assert_eq!(html.matches("footnote-backref").count(), 1);
+    }
+
+    #[test]
+    fn test_render_skips_front_matter() {
+        let html = render_markdown_to_html("---\ntitle: Meeting\n---\n# Heading\n");
+        assert!(!html.contains("title"));
+        assert!(html.contains("<h1>Heading</h1>"));
+    }
 }
@@ -0,0 +1,104 @@
+//! Regex matching in grapheme positions, and the options of
+//! [`crate::EditorCore::find_all`] / [`crate::EditorCore::replace_all`].
+
+use std::ops::Range;
+
+use regex::{Captures, Regex, RegexBuilder};
+use synapse_core::{Error, Result};
+use unicode_segmentation::UnicodeSegmentation;
+
+/// Options for find and replace
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct FindOptions {
+    pub case_sensitive: bool,
+    /// Only match whole words
+    pub whole_word: bool,
+    /// Treat the pattern as a regular expression; the replacement may then refer to capture
+    /// groups as `$1` or `${name}` (`$$` for a literal `$`)
+    pub regex: bool,
+    /// Only match inside the non-empty selections
+    pub in_selection: bool,
+}
+
+impl FindOptions {
+    /// Compile `pattern` with these options (`^` and `$` match at line boundaries)
+    pub(crate) fn build_regex(&self, pattern: &str) -> Result<Regex> {
+        if pattern.is_empty() {
+            return Err(Error::InvalidInput("Empty search pattern".to_string()));
+        }
+        let pattern = if self.regex {
+            pattern.to_string()
+        } else {
+            regex::escape(pattern)
+        };
+        RegexBuilder::new(&pattern)
+            .case_insensitive(!self.case_sensitive)
+            .multi_line(true)
+            .build()
+            .map_err(|e| Error::InvalidInput(format!("Invalid pattern: {}", e)))
+    }
+
+    /// Whether a match at byte range `range` of `text` passes the whole-word option: it may
+    /// not run on into a word at either end. Unlike `\b` this also works for matches that
+    /// start or end with punctuation, such as `#tag` or `C++`.
+    pub(crate) fn accepts(&self, text: &str, range: Range<usize>) -> bool {
+        let is_word = |c: Option<char>| c.is_some_and(|c| c.is_alphanumeric() || c == '_');
+        let matched = &text[range.clone()];
+        let joins_before =
+            is_word(text[..range.start].chars().next_back()) && is_word(matched.chars().next());
+        let joins_after =
+            is_word(matched.chars().next_back()) && is_word(text[range.end..].chars().next());
+        !self.whole_word || !(joins_before || joins_after)
+    }
+}
+
+/// Byte offsets of the grapheme cluster boundaries of `text`, including `text.len()`
+pub(crate) fn grapheme_boundaries(text: &str) -> Vec<usize> {
+    text.grapheme_indices(true)
+        .map(|(i, _)| i)
+        .chain(std::iter::once(text.len()))
+        .collect()
+}
+
+/// Matches of `regex` in `text` with their grapheme ranges.
+///
+/// Matches that start or end inside a grapheme cluster (`e` against a decomposed `é`, half of
+/// a ZWJ emoji sequence) are skipped.
+pub(crate) fn grapheme_matches<'t>(
+    regex: &Regex,
+    text: &'t str,
+) -> Vec<(Captures<'t>, Range<usize>)> {
+    let boundaries = grapheme_boundaries(text);
+    regex
+        .captures_iter(text)
+        .filter_map(|caps| {
+            let m = caps.get(0)?;
+            let start = boundaries.binary_search(&m.start()).ok()?;
+            let end = boundaries.binary_search(&m.end()).ok()?;
+            Some((caps, start..end))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_matches_skip_partial_clusters() {
+        let regex = Regex::new("e").unwrap();
+        // "cafe\u{301}" ends in a decomposed é: its `e` is not a match on its own
+        let ranges: Vec<_> = grapheme_matches(&regex, "e café cafe\u{301}")
+            .into_iter()
+            .map(|(_, range)| range)
+            .collect();
+        assert_eq!(ranges, vec![0..1]);
+
+        let regex = Regex::new("b+").unwrap();
+        let ranges: Vec<_> = grapheme_matches(&regex, "👨‍👩‍👧 bb")
+            .into_iter()
+            .map(|(_, range)| range)
+            .collect();
+        assert_eq!(ranges, vec![2..4]);
+    }
+}
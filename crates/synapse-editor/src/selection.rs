@@ -0,0 +1,156 @@
+//! Selection ranges and the multi-cursor selection set of [`crate::EditorCore`].
+
+use std::ops::Range;
+
+/// A selected range from `anchor` to `head` (grapheme indices); a cursor when they are equal.
+/// The head is where the caret is drawn and can be before the anchor.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Selection {
+    pub anchor: usize,
+    pub head: usize,
+}
+
+impl Selection {
+    pub fn new(anchor: usize, head: usize) -> Self {
+        Self { anchor, head }
+    }
+
+    pub fn cursor(position: usize) -> Self {
+        Self::new(position, position)
+    }
+
+    pub fn start(&self) -> usize {
+        self.anchor.min(self.head)
+    }
+
+    pub fn end(&self) -> usize {
+        self.anchor.max(self.head)
+    }
+
+    pub fn range(&self) -> Range<usize> {
+        self.start()..self.end()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.anchor == self.head
+    }
+
+    fn clamp(self, len: usize) -> Self {
+        Self::new(self.anchor.min(len), self.head.min(len))
+    }
+}
+
+/// Non-empty set of selections, sorted by position and non-overlapping, with one primary
+/// selection (the one the single-cursor API reads and writes)
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct SelectionSet {
+    ranges: Vec<Selection>,
+    primary: usize,
+}
+
+impl SelectionSet {
+    pub(crate) fn single(selection: Selection) -> Self {
+        Self {
+            ranges: vec![selection],
+            primary: 0,
+        }
+    }
+
+    /// Build a set from ranges in any order, merging overlapping ones
+    pub(crate) fn new(ranges: Vec<Selection>, primary: usize, len: usize) -> Self {
+        let mut ranges: Vec<Selection> = ranges.into_iter().map(|s| s.clamp(len)).collect();
+        if ranges.is_empty() {
+            return Self::single(Selection::cursor(len));
+        }
+        let primary_range = ranges[primary.min(ranges.len() - 1)];
+        ranges.sort_by_key(|s| (s.start(), s.end()));
+
+        let mut merged: Vec<Selection> = Vec::with_capacity(ranges.len());
+        let mut primary = 0;
+        for selection in ranges {
+            let is_primary = selection == primary_range;
+            match merged.last_mut() {
+                Some(last) if overlaps(last, &selection) => {
+                    let (start, end) = (last.start(), last.end().max(selection.end()));
+                    // Keep the direction of the range that was there first
+                    *last = if last.head < last.anchor {
+                        Selection::new(end, start)
+                    } else {
+                        Selection::new(start, end)
+                    };
+                }
+                _ => merged.push(selection),
+            }
+            if is_primary {
+                primary = merged.len() - 1;
+            }
+        }
+        Self {
+            ranges: merged,
+            primary,
+        }
+    }
+
+    pub(crate) fn primary(&self) -> Selection {
+        self.ranges[self.primary]
+    }
+
+    pub(crate) fn primary_index(&self) -> usize {
+        self.primary
+    }
+
+    pub(crate) fn ranges(&self) -> &[Selection] {
+        &self.ranges
+    }
+
+    /// Apply `f` to every anchor and head, then re-normalize
+    pub(crate) fn map(&self, len: usize, f: impl Fn(usize) -> usize) -> Self {
+        let ranges = self
+            .ranges
+            .iter()
+            .map(|s| Selection::new(f(s.anchor), f(s.head)))
+            .collect();
+        Self::new(ranges, self.primary, len)
+    }
+
+    pub(crate) fn clamp(&self, len: usize) -> Self {
+        self.map(len, |pos| pos)
+    }
+}
+
+/// Whether `b` (which does not start before `a`) should merge into `a`: overlapping ranges,
+/// equal cursors, or a cursor at the edge of a range
+fn overlaps(a: &Selection, b: &Selection) -> bool {
+    if a.is_empty() {
+        b.start() == a.start()
+    } else if b.is_empty() {
+        b.start() <= a.end()
+    } else {
+        b.start() < a.end()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_new_sorts_and_merges() {
+        let set = SelectionSet::new(
+            vec![
+                Selection::new(8, 4),
+                Selection::cursor(1),
+                Selection::new(6, 10),
+                Selection::cursor(1),
+            ],
+            2,
+            20,
+        );
+        assert_eq!(set.ranges(), &[Selection::cursor(1), Selection::new(10, 4)]);
+        assert_eq!(set.primary_index(), 1);
+
+        // Ranges that only touch stay separate
+        let set = SelectionSet::new(vec![Selection::new(0, 2), Selection::new(2, 4)], 0, 4);
+        assert_eq!(set.ranges().len(), 2);
+    }
+}
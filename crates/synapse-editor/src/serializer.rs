@@ -0,0 +1,70 @@
+//! Markdown serializer: Block list -> content (inverse of [`crate::parse_markdown_to_blocks`]).
+//!
+//! Blocks that keep their Markdown source (code blocks, callouts, footnotes, front matter,
+//! ordered list items) are written back verbatim; the rest get their block-level syntax
+//! restored. Items of one list are separated by a single newline so the list stays tight.
+
+use synapse_core::Block;
+
+use crate::parser::ORDERED_ITEM_BLOCK_TYPE;
+
+/// Serialize blocks (in position order) back to Markdown
+pub fn blocks_to_markdown(blocks: &[Block]) -> String {
+    let mut sorted: Vec<&Block> = blocks.iter().filter(|b| !b.is_deleted).collect();
+    sorted.sort_by_key(|b| b.position);
+
+    let mut out = String::new();
+    let mut previous_type: Option<&str> = None;
+    for block in sorted {
+        let block_type = block.block_type.as_str();
+        if let Some(previous) = previous_type {
+            let same_list = previous == block_type
+                && (block_type == "list_item" || block_type == ORDERED_ITEM_BLOCK_TYPE);
+            out.push_str(if same_list { "\n" } else { "\n\n" });
+        }
+        out.push_str(&block_to_markdown(block));
+        previous_type = Some(block_type);
+    }
+    if !out.is_empty() {
+        out.push('\n');
+    }
+    out
+}
+
+/// Serialize a single block
+pub fn block_to_markdown(block: &Block) -> String {
+    let content = block.content.as_str();
+    if let Some(level) = block.block_type.strip_prefix("heading_h") {
+        let level = level.parse::<usize>().unwrap_or(1).clamp(1, 6);
+        return format!("{} {}", "#".repeat(level), content);
+    }
+    match block.block_type.as_str() {
+        "list_item" if content.starts_with("- [") => content.to_string(),
+        "list_item" => format!("- {}", content),
+        "quote" => content
+            .lines()
+            .map(|line| {
+                if line.is_empty() {
+                    ">".to_string()
+                } else {
+                    format!("> {}", line)
+                }
+            })
+            .collect::<Vec<_>>()
+            .join("\n"),
+        _ => content.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parse_markdown_to_blocks;
+
+    #[test]
+    fn test_round_trip() {
+        let content = "# Title\n\nText with a note[^1].\n\n> [!tip] Hint\n> Body\n\n```rust\nlet x = 1;\n```\n\n$$\nx^2 + y^2\n$$\n\nInline $$a$$ and $b$ math.\n\n1. one\n2. two\n\n- three\n- four\n\n3) five\n\n[^1]: The footnote.\n";
+        let blocks = parse_markdown_to_blocks(content, "note-123").unwrap();
+        assert_eq!(blocks_to_markdown(&blocks), content);
+    }
+}
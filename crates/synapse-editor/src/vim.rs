@@ -0,0 +1,1908 @@
+//! Vim mode: a modal key engine on top of [`EditorCore`] (enabled by the `vim` feature).
+//!
+//! Keys are fed one at a time with [`VimEngine::handle_key`] or as Vim key notation with
+//! [`VimEngine::feed`] (`"d2w"`, `"ciwnew<Esc>"`). Positions are grapheme indices, like the
+//! rest of `EditorCore`, and every change is a single undo step.
+//!
+//! Supported:
+//! - modes: normal, insert, visual (`v`) and visual-line (`V`)
+//! - motions: `h j k l w W b B e E 0 ^ $ gg G f F t T ; , % { }`
+//! - operators `d c y > <` with counts (`2d3w`), doubled for whole lines (`dd`, `3>>`),
+//!   plus the shortcuts `x X D C s S Y`
+//! - text objects: `iw aw iW aW i" a" i' a' i( a( i[ a[ i{ a{ i< a< ip ap`
+//! - `i a I A o O p P r u <C-r>`, registers (`"a`–`"z`, `"A` appends, `"0`, `"_`),
+//!   `.` repeat and macros (`qa … q`, `@a`, `@@`)
+//! - the command line: `/` and `?` search with `n`/`N`, and ex commands such as `:s`, `:g`
+//!   and `:w`/`:q` hooks (see the `ex` module)
+
+use std::collections::HashMap;
+use std::ops::Range;
+
+use synapse_core::{Error, Result};
+use unicode_segmentation::UnicodeSegmentation;
+
+use crate::EditorCore;
+
+mod ex;
+
+use ex::{CommandLine, Hooks, Search, Substitution};
+
+/// Nested replays (macros calling macros) deeper than this are ignored
+const MAX_REPLAY_DEPTH: usize = 100;
+
+/// Counts are clamped to this, so a mistyped `9999999999p` cannot exhaust memory
+const MAX_COUNT: usize = 10_000;
+
+/// Puts and repeated inserts that would add more text than this are refused
+const MAX_REPEATED_BYTES: usize = 64 * 1024 * 1024;
+
+/// A key press
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Key {
+    Char(char),
+    Ctrl(char),
+    Esc,
+    Enter,
+    Backspace,
+    Tab,
+}
+
+impl Key {
+    /// Parse Vim key notation: plain characters plus `<Esc>`, `<CR>`, `<BS>`, `<Tab>`,
+    /// `<C-x>` and `<lt>`
+    pub fn parse_sequence(keys: &str) -> Vec<Key> {
+        let mut out = Vec::new();
+        let mut rest = keys;
+        while let Some(c) = rest.chars().next() {
+            if c == '<'
+                && let Some(end) = rest.find('>')
+                && let Some(key) = Key::from_name(&rest[1..end])
+            {
+                out.push(key);
+                rest = &rest[end + 1..];
+                continue;
+            }
+            out.push(match c {
+                '\n' => Key::Enter,
+                '\t' => Key::Tab,
+                c => Key::Char(c),
+            });
+            rest = &rest[c.len_utf8()..];
+        }
+        out
+    }
+
+    /// Format keys as Vim key notation (inverse of [`Key::parse_sequence`])
+    pub fn to_notation(keys: &[Key]) -> String {
+        keys.iter()
+            .map(|key| match key {
+                Key::Char('<') => "<lt>".to_string(),
+                Key::Char(c) => c.to_string(),
+                Key::Ctrl(c) => format!("<C-{}>", c),
+                Key::Esc => "<Esc>".to_string(),
+                Key::Enter => "<CR>".to_string(),
+                Key::Backspace => "<BS>".to_string(),
+                Key::Tab => "<Tab>".to_string(),
+            })
+            .collect()
+    }
+
+    fn from_name(name: &str) -> Option<Key> {
+        let lower = name.to_ascii_lowercase();
+        match lower.as_str() {
+            "esc" => Some(Key::Esc),
+            "cr" | "enter" | "return" => Some(Key::Enter),
+            "bs" => Some(Key::Backspace),
+            "tab" => Some(Key::Tab),
+            "lt" => Some(Key::Char('<')),
+            _ => {
+                let rest = lower.strip_prefix("c-")?;
+                let mut chars = rest.chars();
+                let c = chars.next()?;
+                chars.next().is_none().then_some(Key::Ctrl(c))
+            }
+        }
+    }
+}
+
+/// Current mode of the engine
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum VimMode {
+    Normal,
+    Insert,
+    Visual,
+    VisualLine,
+    /// Typing after `:`, `/` or `?`
+    CommandLine,
+}
+
+/// Register content
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Register {
+    pub text: String,
+    /// Whole lines (from `dd`, `yy`, `V`…), pasted as new lines
+    pub linewise: bool,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Find {
+    ch: char,
+    forward: bool,
+    till: bool,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Motion {
+    Left,
+    Right,
+    Up,
+    Down,
+    WordForward(bool),
+    WordBackward(bool),
+    WordEnd(bool),
+    LineStart,
+    FirstNonBlank,
+    LineEnd,
+    FileStart,
+    FileEnd,
+    Find(Find),
+    RepeatFind(bool),
+    MatchPair,
+    ParagraphForward,
+    ParagraphBackward,
+    /// `n` (false) and `N` (true)
+    SearchNext(bool),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum MotionKind {
+    Exclusive,
+    Inclusive,
+    Linewise,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Operator {
+    Delete,
+    Change,
+    Yank,
+    Indent,
+    Outdent,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum TextObject {
+    Word {
+        big: bool,
+        around: bool,
+    },
+    Quote {
+        quote: char,
+        around: bool,
+    },
+    Pair {
+        open: char,
+        close: char,
+        around: bool,
+    },
+    Paragraph {
+        around: bool,
+    },
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Target {
+    Motion(Motion),
+    Object(TextObject),
+    Lines,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum InsertAt {
+    Before,
+    After,
+    LineStart,
+    LineEnd,
+    LineBelow,
+    LineAbove,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Action {
+    Escape,
+    Move(Motion),
+    Operate {
+        op: Operator,
+        count: Option<usize>,
+        target: Target,
+    },
+    VisualOperate(Operator),
+    VisualObject(TextObject),
+    VisualSwap,
+    Insert(InsertAt),
+    Put {
+        before: bool,
+    },
+    Replace(char),
+    Undo,
+    Redo,
+    Repeat,
+    Visual {
+        linewise: bool,
+    },
+    RecordMacro(char),
+    StopMacro,
+    PlayMacro(Option<char>),
+    CommandLine(char),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Command {
+    register: Option<char>,
+    count: Option<usize>,
+    action: Action,
+    /// Index of the first key after the register and count
+    body: usize,
+}
+
+enum Parse<T> {
+    Done(T),
+    More,
+    Invalid,
+}
+
+/// Operator target: a grapheme range or whole lines (inclusive line indices)
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Region {
+    Chars(Range<usize>),
+    Lines(usize, usize),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct LastChange {
+    register: Option<char>,
+    count: Option<usize>,
+    keys: Vec<Key>,
+}
+
+#[derive(Debug)]
+struct InsertSession {
+    count: usize,
+    text: String,
+    /// Prepended to each repetition of the text (`3oabc` repeats `"\nabc"`)
+    repeat_prefix: &'static str,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Class {
+    Blank,
+    Word,
+    Punct,
+}
+
+/// Modal Vim key engine driving an [`EditorCore`]
+#[derive(Debug)]
+pub struct VimEngine {
+    mode: VimMode,
+    pending: Vec<Key>,
+    registers: HashMap<char, Register>,
+    visual_anchor: usize,
+    /// Vim cursor while the editor shows a visual selection (the editor's cursor is the
+    /// selection head, one past the character under the Vim cursor)
+    visual_head: Option<usize>,
+    /// Column kept by `j`/`k` across shorter lines
+    column: Option<usize>,
+    last_find: Option<Find>,
+    insert: Option<InsertSession>,
+    /// A change is in progress (its undo group is open)
+    in_change: bool,
+    /// Keys of the change being recorded for `.`
+    change: Option<LastChange>,
+    last_change: Option<LastChange>,
+    recording_macro: Option<char>,
+    macro_keys: Vec<Key>,
+    last_macro: Option<char>,
+    replay_depth: usize,
+    shiftwidth: usize,
+    command_line: Option<CommandLine>,
+    last_search: Option<Search>,
+    last_substitution: Option<Substitution>,
+    /// First and last line of the last visual selection (`'<` and `'>`)
+    visual_marks: Option<(usize, usize)>,
+    /// Changed since the last `:w`
+    modified: bool,
+    hooks: Hooks,
+}
+
+impl Default for VimEngine {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl VimEngine {
+    pub fn new() -> Self {
+        Self {
+            mode: VimMode::Normal,
+            pending: Vec::new(),
+            registers: HashMap::new(),
+            visual_anchor: 0,
+            visual_head: None,
+            column: None,
+            last_find: None,
+            insert: None,
+            in_change: false,
+            change: None,
+            last_change: None,
+            recording_macro: None,
+            macro_keys: Vec::new(),
+            last_macro: None,
+            replay_depth: 0,
+            shiftwidth: 4,
+            command_line: None,
+            last_search: None,
+            last_substitution: None,
+            visual_marks: None,
+            modified: false,
+            hooks: Hooks::default(),
+        }
+    }
+
+    pub fn mode(&self) -> VimMode {
+        self.mode
+    }
+
+    /// Keys typed so far for an incomplete command (for a status line)
+    pub fn pending_keys(&self) -> String {
+        Key::to_notation(&self.pending)
+    }
+
+    /// Register content (`'"'` is the unnamed register)
+    pub fn register(&self, name: char) -> Option<&Register> {
+        self.registers.get(&name.to_ascii_lowercase())
+    }
+
+    pub fn set_register(&mut self, name: char, register: Register) {
+        self.registers.insert(name.to_ascii_lowercase(), register);
+    }
+
+    /// Register a macro is being recorded into, if any
+    pub fn recording_macro(&self) -> Option<char> {
+        self.recording_macro
+    }
+
+    /// Spaces added or removed by `>` and `<` (default 4)
+    pub fn set_shiftwidth(&mut self, width: usize) {
+        self.shiftwidth = width.max(1);
+    }
+
+    /// Feed keys in Vim notation, e.g. `"dd"` or `"ciwword<Esc>"`
+    pub fn feed(&mut self, editor: &mut EditorCore, keys: &str) -> Result<()> {
+        for key in Key::parse_sequence(keys) {
+            self.handle_key(editor, key)?;
+        }
+        Ok(())
+    }
+
+    /// Handle a single key press
+    pub fn handle_key(&mut self, editor: &mut EditorCore, key: Key) -> Result<()> {
+        if self.recording_macro.is_some() && self.replay_depth == 0 {
+            self.macro_keys.push(key);
+        }
+        match self.mode {
+            VimMode::Insert => self.insert_key(editor, key),
+            VimMode::CommandLine => self.command_line_key(editor, key),
+            VimMode::Normal | VimMode::Visual | VimMode::VisualLine => self.normal_key(editor, key),
+        }
+    }
+
+    fn normal_key(&mut self, editor: &mut EditorCore, key: Key) -> Result<()> {
+        self.restore_visual_cursor(editor)?;
+        // The editor may hand over a cursor past the last character (a fresh buffer puts
+        // it at the end), which normal mode never allows
+        clamp_cursor(editor);
+        self.pending.push(key);
+        let visual = self.mode != VimMode::Normal;
+        let command = match parse_command(&self.pending, visual, self.recording_macro.is_some()) {
+            Parse::More => return Ok(()),
+            Parse::Invalid => {
+                self.pending.clear();
+                return Ok(());
+            }
+            Parse::Done(command) => command,
+        };
+        let keys = std::mem::take(&mut self.pending);
+        self.execute(editor, command, &keys)?;
+
+        if self.mode != VimMode::Insert {
+            clamp_cursor(editor);
+            if self.visual_mode().is_some() {
+                self.update_selection(editor)?;
+            }
+        }
+        Ok(())
+    }
+
+    fn execute(&mut self, editor: &mut EditorCore, command: Command, keys: &[Key]) -> Result<()> {
+        let count = command.count.unwrap_or(1);
+        match command.action {
+            Action::Escape => {
+                if self.mode != VimMode::Normal {
+                    self.exit_visual(editor);
+                }
+            }
+            Action::Move(motion) => {
+                let from = editor.cursor_position();
+                if let Some((target, _)) =
+                    self.motion_target(editor, from, motion, command.count, false)
+                {
+                    editor.set_cursor(target)?;
+                }
+            }
+            Action::Operate {
+                op,
+                count: count2,
+                target,
+            } => {
+                let total = match (command.count, count2) {
+                    (None, None) => None,
+                    (a, b) => Some((a.unwrap_or(1) * b.unwrap_or(1)).min(MAX_COUNT)),
+                };
+                // A motion that fails (`dfz` with no `z`) is not a change: it leaves `.`
+                // and the modified flag alone
+                let Some(region) = self.operator_region(editor, op, target, total) else {
+                    return Ok(());
+                };
+                if op != Operator::Yank {
+                    self.begin_change(editor, &command, keys);
+                }
+                self.apply_operator(editor, op, region, command.register)?;
+                if self.mode != VimMode::Insert {
+                    self.finish_change(editor);
+                }
+            }
+            Action::VisualOperate(op) => {
+                let region = self.visual_region(editor);
+                self.exit_visual(editor);
+                if op != Operator::Yank {
+                    // Visual changes are undoable as one step but not repeatable with `.`
+                    self.begin_change(editor, &command, &[]);
+                    self.change = None;
+                }
+                self.apply_operator(editor, op, region, command.register)?;
+                if self.mode != VimMode::Insert {
+                    self.finish_change(editor);
+                }
+            }
+            Action::VisualObject(object) => {
+                let cursor = editor.cursor_position();
+                match text_object(editor, cursor, object, count) {
+                    Some(Region::Chars(range)) if !range.is_empty() => {
+                        if self.visual_anchor == cursor {
+                            self.visual_anchor = range.start;
+                        }
+                        editor.set_cursor(range.end - 1)?;
+                    }
+                    Some(Region::Lines(first, last)) => {
+                        self.mode = VimMode::VisualLine;
+                        self.visual_anchor = line_span(editor, first).start;
+                        editor.set_cursor(line_span(editor, last).start)?;
+                    }
+                    _ => {}
+                }
+            }
+            Action::VisualSwap => {
+                let cursor = editor.cursor_position();
+                editor.set_cursor(self.visual_anchor)?;
+                self.visual_anchor = cursor;
+            }
+            Action::Insert(at) => {
+                self.begin_change(editor, &command, keys);
+                self.start_insert(editor, at, count)?;
+            }
+            Action::Put { before } => {
+                self.begin_change(editor, &command, keys);
+                self.put(editor, command.register, before, count)?;
+                self.finish_change(editor);
+            }
+            Action::Replace(ch) => {
+                let cursor = editor.cursor_position();
+                let line_end = line_span(editor, editor.line_index_of(cursor)).end;
+                if cursor + count <= line_end {
+                    self.begin_change(editor, &command, keys);
+                    editor.replace(cursor..cursor + count, &ch.to_string().repeat(count))?;
+                    editor.set_cursor(cursor + count - 1)?;
+                    self.finish_change(editor);
+                }
+            }
+            Action::Undo => {
+                for _ in 0..count {
+                    if !editor.undo()? {
+                        break;
+                    }
+                    self.modified = true;
+                }
+            }
+            Action::Redo => {
+                for _ in 0..count {
+                    if !editor.redo()? {
+                        break;
+                    }
+                    self.modified = true;
+                }
+            }
+            Action::Repeat => {
+                if let Some(last) = self.last_change.clone() {
+                    let mut replay = Vec::new();
+                    if let Some(register) = last.register {
+                        replay.extend([Key::Char('"'), Key::Char(register)]);
+                    }
+                    if let Some(n) = command.count.or(last.count) {
+                        replay.extend(n.to_string().chars().map(Key::Char));
+                    }
+                    replay.extend(last.keys);
+                    self.replay(editor, replay)?;
+                }
+            }
+            Action::Visual { linewise } => {
+                let mode = if linewise {
+                    VimMode::VisualLine
+                } else {
+                    VimMode::Visual
+                };
+                if self.mode == mode {
+                    self.exit_visual(editor);
+                } else {
+                    if self.mode == VimMode::Normal {
+                        self.visual_anchor = editor.cursor_position();
+                    }
+                    self.mode = mode;
+                }
+            }
+            Action::RecordMacro(register) => {
+                self.recording_macro = Some(register);
+                self.macro_keys.clear();
+            }
+            Action::StopMacro => {
+                if let Some(register) = self.recording_macro.take() {
+                    // Drop the `q` that stopped the recording
+                    self.macro_keys.pop();
+                    let text = Key::to_notation(&self.macro_keys);
+                    self.store_register(Some(register), text, false, false);
+                }
+            }
+            Action::CommandLine(prefix) => self.open_command_line(editor, prefix, command.count),
+            Action::PlayMacro(register) => {
+                let Some(register) = register.or(self.last_macro) else {
+                    return Ok(());
+                };
+                self.last_macro = Some(register);
+                if let Some(content) = self.register(register) {
+                    let keys = Key::parse_sequence(&content.text);
+                    for _ in 0..count {
+                        self.replay(editor, keys.clone())?;
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn insert_key(&mut self, editor: &mut EditorCore, key: Key) -> Result<()> {
+        if let Some(change) = &mut self.change {
+            change.keys.push(key);
+        }
+        let text = match key {
+            Key::Esc => return self.finish_insert(editor),
+            Key::Char(c) => c.to_string(),
+            Key::Enter => "\n".to_string(),
+            Key::Tab => "\t".to_string(),
+            Key::Backspace => {
+                if editor.delete_backward(1)? > 0
+                    && let Some(session) = &mut self.insert
+                {
+                    // The buffer deleted a whole grapheme, e.g. `e` and a combining accent
+                    let last = session.text.grapheme_indices(true).next_back();
+                    session.text.truncate(last.map_or(0, |(i, _)| i));
+                }
+                return Ok(());
+            }
+            Key::Ctrl(_) => return Ok(()),
+        };
+        editor.insert_at_cursor(&text)?;
+        if let Some(session) = &mut self.insert {
+            session.text.push_str(&text);
+        }
+        Ok(())
+    }
+
+    fn finish_insert(&mut self, editor: &mut EditorCore) -> Result<()> {
+        if let Some(session) = self.insert.take() {
+            let repeated = format!("{}{}", session.repeat_prefix, session.text);
+            check_repeat_size(&repeated, session.count)?;
+            for _ in 1..session.count {
+                editor.insert_at_cursor(&repeated)?;
+            }
+        }
+        let cursor = editor.cursor_position();
+        if cursor > line_span(editor, editor.line_index_of(cursor)).start {
+            editor.set_cursor(cursor - 1)?;
+        }
+        self.mode = VimMode::Normal;
+        self.finish_change(editor);
+        clamp_cursor(editor);
+        Ok(())
+    }
+
+    fn start_insert(&mut self, editor: &mut EditorCore, at: InsertAt, count: usize) -> Result<()> {
+        let cursor = editor.cursor_position();
+        let line = editor.line_index_of(cursor);
+        let span = line_span(editor, line);
+        let mut repeat_prefix = "";
+        match at {
+            InsertAt::Before => {}
+            InsertAt::After => editor.set_cursor((cursor + 1).min(span.end))?,
+            InsertAt::LineStart => editor.set_cursor(first_non_blank(editor, line))?,
+            InsertAt::LineEnd => editor.set_cursor(span.end)?,
+            InsertAt::LineBelow => {
+                editor.set_cursor(span.end)?;
+                editor.insert_at_cursor("\n")?;
+                repeat_prefix = "\n";
+            }
+            InsertAt::LineAbove => {
+                editor.set_cursor(span.start)?;
+                editor.insert_at_cursor("\n")?;
+                editor.set_cursor(span.start)?;
+                repeat_prefix = "\n";
+            }
+        }
+        self.enter_insert(count, repeat_prefix);
+        Ok(())
+    }
+
+    fn enter_insert(&mut self, count: usize, repeat_prefix: &'static str) {
+        self.mode = VimMode::Insert;
+        self.insert = Some(InsertSession {
+            count,
+            text: String::new(),
+            repeat_prefix,
+        });
+    }
+
+    /// Open an undo group and start recording keys for `.`
+    fn begin_change(&mut self, editor: &mut EditorCore, command: &Command, keys: &[Key]) {
+        if !self.in_change {
+            editor.begin_group();
+            self.in_change = true;
+        }
+        self.modified = true;
+        self.change = Some(LastChange {
+            register: command.register,
+            count: command.count,
+            keys: keys.get(command.body..).unwrap_or_default().to_vec(),
+        });
+    }
+
+    fn finish_change(&mut self, editor: &mut EditorCore) {
+        if self.in_change {
+            editor.end_group();
+            self.in_change = false;
+        }
+        if let Some(change) = self.change.take() {
+            self.last_change = Some(change);
+        }
+    }
+
+    fn replay(&mut self, editor: &mut EditorCore, keys: Vec<Key>) -> Result<()> {
+        if self.replay_depth >= MAX_REPLAY_DEPTH {
+            return Ok(());
+        }
+        self.replay_depth += 1;
+        let result = keys
+            .into_iter()
+            .try_for_each(|key| self.handle_key(editor, key));
+        self.replay_depth -= 1;
+        result
+    }
+
+    fn exit_visual(&mut self, editor: &mut EditorCore) {
+        let anchor = editor.line_index_of(self.visual_anchor);
+        let head = editor.line_index_of(editor.cursor_position());
+        self.visual_marks = Some((anchor.min(head), anchor.max(head)));
+        self.mode = VimMode::Normal;
+        editor.clear_selection();
+    }
+
+    /// Visual mode in effect, also while typing a search started from visual mode
+    fn visual_mode(&self) -> Option<VimMode> {
+        let mode = match (&self.command_line, self.mode) {
+            (Some(line), VimMode::CommandLine) => line.return_mode,
+            (_, mode) => mode,
+        };
+        matches!(mode, VimMode::Visual | VimMode::VisualLine).then_some(mode)
+    }
+
+    /// Put the editor cursor back on the Vim cursor after [`VimEngine::update_selection`]
+    fn restore_visual_cursor(&mut self, editor: &mut EditorCore) -> Result<()> {
+        match self.visual_head.take() {
+            Some(head) => editor.set_cursor(head),
+            None => Ok(()),
+        }
+    }
+
+    /// Mirror the visual selection into the editor as an exclusive range
+    fn update_selection(&mut self, editor: &mut EditorCore) -> Result<()> {
+        let anchor = self.visual_anchor;
+        let head = editor.cursor_position();
+        self.visual_head = Some(head);
+        match self.visual_mode() {
+            Some(VimMode::Visual) if head >= anchor => editor.set_selection(anchor, head + 1),
+            Some(VimMode::Visual) => editor.set_selection(anchor + 1, head),
+            Some(VimMode::VisualLine) => {
+                let anchor_line = editor.line_index_of(anchor);
+                let head_line = editor.line_index_of(head);
+                if head_line >= anchor_line {
+                    let start = line_span(editor, anchor_line).start;
+                    editor.set_selection(start, line_span(editor, head_line).end)
+                } else {
+                    let end = line_span(editor, anchor_line).end;
+                    editor.set_selection(end, line_span(editor, head_line).start)
+                }
+            }
+            _ => Ok(()),
+        }
+    }
+
+    fn visual_region(&self, editor: &EditorCore) -> Region {
+        let anchor = self.visual_anchor;
+        let head = editor.cursor_position();
+        if self.mode == VimMode::VisualLine {
+            let (a, b) = (editor.line_index_of(anchor), editor.line_index_of(head));
+            Region::Lines(a.min(b), a.max(b))
+        } else {
+            let end = (anchor.max(head) + 1).min(editor.len());
+            Region::Chars(anchor.min(head)..end)
+        }
+    }
+
+    fn motion_target(
+        &mut self,
+        editor: &EditorCore,
+        from: usize,
+        motion: Motion,
+        count: Option<usize>,
+        operator: bool,
+    ) -> Option<(usize, MotionKind)> {
+        let n = count.unwrap_or(1);
+        let line = editor.line_index_of(from).min(last_line(editor));
+        let span = line_span(editor, line);
+        if !matches!(motion, Motion::Up | Motion::Down) {
+            self.column = None;
+        }
+
+        let result = match motion {
+            Motion::Left => (
+                from.saturating_sub(n).max(span.start),
+                MotionKind::Exclusive,
+            ),
+            Motion::Right => {
+                let max = if operator {
+                    span.end
+                } else {
+                    last_column(&span)
+                };
+                ((from + n).min(max), MotionKind::Exclusive)
+            }
+            Motion::Up | Motion::Down => {
+                let column = *self.column.get_or_insert(from - span.start);
+                let target_line = if motion == Motion::Up {
+                    line.checked_sub(n)?
+                } else {
+                    let target = line + n;
+                    if target > last_line(editor) {
+                        return None;
+                    }
+                    target
+                };
+                let target_span = line_span(editor, target_line);
+                // `$` leaves `column` at usize::MAX so `j`/`k` keep to line ends
+                let target = target_span
+                    .start
+                    .saturating_add(column)
+                    .min(last_column(&target_span));
+                (target, MotionKind::Linewise)
+            }
+            Motion::WordForward(big) => {
+                let mut pos = from;
+                for _ in 0..n {
+                    pos = word_forward(editor, pos, big);
+                }
+                (pos, MotionKind::Exclusive)
+            }
+            Motion::WordBackward(big) => {
+                let mut pos = from;
+                for _ in 0..n {
+                    pos = word_backward(editor, pos, big);
+                }
+                (pos, MotionKind::Exclusive)
+            }
+            Motion::WordEnd(big) => {
+                let mut pos = from;
+                for _ in 0..n {
+                    pos = word_end(editor, pos, big);
+                }
+                (pos, MotionKind::Inclusive)
+            }
+            Motion::LineStart => (span.start, MotionKind::Exclusive),
+            Motion::FirstNonBlank => (first_non_blank(editor, line), MotionKind::Exclusive),
+            Motion::LineEnd => {
+                self.column = Some(usize::MAX);
+                let target_line = (line + n - 1).min(last_line(editor));
+                let target_span = line_span(editor, target_line);
+                (last_column(&target_span), MotionKind::Inclusive)
+            }
+            Motion::FileStart | Motion::FileEnd => {
+                let default = if motion == Motion::FileStart {
+                    0
+                } else {
+                    last_line(editor)
+                };
+                let target_line = count
+                    .map(|c| c.saturating_sub(1))
+                    .unwrap_or(default)
+                    .min(last_line(editor));
+                (first_non_blank(editor, target_line), MotionKind::Linewise)
+            }
+            Motion::Find(find) => {
+                self.last_find = Some(find);
+                (
+                    find_in_line(editor, from, find, n, false)?,
+                    MotionKind::Inclusive,
+                )
+            }
+            Motion::RepeatFind(reverse) => {
+                let mut find = self.last_find?;
+                if reverse {
+                    find.forward = !find.forward;
+                }
+                (
+                    find_in_line(editor, from, find, n, true)?,
+                    MotionKind::Inclusive,
+                )
+            }
+            Motion::MatchPair => (match_pair(editor, from)?, MotionKind::Inclusive),
+            Motion::SearchNext(reverse) => {
+                let mut pos = from;
+                for _ in 0..n {
+                    pos = self.search_next(editor, pos, reverse).ok()??;
+                }
+                (pos, MotionKind::Exclusive)
+            }
+            Motion::ParagraphForward => {
+                let last = last_line(editor);
+                let mut l = line;
+                for _ in 0..n {
+                    while l < last && is_blank_line(editor, l) {
+                        l += 1;
+                    }
+                    while l < last && !is_blank_line(editor, l) {
+                        l += 1;
+                    }
+                }
+                let target = if is_blank_line(editor, l) {
+                    line_span(editor, l).start
+                } else {
+                    line_span(editor, l).end
+                };
+                (target, MotionKind::Exclusive)
+            }
+            Motion::ParagraphBackward => {
+                let mut l = line;
+                for _ in 0..n {
+                    while l > 0 && is_blank_line(editor, l) {
+                        l -= 1;
+                    }
+                    while l > 0 && !is_blank_line(editor, l) {
+                        l -= 1;
+                    }
+                }
+                let target = if is_blank_line(editor, l) {
+                    line_span(editor, l).start
+                } else {
+                    0
+                };
+                (target, MotionKind::Exclusive)
+            }
+        };
+        Some(result)
+    }
+
+    fn operator_region(
+        &mut self,
+        editor: &EditorCore,
+        op: Operator,
+        target: Target,
+        count: Option<usize>,
+    ) -> Option<Region> {
+        let cursor = editor.cursor_position();
+        let n = count.unwrap_or(1);
+        match target {
+            Target::Lines => {
+                let line = editor.line_index_of(cursor).min(last_line(editor));
+                Some(Region::Lines(line, (line + n - 1).min(last_line(editor))))
+            }
+            Target::Object(object) => text_object(editor, cursor, object, n),
+            Target::Motion(motion) => {
+                // `cw` on a word changes to the end of the word, like `ce`
+                let motion = match motion {
+                    Motion::WordForward(big)
+                        if op == Operator::Change
+                            && class_at(editor, cursor, big) != Class::Blank =>
+                    {
+                        if n == 1
+                            && class_at(editor, cursor + 1, big) != class_at(editor, cursor, big)
+                        {
+                            return Some(Region::Chars(cursor..cursor + 1));
+                        }
+                        Motion::WordEnd(big)
+                    }
+                    other => other,
+                };
+                let (target, kind) = self.motion_target(editor, cursor, motion, count, true)?;
+                let (start, end) = (cursor.min(target), cursor.max(target));
+                match kind {
+                    MotionKind::Linewise => Some(Region::Lines(
+                        editor.line_index_of(start),
+                        editor.line_index_of(end).min(last_line(editor)),
+                    )),
+                    MotionKind::Inclusive => {
+                        let line_end = line_span(editor, editor.line_index_of(end)).end;
+                        Some(Region::Chars(start..(end + 1).min(line_end.max(end))))
+                    }
+                    MotionKind::Exclusive => Some(exclusive_region(editor, start, end, motion)),
+                }
+            }
+        }
+    }
+
+    fn apply_operator(
+        &mut self,
+        editor: &mut EditorCore,
+        op: Operator,
+        region: Region,
+        register: Option<char>,
+    ) -> Result<()> {
+        match region {
+            Region::Chars(range) => {
+                let start = range.start;
+                match op {
+                    Operator::Yank => {
+                        let text = editor.text_range(range);
+                        self.store_register(register, text, false, true);
+                        editor.set_cursor(start)?;
+                    }
+                    Operator::Delete | Operator::Change => {
+                        if !range.is_empty() {
+                            let text = editor.text_range(range.clone());
+                            self.store_register(register, text, false, false);
+                            editor.replace(range, "")?;
+                        }
+                        editor.set_cursor(start)?;
+                        if op == Operator::Change {
+                            self.enter_insert(1, "");
+                        }
+                    }
+                    Operator::Indent | Operator::Outdent => {
+                        let last = editor.line_index_of(range.end.saturating_sub(1).max(start));
+                        self.shift_lines(editor, editor.line_index_of(start), last, op)?;
+                    }
+                }
+            }
+            Region::Lines(first, last) => {
+                let text = format!(
+                    "{}\n",
+                    editor.text_range(line_span(editor, first).start..line_span(editor, last).end)
+                );
+                match op {
+                    Operator::Yank => {
+                        self.store_register(register, text, true, true);
+                        if editor.line_index_of(editor.cursor_position()) != first {
+                            editor.set_cursor(line_span(editor, first).start)?;
+                        }
+                    }
+                    Operator::Delete => {
+                        self.store_register(register, text, true, false);
+                        editor.replace(lines_range(editor, first, last), "")?;
+                        let line = first.min(last_line(editor));
+                        editor.set_cursor(first_non_blank(editor, line))?;
+                    }
+                    Operator::Change => {
+                        self.store_register(register, text, true, false);
+                        let start = line_span(editor, first).start;
+                        editor.replace(start..line_span(editor, last).end, "")?;
+                        editor.set_cursor(start)?;
+                        self.enter_insert(1, "");
+                    }
+                    Operator::Indent | Operator::Outdent => {
+                        self.shift_lines(editor, first, last, op)?;
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn shift_lines(
+        &mut self,
+        editor: &mut EditorCore,
+        first: usize,
+        last: usize,
+        op: Operator,
+    ) -> Result<()> {
+        for line in first..=last {
+            let span = line_span(editor, line);
+            if span.is_empty() {
+                continue;
+            }
+            if op == Operator::Indent {
+                editor.replace(span.start..span.start, &" ".repeat(self.shiftwidth))?;
+            } else {
+                let content = editor.text_range(span.clone());
+                let remove = if content.starts_with('\t') {
+                    1
+                } else {
+                    content
+                        .chars()
+                        .take(self.shiftwidth)
+                        .take_while(|c| *c == ' ')
+                        .count()
+                };
+                editor.replace(span.start..span.start + remove, "")?;
+            }
+        }
+        editor.set_cursor(first_non_blank(editor, first))
+    }
+
+    fn put(
+        &mut self,
+        editor: &mut EditorCore,
+        register: Option<char>,
+        before: bool,
+        count: usize,
+    ) -> Result<()> {
+        let Some(content) = self.register(register.unwrap_or('"')).cloned() else {
+            return Ok(());
+        };
+        check_repeat_size(&content.text, count)?;
+        let cursor = editor.cursor_position();
+        let line = editor.line_index_of(cursor).min(last_line(editor));
+        let span = line_span(editor, line);
+        if content.linewise {
+            let lines = content.text.repeat(count);
+            let first = if before {
+                editor.replace(span.start..span.start, &lines)?;
+                line
+            } else {
+                let text = format!("\n{}", lines.strip_suffix('\n').unwrap_or(&lines));
+                editor.replace(span.end..span.end, &text)?;
+                line + 1
+            };
+            editor.set_cursor(first_non_blank(editor, first))
+        } else {
+            let at = if before || span.is_empty() {
+                cursor
+            } else {
+                (cursor + 1).min(span.end)
+            };
+            let inserted = editor.replace(at..at, &content.text.repeat(count))?;
+            editor.set_cursor(inserted.end.saturating_sub(1).max(inserted.start))
+        }
+    }
+
+    fn store_register(&mut self, name: Option<char>, text: String, linewise: bool, yank: bool) {
+        let register = Register { text, linewise };
+        match name {
+            Some('_') => return,
+            Some(c) if c.is_ascii_uppercase() => {
+                let entry = self.registers.entry(c.to_ascii_lowercase()).or_default();
+                if register.linewise && !entry.linewise && !entry.text.is_empty() {
+                    entry.text.push('\n');
+                }
+                entry.text.push_str(&register.text);
+                entry.linewise |= register.linewise;
+            }
+            Some(c) if c != '"' => {
+                self.registers.insert(c, register.clone());
+            }
+            _ => {
+                if yank {
+                    self.registers.insert('0', register.clone());
+                }
+            }
+        }
+        let unnamed = match name {
+            Some(c) if c.is_ascii_uppercase() => self.registers[&c.to_ascii_lowercase()].clone(),
+            _ => register,
+        };
+        self.registers.insert('"', unnamed);
+    }
+}
+
+fn is_register(c: char) -> bool {
+    c.is_ascii_alphanumeric() || c == '"' || c == '_'
+}
+
+/// Refuse to repeat `text` `count` times when the result would be too large
+fn check_repeat_size(text: &str, count: usize) -> Result<()> {
+    if text.len().saturating_mul(count) > MAX_REPEATED_BYTES {
+        return Err(Error::InvalidInput(format!(
+            "Repeating {} bytes {} times is too large",
+            text.len(),
+            count
+        )));
+    }
+    Ok(())
+}
+
+fn parse_count(keys: &[Key], mut i: usize) -> (Option<usize>, usize) {
+    let mut count: Option<usize> = None;
+    while let Some(Key::Char(c)) = keys.get(i) {
+        let Some(digit) = c.to_digit(10) else { break };
+        if digit == 0 && count.is_none() {
+            break;
+        }
+        count = Some(
+            count
+                .unwrap_or(0)
+                .saturating_mul(10)
+                .saturating_add(digit as usize)
+                .min(MAX_COUNT),
+        );
+        i += 1;
+    }
+    (count, i)
+}
+
+fn parse_motion(keys: &[Key], i: usize) -> Parse<Motion> {
+    let Some(&key) = keys.get(i) else {
+        return Parse::More;
+    };
+    let motion = match key {
+        Key::Char('h') | Key::Backspace => Motion::Left,
+        Key::Char('l') | Key::Char(' ') => Motion::Right,
+        Key::Char('j') | Key::Enter => Motion::Down,
+        Key::Char('k') => Motion::Up,
+        Key::Char('w') => Motion::WordForward(false),
+        Key::Char('W') => Motion::WordForward(true),
+        Key::Char('b') => Motion::WordBackward(false),
+        Key::Char('B') => Motion::WordBackward(true),
+        Key::Char('e') => Motion::WordEnd(false),
+        Key::Char('E') => Motion::WordEnd(true),
+        Key::Char('0') => Motion::LineStart,
+        Key::Char('^') => Motion::FirstNonBlank,
+        Key::Char('$') => Motion::LineEnd,
+        Key::Char('G') => Motion::FileEnd,
+        Key::Char(';') => Motion::RepeatFind(false),
+        Key::Char(',') => Motion::RepeatFind(true),
+        Key::Char('%') => Motion::MatchPair,
+        Key::Char('}') => Motion::ParagraphForward,
+        Key::Char('{') => Motion::ParagraphBackward,
+        Key::Char('n') => Motion::SearchNext(false),
+        Key::Char('N') => Motion::SearchNext(true),
+        Key::Char('g') => {
+            return match keys.get(i + 1) {
+                None => Parse::More,
+                Some(Key::Char('g')) => Parse::Done(Motion::FileStart),
+                _ => Parse::Invalid,
+            };
+        }
+        Key::Char(c @ ('f' | 'F' | 't' | 'T')) => {
+            return match keys.get(i + 1) {
+                None => Parse::More,
+                Some(Key::Char(ch)) => Parse::Done(Motion::Find(Find {
+                    ch: *ch,
+                    forward: c == 'f' || c == 't',
+                    till: c == 't' || c == 'T',
+                })),
+                _ => Parse::Invalid,
+            };
+        }
+        _ => return Parse::Invalid,
+    };
+    Parse::Done(motion)
+}
+
+fn parse_object(keys: &[Key], i: usize, around: bool) -> Parse<TextObject> {
+    let Some(&key) = keys.get(i) else {
+        return Parse::More;
+    };
+    let pair = |open, close| TextObject::Pair {
+        open,
+        close,
+        around,
+    };
+    let object = match key {
+        Key::Char('w') => TextObject::Word { big: false, around },
+        Key::Char('W') => TextObject::Word { big: true, around },
+        Key::Char(quote @ ('"' | '\'' | '`')) => TextObject::Quote { quote, around },
+        Key::Char('(' | ')' | 'b') => pair('(', ')'),
+        Key::Char('[' | ']') => pair('[', ']'),
+        Key::Char('{' | '}' | 'B') => pair('{', '}'),
+        Key::Char('<' | '>') => pair('<', '>'),
+        Key::Char('p') => TextObject::Paragraph { around },
+        _ => return Parse::Invalid,
+    };
+    Parse::Done(object)
+}
+
+fn parse_command(keys: &[Key], visual: bool, recording: bool) -> Parse<Command> {
+    let mut i = 0;
+    let mut register = None;
+    if keys.first() == Some(&Key::Char('"')) {
+        match keys.get(1) {
+            None => return Parse::More,
+            Some(Key::Char(c)) if is_register(*c) => register = Some(*c),
+            _ => return Parse::Invalid,
+        }
+        i = 2;
+    }
+    let (count, i) = parse_count(keys, i);
+    let Some(&key) = keys.get(i) else {
+        return Parse::More;
+    };
+    let next = i + 1;
+    let operate = |op, target| Action::Operate {
+        op,
+        count: None,
+        target,
+    };
+
+    let action = match key {
+        Key::Esc => Action::Escape,
+        Key::Ctrl('r') => Action::Redo,
+        Key::Char(c @ ('d' | 'c' | 'y' | '>' | '<')) => {
+            let op = match c {
+                'd' => Operator::Delete,
+                'c' => Operator::Change,
+                'y' => Operator::Yank,
+                '>' => Operator::Indent,
+                _ => Operator::Outdent,
+            };
+            if visual {
+                Action::VisualOperate(op)
+            } else {
+                let (count2, j) = parse_count(keys, next);
+                let target = match keys.get(j) {
+                    None => return Parse::More,
+                    Some(Key::Char(k)) if *k == c => Target::Lines,
+                    Some(Key::Char(k @ ('i' | 'a'))) => {
+                        match parse_object(keys, j + 1, *k == 'a') {
+                            Parse::Done(object) => Target::Object(object),
+                            Parse::More => return Parse::More,
+                            Parse::Invalid => return Parse::Invalid,
+                        }
+                    }
+                    Some(_) => match parse_motion(keys, j) {
+                        Parse::Done(motion) => Target::Motion(motion),
+                        Parse::More => return Parse::More,
+                        Parse::Invalid => return Parse::Invalid,
+                    },
+                };
+                Action::Operate {
+                    op,
+                    count: count2,
+                    target,
+                }
+            }
+        }
+        Key::Char(c @ ('i' | 'a')) if visual => match parse_object(keys, next, c == 'a') {
+            Parse::Done(object) => Action::VisualObject(object),
+            Parse::More => return Parse::More,
+            Parse::Invalid => return Parse::Invalid,
+        },
+        Key::Char('x') if visual => Action::VisualOperate(Operator::Delete),
+        Key::Char('s') if visual => Action::VisualOperate(Operator::Change),
+        Key::Char('o') if visual => Action::VisualSwap,
+        Key::Char('i') => Action::Insert(InsertAt::Before),
+        Key::Char('a') => Action::Insert(InsertAt::After),
+        Key::Char('I') => Action::Insert(InsertAt::LineStart),
+        Key::Char('A') => Action::Insert(InsertAt::LineEnd),
+        Key::Char('o') => Action::Insert(InsertAt::LineBelow),
+        Key::Char('O') => Action::Insert(InsertAt::LineAbove),
+        Key::Char('x') => operate(Operator::Delete, Target::Motion(Motion::Right)),
+        Key::Char('X') => operate(Operator::Delete, Target::Motion(Motion::Left)),
+        Key::Char('D') => operate(Operator::Delete, Target::Motion(Motion::LineEnd)),
+        Key::Char('C') => operate(Operator::Change, Target::Motion(Motion::LineEnd)),
+        Key::Char('s') => operate(Operator::Change, Target::Motion(Motion::Right)),
+        Key::Char('S') => operate(Operator::Change, Target::Lines),
+        Key::Char('Y') => operate(Operator::Yank, Target::Lines),
+        Key::Char('p') => Action::Put { before: false },
+        Key::Char('P') => Action::Put { before: true },
+        Key::Char('u') => Action::Undo,
+        Key::Char('.') => Action::Repeat,
+        Key::Char(c @ (':' | '/' | '?')) => Action::CommandLine(c),
+        Key::Char('v') => Action::Visual { linewise: false },
+        Key::Char('V') => Action::Visual { linewise: true },
+        Key::Char('q') if recording => Action::StopMacro,
+        Key::Char(c @ ('r' | 'q' | '@')) => match keys.get(next) {
+            None => return Parse::More,
+            Some(Key::Char(arg)) => match c {
+                'r' => Action::Replace(*arg),
+                'q' if arg.is_ascii_alphanumeric() => Action::RecordMacro(arg.to_ascii_lowercase()),
+                '@' if *arg == '@' => Action::PlayMacro(None),
+                '@' if arg.is_ascii_alphanumeric() => Action::PlayMacro(Some(*arg)),
+                _ => return Parse::Invalid,
+            },
+            Some(_) => return Parse::Invalid,
+        },
+        _ => match parse_motion(keys, i) {
+            Parse::Done(motion) => Action::Move(motion),
+            Parse::More => return Parse::More,
+            Parse::Invalid => return Parse::Invalid,
+        },
+    };
+
+    let allowed_in_visual = matches!(
+        action,
+        Action::Escape
+            | Action::Move(_)
+            | Action::VisualOperate(_)
+            | Action::VisualObject(_)
+            | Action::VisualSwap
+            | Action::Visual { .. }
+            | Action::CommandLine(_)
+    );
+    if visual && !allowed_in_visual {
+        return Parse::Invalid;
+    }
+    Parse::Done(Command {
+        register,
+        count,
+        action,
+        body: i,
+    })
+}
+
+/// Region of an exclusive motion, with Vim's adjustments for motions ending in column 0
+fn exclusive_region(editor: &EditorCore, start: usize, end: usize, motion: Motion) -> Region {
+    let start_line = editor.line_index_of(start);
+    let end_line = editor.line_index_of(end);
+    let at_line_start = end_line > start_line && end == line_span(editor, end_line).start;
+    if !at_line_start {
+        return Region::Chars(start..end);
+    }
+    // `dw` on the last word of a line stops at the end of that line
+    if matches!(motion, Motion::WordForward(_)) {
+        return Region::Chars(start..line_span(editor, end_line - 1).end);
+    }
+    if start <= first_non_blank(editor, start_line) {
+        Region::Lines(start_line, end_line - 1)
+    } else {
+        Region::Chars(start..line_span(editor, end_line - 1).end)
+    }
+}
+
+fn text_object(
+    editor: &EditorCore,
+    pos: usize,
+    object: TextObject,
+    count: usize,
+) -> Option<Region> {
+    match object {
+        TextObject::Word { big, around } => {
+            let span = line_span(editor, editor.line_index_of(pos));
+            if span.is_empty() {
+                return None;
+            }
+            let class = class_at(editor, pos, big);
+            let mut start = pos;
+            while start > span.start && class_at(editor, start - 1, big) == class {
+                start -= 1;
+            }
+            let mut end = run_end(editor, pos, span.end, big);
+            for _ in 1..count {
+                if end >= span.end {
+                    break;
+                }
+                end = run_end(editor, end, span.end, big);
+            }
+            if around {
+                if class != Class::Blank {
+                    if end < span.end && class_at(editor, end, big) == Class::Blank {
+                        end = run_end(editor, end, span.end, big);
+                    } else {
+                        while start > span.start && class_at(editor, start - 1, big) == Class::Blank
+                        {
+                            start -= 1;
+                        }
+                    }
+                } else if end < span.end {
+                    end = run_end(editor, end, span.end, big);
+                }
+            }
+            Some(Region::Chars(start..end))
+        }
+        TextObject::Quote { quote, around } => {
+            let span = line_span(editor, editor.line_index_of(pos));
+            let quote = quote.to_string();
+            let quotes: Vec<usize> = span
+                .clone()
+                .filter(|&i| {
+                    editor.grapheme_at(i).as_deref() == Some(quote.as_str())
+                        && (i == span.start || editor.grapheme_at(i - 1).as_deref() != Some("\\"))
+                })
+                .collect();
+            let (open, close) = quotes
+                .chunks_exact(2)
+                .map(|pair| (pair[0], pair[1]))
+                .find(|&(_, close)| pos <= close)?;
+            if around {
+                let mut end = close + 1;
+                let mut start = open;
+                if end < span.end && class_at(editor, end, false) == Class::Blank {
+                    end = run_end(editor, end, span.end, false);
+                } else {
+                    while start > span.start && class_at(editor, start - 1, false) == Class::Blank {
+                        start -= 1;
+                    }
+                }
+                Some(Region::Chars(start..end))
+            } else {
+                Some(Region::Chars(open + 1..close))
+            }
+        }
+        TextObject::Pair {
+            open,
+            close,
+            around,
+        } => {
+            let (open, close) = (open.to_string(), close.to_string());
+            let mut start = find_open(editor, pos, &open, &close)?;
+            for _ in 1..count {
+                start = unmatched_open_before(editor, start, &open, &close)?;
+            }
+            let end = find_close(editor, start, &open, &close)?;
+            if around {
+                Some(Region::Chars(start..end + 1))
+            } else {
+                Some(Region::Chars(start + 1..end))
+            }
+        }
+        TextObject::Paragraph { around } => {
+            let last = last_line(editor);
+            let line = editor.line_index_of(pos).min(last);
+            let blank = is_blank_line(editor, line);
+            let mut first = line;
+            while first > 0 && is_blank_line(editor, first - 1) == blank {
+                first -= 1;
+            }
+            let mut end = line;
+            let mut runs = count + usize::from(around && !blank);
+            loop {
+                let kind = is_blank_line(editor, end);
+                while end < last && is_blank_line(editor, end + 1) == kind {
+                    end += 1;
+                }
+                runs -= 1;
+                if runs == 0 || end >= last {
+                    break;
+                }
+                end += 1;
+            }
+            // `ap` at the end of the buffer takes the blank lines before the paragraph instead
+            if around && !blank && !is_blank_line(editor, end) {
+                while first > 0 && is_blank_line(editor, first - 1) {
+                    first -= 1;
+                }
+            }
+            Some(Region::Lines(first, end))
+        }
+    }
+}
+
+/// End (exclusive) of the run of same-class graphemes starting at `pos`
+fn run_end(editor: &EditorCore, pos: usize, limit: usize, big: bool) -> usize {
+    let class = class_at(editor, pos, big);
+    let mut end = pos + 1;
+    while end < limit && class_at(editor, end, big) == class {
+        end += 1;
+    }
+    end
+}
+
+/// `open` at `pos`, or else the nearest unmatched `open` before it (so a `close` at `pos`
+/// finds its partner)
+fn find_open(editor: &EditorCore, pos: usize, open: &str, close: &str) -> Option<usize> {
+    if editor.grapheme_at(pos).as_deref() == Some(open) {
+        return Some(pos);
+    }
+    unmatched_open_before(editor, pos, open, close)
+}
+
+fn unmatched_open_before(
+    editor: &EditorCore,
+    pos: usize,
+    open: &str,
+    close: &str,
+) -> Option<usize> {
+    let mut depth = 0usize;
+    for i in (0..pos).rev() {
+        let g = editor.grapheme_at(i);
+        if g.as_deref() == Some(close) {
+            depth += 1;
+        } else if g.as_deref() == Some(open) {
+            if depth == 0 {
+                return Some(i);
+            }
+            depth -= 1;
+        }
+    }
+    None
+}
+
+fn find_close(editor: &EditorCore, open_pos: usize, open: &str, close: &str) -> Option<usize> {
+    let mut depth = 0usize;
+    for i in open_pos + 1..editor.len() {
+        let g = editor.grapheme_at(i);
+        if g.as_deref() == Some(open) {
+            depth += 1;
+        } else if g.as_deref() == Some(close) {
+            if depth == 0 {
+                return Some(i);
+            }
+            depth -= 1;
+        }
+    }
+    None
+}
+
+/// `%`: the bracket at or after the cursor on this line, jumped to its partner
+fn match_pair(editor: &EditorCore, from: usize) -> Option<usize> {
+    let span = line_span(editor, editor.line_index_of(from));
+    let (at, bracket) = (from..span.end).find_map(|i| {
+        let g = editor.grapheme_at(i)?;
+        "()[]{}".contains(g.as_str()).then_some((i, g))
+    })?;
+    let (open, close) = match bracket.as_str() {
+        "(" | ")" => ("(", ")"),
+        "[" | "]" => ("[", "]"),
+        _ => ("{", "}"),
+    };
+    if bracket == open {
+        find_close(editor, at, open, close)
+    } else {
+        unmatched_open_before(editor, at, open, close)
+    }
+}
+
+fn find_in_line(
+    editor: &EditorCore,
+    from: usize,
+    find: Find,
+    count: usize,
+    repeat: bool,
+) -> Option<usize> {
+    let span = line_span(editor, editor.line_index_of(from));
+    let target = find.ch.to_string();
+    let matches = |i: &usize| editor.grapheme_at(*i).as_deref() == Some(target.as_str());
+    // Repeating `t`/`T` must not stop on the match right next to the cursor
+    let skip = usize::from(repeat && find.till);
+    let found = if find.forward {
+        (from + 1 + skip..span.end).filter(matches).nth(count - 1)?
+    } else {
+        (span.start..from.saturating_sub(skip))
+            .rev()
+            .filter(matches)
+            .nth(count - 1)?
+    };
+    Some(match (find.till, find.forward) {
+        (true, true) => found - 1,
+        (true, false) => found + 1,
+        _ => found,
+    })
+}
+
+fn word_forward(editor: &EditorCore, pos: usize, big: bool) -> usize {
+    let len = editor.len();
+    let mut p = pos;
+    let start = class_at(editor, p, big);
+    if start != Class::Blank {
+        while p < len && class_at(editor, p, big) == start {
+            p += 1;
+        }
+    }
+    while p < len && class_at(editor, p, big) == Class::Blank {
+        // An empty line counts as a word
+        if p != pos && is_break_at(editor, p) && (p == 0 || is_break_at(editor, p - 1)) {
+            return p;
+        }
+        p += 1;
+    }
+    p
+}
+
+fn word_backward(editor: &EditorCore, pos: usize, big: bool) -> usize {
+    if pos == 0 {
+        return 0;
+    }
+    let mut p = pos - 1;
+    while p > 0 && class_at(editor, p, big) == Class::Blank {
+        if is_break_at(editor, p) && is_break_at(editor, p - 1) {
+            return p;
+        }
+        p -= 1;
+    }
+    let class = class_at(editor, p, big);
+    if class == Class::Blank {
+        return p;
+    }
+    while p > 0 && class_at(editor, p - 1, big) == class {
+        p -= 1;
+    }
+    p
+}
+
+fn word_end(editor: &EditorCore, pos: usize, big: bool) -> usize {
+    let len = editor.len();
+    if len == 0 {
+        return 0;
+    }
+    let mut p = pos + 1;
+    while p < len && class_at(editor, p, big) == Class::Blank {
+        p += 1;
+    }
+    if p >= len {
+        return len - 1;
+    }
+    let class = class_at(editor, p, big);
+    while p + 1 < len && class_at(editor, p + 1, big) == class {
+        p += 1;
+    }
+    p
+}
+
+fn class_at(editor: &EditorCore, pos: usize, big: bool) -> Class {
+    let Some(c) = editor.grapheme_at(pos).and_then(|g| g.chars().next()) else {
+        return Class::Blank;
+    };
+    if c.is_whitespace() {
+        Class::Blank
+    } else if big || c.is_alphanumeric() || c == '_' {
+        Class::Word
+    } else {
+        Class::Punct
+    }
+}
+
+fn is_break_at(editor: &EditorCore, pos: usize) -> bool {
+    matches!(editor.grapheme_at(pos).as_deref(), Some("\n" | "\r\n"))
+}
+
+fn last_line(editor: &EditorCore) -> usize {
+    editor.line_count() - 1
+}
+
+fn line_span(editor: &EditorCore, line: usize) -> Range<usize> {
+    editor.line_range(line).unwrap_or_else(|| {
+        let len = editor.len();
+        len..len
+    })
+}
+
+/// Last position the normal-mode cursor may take on a line
+fn last_column(span: &Range<usize>) -> usize {
+    if span.is_empty() {
+        span.start
+    } else {
+        span.end - 1
+    }
+}
+
+fn first_non_blank(editor: &EditorCore, line: usize) -> usize {
+    let span = line_span(editor, line);
+    (span.start..span.end)
+        .find(|&i| class_at(editor, i, false) != Class::Blank)
+        .unwrap_or(last_column(&span))
+}
+
+fn is_blank_line(editor: &EditorCore, line: usize) -> bool {
+    editor
+        .line_content(line)
+        .is_none_or(|content| content.trim().is_empty())
+}
+
+/// Grapheme range of whole lines including their line break; the last line of the buffer
+/// takes the preceding break instead
+fn lines_range(editor: &EditorCore, first: usize, last: usize) -> Range<usize> {
+    let start = line_span(editor, first).start;
+    if last < last_line(editor) {
+        return start..line_span(editor, last + 1).start;
+    }
+    if first == 0 {
+        return 0..editor.len();
+    }
+    line_span(editor, first - 1).end..line_span(editor, last).end
+}
+
+/// Keep the normal-mode cursor on a character (not past the end of its line)
+fn clamp_cursor(editor: &mut EditorCore) {
+    let cursor = editor.cursor_position();
+    let line = editor.line_index_of(cursor).min(last_line(editor));
+    let span = line_span(editor, line);
+    let clamped = cursor.clamp(span.start, last_column(&span));
+    if clamped != cursor {
+        let _ = editor.set_cursor(clamped);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn run(content: &str, cursor: usize, keys: &str) -> (EditorCore, VimEngine) {
+        let mut editor = EditorCore::new(content);
+        editor.set_cursor(cursor).unwrap();
+        let mut vim = VimEngine::new();
+        vim.feed(&mut editor, keys).unwrap();
+        (editor, vim)
+    }
+
+    fn cursor_after(content: &str, cursor: usize, keys: &str) -> usize {
+        run(content, cursor, keys).0.cursor_position()
+    }
+
+    fn text_after(content: &str, cursor: usize, keys: &str) -> String {
+        run(content, cursor, keys).0.buffer_content()
+    }
+
+    #[test]
+    fn test_key_notation() {
+        let keys = Key::parse_sequence("ci(x<Esc><C-r><lt><CR>");
+        assert_eq!(keys[3], Key::Char('x'));
+        assert_eq!(keys[4], Key::Esc);
+        assert_eq!(keys[5], Key::Ctrl('r'));
+        assert_eq!(keys[6], Key::Char('<'));
+        assert_eq!(keys[7], Key::Enter);
+        assert_eq!(Key::to_notation(&keys), "ci(x<Esc><C-r><lt><CR>");
+    }
+
+    #[test]
+    fn test_motions() {
+        let text = "one two.three\n  four (five [six]) end\n\npara two";
+        assert_eq!(cursor_after(text, 0, "w"), 4);
+        assert_eq!(cursor_after(text, 0, "3w"), 8);
+        assert_eq!(cursor_after(text, 0, "W"), 4);
+        assert_eq!(cursor_after(text, 8, "b"), 7);
+        assert_eq!(cursor_after(text, 0, "e"), 2);
+        assert_eq!(cursor_after(text, 5, "0"), 0);
+        assert_eq!(cursor_after(text, 0, "$"), 12);
+        assert_eq!(cursor_after(text, 0, "j^"), 16);
+        assert_eq!(cursor_after(text, 20, "gg"), 0);
+        assert_eq!(cursor_after(text, 0, "G"), 39);
+        assert_eq!(cursor_after(text, 0, "2G"), 16);
+        assert_eq!(cursor_after(text, 0, "ft"), 4);
+        assert_eq!(cursor_after(text, 0, "tt"), 3);
+        assert_eq!(cursor_after(text, 0, "fe;"), 11);
+        assert_eq!(cursor_after(text, 14, "f(%"), 32);
+        assert_eq!(cursor_after(text, 32, "%"), 21);
+        assert_eq!(cursor_after(text, 0, "}"), 38);
+        assert_eq!(cursor_after(text, 42, "{"), 38);
+        // `j` keeps the column, clamped to shorter lines
+        assert_eq!(cursor_after("abcdef\nab\nabcdef", 4, "j"), 8);
+        assert_eq!(cursor_after("abcdef\nab\nabcdef", 4, "jj"), 14);
+        // After `$`, `j` and `k` go to the end of each line
+        assert_eq!(cursor_after("ab\ncd\nef", 0, "j$k"), 1);
+        assert_eq!(cursor_after("abc\nabcdef\nab", 0, "$j"), 9);
+    }
+
+    #[test]
+    fn test_operators_with_counts() {
+        assert_eq!(text_after("one two three four", 0, "d2w"), "three four");
+        assert_eq!(text_after("one two three four", 0, "2d2w"), "");
+        assert_eq!(text_after("one two\nthree", 4, "dw"), "one \nthree");
+        assert_eq!(text_after("a\nb\nc\nd", 2, "2dd"), "a\nd");
+        assert_eq!(text_after("a\nb\nc", 4, "dd"), "a\nb");
+        assert_eq!(
+            text_after("one two three", 4, "cwxyz<Esc>"),
+            "one xyz three"
+        );
+        assert_eq!(text_after("one two three", 4, "C!<Esc>"), "one !");
+        assert_eq!(text_after("abcdef", 1, "3x"), "aef");
+        assert_eq!(text_after("a\nb", 0, ">>"), "    a\nb");
+        assert_eq!(text_after("      a\n  b", 0, "<j"), "  a\nb");
+        assert_eq!(text_after("a\nb\nc", 0, "yjGp"), "a\nb\nc\na\nb");
+        assert_eq!(text_after("abc", 0, "ylp"), "aabc");
+        assert_eq!(text_after("abc", 1, "rx"), "axc");
+        assert_eq!(text_after("abc", 0, "3ix<Esc>"), "xxxabc");
+        // Backspace takes back a whole grapheme, combining mark included
+        assert_eq!(text_after("", 0, "2iae\u{301}<BS><Esc>"), "aa");
+        // Huge counts are clamped instead of exhausting memory
+        assert_eq!(text_after("abc", 0, "yl9999999999p").len(), 3 + MAX_COUNT);
+        assert_eq!(text_after("a\nb", 0, "2ox<Esc>"), "a\nx\nx\nb");
+    }
+
+    #[test]
+    fn test_text_objects() {
+        assert_eq!(text_after("foo bar baz", 5, "ciwqux<Esc>"), "foo qux baz");
+        assert_eq!(text_after("foo bar baz", 5, "daw"), "foo baz");
+        assert_eq!(
+            text_after("say \"hello there\" now", 8, "ci\"x<Esc>"),
+            "say \"x\" now"
+        );
+        assert_eq!(text_after("say \"hello\" now", 0, "da\""), "say now");
+        assert_eq!(text_after("f(a, (b), c) + 1", 3, "da("), "f + 1");
+        assert_eq!(text_after("f(a, (b), c)", 6, "di("), "f(a, (), c)");
+        assert_eq!(text_after("f(a, (b), c)", 6, "2di("), "f()");
+        assert_eq!(text_after("a\nb\n\nc\nd", 0, "dip"), "\nc\nd");
+        assert_eq!(text_after("a\nb\n\nc\nd", 0, "dap"), "c\nd");
+    }
+
+    #[test]
+    fn test_visual_modes() {
+        let (editor, vim) = run("hello world", 0, "vel");
+        assert_eq!(vim.mode(), VimMode::Visual);
+        assert_eq!(editor.selection(), Some((0, 6)));
+        assert_eq!(text_after("hello world", 0, "ved"), " world");
+        assert_eq!(
+            text_after("hello world", 6, "viwcthere<Esc>"),
+            "hello there"
+        );
+        assert_eq!(text_after("a\nb\nc", 0, "Vjd"), "c");
+        assert_eq!(text_after("a\nb\nc", 2, "VyP"), "a\nb\nb\nc");
+        assert_eq!(text_after("a\nb", 0, "Vj>"), "    a\n    b");
+
+        let (editor, vim) = run("hello", 0, "vl<Esc>");
+        assert_eq!(vim.mode(), VimMode::Normal);
+        assert_eq!(editor.selection(), None);
+    }
+
+    #[test]
+    fn test_registers() {
+        let (editor, vim) = run("one\ntwo", 0, "\"ayyj\"byy\"ap");
+        assert_eq!(editor.buffer_content(), "one\ntwo\none");
+        assert_eq!(vim.register('b').unwrap().text, "two\n");
+        assert!(vim.register('a').unwrap().linewise);
+
+        let (_, vim) = run("one two", 0, "\"ayw\"Ayw");
+        assert_eq!(vim.register('a').unwrap().text, "one one ");
+
+        // The black hole register leaves the unnamed register alone
+        let (editor, vim) = run("one two", 0, "yw\"_dwP");
+        assert_eq!(editor.buffer_content(), "one two");
+        assert_eq!(vim.register('0').unwrap().text, "one ");
+    }
+
+    #[test]
+    fn test_dot_repeat_and_undo() {
+        assert_eq!(text_after("a b c d e", 0, "dw.."), "d e");
+        assert_eq!(text_after("a b c d e", 0, "dw2."), "d e");
+        assert_eq!(text_after("foo bar", 0, "ciwx<Esc>w."), "x x");
+        assert_eq!(text_after("a\nb", 0, "Ax;<Esc>j."), "ax;\nbx;");
+
+        // A failed motion neither replaces the last change nor modifies the buffer
+        assert_eq!(text_after("abc def", 0, "0xdfz."), "c def");
+        let (_, vim) = run("abc def", 0, "0dfz");
+        assert!(!vim.is_modified());
+
+        // A fresh editor leaves the cursor past the end; normal mode pulls it back
+        let mut editor = EditorCore::new("abc def");
+        VimEngine::new().feed(&mut editor, "x").unwrap();
+        assert_eq!(editor.buffer_content(), "abc de");
+
+        // A whole insert session is one undo step
+        let (mut editor, mut vim) = run("abc", 0, "ihello <Esc>");
+        assert_eq!(editor.buffer_content(), "hello abc");
+        vim.feed(&mut editor, "u").unwrap();
+        assert_eq!(editor.buffer_content(), "abc");
+        vim.feed(&mut editor, "<C-r>").unwrap();
+        assert_eq!(editor.buffer_content(), "hello abc");
+    }
+
+    #[test]
+    fn test_macros() {
+        let (editor, vim) = run("1\n2\n3\n4", 0, "qaA!<Esc>jq2@a");
+        assert_eq!(editor.buffer_content(), "1!\n2!\n3!\n4");
+        assert_eq!(vim.register('a').unwrap().text, "A!<Esc>j");
+        assert_eq!(text_after("1\n2\n3", 0, "qqI-<Esc>jq@q@@"), "-1\n-2\n-3");
+    }
+}
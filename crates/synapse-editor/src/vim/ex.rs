@@ -0,0 +1,751 @@
+//! Command line of the Vim engine: `/` and `?` search and ex commands.
+//!
+//! Patterns use the syntax of the `regex` crate (`(?i)` for case-insensitive), not Vim's
+//! magic syntax. Matches are found on grapheme boundaries, so a pattern never splits an
+//! emoji or a letter with combining marks.
+//!
+//! Ex commands:
+//! - `:{range}s/pat/rep/[gieI]`: substitute; `\1`–`\9` and `&` in the replacement refer to
+//!   groups, `\n` or `\r` inserts a line break; an empty pattern reuses the last search and
+//!   `:s` alone repeats the last substitution
+//! - `:{range}g/pat/d` and `:{range}v/pat/d` (`:g!`): delete the (non-)matching lines
+//! - `:{range}d [x]`: delete lines into a register
+//! - `:{line}`: go to a line
+//! - `:w`, `:q`, `:q!`, `:wq`, `:x`: call the hooks set with [`VimEngine::set_write_hook`]
+//!   and [`VimEngine::set_quit_hook`]
+//!
+//! Ranges are `%`, `.`, `$`, line numbers, `'<` and `'>` (the last visual selection), each
+//! with optional `+N`/`-N` offsets, joined by `,`. Commands without a range act on the
+//! current line, except `:g` which defaults to the whole buffer.
+
+use std::fmt;
+
+use regex::{Regex, RegexBuilder};
+use synapse_core::{Error, Result};
+
+use super::{
+    Key, Operator, Region, VimEngine, VimMode, clamp_cursor, first_non_blank, last_line, line_span,
+};
+use crate::EditorCore;
+use crate::search::grapheme_matches;
+
+type WriteHook = Box<dyn FnMut(&str) -> Result<()> + Send>;
+type QuitHook = Box<dyn FnMut(bool) -> Result<()> + Send>;
+
+/// Callbacks for `:w` and `:q`
+#[derive(Default)]
+pub(super) struct Hooks {
+    write: Option<WriteHook>,
+    quit: Option<QuitHook>,
+}
+
+impl fmt::Debug for Hooks {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Hooks")
+            .field("write", &self.write.is_some())
+            .field("quit", &self.quit.is_some())
+            .finish()
+    }
+}
+
+/// Text being typed after `:`, `/` or `?`
+#[derive(Debug)]
+pub(super) struct CommandLine {
+    pub(super) prefix: char,
+    pub(super) text: String,
+    /// Mode to go back to when the line is run or cancelled
+    pub(super) return_mode: VimMode,
+}
+
+#[derive(Debug, Clone)]
+pub(super) struct Search {
+    pattern: String,
+    forward: bool,
+}
+
+#[derive(Debug, Clone)]
+pub(super) struct Substitution {
+    pattern: String,
+    replacement: String,
+    flags: SubstituteFlags,
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+struct SubstituteFlags {
+    global: bool,
+    ignore_case: bool,
+    /// `e`: no error when the pattern is not found
+    quiet: bool,
+}
+
+impl VimEngine {
+    /// Called by `:w`, `:wq` and `:x` with the buffer content, typically bound to
+    /// `NoteService::update_content` for the note being edited
+    pub fn set_write_hook(&mut self, hook: impl FnMut(&str) -> Result<()> + Send + 'static) {
+        self.hooks.write = Some(Box::new(hook));
+    }
+
+    /// Called by `:q`, `:wq` and `:x`; the argument is true when forced with `!`.
+    /// `:q` without `!` fails instead while there are changes since the last `:w`.
+    pub fn set_quit_hook(&mut self, hook: impl FnMut(bool) -> Result<()> + Send + 'static) {
+        self.hooks.quit = Some(Box::new(hook));
+    }
+
+    /// The command line being typed, including its `:`, `/` or `?` prefix
+    pub fn command_line(&self) -> Option<String> {
+        self.command_line
+            .as_ref()
+            .map(|line| format!("{}{}", line.prefix, line.text))
+    }
+
+    /// Whether the buffer changed since the last `:w`
+    pub fn is_modified(&self) -> bool {
+        self.modified
+    }
+
+    /// Run an ex command as if typed after `:` (without the colon)
+    pub fn execute_command(&mut self, editor: &mut EditorCore, command: &str) -> Result<()> {
+        let result = self.run_ex(editor, command);
+        clamp_cursor(editor);
+        result
+    }
+
+    pub(super) fn open_command_line(
+        &mut self,
+        editor: &mut EditorCore,
+        prefix: char,
+        count: Option<usize>,
+    ) {
+        let mut text = String::new();
+        let mut return_mode = self.mode;
+        if prefix == ':' {
+            if self.mode != VimMode::Normal {
+                self.exit_visual(editor);
+                text = "'<,'>".to_string();
+            } else if let Some(n) = count {
+                text = if n == 1 {
+                    ".".to_string()
+                } else {
+                    format!(".,.+{}", n - 1)
+                };
+            }
+            return_mode = VimMode::Normal;
+        }
+        self.command_line = Some(CommandLine {
+            prefix,
+            text,
+            return_mode,
+        });
+        self.mode = VimMode::CommandLine;
+    }
+
+    pub(super) fn command_line_key(&mut self, editor: &mut EditorCore, key: Key) -> Result<()> {
+        let Some(line) = &mut self.command_line else {
+            self.mode = VimMode::Normal;
+            return Ok(());
+        };
+        match key {
+            Key::Char(c) => line.text.push(c),
+            Key::Tab => line.text.push('\t'),
+            Key::Backspace if !line.text.is_empty() => {
+                line.text.pop();
+            }
+            Key::Backspace | Key::Esc | Key::Ctrl('c') => {
+                self.restore_visual_cursor(editor)?;
+                let line = self.command_line.take().expect("command line is open");
+                self.mode = line.return_mode;
+                if self.visual_mode().is_some() {
+                    self.update_selection(editor)?;
+                }
+            }
+            Key::Enter => {
+                self.restore_visual_cursor(editor)?;
+                let line = self.command_line.take().expect("command line is open");
+                self.mode = line.return_mode;
+                let result = match line.prefix {
+                    ':' => self.run_ex(editor, &line.text),
+                    prefix => self.run_search(editor, &line.text, prefix == '/'),
+                };
+                clamp_cursor(editor);
+                if self.visual_mode().is_some() {
+                    self.update_selection(editor)?;
+                }
+                return result;
+            }
+            Key::Ctrl(_) => {}
+        }
+        Ok(())
+    }
+
+    /// Start of the next match of the last search, wrapping around the buffer
+    pub(super) fn search_next(
+        &self,
+        editor: &EditorCore,
+        from: usize,
+        reverse: bool,
+    ) -> Result<Option<usize>> {
+        let Some(search) = &self.last_search else {
+            return Err(Error::InvalidInput(
+                "No previous search pattern".to_string(),
+            ));
+        };
+        let regex = build_regex(&search.pattern, false)?;
+        let starts: Vec<usize> = editor
+            .find_regex(&regex)
+            .into_iter()
+            .map(|range| range.start)
+            .collect();
+        let found = if search.forward != reverse {
+            starts
+                .iter()
+                .find(|&&start| start > from)
+                .or(starts.first())
+        } else {
+            starts
+                .iter()
+                .rev()
+                .find(|&&start| start < from)
+                .or(starts.last())
+        };
+        Ok(found.copied())
+    }
+
+    fn run_search(&mut self, editor: &mut EditorCore, pattern: &str, forward: bool) -> Result<()> {
+        let pattern = self.pattern_or_last(pattern)?;
+        self.last_search = Some(Search {
+            pattern: pattern.clone(),
+            forward,
+        });
+        match self.search_next(editor, editor.cursor_position(), false)? {
+            Some(position) => editor.set_cursor(position),
+            None => Err(Error::NotFound(format!("Pattern not found: {}", pattern))),
+        }
+    }
+
+    fn run_ex(&mut self, editor: &mut EditorCore, input: &str) -> Result<()> {
+        let input = input.trim_start_matches([':', ' ']);
+        let (range, rest) = self.parse_range(editor, input)?;
+        let rest = rest.trim_start();
+        let name_len = rest
+            .find(|c: char| !c.is_ascii_alphabetic())
+            .unwrap_or(rest.len());
+        let (name, mut args) = rest.split_at(name_len);
+        let bang = args.starts_with('!');
+        if bang {
+            args = &args[1..];
+        }
+        let current = editor
+            .line_index_of(editor.cursor_position())
+            .min(last_line(editor));
+        let lines = range.unwrap_or((current, current));
+        if !name.is_empty() && lines.1 > last_line(editor) {
+            return Err(Error::InvalidInput("Invalid range".to_string()));
+        }
+
+        match name {
+            // A line past the end goes to the last line, like Vim
+            "" => match range {
+                Some((_, line)) => {
+                    editor.set_cursor(first_non_blank(editor, line.min(last_line(editor))))
+                }
+                None => Ok(()),
+            },
+            "s" | "substitute" => self.substitute(editor, lines, args),
+            "g" | "global" | "v" | "vglobal" => {
+                let lines = range.unwrap_or((0, last_line(editor)));
+                self.global(editor, lines, args, bang || name.starts_with('v'))
+            }
+            "d" | "delete" => {
+                let register = args.trim().chars().next();
+                self.in_group(editor, |vim, editor| {
+                    vim.apply_operator(
+                        editor,
+                        Operator::Delete,
+                        Region::Lines(lines.0, lines.1),
+                        register,
+                    )
+                })
+            }
+            "w" | "write" => self.write(editor),
+            "q" | "quit" => self.quit(bang),
+            "wq" | "x" | "xit" => {
+                self.write(editor)?;
+                self.quit(bang)
+            }
+            _ => Err(Error::InvalidInput(format!(
+                "Not an editor command: {}",
+                rest
+            ))),
+        }
+    }
+
+    fn write(&mut self, editor: &EditorCore) -> Result<()> {
+        let Some(hook) = &mut self.hooks.write else {
+            return Err(Error::InvalidInput("No write hook set".to_string()));
+        };
+        hook(&editor.buffer_content())?;
+        self.modified = false;
+        Ok(())
+    }
+
+    fn quit(&mut self, force: bool) -> Result<()> {
+        if self.modified && !force {
+            return Err(Error::InvalidInput(
+                "No write since last change (add ! to override)".to_string(),
+            ));
+        }
+        match &mut self.hooks.quit {
+            Some(hook) => hook(force),
+            None => Ok(()),
+        }
+    }
+
+    fn substitute(
+        &mut self,
+        editor: &mut EditorCore,
+        (first, last): (usize, usize),
+        args: &str,
+    ) -> Result<()> {
+        let substitution = if args.trim().is_empty() {
+            self.last_substitution
+                .clone()
+                .ok_or_else(|| Error::InvalidInput("No previous substitute".to_string()))?
+        } else {
+            parse_substitution(args)?
+        };
+        let pattern = self.pattern_or_last(&substitution.pattern)?;
+        let flags = substitution.flags;
+        let regex = build_regex(&pattern, flags.ignore_case)?;
+        let template = vim_replacement(&substitution.replacement);
+        self.set_search_pattern(&pattern);
+        self.last_substitution = Some(substitution);
+
+        let mut count = 0;
+        let mut last_changed = None;
+        self.in_group(editor, |_, editor| {
+            // Replacements may insert line breaks; `added` keeps the range on the original lines
+            let mut added = 0;
+            for line in first..=last {
+                let index = line + added;
+                let content = editor.line_content(index).unwrap_or_default();
+                let matches = grapheme_matches(&regex, &content);
+                if matches.is_empty() {
+                    continue;
+                }
+                let limit = if flags.global { matches.len() } else { 1 };
+                let mut replaced = String::new();
+                let mut copied = 0;
+                for (caps, _) in matches.iter().take(limit) {
+                    let whole = caps.get(0).expect("group 0 is the whole match");
+                    replaced.push_str(&content[copied..whole.start()]);
+                    caps.expand(&template, &mut replaced);
+                    copied = whole.end();
+                    count += 1;
+                }
+                replaced.push_str(&content[copied..]);
+                editor.replace(line_span(editor, index), &replaced)?;
+                added += replaced.matches('\n').count();
+                last_changed = Some(line + added);
+            }
+            Ok(())
+        })?;
+
+        match last_changed {
+            Some(line) => editor.set_cursor(first_non_blank(editor, line)),
+            None if flags.quiet => Ok(()),
+            None => Err(Error::NotFound(format!("Pattern not found: {}", pattern))),
+        }
+    }
+
+    fn global(
+        &mut self,
+        editor: &mut EditorCore,
+        (first, last): (usize, usize),
+        args: &str,
+        invert: bool,
+    ) -> Result<()> {
+        let delimiter = delimiter(args)?;
+        let (pattern, rest) = split_delimited(&args[delimiter.len_utf8()..], delimiter);
+        let command = rest.unwrap_or_default().trim();
+        if !matches!(command, "d" | "delete") {
+            return Err(Error::InvalidInput(format!(
+                "Unsupported command for :g: {}",
+                command
+            )));
+        }
+        let pattern = self.pattern_or_last(&pattern)?;
+        let regex = build_regex(&pattern, false)?;
+        self.set_search_pattern(&pattern);
+
+        let lines: Vec<usize> = (first..=last)
+            .filter(|&line| {
+                let content = editor.line_content(line).unwrap_or_default();
+                grapheme_matches(&regex, &content).is_empty() == invert
+            })
+            .collect();
+        if lines.is_empty() {
+            return Err(Error::NotFound(format!("Pattern not found: {}", pattern)));
+        }
+        self.in_group(editor, |vim, editor| {
+            for (deleted, line) in lines.iter().enumerate() {
+                let index = line - deleted;
+                vim.apply_operator(editor, Operator::Delete, Region::Lines(index, index), None)?;
+            }
+            Ok(())
+        })
+    }
+
+    /// Run `f` as one undo step that marks the buffer modified
+    fn in_group(
+        &mut self,
+        editor: &mut EditorCore,
+        f: impl FnOnce(&mut Self, &mut EditorCore) -> Result<()>,
+    ) -> Result<()> {
+        editor.begin_group();
+        let result = f(self, editor);
+        editor.end_group();
+        self.modified = true;
+        result
+    }
+
+    fn pattern_or_last(&self, pattern: &str) -> Result<String> {
+        if !pattern.is_empty() {
+            return Ok(pattern.to_string());
+        }
+        self.last_search
+            .as_ref()
+            .map(|search| search.pattern.clone())
+            .ok_or_else(|| Error::InvalidInput("No previous search pattern".to_string()))
+    }
+
+    /// Remember a pattern for `n`/`N`, keeping the search direction
+    fn set_search_pattern(&mut self, pattern: &str) {
+        let forward = self
+            .last_search
+            .as_ref()
+            .is_none_or(|search| search.forward);
+        self.last_search = Some(Search {
+            pattern: pattern.to_string(),
+            forward,
+        });
+    }
+
+    fn parse_range<'a>(
+        &self,
+        editor: &EditorCore,
+        input: &'a str,
+    ) -> Result<(Option<(usize, usize)>, &'a str)> {
+        if let Some(rest) = input.strip_prefix('%') {
+            return Ok((Some((0, last_line(editor))), rest));
+        }
+        let (first, rest) = self.parse_address(editor, input)?;
+        let Some(rest_after_comma) = rest.trim_start().strip_prefix([',', ';']) else {
+            return Ok((first.map(|line| (line, line)), rest));
+        };
+        let current = editor
+            .line_index_of(editor.cursor_position())
+            .min(last_line(editor));
+        let first = first.unwrap_or(current);
+        let (second, rest) = self.parse_address(editor, rest_after_comma)?;
+        let second = second.unwrap_or(current);
+        Ok((Some((first.min(second), first.max(second))), rest))
+    }
+
+    fn parse_address<'a>(
+        &self,
+        editor: &EditorCore,
+        input: &'a str,
+    ) -> Result<(Option<usize>, &'a str)> {
+        let current = editor
+            .line_index_of(editor.cursor_position())
+            .min(last_line(editor)) as i64;
+        let mut rest = input.trim_start();
+        let mut line = if let Some(after) = rest.strip_prefix('.') {
+            rest = after;
+            Some(current)
+        } else if let Some(after) = rest.strip_prefix('$') {
+            rest = after;
+            Some(last_line(editor) as i64)
+        } else if let Some(after) = rest.strip_prefix("'<").or(rest.strip_prefix("'>")) {
+            let (start, end) = self
+                .visual_marks
+                .ok_or_else(|| Error::InvalidInput("Mark not set".to_string()))?;
+            let mark = if rest.starts_with("'<") { start } else { end };
+            rest = after;
+            Some(mark as i64)
+        } else {
+            let (number, after) = take_number(rest);
+            rest = after;
+            number.map(|n| n as i64 - 1)
+        };
+        while let Some(sign) = rest.chars().next().filter(|c| matches!(c, '+' | '-')) {
+            let (number, after) = take_number(&rest[1..]);
+            let offset = number.unwrap_or(1) as i64;
+            let base = line.unwrap_or(current);
+            line = Some(if sign == '+' {
+                base + offset
+            } else {
+                base - offset
+            });
+            rest = after;
+        }
+        match line {
+            Some(line) if line < -1 => Err(Error::InvalidInput("Invalid range".to_string())),
+            // Line 0 is accepted as the first line, like `:0`. Lines past the end are checked
+            // by the command, since `:99` alone goes to the last line.
+            Some(line) => Ok((Some(line.max(0) as usize), rest)),
+            None => Ok((None, rest)),
+        }
+    }
+}
+
+fn take_number(input: &str) -> (Option<usize>, &str) {
+    let digits = input
+        .find(|c: char| !c.is_ascii_digit())
+        .unwrap_or(input.len());
+    (input[..digits].parse().ok(), &input[digits..])
+}
+
+fn build_regex(pattern: &str, ignore_case: bool) -> Result<Regex> {
+    RegexBuilder::new(pattern)
+        .case_insensitive(ignore_case)
+        .multi_line(true)
+        .build()
+        .map_err(|e| Error::InvalidInput(format!("Invalid pattern: {}", e)))
+}
+
+fn delimiter(args: &str) -> Result<char> {
+    args.chars()
+        .next()
+        .filter(|c| !c.is_alphanumeric() && !c.is_whitespace() && !matches!(c, '\\' | '"' | '|'))
+        .ok_or_else(|| Error::InvalidInput("Missing pattern delimiter".to_string()))
+}
+
+/// Split at the first unescaped `delimiter`; `\<delimiter>` becomes the delimiter, other
+/// escapes are kept. Returns the part and what follows the delimiter, if there was one.
+fn split_delimited(input: &str, delimiter: char) -> (String, Option<&str>) {
+    let mut part = String::new();
+    let mut chars = input.char_indices();
+    while let Some((i, c)) = chars.next() {
+        if c == delimiter {
+            return (part, Some(&input[i + c.len_utf8()..]));
+        }
+        if c == '\\'
+            && let Some((_, next)) = chars.next()
+        {
+            if next != delimiter {
+                part.push('\\');
+            }
+            part.push(next);
+            continue;
+        }
+        part.push(c);
+    }
+    (part, None)
+}
+
+fn parse_substitution(args: &str) -> Result<Substitution> {
+    let delimiter = delimiter(args)?;
+    let (pattern, rest) = split_delimited(&args[delimiter.len_utf8()..], delimiter);
+    let (replacement, rest) = split_delimited(rest.unwrap_or_default(), delimiter);
+    let mut flags = SubstituteFlags::default();
+    for flag in rest.unwrap_or_default().trim().chars() {
+        match flag {
+            'g' => flags.global = true,
+            'i' => flags.ignore_case = true,
+            'I' => flags.ignore_case = false,
+            'e' => flags.quiet = true,
+            other => {
+                return Err(Error::InvalidInput(format!(
+                    "Unsupported substitute flag: {}",
+                    other
+                )));
+            }
+        }
+    }
+    Ok(Substitution {
+        pattern,
+        replacement,
+        flags,
+    })
+}
+
+/// Translate a Vim replacement (`\1`, `&`, `\n`) into a `regex` expansion template
+fn vim_replacement(replacement: &str) -> String {
+    let mut template = String::new();
+    let mut chars = replacement.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => match chars.next() {
+                Some(d @ '0'..='9') => template.push_str(&format!("${{{}}}", d)),
+                Some('n' | 'r') => template.push('\n'),
+                Some('t') => template.push('\t'),
+                Some('$') => template.push_str("$$"),
+                Some(other) => template.push(other),
+                None => template.push('\\'),
+            },
+            '&' => template.push_str("${0}"),
+            '$' => template.push_str("$$"),
+            c => template.push(c),
+        }
+    }
+    template
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{Arc, Mutex};
+
+    use super::*;
+
+    fn run(content: &str, cursor: usize, keys: &str) -> (EditorCore, VimEngine, Result<()>) {
+        let mut editor = EditorCore::new(content);
+        editor.set_cursor(cursor).unwrap();
+        let mut vim = VimEngine::new();
+        let result = vim.feed(&mut editor, keys);
+        (editor, vim, result)
+    }
+
+    fn text_after(content: &str, cursor: usize, keys: &str) -> String {
+        let (editor, _, result) = run(content, cursor, keys);
+        result.unwrap();
+        editor.buffer_content()
+    }
+
+    #[test]
+    fn test_search_and_repeat() {
+        let text = "foo bar\nbaz foo\nfoo";
+        let (editor, vim, _) = run(text, 0, "/foo<CR>");
+        assert_eq!(editor.cursor_position(), 12);
+        assert_eq!(vim.mode(), VimMode::Normal);
+        assert_eq!(run(text, 0, "/foo<CR>n").0.cursor_position(), 16);
+        // Wraps around to the first match
+        assert_eq!(run(text, 0, "/foo<CR>nn").0.cursor_position(), 0);
+        assert_eq!(run(text, 0, "/foo<CR>nN").0.cursor_position(), 12);
+        assert_eq!(run(text, 16, "?ba<CR>").0.cursor_position(), 8);
+        assert_eq!(run(text, 16, "?ba<CR>n").0.cursor_position(), 4);
+        // `n` is a motion for operators too
+        assert_eq!(text_after("a b x c", 0, "/x<CR>ggdn"), "x c");
+
+        let (_, vim, result) = run(text, 0, "/missing<CR>");
+        assert!(matches!(result, Err(Error::NotFound(_))));
+        assert_eq!(vim.mode(), VimMode::Normal);
+    }
+
+    #[test]
+    fn test_search_uses_grapheme_positions() {
+        let text = "👨‍👩‍👧 cafe\u{301} café";
+        // "fe" would end inside the decomposed "e\u{301}" cluster
+        assert_eq!(run(text, 0, "/f.<CR>").0.cursor_position(), 9);
+        assert_eq!(run(text, 0, "/caf<CR>n").0.cursor_position(), 7);
+    }
+
+    #[test]
+    fn test_command_line_editing() {
+        let (_, vim, _) = run("abc", 0, ":s/b/x");
+        assert_eq!(vim.mode(), VimMode::CommandLine);
+        assert_eq!(vim.command_line().as_deref(), Some(":s/b/x"));
+        let (editor, vim, _) = run("abc", 0, ":s/b/x<Esc>");
+        assert_eq!(vim.mode(), VimMode::Normal);
+        assert_eq!(editor.buffer_content(), "abc");
+        assert_eq!(text_after("abc", 0, ":s/b/xy<BS><CR>"), "axc");
+    }
+
+    #[test]
+    fn test_substitute() {
+        let text = "a-a\na-a\na-a";
+        assert_eq!(text_after(text, 0, ":s/a/b/<CR>"), "b-a\na-a\na-a");
+        assert_eq!(text_after(text, 0, ":s/a/b/g<CR>"), "b-b\na-a\na-a");
+        assert_eq!(text_after(text, 0, ":%s/a/b/g<CR>"), "b-b\nb-b\nb-b");
+        assert_eq!(text_after(text, 0, ":2,$s/-/+/<CR>"), "a-a\na+a\na+a");
+        assert_eq!(text_after(text, 4, ":.,.+1s/a$/z/<CR>"), "a-a\na-z\na-z");
+        assert_eq!(text_after(text, 0, "Vj:s/a/X/<CR>"), "X-a\nX-a\na-a");
+        assert_eq!(
+            text_after("John Smith", 0, r":s/(\w+) (\w+)/\2, \1<CR>"),
+            "Smith, John"
+        );
+        assert_eq!(text_after("cost 5", 0, ":s/\\d/[&$]/<CR>"), "cost [5$]");
+        assert_eq!(
+            text_after("a,b\nc", 0, r":s/,/\n/<CR>:%s/c/C/<CR>"),
+            "a\nb\nC"
+        );
+        assert_eq!(text_after("path a/b", 0, r":s#a/b#c\#d#<CR>"), "path c#d");
+        assert_eq!(text_after("ABC", 0, ":s/b/x/i<CR>"), "AxC");
+        // Empty pattern reuses the last search; `:s` repeats the last substitution
+        assert_eq!(
+            text_after("ab ab\nab", 0, "/b<CR>:s//X/<CR>j:s<CR>"),
+            "aX ab\naX"
+        );
+
+        let (editor, _, result) = run(text, 0, ":%s/q/r/<CR>");
+        assert!(matches!(result, Err(Error::NotFound(_))));
+        assert_eq!(editor.buffer_content(), text);
+        assert!(run(text, 0, ":%s/q/r/e<CR>").2.is_ok());
+        assert!(matches!(
+            run(text, 0, ":9s/a/b/<CR>").2,
+            Err(Error::InvalidInput(_))
+        ));
+
+        // A whole substitution is one undo step
+        assert_eq!(text_after(text, 0, ":%s/a/b/g<CR>u"), text);
+    }
+
+    #[test]
+    fn test_global_delete_and_goto() {
+        let text = "keep 1\ndrop\nkeep 2\ndrop\n";
+        assert_eq!(text_after(text, 0, ":g/drop/d<CR>"), "keep 1\nkeep 2\n");
+        assert_eq!(text_after(text, 0, ":v/drop/d<CR>"), "drop\ndrop\n");
+        assert_eq!(text_after(text, 0, ":g!/drop/d<CR>"), "drop\ndrop\n");
+        assert_eq!(text_after(text, 0, ":g/drop/d<CR>u"), text);
+        assert_eq!(text_after("a\nb\nc\nd", 0, ":2,3d<CR>"), "a\nd");
+
+        let (editor, _, _) = run("a\n  b\nc", 0, ":2<CR>");
+        assert_eq!(editor.cursor_position(), 4);
+        let (editor, _, result) = run("a\n  b\nc", 0, ":99<CR>");
+        result.unwrap();
+        assert_eq!(editor.cursor_position(), 6);
+        assert!(run("a\nb\nc", 0, ":2,99d<CR>").2.is_err());
+    }
+
+    #[test]
+    fn test_write_and_quit_hooks() {
+        // Hooks are `Send`, so an engine can move to another thread
+        fn assert_send<T: Send>() {}
+        assert_send::<VimEngine>();
+
+        let written = Arc::new(Mutex::new(Vec::new()));
+        let quit = Arc::new(Mutex::new(None));
+        let mut editor = EditorCore::new("note");
+        editor.set_cursor(0).unwrap();
+        let mut vim = VimEngine::new();
+        let sink = Arc::clone(&written);
+        vim.set_write_hook(move |content| {
+            sink.lock().unwrap().push(content.to_string());
+            Ok(())
+        });
+        let sink = Arc::clone(&quit);
+        vim.set_quit_hook(move |force| {
+            *sink.lock().unwrap() = Some(force);
+            Ok(())
+        });
+
+        vim.feed(&mut editor, "iA <Esc>").unwrap();
+        assert!(vim.is_modified());
+        assert!(vim.feed(&mut editor, ":q<CR>").is_err());
+        assert_eq!(*quit.lock().unwrap(), None);
+
+        vim.feed(&mut editor, ":w<CR>").unwrap();
+        assert_eq!(*written.lock().unwrap(), vec!["A note".to_string()]);
+        assert!(!vim.is_modified());
+        vim.feed(&mut editor, ":q<CR>").unwrap();
+        assert_eq!(*quit.lock().unwrap(), Some(false));
+
+        vim.feed(&mut editor, "x:q!<CR>").unwrap();
+        assert_eq!(*quit.lock().unwrap(), Some(true));
+        vim.execute_command(&mut editor, "wq").unwrap();
+        assert_eq!(
+            written.lock().unwrap().last().map(String::as_str),
+            Some("Anote")
+        );
+    }
+}
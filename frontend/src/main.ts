@@ -13,13 +13,25 @@ interface Note {
   deleted_at: number | null;
 }
 
+interface Page<T> {
+  items: T[];
+  next_cursor: string | null;
+  total_estimate: number;
+}
+
 interface NoteWithContent {
   note: Note;
   content: string;
 }
 
+// Notes fetched per page of the note list
+const PAGE_SIZE = 50;
+
 // App state
 let notes: Note[] = [];
+// Cursor of the next page of the note list, or null once it is fully loaded
+let nextCursor: string | null = null;
+let loadingPage = false;
 let currentNote: NoteWithContent | null = null;
 let isNewNote = false;
 
@@ -35,9 +47,51 @@ const el = {
   btnDelete: () => document.getElementById('btn-delete')!,
 };
 
-async function listNotes(): Promise<Note[]> {
-  const json = await invoke<string>('list_notes', { include_deleted: false });
-  return JSON.parse(json) as Note[];
+async function listNotes(cursor: string | null): Promise<Page<Note>> {
+  const json = await invoke<string>('list_notes', { include_deleted: false, limit: PAGE_SIZE, cursor });
+  return JSON.parse(json) as Page<Note>;
+}
+
+// Load the first page of the note list again, e.g. after a note changed
+async function reloadNotes() {
+  const page = await listNotes(null);
+  notes = page.items;
+  nextCursor = page.next_cursor;
+  renderNoteList();
+  await fillNoteList();
+}
+
+// Append the next page of the note list; false if there was none or one is already loading
+async function loadMoreNotes(): Promise<boolean> {
+  const cursor = nextCursor;
+  if (!cursor || loadingPage) return false;
+  loadingPage = true;
+  try {
+    const page = await listNotes(cursor);
+    // The list was reloaded meanwhile
+    if (nextCursor !== cursor) return false;
+    notes.push(...page.items);
+    nextCursor = page.next_cursor;
+    renderNoteList();
+    return true;
+  } finally {
+    loadingPage = false;
+  }
+}
+
+// Load pages until the list can scroll, so scrolling can reach the next page
+async function fillNoteList() {
+  const ul = el.noteList();
+  while (ul.scrollHeight <= ul.clientHeight && (await loadMoreNotes())) {
+    // Keep loading
+  }
+}
+
+function onNoteListScroll() {
+  const ul = el.noteList();
+  if (ul.scrollTop + ul.clientHeight >= ul.scrollHeight - 100) {
+    loadMoreNotes().catch((e) => setStatus('Error: ' + String(e), true));
+  }
 }
 
 async function getNote(id: string): Promise<NoteWithContent> {
@@ -122,14 +176,13 @@ async function saveNote() {
     setStatus('Saving…');
     if (isNewNote) {
       const created = await createNote(title || 'Untitled', content);
-      notes = await listNotes();
+      await reloadNotes();
       await selectNote(created.id);
       setStatus('Created');
     } else if (currentNote) {
       await updateNote(currentNote.note.id, title || undefined, content);
       currentNote = { note: { ...currentNote.note, title: title || 'Untitled' }, content };
-      notes = await listNotes();
-      renderNoteList();
+      await reloadNotes();
       setStatus('Saved');
     }
   } catch (e) {
@@ -143,7 +196,7 @@ async function deleteCurrentNote() {
   try {
     setStatus('Deleting…');
     await deleteNote(currentNote.note.id);
-    notes = await listNotes();
+    await reloadNotes();
     clearEditor();
     setStatus('Deleted');
   } catch (e) {
@@ -179,14 +232,14 @@ function buildUI() {
   el.btnNew().addEventListener('click', clearEditor);
   el.btnSave().addEventListener('click', saveNote);
   el.btnDelete().addEventListener('click', deleteCurrentNote);
+  el.noteList().addEventListener('scroll', onNoteListScroll);
 }
 
 async function init() {
   buildUI();
   try {
     setStatus('Loading…');
-    notes = await listNotes();
-    renderNoteList();
+    await reloadNotes();
     if (notes.length > 0) {
       await selectNote(notes[0].id);
     } else {
@@ -195,6 +248,7 @@ async function init() {
   } catch (e) {
     setStatus('Error: ' + String(e), true);
     notes = [];
+    nextCursor = null;
     renderNoteList();
     clearEditor();
   }
@@ -1,21 +1,11 @@
 // Prevents additional console window on Windows in release, DO NOT REMOVE!!
 #![cfg_attr(not(debug_assertions), windows_subsystem = "windows")]
 
-use std::path::PathBuf;
+use synapse_knowledge_manager::core::{
+    AsyncServiceContext, ContentConfig, FolderService, NoteService, NoteSort, PageRequest,
+    SavedSearchService, ServiceContext, DEFAULT_PAGE_SIZE,
+};
 use tauri::Manager;
-use synapse_knowledge_manager::core::Result;
-use synapse_knowledge_manager::core::{Note, NoteService, ServiceContext};
-
-/// App data paths (db + data dir), resolved from app_data_dir at startup
-struct AppPaths {
-    db_path: PathBuf,
-    data_dir: PathBuf,
-}
-
-fn service_context(paths: &AppPaths) -> Result<ServiceContext, String> {
-    ServiceContext::new(&paths.db_path, &paths.data_dir)
-        .map_err(|e| format!("Failed to create service context: {}", e))
-}
 
 #[tauri::command]
 fn greet(name: &str) -> String {
@@ -26,18 +16,23 @@ fn greet(name: &str) -> String {
 async fn create_note(
     title: String,
     content: String,
-    state: tauri::State<'_, AppPaths>,
+    ctx: tauri::State<'_, AsyncServiceContext>,
 ) -> Result<String, String> {
-    let ctx = service_context(&state)?;
-    let note = NoteService::create(&ctx, title, content)
+    let note = ctx
+        .run(move |ctx| NoteService::create(ctx, title, content))
+        .await
         .map_err(|e| format!("Failed to create note: {}", e))?;
     Ok(serde_json::to_string(&note).map_err(|e| format!("Serialization error: {}", e))?)
 }
 
 #[tauri::command]
-async fn get_note(id: String, state: tauri::State<'_, AppPaths>) -> Result<String, String> {
-    let ctx = service_context(&state)?;
-    let note_with_content = NoteService::get_by_id(&ctx, &id, false)
+async fn get_note(
+    id: String,
+    ctx: tauri::State<'_, AsyncServiceContext>,
+) -> Result<String, String> {
+    let note_with_content = ctx
+        .run(move |ctx| NoteService::get_by_id(ctx, &id, false))
+        .await
         .map_err(|e| format!("Failed to get note: {}", e))?;
     match note_with_content {
         Some(n) => Ok(serde_json::to_string(&n).map_err(|e| format!("Serialization error: {}", e))?),
@@ -48,12 +43,18 @@ async fn get_note(id: String, state: tauri::State<'_, AppPaths>) -> Result<Strin
 #[tauri::command]
 async fn list_notes(
     include_deleted: bool,
-    state: tauri::State<'_, AppPaths>,
+    sort: Option<NoteSort>,
+    limit: Option<usize>,
+    cursor: Option<String>,
+    ctx: tauri::State<'_, AsyncServiceContext>,
 ) -> Result<String, String> {
-    let ctx = service_context(&state)?;
-    let notes: Vec<Note> = NoteService::list(&ctx, include_deleted)
+    let request = PageRequest::new(sort.unwrap_or_default(), limit.unwrap_or(DEFAULT_PAGE_SIZE))
+        .with_cursor(cursor);
+    let page = ctx
+        .run(move |ctx| NoteService::list_page(ctx, include_deleted, &request))
+        .await
         .map_err(|e| format!("Failed to list notes: {}", e))?;
-    Ok(serde_json::to_string(&notes).map_err(|e| format!("Serialization error: {}", e))?)
+    Ok(serde_json::to_string(&page).map_err(|e| format!("Serialization error: {}", e))?)
 }
 
 #[tauri::command]
@@ -61,21 +62,62 @@ async fn update_note(
     id: String,
     title: Option<String>,
     content: Option<String>,
-    state: tauri::State<'_, AppPaths>,
+    ctx: tauri::State<'_, AsyncServiceContext>,
 ) -> Result<(), String> {
-    let ctx = service_context(&state)?;
-    NoteService::update(&ctx, &id, title, content)
+    ctx.run(move |ctx| NoteService::update(ctx, &id, title, content))
+        .await
         .map_err(|e| format!("Failed to update note: {}", e))?;
     Ok(())
 }
 
 #[tauri::command]
-async fn delete_note(id: String, state: tauri::State<'_, AppPaths>) -> Result<(), String> {
-    let ctx = service_context(&state)?;
-    NoteService::delete(&ctx, &id).map_err(|e| format!("Failed to delete note: {}", e))?;
+async fn delete_note(id: String, ctx: tauri::State<'_, AsyncServiceContext>) -> Result<(), String> {
+    ctx.run(move |ctx| NoteService::delete(ctx, &id))
+        .await
+        .map_err(|e| format!("Failed to delete note: {}", e))?;
     Ok(())
 }
 
+#[tauri::command]
+async fn get_folder_tree(ctx: tauri::State<'_, AsyncServiceContext>) -> Result<String, String> {
+    let tree = ctx
+        .run(FolderService::tree)
+        .await
+        .map_err(|e| format!("Failed to load folder tree: {}", e))?;
+    Ok(serde_json::to_string(&tree).map_err(|e| format!("Serialization error: {}", e))?)
+}
+
+#[tauri::command]
+async fn create_saved_search(
+    name: String,
+    query: String,
+    sort: Option<NoteSort>,
+    ctx: tauri::State<'_, AsyncServiceContext>,
+) -> Result<String, String> {
+    let search = ctx
+        .run(move |ctx| SavedSearchService::create(ctx, name, query, sort.unwrap_or_default()))
+        .await
+        .map_err(|e| format!("Failed to save search: {}", e))?;
+    Ok(serde_json::to_string(&search).map_err(|e| format!("Serialization error: {}", e))?)
+}
+
+#[tauri::command]
+async fn run_saved_search(
+    id: String,
+    limit: Option<usize>,
+    cursor: Option<String>,
+    ctx: tauri::State<'_, AsyncServiceContext>,
+) -> Result<String, String> {
+    // The saved search decides the order; only the page size and position come from here
+    let request = PageRequest::new(NoteSort::default(), limit.unwrap_or(DEFAULT_PAGE_SIZE))
+        .with_cursor(cursor);
+    let page = ctx
+        .run(move |ctx| SavedSearchService::evaluate_page(ctx, &id, &request))
+        .await
+        .map_err(|e| format!("Failed to run saved search: {}", e))?;
+    Ok(serde_json::to_string(&page).map_err(|e| format!("Serialization error: {}", e))?)
+}
+
 fn main() {
     tauri::Builder::default()
         .setup(|app| {
@@ -85,7 +127,21 @@ fn main() {
                 .map_err(|e| e.to_string())?;
             std::fs::create_dir_all(&data_dir)?;
             let db_path = data_dir.join("synapse.db");
-            app.manage(AppPaths { db_path, data_dir });
+            // Where a new vault keeps note content, as for the CLI
+            let content = match std::env::var("SYNAPSE_CONTENT") {
+                Ok(name) => Some(
+                    ContentConfig::parse(&name)
+                        .ok_or_else(|| format!("Unknown SYNAPSE_CONTENT: {}", name))?,
+                ),
+                Err(_) => None,
+            };
+            // One context for all commands: its pool is shared by the async runtime's threads
+            let ctx = match content {
+                Some(content) => ServiceContext::open(&db_path, &data_dir, content),
+                None => ServiceContext::new(&db_path, &data_dir),
+            }
+            .map_err(|e| format!("Failed to create service context: {}", e))?;
+            app.manage(AsyncServiceContext::new(ctx));
             Ok(())
         })
         .invoke_handler(tauri::generate_handler![
@@ -95,6 +151,9 @@ fn main() {
             list_notes,
             update_note,
             delete_note,
+            get_folder_tree,
+            create_saved_search,
+            run_saved_search,
         ])
         .run(tauri::generate_context!())
         .expect("error while running tauri application");
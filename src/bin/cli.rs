@@ -2,10 +2,13 @@
 //!
 //! This tool allows testing backend functionality in headless environments.
 
-use synapse_knowledge_manager::core::{ServiceContext, NoteService, TagService, FolderService};
-use synapse_knowledge_manager::core::Result;
 use std::env;
-use std::path::PathBuf;
+use synapse_knowledge_manager::core::{
+    ContentConfig, Error, FolderTreeNode, MAX_PAGE_SIZE, NoteSort, PageRequest, Result, SortKey,
+};
+use synapse_knowledge_manager::core::{
+    FolderService, NoteService, SavedSearchService, ServiceContext, TagService,
+};
 
 fn print_usage() {
     println!("Synapse Knowledge Manager CLI");
@@ -15,16 +18,26 @@ fn print_usage() {
     println!("Commands:");
     println!("  create-note <title> <content>    Create a new note");
     println!("  get-note <id>                    Get a note by ID");
-    println!("  list-notes                       List all notes");
+    println!("  list-notes [options]             List notes, one page at a time");
+    println!("      --sort <key>                 title, created, updated (default) or word_count");
+    println!("      --asc                        Ascending order (default: descending)");
+    println!("      --limit <n>                  Page size (default: 50, at most 1000)");
+    println!("      --cursor <cursor>            Continue after a previous page");
     println!("  search <query>                  Search notes");
     println!("  create-tag <name>                Create a tag");
     println!("  list-tags                        List all tags");
     println!("  create-folder <name> [parent]    Create a folder");
-    println!("  list-folders                     List all folders");
+    println!("  list-folders                     Show the folder tree with smart folders");
+    println!("  save-search <name> <query>       Save a search as a smart folder");
+    println!("  run-search <id> [options]        List the notes of a saved search, paged");
+    println!("      --limit <n>, --cursor <c>    As for list-notes");
     println!();
     println!("Environment variables:");
     println!("  SYNAPSE_DB_PATH                  Database path (default: ./data/synapse.db)");
     println!("  SYNAPSE_DATA_DIR                 Data directory (default: ./data)");
+    println!(
+        "  SYNAPSE_CONTENT                  Where a new vault keeps note content: files or database (default: files)"
+    );
 }
 
 fn get_ctx() -> Result<ServiceContext> {
@@ -33,11 +46,21 @@ fn get_ctx() -> Result<ServiceContext> {
     let data_dir = env::var("SYNAPSE_DATA_DIR")
         .unwrap_or_else(|_| "./data".to_string());
     
+    let content = match env::var("SYNAPSE_CONTENT") {
+        Ok(name) => Some(
+            ContentConfig::parse(&name)
+                .ok_or_else(|| Error::InvalidInput(format!("Unknown SYNAPSE_CONTENT: {}", name)))?,
+        ),
+        Err(_) => None,
+    };
+
     // Ensure data directory exists
     std::fs::create_dir_all(&data_dir)?;
-    std::fs::create_dir_all(PathBuf::from(&data_dir).join("notes"))?;
-    
-    ServiceContext::new(&db_path, &data_dir)
+
+    match content {
+        Some(content) => ServiceContext::open(&db_path, &data_dir, content),
+        None => ServiceContext::new(&db_path, &data_dir),
+    }
 }
 
 fn main() {
@@ -96,12 +119,28 @@ fn main() {
             }
         }
         "list-notes" => {
-            match NoteService::list(&ctx, false) {
-                Ok(notes) => {
-                    println!("Found {} notes:", notes.len());
-                    for note in notes {
-                        println!("  - {}: {} (updated: {})", 
-                            note.id, note.title, note.updated_at);
+            let request = match parse_page_request(&args[2..]) {
+                Ok(request) => request,
+                Err(e) => {
+                    eprintln!("Error: {}", e);
+                    std::process::exit(1);
+                }
+            };
+            match NoteService::list_page(&ctx, false, &request) {
+                Ok(page) => {
+                    println!(
+                        "Showing {} of {} notes:",
+                        page.items.len(),
+                        page.total_estimate
+                    );
+                    for note in page.items {
+                        println!(
+                            "  - {}: {} (updated: {})",
+                            note.id, note.title, note.updated_at
+                        );
+                    }
+                    if let Some(cursor) = page.next_cursor {
+                        println!("Next page: --cursor {}", cursor);
                     }
                 }
                 Err(e) => {
@@ -178,12 +217,61 @@ fn main() {
                 }
             }
         }
-        "list-folders" => {
-            match FolderService::get_roots(&ctx) {
-                Ok(folders) => {
-                    println!("Found {} root folders:", folders.len());
-                    for folder in folders {
-                        println!("  - {}: {} ({})", folder.id, folder.name, folder.path);
+        "list-folders" => match FolderService::tree(&ctx) {
+            Ok(tree) => {
+                println!("Folder tree:");
+                print_tree(&tree, 1);
+            }
+            Err(e) => {
+                eprintln!("Error: {}", e);
+                std::process::exit(1);
+            }
+        },
+        "save-search" => {
+            if args.len() < 4 {
+                eprintln!("Error: save-search requires <name> <query>");
+                std::process::exit(1);
+            }
+            match SavedSearchService::create(
+                &ctx,
+                args[2].clone(),
+                args[3].clone(),
+                NoteSort::default(),
+            ) {
+                Ok(search) => {
+                    println!("Saved search: {} ({})", search.name, search.id);
+                    println!("Query: {}", search.query);
+                }
+                Err(e) => {
+                    eprintln!("Error: {}", e);
+                    std::process::exit(1);
+                }
+            }
+        }
+        "run-search" => {
+            if args.len() < 3 {
+                eprintln!("Error: run-search requires <id>");
+                std::process::exit(1);
+            }
+            let request = match parse_page_request(&args[3..]) {
+                Ok(request) => request,
+                Err(e) => {
+                    eprintln!("Error: {}", e);
+                    std::process::exit(1);
+                }
+            };
+            match SavedSearchService::evaluate_page(&ctx, &args[2], &request) {
+                Ok(page) => {
+                    println!(
+                        "Showing {} of {} notes:",
+                        page.items.len(),
+                        page.total_estimate
+                    );
+                    for note in page.items {
+                        println!("  - {}: {}", note.id, note.title);
+                    }
+                    if let Some(cursor) = page.next_cursor {
+                        println!("Next page: --cursor {}", cursor);
                     }
                 }
                 Err(e) => {
@@ -199,3 +287,55 @@ fn main() {
         }
     }
 }
+
+fn parse_page_request(options: &[String]) -> std::result::Result<PageRequest, String> {
+    let mut request = PageRequest::default();
+    let mut options = options.iter();
+    while let Some(option) = options.next() {
+        match option.as_str() {
+            "--asc" => request.sort.descending = false,
+            "--sort" | "--limit" | "--cursor" => {
+                let value = options
+                    .next()
+                    .ok_or_else(|| format!("{} requires a value", option))?;
+                match option.as_str() {
+                    "--sort" => {
+                        request.sort.key = SortKey::parse(value)
+                            .filter(|key| *key != SortKey::Position)
+                            .ok_or_else(|| format!("Unknown sort key: {}", value))?;
+                    }
+                    "--limit" => {
+                        let limit: usize = value
+                            .parse()
+                            .map_err(|_| format!("Invalid limit: {}", value))?;
+                        request.limit = limit.min(MAX_PAGE_SIZE);
+                    }
+                    _ => request.cursor = Some(value.clone()),
+                }
+            }
+            _ => return Err(format!("Unknown option: {}", option)),
+        }
+    }
+    Ok(request)
+}
+
+fn print_tree(nodes: &[FolderTreeNode], depth: usize) {
+    let indent = "  ".repeat(depth);
+    for node in nodes {
+        match node {
+            FolderTreeNode::Folder { folder, children } => {
+                println!(
+                    "{}- {}: {} ({})",
+                    indent, folder.id, folder.name, folder.path
+                );
+                print_tree(children, depth + 1);
+            }
+            FolderTreeNode::SmartFolder { search } => {
+                println!(
+                    "{}* {}: {} [{}]",
+                    indent, search.id, search.name, search.query
+                );
+            }
+        }
+    }
+}